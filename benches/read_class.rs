@@ -2,6 +2,8 @@ use criterion::{criterion_group, criterion_main, Criterion, Throughput, BatchSiz
 use classfile::classfile::ClassFile;
 use std::io::{Cursor};
 use std::fs;
+use std::fmt::Write;
+use std::process::Command;
 
 fn read_class_bench(c: &mut Criterion) {
 	let mut group = c.benchmark_group("read_class");
@@ -18,7 +20,7 @@ fn read_class_bench(c: &mut Criterion) {
 						group.throughput(Throughput::Bytes(bytes.len() as u64));
 						group.bench_with_input(BenchmarkId::from_parameter(entry.file_name().into_string().unwrap()), &bytes, |b, bytes| {
 							b.iter_batched(|| Cursor::new(bytes), | mut slice |{
-								ClassFile::parse(&mut slice)
+								ClassFile::parse(&mut slice).unwrap()
 							}, BatchSize::SmallInput);
 						});
 					}
@@ -28,5 +30,52 @@ fn read_class_bench(c: &mut Criterion) {
 	}
 }
 
-criterion_group!(benches, read_class_bench);
+/// Generates and compiles a class whose methods are dominated by table and lookup switches, where
+/// nearly every instruction is a jump target
+fn switch_heavy_class() -> Vec<u8> {
+	let mut src = String::from("public class Switches {\n");
+	for method in 0..32 {
+		writeln!(src, "\tpublic static int table{}(int i, int j) {{", method).unwrap();
+		src.push_str("\t\tint r = 0;\n\t\tfor (int k = 0; k < j; k++) {\n\t\t\tswitch ((i + k) % 64) {\n");
+		for case in 0..64 {
+			writeln!(src, "\t\t\t\tcase {}: r += {}; if (r > {}) r -= k; break;", case, case * 7 + method, case * 100).unwrap();
+		}
+		src.push_str("\t\t\t\tdefault: r--;\n\t\t\t}\n\t\t}\n\t\treturn r;\n\t}\n");
+		
+		writeln!(src, "\tpublic static int lookup{}(int i) {{", method).unwrap();
+		src.push_str("\t\tswitch (i) {\n");
+		for case in 0..64 {
+			writeln!(src, "\t\t\tcase {}: return i > {} ? {} : -{};", case * case * 131 - 4096, case, case, method).unwrap();
+		}
+		src.push_str("\t\t\tdefault: return 0;\n\t\t}\n\t}\n");
+	}
+	src.push_str("}\n");
+	
+	let dir = std::env::temp_dir().join("classfile-rs-bench");
+	fs::create_dir_all(&dir).unwrap();
+	let source = dir.join("Switches.java");
+	fs::write(&source, src).unwrap();
+	let status = Command::new("javac")
+		.args(["-source", "1.8", "-target", "1.8", "-d"])
+		.arg(&dir)
+		.arg(&source)
+		.status()
+		.unwrap();
+	assert!(status.success());
+	fs::read(dir.join("Switches.class")).unwrap()
+}
+
+/// Times decoding code where nearly every instruction is a jump target, each of which needs a label
+fn read_switches_bench(c: &mut Criterion) {
+	let mut group = c.benchmark_group("read_switches");
+	let bytes = switch_heavy_class();
+	group.throughput(Throughput::Bytes(bytes.len() as u64));
+	group.bench_with_input(BenchmarkId::from_parameter("Switches.class"), &bytes, |b, bytes| {
+		b.iter_batched(|| Cursor::new(bytes), | mut slice |{
+			ClassFile::parse(&mut slice).unwrap()
+		}, BatchSize::SmallInput);
+	});
+}
+
+criterion_group!(benches, read_class_bench, read_switches_bench);
 criterion_main!(benches);
@@ -0,0 +1,81 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput, BatchSize, BenchmarkId};
+use classfile::classfile::ClassFile;
+use classfile::constantpool::{ConstantPoolWriter, ConstantType};
+use std::io::{Cursor};
+use std::fs;
+
+/// Reads every class in the benchmarking corpus, failing if any of them cannot be written back
+fn writable_classes() -> Vec<(String, Vec<u8>, ClassFile)> {
+	let mut classes = Vec::new();
+	for entry in fs::read_dir("classes/benchmarking").unwrap() {
+		let entry = entry.unwrap();
+		let path = entry.path();
+		if path.is_file() && path.extension().and_then(|ex| ex.to_str()) == Some("class") {
+			let bytes: Vec<u8> = fs::read(&path).unwrap();
+			let class = ClassFile::parse(&mut Cursor::new(&bytes)).unwrap();
+			let name = entry.file_name().into_string().unwrap();
+			let mut written = Vec::with_capacity(bytes.len());
+			if let Err(error) = class.write(&mut written) {
+				panic!("Failed to write {}: {:?}", name, error);
+			}
+			classes.push((name, bytes, class));
+		}
+	}
+	classes
+}
+
+fn write_class_bench(c: &mut Criterion) {
+	let mut group = c.benchmark_group("write_class");
+	
+	for (name, bytes, class) in writable_classes() {
+		group.throughput(Throughput::Bytes(bytes.len() as u64));
+		group.bench_with_input(BenchmarkId::from_parameter(name), &class, |b, class| {
+			b.iter_batched(|| Vec::with_capacity(bytes.len()), | mut wtr |{
+				class.write(&mut wtr)
+			}, BatchSize::SmallInput);
+		});
+	}
+}
+
+fn round_trip_bench(c: &mut Criterion) {
+	let mut group = c.benchmark_group("round_trip");
+	
+	for (name, bytes, _) in writable_classes() {
+		group.throughput(Throughput::Bytes(bytes.len() as u64));
+		group.bench_with_input(BenchmarkId::from_parameter(name), &bytes, |b, bytes| {
+			b.iter_batched(|| (Cursor::new(bytes), Vec::with_capacity(bytes.len())), | (mut slice, mut wtr) |{
+				ClassFile::parse(&mut slice)?.write(&mut wtr)
+			}, BatchSize::SmallInput);
+		});
+	}
+}
+
+/// Puts every constant of each pool into a fresh writer twice, so that half of the insertions are
+/// answered by deduplication
+fn constant_pool_dedup_bench(c: &mut Criterion) {
+	let mut group = c.benchmark_group("constant_pool_dedup");
+	
+	for (name, bytes, _) in writable_classes() {
+		let class = ClassFile::parse_preserving_pool(&mut Cursor::new(&bytes)).unwrap();
+		let constants: Vec<ConstantType> = class.constant_pool.unwrap()
+			.iter()
+			.map(|(_, constant)| constant.clone())
+			.collect();
+		group.throughput(Throughput::Elements(constants.len() as u64 * 2));
+		group.bench_with_input(BenchmarkId::from_parameter(name), &constants, |b, constants| {
+			b.iter_batched(|| constants.clone(), | constants |{
+				let mut pool = ConstantPoolWriter::new();
+				for constant in constants.iter() {
+					pool.put(constant.clone()).unwrap();
+				}
+				for constant in constants {
+					pool.put(constant).unwrap();
+				}
+				pool
+			}, BatchSize::SmallInput);
+		});
+	}
+}
+
+criterion_group!(benches, write_class_bench, round_trip_bench, constant_pool_dedup_bench);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+import java.util.function.Supplier;
+
+public class Calls {
+	public static void main(String[] args) {
+		Animal animal = args.length > 0 ? new Dog() : new Cat();
+		System.out.println(describe(animal));
+	}
+
+	static String describe(Animal animal) {
+		Supplier<String> sound = () -> animal.sound();
+		return sound.get();
+	}
+
+	static void unused() {
+		helper();
+	}
+
+	private static void helper() {
+	}
+}
+
+interface Animal {
+	String sound();
+}
+
+class Dog implements Animal {
+	public String sound() {
+		return "woof";
+	}
+}
+
+class Cat implements Animal {
+	public String sound() {
+		return "meow";
+	}
+}
@@ -0,0 +1,13 @@
+public class Compat {
+	public static final int LIMIT = 1;
+
+	public int count;
+
+	public void run() {
+		count++;
+	}
+
+	protected String name() {
+		return "compat";
+	}
+}
@@ -0,0 +1,6 @@
+public interface Constants {
+	int INT = 94132;
+	long LONG = 1L << 40;
+	double DOUBLE = 0.5;
+	String STRING = "constant";
+}
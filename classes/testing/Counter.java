@@ -0,0 +1,31 @@
+public class Counter {
+	public static final String NAME = "counter";
+
+	private final long[] counts;
+	private int total;
+
+	public Counter(int size) {
+		counts = new long[size];
+	}
+
+	public void add(int index, long amount) {
+		try {
+			counts[index] += amount;
+		} catch (ArrayIndexOutOfBoundsException e) {
+			throw new IllegalArgumentException("No count " + index, e);
+		}
+		total++;
+	}
+
+	public long sum() {
+		long sum = 0;
+		for (long count : counts) {
+			sum += count;
+		}
+		return sum;
+	}
+
+	public int total() {
+		return total;
+	}
+}
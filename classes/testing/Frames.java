@@ -0,0 +1,31 @@
+public class Frames {
+	private final String name;
+	
+	public Frames(String name) {
+		this.name = name;
+	}
+	
+	public static int sum(int[] values, long start) {
+		long total = start;
+		for (int i = 0; i < values.length; i++) {
+			if (values[i] > 0) {
+				total += values[i];
+			}
+		}
+		return (int) total;
+	}
+	
+	public String describe(double x) {
+		String s;
+		if (x > 0.5) {
+			s = "big";
+		} else {
+			s = name;
+		}
+		try {
+			return s.trim();
+		} catch (RuntimeException e) {
+			return null;
+		}
+	}
+}
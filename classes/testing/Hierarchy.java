@@ -0,0 +1,28 @@
+public class Hierarchy {
+	public static Shape pick(boolean round) {
+		return round ? new Circle() : new Square();
+	}
+}
+
+interface Shape extends Comparable<Shape> {
+	double area();
+
+	default int compareTo(Shape other) {
+		return Double.compare(area(), other.area());
+	}
+}
+
+abstract class Base implements Shape {
+}
+
+class Circle extends Base implements java.io.Serializable {
+	public double area() {
+		return Math.PI;
+	}
+}
+
+class Square extends Base {
+	public double area() {
+		return 1;
+	}
+}
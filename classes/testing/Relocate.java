@@ -0,0 +1,29 @@
+package com.example.lib;
+
+import java.util.ServiceLoader;
+
+public class Relocate {
+	static final String NAME = "com.example.lib.Relocate";
+
+	public static void main(String[] args) throws Exception {
+		Class<?> self = Class.forName(NAME);
+		boolean found = self.getResource("/com/example/lib/data.txt") != null;
+		System.out.println(self.getName() + " " + new Inner().getClass().getSimpleName() + " " + found);
+		for (Greeter greeter : ServiceLoader.load(Greeter.class)) {
+			System.out.println(greeter.greet());
+		}
+	}
+
+	static class Inner {
+	}
+
+	public interface Greeter {
+		String greet();
+	}
+
+	public static class Hello implements Greeter {
+		public String greet() {
+			return "hello";
+		}
+	}
+}
@@ -0,0 +1,79 @@
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.util.Arrays;
+import java.util.List;
+import java.util.function.Function;
+
+@Label("remap")
+public class Remap {
+	static Holder<Vehicle>.Item item;
+
+	public static void main(String[] args) {
+		List<Vehicle> vehicles = Arrays.asList(new Car(4), new Car(6));
+		Function<Vehicle, Integer> wheels = Vehicle::wheels;
+		int total = 0;
+		for (Vehicle vehicle : vehicles) {
+			total += wheels.apply(vehicle);
+		}
+		System.out.println(total + " " + vehicles.get(0).name() + " " + Remap.class.getAnnotation(Label.class).value());
+	}
+
+	static class Holder<T> {
+		class Item {
+			T value;
+		}
+	}
+}
+
+@Retention(RetentionPolicy.RUNTIME)
+@interface Label {
+	String value();
+
+	Class<?> type() default Object.class;
+
+	ElementType kind() default ElementType.TYPE;
+}
+
+abstract class Vehicle {
+	abstract int wheels();
+
+	String name() {
+		return "vehicle";
+	}
+}
+
+class Car extends Vehicle {
+	@Label(value = "count", type = Car.class, kind = ElementType.FIELD)
+	int count;
+
+	Car(int count) {
+		this.count = count;
+	}
+
+	int wheels() {
+		return count;
+	}
+
+	String name() {
+		return "car";
+	}
+}
+
+class Van extends Car {
+	Van() {
+		super(3);
+	}
+
+	int seats() {
+		return count;
+	}
+}
+
+class Truck extends Car {
+	long count;
+
+	Truck() {
+		super(8);
+	}
+}
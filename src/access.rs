@@ -13,6 +13,8 @@ bitflags! {
 		const PROTECTED = 0x0004;
 		const STATIC = 0x0008;
 		const FINAL = 0x0010;
+		/// Set by every modern compiler, and kept so that classes write back as they were read
+		const SUPER = 0x0020;
 		const INTERFACE = 0x0200;
 		const ABSTRACT = 0x0400;
 		const SYNTHETIC = 0x1000;
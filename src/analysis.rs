@@ -0,0 +1,820 @@
+use crate::method::Method;
+use crate::access::MethodAccessFlags;
+use crate::ast::{Insn, LabelInsn, LdcType, OpType, PrimitiveType, IntegerType, ReturnType, InvokeType, JumpCondition};
+use crate::types::Type;
+use crate::classpath::{ClassPath, OBJECT};
+use crate::typecheck::method_types;
+use crate::verify::field_descriptor_len;
+use crate::intern::Name;
+use crate::error::{Result, ParserError};
+use derive_more::Constructor;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::Debug;
+
+/// A value of the lattice an interpreter computes over
+pub trait Value: Clone + PartialEq + Debug {
+	/// The number of slots the value takes in the locals or on the stack, 2 for longs and doubles
+	fn size(&self) -> usize;
+}
+
+/// Computes the values produced by instructions, for an `Analyzer`. Each operation is named after
+/// the number of values the instruction pops, which are given in the order they were pushed, and
+/// returns the value it pushes, None if it pushes nothing. Operations are also called to check the
+/// values an instruction consumes, failing with `ParserError::analysis` if they are invalid.
+///
+/// Instructions are sorted as in ASM's `Interpreter`:
+/// - `new_operation`: `Ldc`, static `GetField` and `NewObject`
+/// - `copy_operation`: `LocalLoad`, `LocalStore`, `Dup` and `Swap`, once per copied value
+/// - `unary_operation`: `Negate`, `IncrementInt`, `Convert`, conditional jumps comparing with zero or
+///   null, switches, `Return`, static `PutField`, instance `GetField`, `NewArray`, `ArrayLength`,
+///   `Throw`, `CheckCast`, `InstanceOf` and the monitor instructions
+/// - `binary_operation`: `ArrayLoad`, arithmetic, `Compare`, conditional jumps comparing two values
+///   and instance `PutField`
+/// - `ternary_operation`: `ArrayStore`
+/// - `nary_operation`: `Invoke`, `InvokeDynamic` and `MultiNewArray`
+pub trait Interpreter {
+	type Value: Value;
+	
+	/// The value of locals that have not been set, including the second slot of longs and doubles
+	fn empty_value(&self) -> Self::Value;
+	
+	/// The value of `this` in an instance method of the given class
+	fn this_value(&mut self, class: &Name) -> Result<Self::Value>;
+	
+	/// The value of a parameter with the given field descriptor
+	fn parameter_value(&mut self, descriptor: &str) -> Result<Self::Value>;
+	
+	/// The exception pushed when entering a handler catching the given type, None for any exception
+	fn exception_value(&mut self, catch_type: Option<&Name>) -> Result<Self::Value>;
+	
+	fn new_operation(&mut self, index: usize, insn: &Insn) -> Result<Self::Value>;
+	
+	/// The value loaded, stored or duplicated by the instruction
+	fn copy_operation(&mut self, index: usize, insn: &Insn, value: &Self::Value) -> Result<Self::Value>;
+	
+	fn unary_operation(&mut self, index: usize, insn: &Insn, value: &Self::Value) -> Result<Option<Self::Value>>;
+	
+	fn binary_operation(&mut self, index: usize, insn: &Insn, a: &Self::Value, b: &Self::Value) -> Result<Option<Self::Value>>;
+	
+	fn ternary_operation(&mut self, index: usize, insn: &Insn, a: &Self::Value, b: &Self::Value, c: &Self::Value) -> Result<()>;
+	
+	fn nary_operation(&mut self, index: usize, insn: &Insn, values: &[Self::Value]) -> Result<Option<Self::Value>>;
+	
+	/// The value of a local or stack entry where two paths meet
+	fn merge(&mut self, a: &Self::Value, b: &Self::Value) -> Result<Self::Value>;
+}
+
+/// The locals and stack before an instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame<V> {
+	/// One entry per slot, a long or double being followed by an empty value
+	pub locals: Vec<V>,
+	/// One entry per value, from the bottom of the stack
+	pub stack: Vec<V>
+}
+
+impl<V: Value> Frame<V> {
+	/// The number of slots taken by the stack
+	pub fn stack_size(&self) -> usize {
+		self.stack.iter().map(|value| value.size()).sum()
+	}
+	
+	fn pop(&mut self, index: usize) -> Result<V> {
+		self.stack.pop().ok_or_else(|| ParserError::analysis(index, "Stack underflow"))
+	}
+	
+	/// Pops the given number of values, returning them in the order they were pushed
+	fn pop_values(&mut self, index: usize, count: usize) -> Result<Vec<V>> {
+		if count > self.stack.len() {
+			return Err(ParserError::analysis(index, "Stack underflow"));
+		}
+		Ok(self.stack.split_off(self.stack.len() - count))
+	}
+	
+	/// Pops values taking exactly the given number of slots, in the order they were pushed
+	fn pop_slots(&mut self, index: usize, slots: usize) -> Result<Vec<V>> {
+		let mut values = Vec::new();
+		let mut popped = 0;
+		while popped < slots {
+			let value = self.pop(index)?;
+			popped += value.size();
+			values.push(value);
+		}
+		if popped != slots {
+			return Err(ParserError::analysis(index, "Instruction splits a long or double on the stack"));
+		}
+		values.reverse();
+		Ok(values)
+	}
+	
+	fn local(&self, index: usize, local: u16) -> Result<&V> {
+		self.locals.get(local as usize)
+			.ok_or_else(|| ParserError::analysis(index, format!("Local {} is beyond max_locals {}", local, self.locals.len())))
+	}
+	
+	fn set_local<I: Interpreter<Value = V>>(&mut self, index: usize, local: u16, value: V, interpreter: &I) -> Result<()> {
+		let local = local as usize;
+		if local + value.size() > self.locals.len() {
+			return Err(ParserError::analysis(index, format!("Local {} is beyond max_locals {}", local, self.locals.len())));
+		}
+		// a long or double in the previous local loses its second half
+		if local > 0 && self.locals[local - 1].size() == 2 {
+			self.locals[local - 1] = interpreter.empty_value();
+		}
+		if value.size() == 2 {
+			self.locals[local + 1] = interpreter.empty_value();
+		}
+		self.locals[local] = value;
+		Ok(())
+	}
+	
+	/// Merges another frame into this one, returning whether this one changed
+	fn merge<I: Interpreter<Value = V>>(&mut self, index: usize, other: &Frame<V>, interpreter: &mut I) -> Result<bool> {
+		if self.stack.len() != other.stack.len() {
+			return Err(ParserError::analysis(index, "Incompatible stack heights"));
+		}
+		let mut changed = false;
+		for (value, other) in self.locals.iter_mut().chain(self.stack.iter_mut()).zip(other.locals.iter().chain(other.stack.iter())) {
+			let merged = interpreter.merge(value, other)?;
+			if &merged != value {
+				*value = merged;
+				changed = true;
+			}
+		}
+		Ok(changed)
+	}
+	
+	/// Simulates an instruction, returning where control may go next
+	fn execute<I: Interpreter<Value = V>>(&mut self, index: usize, insn: &Insn, interpreter: &mut I) -> Result<Vec<Flow>> {
+		match insn {
+			Insn::Label(_) | Insn::Nop(_) | Insn::BreakPoint(_) | Insn::ImpDep1(_) | Insn::ImpDep2(_) => {},
+			Insn::Ldc(_) | Insn::NewObject(_) => {
+				let value = interpreter.new_operation(index, insn)?;
+				self.stack.push(value);
+			},
+			Insn::GetField(x) if !x.instance => {
+				let value = interpreter.new_operation(index, insn)?;
+				self.stack.push(value);
+			},
+			Insn::LocalLoad(x) => {
+				let value = interpreter.copy_operation(index, insn, self.local(index, x.index)?)?;
+				self.stack.push(value);
+			},
+			Insn::LocalStore(x) => {
+				let value = self.pop(index)?;
+				let value = interpreter.copy_operation(index, insn, &value)?;
+				self.set_local(index, x.index, value, interpreter)?;
+			},
+			Insn::Dup(x) => {
+				let top = self.pop_slots(index, x.num as usize)?;
+				let below = self.pop_slots(index, x.down as usize)?;
+				let copies = top.iter()
+					.map(|value| interpreter.copy_operation(index, insn, value))
+					.collect::<Result<Vec<V>>>()?;
+				// as in ASM, a plain dup pushes its copies on top and the others insert them below
+				if below.is_empty() {
+					self.stack.extend(top);
+					self.stack.extend(copies);
+				} else {
+					self.stack.extend(copies);
+					self.stack.extend(below);
+					self.stack.extend(top);
+				}
+			},
+			Insn::Swap(_) => {
+				let values = self.pop_values(index, 2)?;
+				if values.iter().any(|value| value.size() == 2) {
+					return Err(ParserError::analysis(index, "Swaps a long or double"));
+				}
+				let b = interpreter.copy_operation(index, insn, &values[1])?;
+				let a = interpreter.copy_operation(index, insn, &values[0])?;
+				self.stack.push(b);
+				self.stack.push(a);
+			},
+			Insn::Pop(x) => {
+				self.pop_slots(index, if x.pop_two { 2 } else { 1 })?;
+			},
+			Insn::IncrementInt(x) => {
+				let value = self.local(index, x.index)?.clone();
+				if let Some(value) = interpreter.unary_operation(index, insn, &value)? {
+					self.set_local(index, x.index, value, interpreter)?;
+				}
+			},
+			Insn::Negate(_) | Insn::Convert(_) | Insn::GetField(_) | Insn::NewArray(_) | Insn::ArrayLength(_)
+				| Insn::CheckCast(_) | Insn::InstanceOf(_) | Insn::MonitorEnter(_) | Insn::MonitorExit(_) => {
+				self.unary(index, insn, interpreter)?;
+			},
+			Insn::PutField(x) if !x.instance => {
+				self.unary(index, insn, interpreter)?;
+			},
+			Insn::Return(x) => {
+				if x.kind != ReturnType::Void {
+					self.unary(index, insn, interpreter)?;
+				}
+				return Ok(Vec::new());
+			},
+			Insn::Throw(_) => {
+				self.unary(index, insn, interpreter)?;
+				return Ok(Vec::new());
+			},
+			Insn::LookupSwitch(x) => {
+				self.unary(index, insn, interpreter)?;
+				return Ok(std::iter::once(x.default).chain(x.cases.values().copied()).map(Flow::Jump).collect());
+			},
+			Insn::TableSwitch(x) => {
+				self.unary(index, insn, interpreter)?;
+				return Ok(std::iter::once(x.default).chain(x.cases.iter().copied()).map(Flow::Jump).collect());
+			},
+			Insn::Jump(x) => return Ok(vec![Flow::Jump(x.jump_to)]),
+			Insn::ConditionalJump(x) => {
+				match x.condition {
+					JumpCondition::IsNull | JumpCondition::NotNull | JumpCondition::IntEqZero | JumpCondition::IntNotEqZero
+						| JumpCondition::IntLessThanZero | JumpCondition::IntLessThanOrEqZero | JumpCondition::IntGreaterThanZero
+						| JumpCondition::IntGreaterThanOrEqZero => self.unary(index, insn, interpreter)?,
+					_ => self.binary(index, insn, interpreter)?
+				}
+				return Ok(vec![Flow::Next, Flow::Jump(x.jump_to)]);
+			},
+			Insn::ArrayLoad(_) | Insn::Add(_) | Insn::Subtract(_) | Insn::Multiply(_) | Insn::Divide(_) | Insn::Remainder(_)
+				| Insn::And(_) | Insn::Or(_) | Insn::Xor(_) | Insn::ShiftLeft(_) | Insn::ShiftRight(_)
+				| Insn::LogicalShiftRight(_) | Insn::Compare(_) | Insn::PutField(_) => {
+				self.binary(index, insn, interpreter)?;
+			},
+			Insn::ArrayStore(_) => {
+				let values = self.pop_values(index, 3)?;
+				interpreter.ternary_operation(index, insn, &values[0], &values[1], &values[2])?;
+			},
+			Insn::Invoke(x) => {
+				let (parameters, _) = method_types(&x.descriptor)
+					.ok_or_else(|| ParserError::invalid_descriptor(x.descriptor.to_string()))?;
+				let receiver = if x.kind == InvokeType::Static { 0 } else { 1 };
+				self.nary(index, insn, parameters.len() + receiver, interpreter)?;
+			},
+			Insn::InvokeDynamic(x) => {
+				let (parameters, _) = method_types(&x.descriptor)
+					.ok_or_else(|| ParserError::invalid_descriptor(x.descriptor.to_string()))?;
+				self.nary(index, insn, parameters.len(), interpreter)?;
+			},
+			Insn::MultiNewArray(x) => {
+				self.nary(index, insn, x.dimensions as usize, interpreter)?;
+			}
+		}
+		Ok(vec![Flow::Next])
+	}
+	
+	fn unary<I: Interpreter<Value = V>>(&mut self, index: usize, insn: &Insn, interpreter: &mut I) -> Result<()> {
+		let value = self.pop(index)?;
+		if let Some(value) = interpreter.unary_operation(index, insn, &value)? {
+			self.stack.push(value);
+		}
+		Ok(())
+	}
+	
+	fn binary<I: Interpreter<Value = V>>(&mut self, index: usize, insn: &Insn, interpreter: &mut I) -> Result<()> {
+		let values = self.pop_values(index, 2)?;
+		if let Some(value) = interpreter.binary_operation(index, insn, &values[0], &values[1])? {
+			self.stack.push(value);
+		}
+		Ok(())
+	}
+	
+	fn nary<I: Interpreter<Value = V>>(&mut self, index: usize, insn: &Insn, count: usize, interpreter: &mut I) -> Result<()> {
+		let values = self.pop_values(index, count)?;
+		if let Some(value) = interpreter.nary_operation(index, insn, &values)? {
+			self.stack.push(value);
+		}
+		Ok(())
+	}
+}
+
+/// Where control goes after an instruction
+enum Flow {
+	Next,
+	Jump(LabelInsn)
+}
+
+/// Computes the frame before each instruction of a method by simulating its code with an
+/// interpreter, along every path until the frames no longer change, as ASM's `Analyzer` does.
+/// Exception handlers are entered with the locals of each instruction they cover and the exception
+/// alone on the stack
+#[derive(Constructor, Clone, Debug)]
+pub struct Analyzer<I> {
+	pub interpreter: I
+}
+
+impl<I: Interpreter> Analyzer<I> {
+	/// The frame before each instruction of the method's code, by index in its `InsnList`, None for
+	/// instructions that cannot be reached. Methods without code have no frames. Code that was
+	/// parsed lazily is decoded
+	pub fn analyze(&mut self, class: &Name, method: &Method) -> Result<Vec<Option<Frame<I::Value>>>> {
+		let code = match method.decode_code()? {
+			Some(x) => x,
+			None => return Ok(Vec::new())
+		};
+		let insns = &code.insns.insns;
+		let (parameters, _) = method_types(&method.descriptor)
+			.ok_or_else(|| ParserError::invalid_descriptor(method.descriptor.to_string()))?;
+		if insns.is_empty() {
+			return Ok(Vec::new());
+		}
+		let interpreter = &mut self.interpreter;
+		let labels: HashMap<LabelInsn, usize> = insns.iter()
+			.enumerate()
+			.filter_map(|(index, insn)| match insn {
+				Insn::Label(label) => Some((*label, index)),
+				_ => None
+			})
+			.collect();
+		let label_index = |label: &LabelInsn| labels.get(label).copied().ok_or_else(ParserError::unmapped_label);
+		let mut handlers = Vec::new();
+		for handler in code.exceptions.iter() {
+			handlers.push((label_index(&handler.start)?, label_index(&handler.end)?, label_index(&handler.handler)?, handler.catch_type.as_ref()));
+		}
+	
+		let mut locals = vec![interpreter.empty_value(); code.max_locals as usize];
+		let mut initial = Vec::new();
+		if !method.access_flags.contains(MethodAccessFlags::STATIC) {
+			initial.push(interpreter.this_value(class)?);
+		}
+		for parameter in parameters {
+			initial.push(interpreter.parameter_value(parameter)?);
+		}
+		let mut slot = 0;
+		for value in initial {
+			if slot + value.size() > locals.len() {
+				return Err(ParserError::analysis(0, format!("Parameters do not fit in max_locals {}", code.max_locals)));
+			}
+			let size = value.size();
+			locals[slot] = value;
+			slot += size;
+		}
+	
+		let mut frames: Vec<Option<Frame<I::Value>>> = vec![None; insns.len()];
+		frames[0] = Some(Frame {
+			locals,
+			stack: Vec::new()
+		});
+		let mut queue = VecDeque::from(vec![0]);
+		let mut queued = vec![false; insns.len()];
+		queued[0] = true;
+		while let Some(index) = queue.pop_front() {
+			queued[index] = false;
+			let before = frames[index].clone().unwrap();
+			let mut targets = Vec::new();
+			for (start, end, handler, catch_type) in handlers.iter() {
+				if *start <= index && index < *end {
+					let frame = Frame {
+						locals: before.locals.clone(),
+						stack: vec![interpreter.exception_value(*catch_type)?]
+					};
+					targets.push((*handler, frame));
+				}
+			}
+			let mut after = before;
+			let flows = after.execute(index, &insns[index], interpreter)?;
+			if after.stack_size() > code.max_stack as usize {
+				return Err(ParserError::analysis(index, format!("Stack of {} slots exceeds max_stack {}", after.stack_size(), code.max_stack)));
+			}
+			for flow in flows {
+				match flow {
+					Flow::Next if index + 1 == insns.len() => {
+						return Err(ParserError::analysis(index, "Execution falls off the end of the code"));
+					},
+					Flow::Next => targets.push((index + 1, after.clone())),
+					Flow::Jump(label) => targets.push((label_index(&label)?, after.clone()))
+				}
+			}
+			for (target, frame) in targets {
+				let changed = match &mut frames[target] {
+					Some(existing) => existing.merge(target, &frame, interpreter)?,
+					None => {
+						frames[target] = Some(frame);
+						true
+					}
+				};
+				if changed && !queued[target] {
+					queued[target] = true;
+					queue.push_back(target);
+				}
+			}
+		}
+		Ok(frames)
+	}
+}
+
+/// The value computed by `BasicInterpreter`, one per kind of local or stack value
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BasicValue {
+	/// A local that is unset, the second slot of a long or double, or values of different kinds
+	/// where paths meet
+	Uninitialized,
+	Int,
+	Float,
+	Long,
+	Double,
+	Reference
+}
+
+impl BasicValue {
+	/// The kind of load or store that moves the value, None if it cannot be used
+	pub fn op_type(&self) -> Option<OpType> {
+		match self {
+			BasicValue::Uninitialized => None,
+			BasicValue::Int => Some(OpType::Int),
+			BasicValue::Float => Some(OpType::Float),
+			BasicValue::Long => Some(OpType::Long),
+			BasicValue::Double => Some(OpType::Double),
+			BasicValue::Reference => Some(OpType::Reference)
+		}
+	}
+}
+
+impl Value for BasicValue {
+	fn size(&self) -> usize {
+		match self {
+			BasicValue::Long | BasicValue::Double => 2,
+			_ => 1
+		}
+	}
+}
+
+impl From<&TypeValue> for BasicValue {
+	fn from(value: &TypeValue) -> Self {
+		match value {
+			TypeValue::Uninitialized => BasicValue::Uninitialized,
+			TypeValue::Int => BasicValue::Int,
+			TypeValue::Float => BasicValue::Float,
+			TypeValue::Long => BasicValue::Long,
+			TypeValue::Double => BasicValue::Double,
+			TypeValue::Null | TypeValue::Reference(_) => BasicValue::Reference
+		}
+	}
+}
+
+/// Computes the kind of each value, without checking the values instructions consume
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BasicInterpreter;
+
+impl Interpreter for BasicInterpreter {
+	type Value = BasicValue;
+	
+	fn empty_value(&self) -> BasicValue {
+		BasicValue::Uninitialized
+	}
+	
+	fn this_value(&mut self, _class: &Name) -> Result<BasicValue> {
+		Ok(BasicValue::Reference)
+	}
+	
+	fn parameter_value(&mut self, descriptor: &str) -> Result<BasicValue> {
+		Ok(BasicValue::from(&TypeValue::of_descriptor(descriptor)?))
+	}
+	
+	fn exception_value(&mut self, _catch_type: Option<&Name>) -> Result<BasicValue> {
+		Ok(BasicValue::Reference)
+	}
+	
+	fn new_operation(&mut self, _index: usize, insn: &Insn) -> Result<BasicValue> {
+		Ok(pushed(insn, None)?.as_ref().map(BasicValue::from).unwrap_or(BasicValue::Uninitialized))
+	}
+	
+	fn copy_operation(&mut self, _index: usize, _insn: &Insn, value: &BasicValue) -> Result<BasicValue> {
+		Ok(*value)
+	}
+	
+	fn unary_operation(&mut self, _index: usize, insn: &Insn, _value: &BasicValue) -> Result<Option<BasicValue>> {
+		Ok(pushed(insn, None)?.as_ref().map(BasicValue::from))
+	}
+	
+	fn binary_operation(&mut self, _index: usize, insn: &Insn, _a: &BasicValue, _b: &BasicValue) -> Result<Option<BasicValue>> {
+		Ok(pushed(insn, None)?.as_ref().map(BasicValue::from))
+	}
+	
+	fn ternary_operation(&mut self, _index: usize, _insn: &Insn, _a: &BasicValue, _b: &BasicValue, _c: &BasicValue) -> Result<()> {
+		Ok(())
+	}
+	
+	fn nary_operation(&mut self, _index: usize, insn: &Insn, _values: &[BasicValue]) -> Result<Option<BasicValue>> {
+		Ok(pushed(insn, None)?.as_ref().map(BasicValue::from))
+	}
+	
+	fn merge(&mut self, a: &BasicValue, b: &BasicValue) -> Result<BasicValue> {
+		Ok(if a == b { *a } else { BasicValue::Uninitialized })
+	}
+}
+
+/// The value computed by `TypeInterpreter`, references having their exact type
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TypeValue {
+	/// A local that is unset, the second slot of a long or double, or values of different kinds
+	/// where paths meet
+	Uninitialized,
+	Int,
+	Float,
+	Long,
+	Double,
+	Null,
+	/// An object of the class with this internal name, or an array with this descriptor
+	Reference(Name)
+}
+
+impl TypeValue {
+	/// The value of a field descriptor, booleans, bytes, chars and shorts being ints
+	pub fn of_descriptor(descriptor: &str) -> Result<TypeValue> {
+		if descriptor == "V" || field_descriptor_len(descriptor) != Some(descriptor.len()) {
+			return Err(ParserError::invalid_descriptor(descriptor));
+		}
+		Ok(match descriptor.as_bytes()[0] {
+			b'F' => TypeValue::Float,
+			b'J' => TypeValue::Long,
+			b'D' => TypeValue::Double,
+			b'L' => TypeValue::Reference(Name::from(&descriptor[1..descriptor.len() - 1])),
+			b'[' => TypeValue::Reference(Name::from(descriptor)),
+			_ => TypeValue::Int
+		})
+	}
+	
+	fn of_primitive(kind: PrimitiveType) -> TypeValue {
+		match kind {
+			PrimitiveType::Long => TypeValue::Long,
+			PrimitiveType::Float => TypeValue::Float,
+			PrimitiveType::Double => TypeValue::Double,
+			_ => TypeValue::Int
+		}
+	}
+	
+	fn of_integer(kind: IntegerType) -> TypeValue {
+		match kind {
+			IntegerType::Int => TypeValue::Int,
+			IntegerType::Long => TypeValue::Long
+		}
+	}
+}
+
+impl Value for TypeValue {
+	fn size(&self) -> usize {
+		match self {
+			TypeValue::Long | TypeValue::Double => 2,
+			_ => 1
+		}
+	}
+}
+
+/// What `TypeInterpreter` needs to know about classes to merge references
+pub trait TypeHierarchy {
+	/// The most specific class both given classes extend, interfaces being treated as
+	/// `java/lang/Object`
+	fn common_super_class(&self, a: &str, b: &str) -> Result<Name>;
+}
+
+impl TypeHierarchy for ClassPath {
+	fn common_super_class(&self, a: &str, b: &str) -> Result<Name> {
+		ClassPath::common_super_class(self, a, b)
+	}
+}
+
+/// Computes the type of each value, asking the hierarchy for the common superclass of references
+/// that meet. Arrays of references merge into arrays of the common superclass of their elements,
+/// other arrays that differ into `java/lang/Object`. Objects have their class as soon as they are
+/// created, and the values instructions consume are not checked
+#[derive(Constructor, Copy, Clone, Debug)]
+pub struct TypeInterpreter<'a, H> {
+	pub hierarchy: &'a H
+}
+
+impl<H: TypeHierarchy> TypeInterpreter<'_, H> {
+	fn merge_references(&self, a: &str, b: &str) -> Result<Name> {
+		if a == b {
+			return Ok(Name::from(a));
+		}
+		match (a.strip_prefix('['), b.strip_prefix('[')) {
+			(Some(a), Some(b)) if is_reference_descriptor(a) && is_reference_descriptor(b) => {
+				let element = match (TypeValue::of_descriptor(a)?, TypeValue::of_descriptor(b)?) {
+					(TypeValue::Reference(a), TypeValue::Reference(b)) => self.merge_references(&a, &b)?,
+					_ => unreachable!()
+				};
+				if element.starts_with('[') {
+					Ok(Name::from(format!("[{}", element)))
+				} else {
+					Ok(Name::from(format!("[L{};", element)))
+				}
+			},
+			(None, None) => self.hierarchy.common_super_class(a, b),
+			_ => Ok(Name::from(OBJECT))
+		}
+	}
+}
+
+impl<H: TypeHierarchy> Interpreter for TypeInterpreter<'_, H> {
+	type Value = TypeValue;
+	
+	fn empty_value(&self) -> TypeValue {
+		TypeValue::Uninitialized
+	}
+	
+	fn this_value(&mut self, class: &Name) -> Result<TypeValue> {
+		Ok(TypeValue::Reference(class.clone()))
+	}
+	
+	fn parameter_value(&mut self, descriptor: &str) -> Result<TypeValue> {
+		TypeValue::of_descriptor(descriptor)
+	}
+	
+	fn exception_value(&mut self, catch_type: Option<&Name>) -> Result<TypeValue> {
+		Ok(TypeValue::Reference(catch_type.cloned().unwrap_or_else(|| Name::from("java/lang/Throwable"))))
+	}
+	
+	fn new_operation(&mut self, _index: usize, insn: &Insn) -> Result<TypeValue> {
+		Ok(pushed(insn, None)?.unwrap_or(TypeValue::Uninitialized))
+	}
+	
+	fn copy_operation(&mut self, _index: usize, _insn: &Insn, value: &TypeValue) -> Result<TypeValue> {
+		Ok(value.clone())
+	}
+	
+	fn unary_operation(&mut self, _index: usize, insn: &Insn, _value: &TypeValue) -> Result<Option<TypeValue>> {
+		pushed(insn, None)
+	}
+	
+	fn binary_operation(&mut self, _index: usize, insn: &Insn, a: &TypeValue, _b: &TypeValue) -> Result<Option<TypeValue>> {
+		pushed(insn, Some(a))
+	}
+	
+	fn ternary_operation(&mut self, _index: usize, _insn: &Insn, _a: &TypeValue, _b: &TypeValue, _c: &TypeValue) -> Result<()> {
+		Ok(())
+	}
+	
+	fn nary_operation(&mut self, _index: usize, insn: &Insn, _values: &[TypeValue]) -> Result<Option<TypeValue>> {
+		pushed(insn, None)
+	}
+	
+	fn merge(&mut self, a: &TypeValue, b: &TypeValue) -> Result<TypeValue> {
+		Ok(match (a, b) {
+			_ if a == b => a.clone(),
+			(TypeValue::Null, TypeValue::Reference(_)) => b.clone(),
+			(TypeValue::Reference(_), TypeValue::Null) => a.clone(),
+			(TypeValue::Reference(a), TypeValue::Reference(b)) => TypeValue::Reference(self.merge_references(a, b)?),
+			_ => TypeValue::Uninitialized
+		})
+	}
+}
+
+/// The value computed by `SourceInterpreter`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SourceValue {
+	pub size: usize,
+	/// The indices of the instructions that may have produced the value, including loads, stores
+	/// and dups. Empty for parameters, `this`, caught exceptions and unset locals
+	pub insns: BTreeSet<usize>
+}
+
+impl SourceValue {
+	fn new(size: usize, index: usize) -> Self {
+		SourceValue {
+			size,
+			insns: std::iter::once(index).collect()
+		}
+	}
+}
+
+impl Value for SourceValue {
+	fn size(&self) -> usize {
+		self.size
+	}
+}
+
+/// Computes which instructions produce each value
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SourceInterpreter;
+
+impl SourceInterpreter {
+	fn produced(&self, index: usize, insn: &Insn) -> Result<Option<SourceValue>> {
+		Ok(pushed(insn, None)?.map(|value| SourceValue::new(value.size(), index)))
+	}
+}
+
+impl Interpreter for SourceInterpreter {
+	type Value = SourceValue;
+	
+	fn empty_value(&self) -> SourceValue {
+		SourceValue {
+			size: 1,
+			insns: BTreeSet::new()
+		}
+	}
+	
+	fn this_value(&mut self, _class: &Name) -> Result<SourceValue> {
+		Ok(self.empty_value())
+	}
+	
+	fn parameter_value(&mut self, descriptor: &str) -> Result<SourceValue> {
+		Ok(SourceValue {
+			size: TypeValue::of_descriptor(descriptor)?.size(),
+			insns: BTreeSet::new()
+		})
+	}
+	
+	fn exception_value(&mut self, _catch_type: Option<&Name>) -> Result<SourceValue> {
+		Ok(self.empty_value())
+	}
+	
+	fn new_operation(&mut self, index: usize, insn: &Insn) -> Result<SourceValue> {
+		Ok(self.produced(index, insn)?.unwrap_or_else(|| SourceValue::new(1, index)))
+	}
+	
+	fn copy_operation(&mut self, index: usize, _insn: &Insn, value: &SourceValue) -> Result<SourceValue> {
+		Ok(SourceValue::new(value.size, index))
+	}
+	
+	fn unary_operation(&mut self, index: usize, insn: &Insn, _value: &SourceValue) -> Result<Option<SourceValue>> {
+		self.produced(index, insn)
+	}
+	
+	fn binary_operation(&mut self, index: usize, insn: &Insn, _a: &SourceValue, _b: &SourceValue) -> Result<Option<SourceValue>> {
+		self.produced(index, insn)
+	}
+	
+	fn ternary_operation(&mut self, _index: usize, _insn: &Insn, _a: &SourceValue, _b: &SourceValue, _c: &SourceValue) -> Result<()> {
+		Ok(())
+	}
+	
+	fn nary_operation(&mut self, index: usize, insn: &Insn, _values: &[SourceValue]) -> Result<Option<SourceValue>> {
+		self.produced(index, insn)
+	}
+	
+	fn merge(&mut self, a: &SourceValue, b: &SourceValue) -> Result<SourceValue> {
+		Ok(SourceValue {
+			size: a.size.min(b.size),
+			insns: a.insns.union(&b.insns).copied().collect()
+		})
+	}
+}
+
+/// The value an instruction pushes, given the array an `ArrayLoad` reads from
+fn pushed(insn: &Insn, array: Option<&TypeValue>) -> Result<Option<TypeValue>> {
+	let reference = |name: &str| Some(TypeValue::Reference(Name::from(name)));
+	Ok(match insn {
+		Insn::Ldc(x) => match &x.constant {
+			LdcType::Null => Some(TypeValue::Null),
+			LdcType::String(_) => reference("java/lang/String"),
+			LdcType::Int(_) => Some(TypeValue::Int),
+			LdcType::Float(_) => Some(TypeValue::Float),
+			LdcType::Long(_) => Some(TypeValue::Long),
+			LdcType::Double(_) => Some(TypeValue::Double),
+			LdcType::Class(_) => reference("java/lang/Class"),
+			LdcType::MethodType(_) => reference("java/lang/invoke/MethodType"),
+			LdcType::MethodHandle() => reference("java/lang/invoke/MethodHandle"),
+			// the type of a dynamic constant is not decoded
+			LdcType::Dynamic() => reference(OBJECT)
+		},
+		Insn::NewObject(x) => Some(TypeValue::Reference(x.kind.clone())),
+		Insn::GetField(x) => Some(TypeValue::of_descriptor(&x.descriptor)?),
+		Insn::IncrementInt(_) | Insn::ArrayLength(_) | Insn::InstanceOf(_) | Insn::Compare(_) => Some(TypeValue::Int),
+		Insn::Negate(x) => Some(TypeValue::of_primitive(x.kind)),
+		Insn::Convert(x) => Some(TypeValue::of_primitive(x.to)),
+		Insn::CheckCast(x) => Some(TypeValue::Reference(x.kind.clone())),
+		Insn::NewArray(x) => Some(TypeValue::Reference(Name::from(format!("[{}", type_descriptor(&x.kind))))),
+		Insn::ArrayLoad(x) => Some(match (&x.kind, array) {
+			(Type::Reference(_), Some(TypeValue::Reference(array))) if array.starts_with('[') => TypeValue::of_descriptor(&array[1..])?,
+			(Type::Reference(_), Some(TypeValue::Null)) => TypeValue::Null,
+			(kind, _) => TypeValue::of_descriptor(&type_descriptor(kind))?
+		}),
+		Insn::Add(x) => Some(TypeValue::of_primitive(x.kind)),
+		Insn::Subtract(x) => Some(TypeValue::of_primitive(x.kind)),
+		Insn::Multiply(x) => Some(TypeValue::of_primitive(x.kind)),
+		Insn::Divide(x) => Some(TypeValue::of_primitive(x.kind)),
+		Insn::Remainder(x) => Some(TypeValue::of_primitive(x.kind)),
+		Insn::And(x) => Some(TypeValue::of_integer(x.kind)),
+		Insn::Or(x) => Some(TypeValue::of_integer(x.kind)),
+		Insn::Xor(x) => Some(TypeValue::of_integer(x.kind)),
+		Insn::ShiftLeft(x) => Some(TypeValue::of_integer(x.kind)),
+		Insn::ShiftRight(x) => Some(TypeValue::of_integer(x.kind)),
+		Insn::LogicalShiftRight(x) => Some(TypeValue::of_integer(x.kind)),
+		Insn::Invoke(x) => method_types(&x.descriptor).map(|(_, result)| result).filter(|x| *x != "V").map(TypeValue::of_descriptor).transpose()?,
+		Insn::InvokeDynamic(x) => method_types(&x.descriptor).map(|(_, result)| result).filter(|x| *x != "V").map(TypeValue::of_descriptor).transpose()?,
+		Insn::MultiNewArray(x) => Some(TypeValue::Reference(x.kind.clone())),
+		_ => None
+	})
+}
+
+/// The field descriptor of a type, `java/lang/Object` if a reference has no class
+fn type_descriptor(kind: &Type) -> String {
+	match kind {
+		Type::Reference(Some(name)) if name.starts_with('[') => name.to_string(),
+		Type::Reference(Some(name)) => format!("L{};", name),
+		Type::Reference(None) => format!("L{};", OBJECT),
+		Type::Boolean => "Z".to_string(),
+		Type::Byte => "B".to_string(),
+		Type::Char => "C".to_string(),
+		Type::Short => "S".to_string(),
+		Type::Int => "I".to_string(),
+		Type::Long => "J".to_string(),
+		Type::Float => "F".to_string(),
+		Type::Double => "D".to_string(),
+		Type::Void => "V".to_string()
+	}
+}
+
+fn is_reference_descriptor(descriptor: &str) -> bool {
+	descriptor.starts_with(['L', '['])
+}
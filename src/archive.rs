@@ -0,0 +1,249 @@
+use crate::classfile::{ClassFile, ParseOptions};
+use crate::error::{Result, ParserError};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, Write, BufReader, BufWriter};
+use std::path::Path;
+use zip::{ZipArchive, ZipWriter, CompressionMethod};
+use zip::write::FileOptions;
+pub use zip::DateTime;
+
+pub const MANIFEST: &str = "META-INF/MANIFEST.MF";
+const VERSIONS: &str = "META-INF/versions/";
+
+/// A file or directory within a jar or zip archive
+#[derive(Clone, Debug)]
+pub struct ArchiveEntry {
+	/// The full path of the entry within the archive, directories end with '/'
+	pub name: String,
+	pub data: Vec<u8>,
+	pub last_modified: DateTime,
+	pub compression: CompressionMethod,
+	pub unix_mode: Option<u32>
+}
+
+impl ArchiveEntry {
+	/// Creates a deflated file entry, timestamped with the earliest zip time so that written
+	/// archives do not depend on when they were created
+	pub fn new<T: Into<String>>(name: T, data: Vec<u8>) -> Self {
+		ArchiveEntry {
+			name: name.into(),
+			data,
+			last_modified: DateTime::default(),
+			compression: CompressionMethod::Deflated,
+			unix_mode: None
+		}
+	}
+	
+	pub fn is_dir(&self) -> bool {
+		self.name.ends_with('/')
+	}
+	
+	pub fn is_class(&self) -> bool {
+		self.name.ends_with(".class") && !self.is_dir()
+	}
+	
+	/// The Java release of a multi-release overlay entry under `META-INF/versions/N/`
+	pub fn release(&self) -> Option<u32> {
+		self.split_release().map(|(release, _)| release)
+	}
+	
+	/// The name of the entry with any `META-INF/versions/N/` prefix removed
+	pub fn path(&self) -> &str {
+		match self.split_release() {
+			Some((_, path)) => path,
+			None => &self.name
+		}
+	}
+	
+	fn split_release(&self) -> Option<(u32, &str)> {
+		let rest = self.name.strip_prefix(VERSIONS)?;
+		let end = rest.find('/')?;
+		Some((rest[..end].parse().ok()?, &rest[end + 1..]))
+	}
+	
+	/// The internal name of the class stored in this entry, such as `java/lang/Object`
+	pub fn class_name(&self) -> Option<&str> {
+		if self.is_class() {
+			self.path().strip_suffix(".class")
+		} else {
+			None
+		}
+	}
+}
+
+/// The entries of a jar or zip archive, in the order they are written
+#[derive(Clone, Debug, Default)]
+pub struct Archive {
+	pub entries: Vec<ArchiveEntry>
+}
+
+impl Archive {
+	pub fn new() -> Self {
+		Archive::default()
+	}
+	
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+		Archive::read(BufReader::new(File::open(path)?))
+	}
+	
+	pub fn read<R: Read + Seek>(rdr: R) -> Result<Self> {
+		let mut zip = ZipArchive::new(rdr)?;
+		let mut entries = Vec::with_capacity(zip.len());
+		for i in 0..zip.len() {
+			let mut file = zip.by_index(i)?;
+			let mut data = Vec::with_capacity(file.size() as usize);
+			file.read_to_end(&mut data)?;
+			entries.push(ArchiveEntry {
+				name: file.name().to_string(),
+				data,
+				last_modified: file.last_modified(),
+				compression: file.compression(),
+				unix_mode: file.unix_mode()
+			});
+		}
+		Ok(Archive {
+			entries
+		})
+	}
+	
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+		let mut wtr = BufWriter::new(File::create(path)?);
+		self.write(&mut wtr)?;
+		wtr.flush()?;
+		Ok(())
+	}
+	
+	/// Writes the archive with the manifest first, as the JDK expects, followed by every other entry
+	/// in order. Entries keep their timestamps, so writing an archive twice gives the same bytes
+	pub fn write<W: Write + Seek>(&self, wtr: W) -> Result<()> {
+		let mut zip = ZipWriter::new(wtr);
+		let manifest = self.entries.iter().filter(|entry| entry.name == "META-INF/" || entry.name == MANIFEST);
+		let rest = self.entries.iter().filter(|entry| entry.name != "META-INF/" && entry.name != MANIFEST);
+		for entry in manifest.chain(rest) {
+			let mut options = FileOptions::default()
+				.compression_method(entry.compression)
+				.last_modified_time(entry.last_modified);
+			if let Some(mode) = entry.unix_mode {
+				options = options.unix_permissions(mode);
+			}
+			if entry.is_dir() {
+				zip.add_directory(entry.name.as_str(), options)?;
+			} else {
+				zip.start_file(entry.name.as_str(), options)?;
+				zip.write_all(&entry.data)?;
+			}
+		}
+		zip.finish()?;
+		Ok(())
+	}
+	
+	pub fn get(&self, name: &str) -> Option<&ArchiveEntry> {
+		self.entries.iter().find(|entry| entry.name == name)
+	}
+	
+	pub fn get_mut(&mut self, name: &str) -> Option<&mut ArchiveEntry> {
+		self.entries.iter_mut().find(|entry| entry.name == name)
+	}
+	
+	/// Replaces the data of an existing entry, keeping its position and timestamp, or appends a new
+	/// entry
+	pub fn insert<T: Into<String>>(&mut self, name: T, data: Vec<u8>) {
+		let name = name.into();
+		match self.get_mut(&name) {
+			Some(entry) => entry.data = data,
+			None => self.entries.push(ArchiveEntry::new(name, data))
+		}
+	}
+	
+	pub fn remove(&mut self, name: &str) -> Option<ArchiveEntry> {
+		let index = self.entries.iter().position(|entry| entry.name == name)?;
+		Some(self.entries.remove(index))
+	}
+	
+	pub fn manifest(&self) -> Option<&ArchiveEntry> {
+		self.get(MANIFEST)
+	}
+	
+	/// Whether the manifest declares `Multi-Release: true`
+	pub fn is_multi_release(&self) -> bool {
+		let manifest = match self.manifest() {
+			Some(x) => x,
+			None => return false
+		};
+		String::from_utf8_lossy(&manifest.data).lines().any(|line| {
+			let mut parts = line.splitn(2, ':');
+			let key = parts.next().unwrap_or("").trim();
+			let value = parts.next().unwrap_or("").trim();
+			key.eq_ignore_ascii_case("Multi-Release") && value.eq_ignore_ascii_case("true")
+		})
+	}
+	
+	/// Every class entry, including multi-release overlays
+	pub fn classes(&self) -> impl Iterator<Item = &ArchiveEntry> {
+		self.entries.iter().filter(|entry| entry.is_class())
+	}
+	
+	/// The class entries a JVM of the given release would load: for each class the overlay with the
+	/// highest release not above the given one, or the base entry if there is none. Overlays are
+	/// ignored unless the archive is multi-release. Entries are returned in archive order
+	pub fn classes_for_release(&self, release: u32) -> Vec<&ArchiveEntry> {
+		self.release_indices(release)
+			.into_iter()
+			.map(|index| &self.entries[index])
+			.collect()
+	}
+	
+	/// The indices of the entries returned by `classes_for_release`
+	pub(crate) fn release_indices(&self, release: u32) -> Vec<usize> {
+		let multi_release = self.is_multi_release();
+		// the chosen entry for each class path, along with its release
+		let mut chosen: HashMap<&str, (usize, u32)> = HashMap::new();
+		for (index, entry) in self.entries.iter().enumerate() {
+			if !entry.is_class() {
+				continue;
+			}
+			let entry_release = match entry.release() {
+				Some(x) if multi_release && x <= release => x,
+				Some(_) => continue,
+				None => 0
+			};
+			let best = chosen.entry(entry.path()).or_insert((index, entry_release));
+			if entry_release > best.1 {
+				*best = (index, entry_release);
+			}
+		}
+		let mut indices: Vec<usize> = chosen.values().map(|(index, _)| *index).collect();
+		indices.sort_unstable();
+		indices
+	}
+	
+	/// Parses every class entry, including multi-release overlays, in archive order
+	pub fn parse_classes(&self, options: &ParseOptions) -> Vec<(&str, Result<ClassFile>)> {
+		self.classes()
+			.map(|entry| (entry.name.as_str(), ClassFile::parse_with_options(&mut entry.data.as_slice(), options)))
+			.collect()
+	}
+	
+	/// Parses every class entry, passes it to the given closure and writes it back into the entry.
+	/// Resources and the manifest are left untouched, as are classes that fail to parse, transform
+	/// or write, which are returned along with their error
+	pub fn transform<F>(&mut self, options: &ParseOptions, mut op: F) -> Vec<(String, ParserError)>
+		where F: FnMut(&ArchiveEntry, &mut ClassFile) -> Result<()> {
+		let mut errors = Vec::new();
+		for entry in self.entries.iter_mut().filter(|entry| entry.is_class()) {
+			let data = ClassFile::parse_with_options(&mut entry.data.as_slice(), options)
+				.and_then(|mut class| {
+					op(entry, &mut class)?;
+					let mut data = Vec::with_capacity(entry.data.len());
+					class.write(&mut data)?;
+					Ok(data)
+				});
+			match data {
+				Ok(data) => entry.data = data,
+				Err(err) => errors.push((entry.name.clone(), err))
+			}
+		}
+		errors
+	}
+}
@@ -1,4 +1,6 @@
 use crate::types::Type;
+use crate::intern::Name;
+use crate::constantpool::MethodHandleKind;
 use derive_more::Constructor;
 use std::collections::{BTreeMap};
 use std::fmt::{Debug, Formatter};
@@ -79,14 +81,14 @@ pub struct LdcInsn {
 #[derive(Clone, Debug, PartialEq)]
 pub enum LdcType {
 	Null,
-	String(String),
+	String(Name),
 	Int(i32),
 	Float(f32),
 	Long(i64),
 	Double(f64),
-	Class(String),
+	Class(Name),
 	/// Method Descriptor (java.lang.invoke.MethodType)
-	MethodType(String),
+	MethodType(Name),
 	/// TODO: Method Handle (java.lang.invoke.MethodHandle)
 	MethodHandle(),
 	// TODO: Constant_Dynamic
@@ -124,7 +126,7 @@ pub struct ThrowInsn {}
 
 #[derive(Constructor, Clone, Debug, PartialEq, Eq)]
 pub struct CheckCastInsn {
-	pub kind: String
+	pub kind: Name
 }
 
 #[derive(Constructor, Copy, Clone, Debug, PartialEq, Eq)]
@@ -222,11 +224,11 @@ pub struct GetFieldInsn {
 	/// Is this field an instance or static field?
 	pub instance: bool,
 	/// The declaring class
-	pub class: String,
+	pub class: Name,
 	/// The field name
-	pub name: String,
+	pub name: Name,
 	/// The field descriptor
-	pub descriptor: String,
+	pub descriptor: Name,
 }
 
 #[derive(Constructor, Clone, Debug, PartialEq, Eq)]
@@ -234,11 +236,11 @@ pub struct PutFieldInsn {
 	/// Is this field an instance or static field?
 	pub instance: bool,
 	/// The declaring class
-	pub class: String,
+	pub class: Name,
 	/// The field name
-	pub name: String,
+	pub name: Name,
 	/// The field descriptor
-	pub descriptor: String,
+	pub descriptor: Name,
 }
 
 /// Unconditional Jump
@@ -299,17 +301,16 @@ pub struct IncrementIntInsn {
 
 #[derive(Constructor, Clone, Debug, PartialEq, Eq)]
 pub struct InstanceOfInsn {
-	pub class: String
+	pub class: Name
 }
 
 #[derive(Constructor, Clone, Debug, PartialEq)]
 pub struct InvokeDynamicInsn {
-	pub name: String,
-	pub descriptor: String,
-	pub bootstrap_type: BootstrapMethodType,
-	pub bootstrap_class: String,
-	pub bootstrap_method: String,
-	pub bootstrap_descriptor: String,
+	pub name: Name,
+	pub descriptor: Name,
+	/// The bootstrap method as it was read, with an owner of "Unimplemented" when the code was parsed
+	/// without the bootstrap methods of its class
+	pub bootstrap_handle: Handle,
 	pub bootstrap_arguments: Vec<BootstrapArgument>
 }
 
@@ -319,22 +320,38 @@ pub enum BootstrapArgument {
 	Float(f32),
 	Long(i64),
 	Double(f64),
-	Class(String)
-	// TODO: Continue. Do we have to do this for every constant type? Spec seems to suggest so
+	Class(Name),
+	String(Name),
+	/// Method Descriptor (java.lang.invoke.MethodType)
+	MethodType(Name),
+	MethodHandle(Handle)
+	// TODO: Constant_Dynamic
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum BootstrapMethodType {
-	InvokeStatic,
-	NewInvokeSpecial
+/// The field or method a method handle refers to
+#[derive(Constructor, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+	pub kind: MethodHandleKind,
+	pub owner: Name,
+	pub name: Name,
+	pub descriptor: Name,
+	/// Whether the owner is an interface
+	pub interface: bool
+}
+
+/// An entry of the BootstrapMethods attribute of a class, used by invokedynamic instructions
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct BootstrapMethod {
+	pub handle: Handle,
+	pub arguments: Vec<BootstrapArgument>
 }
 
 #[derive(Constructor, Clone, Debug, PartialEq, Eq)]
 pub struct InvokeInsn {
 	pub kind: InvokeType,
-	pub class: String,
-	pub name: String,
-	pub descriptor: String,
+	pub class: Name,
+	pub name: Name,
+	pub descriptor: Name,
 	pub interface_method: bool
 }
 
@@ -438,13 +455,13 @@ pub struct MonitorExitInsn {}
 /// New multi dimensional object array
 #[derive(Constructor, Clone, Debug, PartialEq, Eq)]
 pub struct MultiNewArrayInsn {
-	pub kind: String,
+	pub kind: Name,
 	pub dimensions: u8
 }
 
 #[derive(Constructor, Clone, Debug, PartialEq, Eq)]
 pub struct NewObjectInsn {
-	pub kind: String
+	pub kind: Name
 }
 
 #[derive(Constructor, Copy, Clone, Debug, PartialEq, Eq)]
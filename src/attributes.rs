@@ -1,6 +1,6 @@
 use crate::constantpool::{ConstantPool, ConstantType, ConstantPoolWriter};
 use crate::version::{MajorVersion, ClassVersion};
-use crate::code::CodeAttribute;
+use crate::code::{CodeAttribute, LazyCodeAttribute};
 use crate::error::{Result, ParserError};
 use byteorder::{ReadBytesExt, BigEndian, WriteBytesExt};
 use std::io::{Write, Read, Cursor};
@@ -8,6 +8,10 @@ use derive_more::Constructor;
 use crate::ast::LabelInsn;
 use crate::utils::{ReadUtils, MapUtils};
 use std::collections::HashMap;
+use crate::classfile::ParseOptions;
+use crate::intern::Name;
+use crate::access::InnerClassAccessFlags;
+use crate::Serializable;
 
 #[allow(non_snake_case)]
 pub mod Attributes {
@@ -16,14 +20,24 @@ pub mod Attributes {
 	use byteorder::{ReadBytesExt, BigEndian, WriteBytesExt};
 	use crate::version::{ClassVersion};
 	use crate::attributes::{Attribute, AttributeSource};
+	use crate::classfile::ParseOptions;
+	use crate::utils::ReadUtils;
 	use std::collections::HashMap;
 	use crate::ast::LabelInsn;
 	
-	pub fn parse<R: Read>(rdr: &mut R, source: AttributeSource, version: &ClassVersion, constant_pool: &ConstantPool, pc_label_map: &mut Option<HashMap<u32, LabelInsn>>) -> crate::Result<Vec<Attribute>> {
+	/// Attributes excluded by the options are skipped without being decoded
+	pub fn parse<R: Read>(rdr: &mut R, source: AttributeSource, version: &ClassVersion, constant_pool: &ConstantPool, pc_label_map: &mut Option<HashMap<u32, LabelInsn>>, options: &ParseOptions) -> crate::Result<Vec<Attribute>> {
 		let num_attributes = rdr.read_u16::<BigEndian>()? as usize;
 		let mut attributes: Vec<Attribute> = Vec::with_capacity(num_attributes);
 		for _ in 0..num_attributes {
-			attributes.push(Attribute::parse(rdr, &source, version, constant_pool, pc_label_map.as_mut())?);
+			let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
+			let attribute_length = rdr.read_u32::<BigEndian>()? as usize;
+			if options.skips(&name) {
+				rdr.skip_nbytes(attribute_length)?;
+				continue;
+			}
+			let buf: Vec<u8> = rdr.read_nbytes(attribute_length)?;
+			attributes.push(Attribute::parse_named(name, buf, &source, version, constant_pool, pc_label_map.as_mut(), options)?);
 		}
 		Ok(attributes)
 	}
@@ -39,7 +53,7 @@ pub mod Attributes {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ConstantValueAttribute {
-	value: ConstantValue
+	pub value: ConstantValue
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -48,7 +62,7 @@ pub enum ConstantValue {
 	Float(f32),
 	Double(f64),
 	Int(i32),
-	String(String)
+	String(Name)
 }
 
 impl ConstantValueAttribute {
@@ -68,15 +82,12 @@ impl ConstantValueAttribute {
 	}
 	
 	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
-		let const_ref = match self.value.clone() {
-			ConstantValue::Long(x) => constant_pool.long(x),
-			ConstantValue::Float(x) => constant_pool.float(x),
-			ConstantValue::Double(x) => constant_pool.double(x),
-			ConstantValue::Int(x) => constant_pool.integer(x),
-			ConstantValue::String(x) => {
-				let utf = constant_pool.utf8(x);
-				constant_pool.string(utf)
-			}
+		let const_ref = match &self.value {
+			ConstantValue::Long(x) => constant_pool.long(*x)?,
+			ConstantValue::Float(x) => constant_pool.float(*x)?,
+			ConstantValue::Double(x) => constant_pool.double(*x)?,
+			ConstantValue::Int(x) => constant_pool.integer(*x)?,
+			ConstantValue::String(x) => constant_pool.string_utf(x)?
 		};
 		wtr.write_u16::<BigEndian>(const_ref)?; // cp ref
 		Ok(())
@@ -85,11 +96,11 @@ impl ConstantValueAttribute {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct SignatureAttribute {
-	pub signature: String
+	pub signature: Name
 }
 
 impl SignatureAttribute {
-	pub fn new(signature: String) -> Self {
+	pub fn new(signature: Name) -> Self {
 		SignatureAttribute {
 			signature
 		}
@@ -104,18 +115,18 @@ impl SignatureAttribute {
 	}
 	
 	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.signature.clone()))?; // cp ref
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.signature)?)?; // cp ref
 		Ok(())
 	}
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExceptionsAttribute {
-	pub exceptions: Vec<String>
+	pub exceptions: Vec<Name>
 }
 
 impl ExceptionsAttribute {
-	pub fn new(exceptions: Vec<String>) -> Self {
+	pub fn new(exceptions: Vec<Name>) -> Self {
 		ExceptionsAttribute {
 			exceptions
 		}
@@ -124,7 +135,7 @@ impl ExceptionsAttribute {
 	pub fn parse(constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
 		let mut slice = buf.as_slice();
 		let num_exceptions = slice.read_u16::<BigEndian>()?;
-		let mut exceptions: Vec<String> = Vec::with_capacity(num_exceptions as usize);
+		let mut exceptions: Vec<Name> = Vec::with_capacity(num_exceptions as usize);
 		for _ in 0..num_exceptions {
 			exceptions.push(constant_pool.utf8(constant_pool.class(slice.read_u16::<BigEndian>()?)?.name_index)?.str.clone());
 		}
@@ -137,7 +148,7 @@ impl ExceptionsAttribute {
 		let num_exceptions = self.exceptions.len();
 		wtr.write_u16::<BigEndian>(num_exceptions as u16)?;
 		for exception in self.exceptions.iter() {
-			wtr.write_u16::<BigEndian>(constant_pool.utf8(exception.clone()))?;
+			wtr.write_u16::<BigEndian>(constant_pool.class_utf8(exception)?)?;
 		}
 		Ok(())
 	}
@@ -145,12 +156,12 @@ impl ExceptionsAttribute {
 
 #[derive(Constructor, Clone, Debug, PartialEq)]
 pub struct UnknownAttribute {
-	pub name: String,
+	pub name: Name,
 	pub buf: Vec<u8>
 }
 
 impl UnknownAttribute {
-	pub fn parse(name: String, buf: Vec<u8>) -> Result<Self> {
+	pub fn parse(name: Name, buf: Vec<u8>) -> Result<Self> {
 		Ok(UnknownAttribute::new(name, buf))
 	}
 	
@@ -164,9 +175,258 @@ impl UnknownAttribute {
 	}
 }
 
+/// RuntimeVisibleAnnotations or RuntimeInvisibleAnnotations
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct AnnotationsAttribute {
+	pub visible: bool,
+	pub annotations: Vec<Annotation>
+}
+
+/// RuntimeVisibleParameterAnnotations or RuntimeInvisibleParameterAnnotations
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct ParameterAnnotationsAttribute {
+	pub visible: bool,
+	/// The annotations of each parameter in order
+	pub parameters: Vec<Vec<Annotation>>
+}
+
+/// The default value of an annotation interface element
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct AnnotationDefaultAttribute {
+	pub value: ElementValue
+}
+
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct Annotation {
+	/// The field descriptor of the annotation interface, such as `Ljava/lang/Deprecated;`
+	pub descriptor: Name,
+	pub elements: Vec<(Name, ElementValue)>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ElementValue {
+	Byte(i32),
+	Char(i32),
+	Double(f64),
+	Float(f32),
+	Int(i32),
+	Long(i64),
+	Short(i32),
+	Boolean(i32),
+	String(Name),
+	Enum {
+		/// The field descriptor of the enum class
+		descriptor: Name,
+		name: Name
+	},
+	/// The return descriptor of the class, such as `Ljava/lang/Object;` or `V`
+	Class(Name),
+	Annotation(Annotation),
+	Array(Vec<ElementValue>)
+}
+
+impl AnnotationsAttribute {
+	pub fn parse(visible: bool, constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+		let mut slice = buf.as_slice();
+		Ok(AnnotationsAttribute {
+			visible,
+			annotations: Annotation::parse_all(constant_pool, &mut slice)?
+		})
+	}
+	
+	pub fn name(&self) -> &'static str {
+		if self.visible {
+			"RuntimeVisibleAnnotations"
+		} else {
+			"RuntimeInvisibleAnnotations"
+		}
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		Annotation::write_all(&self.annotations, wtr, constant_pool)
+	}
+}
+
+impl ParameterAnnotationsAttribute {
+	pub fn parse(visible: bool, constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+		let mut slice = buf.as_slice();
+		let num_parameters = slice.read_u8()? as usize;
+		let mut parameters = Vec::with_capacity(num_parameters);
+		for _ in 0..num_parameters {
+			parameters.push(Annotation::parse_all(constant_pool, &mut slice)?);
+		}
+		Ok(ParameterAnnotationsAttribute {
+			visible,
+			parameters
+		})
+	}
+	
+	pub fn name(&self) -> &'static str {
+		if self.visible {
+			"RuntimeVisibleParameterAnnotations"
+		} else {
+			"RuntimeInvisibleParameterAnnotations"
+		}
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		wtr.write_u8(self.parameters.len() as u8)?;
+		for annotations in self.parameters.iter() {
+			Annotation::write_all(annotations, wtr, constant_pool)?;
+		}
+		Ok(())
+	}
+}
+
+impl AnnotationDefaultAttribute {
+	pub fn parse(constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+		Ok(AnnotationDefaultAttribute {
+			value: ElementValue::parse(constant_pool, &mut buf.as_slice())?
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		self.value.write(wtr, constant_pool)
+	}
+}
+
+impl Annotation {
+	fn parse_all(constant_pool: &ConstantPool, rdr: &mut &[u8]) -> Result<Vec<Self>> {
+		let num_annotations = rdr.read_u16::<BigEndian>()? as usize;
+		let mut annotations = Vec::with_capacity(num_annotations);
+		for _ in 0..num_annotations {
+			annotations.push(Annotation::parse(constant_pool, rdr)?);
+		}
+		Ok(annotations)
+	}
+	
+	pub fn parse(constant_pool: &ConstantPool, rdr: &mut &[u8]) -> Result<Self> {
+		let descriptor = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
+		let num_elements = rdr.read_u16::<BigEndian>()? as usize;
+		let mut elements = Vec::with_capacity(num_elements);
+		for _ in 0..num_elements {
+			let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
+			elements.push((name, ElementValue::parse(constant_pool, rdr)?));
+		}
+		Ok(Annotation {
+			descriptor,
+			elements
+		})
+	}
+	
+	fn write_all<T: Write>(annotations: &[Annotation], wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		wtr.write_u16::<BigEndian>(annotations.len() as u16)?;
+		for annotation in annotations.iter() {
+			annotation.write(wtr, constant_pool)?;
+		}
+		Ok(())
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.descriptor)?)?;
+		wtr.write_u16::<BigEndian>(self.elements.len() as u16)?;
+		for (name, value) in self.elements.iter() {
+			wtr.write_u16::<BigEndian>(constant_pool.utf8(name)?)?;
+			value.write(wtr, constant_pool)?;
+		}
+		Ok(())
+	}
+}
+
+impl ElementValue {
+	pub fn parse(constant_pool: &ConstantPool, rdr: &mut &[u8]) -> Result<Self> {
+		let tag = rdr.read_u8()?;
+		Ok(match tag {
+			b'B' | b'C' | b'I' | b'S' | b'Z' => {
+				let value = constant_pool.integer(rdr.read_u16::<BigEndian>()?)?.inner();
+				match tag {
+					b'B' => ElementValue::Byte(value),
+					b'C' => ElementValue::Char(value),
+					b'I' => ElementValue::Int(value),
+					b'S' => ElementValue::Short(value),
+					_ => ElementValue::Boolean(value)
+				}
+			},
+			b'D' => ElementValue::Double(constant_pool.double(rdr.read_u16::<BigEndian>()?)?.inner()),
+			b'F' => ElementValue::Float(constant_pool.float(rdr.read_u16::<BigEndian>()?)?.inner()),
+			b'J' => ElementValue::Long(constant_pool.long(rdr.read_u16::<BigEndian>()?)?.inner()),
+			b's' => ElementValue::String(constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone()),
+			b'e' => ElementValue::Enum {
+				descriptor: constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone(),
+				name: constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone()
+			},
+			b'c' => ElementValue::Class(constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone()),
+			b'@' => ElementValue::Annotation(Annotation::parse(constant_pool, rdr)?),
+			b'[' => {
+				let num_values = rdr.read_u16::<BigEndian>()? as usize;
+				let mut values = Vec::with_capacity(num_values);
+				for _ in 0..num_values {
+					values.push(ElementValue::parse(constant_pool, rdr)?);
+				}
+				ElementValue::Array(values)
+			},
+			x => return Err(ParserError::unrecognised("element value tag", (x as char).to_string()))
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		match self {
+			ElementValue::Byte(x) | ElementValue::Char(x) | ElementValue::Int(x) | ElementValue::Short(x)
+				| ElementValue::Boolean(x) => {
+				let tag = match self {
+					ElementValue::Byte(_) => b'B',
+					ElementValue::Char(_) => b'C',
+					ElementValue::Int(_) => b'I',
+					ElementValue::Short(_) => b'S',
+					_ => b'Z'
+				};
+				wtr.write_u8(tag)?;
+				wtr.write_u16::<BigEndian>(constant_pool.integer(*x)?)?;
+			},
+			ElementValue::Double(x) => {
+				wtr.write_u8(b'D')?;
+				wtr.write_u16::<BigEndian>(constant_pool.double(*x)?)?;
+			},
+			ElementValue::Float(x) => {
+				wtr.write_u8(b'F')?;
+				wtr.write_u16::<BigEndian>(constant_pool.float(*x)?)?;
+			},
+			ElementValue::Long(x) => {
+				wtr.write_u8(b'J')?;
+				wtr.write_u16::<BigEndian>(constant_pool.long(*x)?)?;
+			},
+			ElementValue::String(x) => {
+				wtr.write_u8(b's')?;
+				wtr.write_u16::<BigEndian>(constant_pool.utf8(x)?)?;
+			},
+			ElementValue::Enum { descriptor, name } => {
+				wtr.write_u8(b'e')?;
+				wtr.write_u16::<BigEndian>(constant_pool.utf8(descriptor)?)?;
+				wtr.write_u16::<BigEndian>(constant_pool.utf8(name)?)?;
+			},
+			ElementValue::Class(x) => {
+				wtr.write_u8(b'c')?;
+				wtr.write_u16::<BigEndian>(constant_pool.utf8(x)?)?;
+			},
+			ElementValue::Annotation(x) => {
+				wtr.write_u8(b'@')?;
+				x.write(wtr, constant_pool)?;
+			},
+			ElementValue::Array(values) => {
+				wtr.write_u8(b'[')?;
+				wtr.write_u16::<BigEndian>(values.len() as u16)?;
+				for value in values.iter() {
+					value.write(wtr, constant_pool)?;
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct SourceFileAttribute {
-	pub source_file: String
+	pub source_file: Name
 }
 
 impl SourceFileAttribute {
@@ -179,7 +439,149 @@ impl SourceFileAttribute {
 	}
 	
 	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.source_file.clone()))?;
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.source_file)?)?;
+		Ok(())
+	}
+}
+
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct InnerClassesAttribute {
+	pub classes: Vec<InnerClass>
+}
+
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct InnerClass {
+	pub inner_class: Name,
+	/// None for local and anonymous classes
+	pub outer_class: Option<Name>,
+	/// The simple name of the class, None for anonymous classes
+	pub inner_name: Option<Name>,
+	pub access_flags: InnerClassAccessFlags
+}
+
+impl InnerClassesAttribute {
+	pub fn parse(constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+		let mut slice = buf.as_slice();
+		let num_classes = slice.read_u16::<BigEndian>()? as usize;
+		let mut classes = Vec::with_capacity(num_classes);
+		for _ in 0..num_classes {
+			let inner_class = constant_pool.utf8(constant_pool.class(slice.read_u16::<BigEndian>()?)?.name_index)?.str.clone();
+			let outer_class = match slice.read_u16::<BigEndian>()? {
+				0 => None,
+				i => Some(constant_pool.utf8(constant_pool.class(i)?.name_index)?.str.clone())
+			};
+			let inner_name = match slice.read_u16::<BigEndian>()? {
+				0 => None,
+				i => Some(constant_pool.utf8(i)?.str.clone())
+			};
+			classes.push(InnerClass {
+				inner_class,
+				outer_class,
+				inner_name,
+				access_flags: InnerClassAccessFlags::parse(&mut slice)?
+			});
+		}
+		Ok(InnerClassesAttribute {
+			classes
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		wtr.write_u16::<BigEndian>(self.classes.len() as u16)?;
+		for class in self.classes.iter() {
+			wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&class.inner_class)?)?;
+			wtr.write_u16::<BigEndian>(match &class.outer_class {
+				Some(x) => constant_pool.class_utf8(x)?,
+				None => 0
+			})?;
+			wtr.write_u16::<BigEndian>(match &class.inner_name {
+				Some(x) => constant_pool.utf8(x)?,
+				None => 0
+			})?;
+			class.access_flags.write(wtr)?;
+		}
+		Ok(())
+	}
+}
+
+/// The class, and method if any, enclosing a local or anonymous class
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct EnclosingMethodAttribute {
+	pub class: Name,
+	/// The name and descriptor of the method, None outside of a method such as in a field initializer
+	pub method: Option<(Name, Name)>
+}
+
+impl EnclosingMethodAttribute {
+	pub fn parse(constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+		let mut slice = buf.as_slice();
+		let class = constant_pool.utf8(constant_pool.class(slice.read_u16::<BigEndian>()?)?.name_index)?.str.clone();
+		let method = match slice.read_u16::<BigEndian>()? {
+			0 => None,
+			i => {
+				let nameandtype = constant_pool.nameandtype(i)?;
+				let name = constant_pool.utf8(nameandtype.name_index)?.str.clone();
+				Some((name, constant_pool.utf8(nameandtype.descriptor_index)?.str.clone()))
+			}
+		};
+		Ok(EnclosingMethodAttribute {
+			class,
+			method
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&self.class)?)?;
+		wtr.write_u16::<BigEndian>(match &self.method {
+			Some((name, descriptor)) => constant_pool.nameandtype_utf8(name, descriptor)?,
+			None => 0
+		})?;
+		Ok(())
+	}
+}
+
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct NestHostAttribute {
+	pub host: Name
+}
+
+impl NestHostAttribute {
+	pub fn parse(constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+		let index = buf.as_slice().read_u16::<BigEndian>()?;
+		Ok(NestHostAttribute {
+			host: constant_pool.utf8(constant_pool.class(index)?.name_index)?.str.clone()
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&self.host)?)?;
+		Ok(())
+	}
+}
+
+#[derive(Constructor, Clone, Debug, PartialEq)]
+pub struct NestMembersAttribute {
+	pub members: Vec<Name>
+}
+
+impl NestMembersAttribute {
+	pub fn parse(constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+		let mut slice = buf.as_slice();
+		let num_members = slice.read_u16::<BigEndian>()? as usize;
+		let mut members = Vec::with_capacity(num_members);
+		for _ in 0..num_members {
+			members.push(constant_pool.utf8(constant_pool.class(slice.read_u16::<BigEndian>()?)?.name_index)?.str.clone());
+		}
+		Ok(NestMembersAttribute {
+			members
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		wtr.write_u16::<BigEndian>(self.members.len() as u16)?;
+		for member in self.members.iter() {
+			wtr.write_u16::<BigEndian>(constant_pool.class_utf8(member)?)?;
+		}
 		Ok(())
 	}
 }
@@ -193,8 +595,8 @@ pub struct LocalVariableTableAttribute {
 pub struct LocalVariable {
 	pub start: LabelInsn,
 	pub end: LabelInsn,
-	pub name: String,
-	pub descriptor: String,
+	pub name: Name,
+	pub descriptor: Name,
 	pub index: u16
 }
 
@@ -245,37 +647,424 @@ impl LocalVariable {
 		wtr.write_u16::<BigEndian>(start_pc as u16)?;
 		let end_pc = *label_pc_map.get(&self.end).ok_or_else(ParserError::unmapped_label)?;
 		wtr.write_u16::<BigEndian>((end_pc - start_pc) as u16)?;
-		ZIL
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.name.clone()))?;
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.descriptor.clone()))?;
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.name)?)?;
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.descriptor)?)?;
 		
 		wtr.write_u16::<BigEndian>(self.index)?;
 		Ok(())
 	}
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineNumberTableAttribute {
+	pub lines: Vec<LineNumber>
+}
+
+#[derive(Constructor, Copy, Clone, Debug, PartialEq)]
+pub struct LineNumber {
+	/// The first instruction of this line
+	pub start: LabelInsn,
+	pub line: u16
+}
+
+impl LineNumberTableAttribute {
+	pub fn parse(buf: Vec<u8>, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<Self> {
+		let mut buf = Cursor::new(buf);
+		let num_lines = buf.read_u16::<BigEndian>()? as usize;
+		let mut lines: Vec<LineNumber> = Vec::with_capacity(num_lines);
+		for _ in 0..num_lines {
+			let start_pc = buf.read_u16::<BigEndian>()? as u32;
+			pc_label_map.insert_if_not_present(start_pc, LabelInsn::new(pc_label_map.len() as u32));
+			let line = buf.read_u16::<BigEndian>()?;
+			lines.push(LineNumber {
+				start: *pc_label_map.get(&start_pc).ok_or_else(ParserError::unmapped_label)?,
+				line
+			});
+		}
+		Ok(LineNumberTableAttribute {
+			lines
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, label_pc_map: &HashMap<LabelInsn, u32>) -> Result<()> {
+		wtr.write_u16::<BigEndian>(self.lines.len() as u16)?;
+		for line in self.lines.iter() {
+			let start_pc = *label_pc_map.get(&line.start).ok_or_else(ParserError::unmapped_label)?;
+			wtr.write_u16::<BigEndian>(start_pc as u16)?;
+			wtr.write_u16::<BigEndian>(line.line)?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackMapTableAttribute {
+	pub frames: Vec<StackMapFrame>
+}
+
+/// A stack map frame, describing the types of the locals and stack at the frame's label
+#[derive(Clone, Debug, PartialEq)]
+pub enum StackMapFrame {
+	/// The locals of the previous frame and an empty stack
+	Same {
+		start: LabelInsn
+	},
+	/// The locals of the previous frame and a single stack item
+	SameLocals1StackItem {
+		start: LabelInsn,
+		stack: VerificationType
+	},
+	/// The locals of the previous frame without its last `chopped` locals, and an empty stack
+	Chop {
+		start: LabelInsn,
+		chopped: u8
+	},
+	/// The locals of the previous frame followed by `locals`, and an empty stack
+	Append {
+		start: LabelInsn,
+		locals: Vec<VerificationType>
+	},
+	Full {
+		start: LabelInsn,
+		locals: Vec<VerificationType>,
+		stack: Vec<VerificationType>
+	}
+}
+
+/// The type of a local or stack item. Longs and doubles occupy a single entry
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerificationType {
+	Top,
+	Integer,
+	Float,
+	Double,
+	Long,
+	Null,
+	UninitializedThis,
+	Object(Name),
+	/// An object created by the `new` instruction at the label that has not been initialised yet
+	Uninitialized(LabelInsn)
+}
+
+impl StackMapTableAttribute {
+	pub fn parse(constant_pool: &ConstantPool, buf: Vec<u8>, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<Self> {
+		let mut buf = Cursor::new(buf);
+		let num_frames = buf.read_u16::<BigEndian>()? as usize;
+		let mut frames: Vec<StackMapFrame> = Vec::with_capacity(num_frames);
+		let mut pc: Option<u32> = None;
+		for _ in 0..num_frames {
+			let frame_type = buf.read_u8()?;
+			let offset_delta = match frame_type {
+				0..=63 => frame_type as u32,
+				64..=127 => frame_type as u32 - 64,
+				247..=255 => buf.read_u16::<BigEndian>()? as u32,
+				_ => return Err(ParserError::unrecognised("stack map frame type", frame_type.to_string()))
+			};
+			// every frame but the first is one past the previous frame plus its delta
+			let frame_pc = match pc {
+				Some(pc) => pc + offset_delta + 1,
+				None => offset_delta
+			};
+			pc = Some(frame_pc);
+			pc_label_map.insert_if_not_present(frame_pc, LabelInsn::new(pc_label_map.len() as u32));
+			let start = *pc_label_map.get(&frame_pc).ok_or_else(ParserError::unmapped_label)?;
+			
+			frames.push(match frame_type {
+				0..=63 | 251 => StackMapFrame::Same {
+					start
+				},
+				64..=127 | 247 => StackMapFrame::SameLocals1StackItem {
+					start,
+					stack: VerificationType::parse(constant_pool, &mut buf, pc_label_map)?
+				},
+				248..=250 => StackMapFrame::Chop {
+					start,
+					chopped: 251 - frame_type
+				},
+				252..=254 => {
+					let mut locals: Vec<VerificationType> = Vec::with_capacity((frame_type - 251) as usize);
+					for _ in 251..frame_type {
+						locals.push(VerificationType::parse(constant_pool, &mut buf, pc_label_map)?);
+					}
+					StackMapFrame::Append {
+						start,
+						locals
+					}
+				},
+				_ => {
+					let locals = VerificationType::parse_all(constant_pool, &mut buf, pc_label_map)?;
+					let stack = VerificationType::parse_all(constant_pool, &mut buf, pc_label_map)?;
+					StackMapFrame::Full {
+						start,
+						locals,
+						stack
+					}
+				}
+			});
+		}
+		Ok(StackMapTableAttribute {
+			frames
+		})
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter, label_pc_map: &HashMap<LabelInsn, u32>) -> Result<()> {
+		wtr.write_u16::<BigEndian>(self.frames.len() as u16)?;
+		let mut pc: Option<u32> = None;
+		for frame in self.frames.iter() {
+			let frame_pc = *label_pc_map.get(&frame.start()).ok_or_else(ParserError::unmapped_label)?;
+			let offset_delta = match pc {
+				Some(pc) if frame_pc > pc => frame_pc - pc - 1,
+				Some(_) => return Err(ParserError::other("Stack map frames must be in ascending order")),
+				None => frame_pc
+			};
+			pc = Some(frame_pc);
+			
+			match frame {
+				StackMapFrame::Same { .. } => {
+					if offset_delta <= 63 {
+						wtr.write_u8(offset_delta as u8)?;
+					} else {
+						wtr.write_u8(251)?;
+						wtr.write_u16::<BigEndian>(offset_delta as u16)?;
+					}
+				},
+				StackMapFrame::SameLocals1StackItem { stack, .. } => {
+					if offset_delta <= 63 {
+						wtr.write_u8(64 + offset_delta as u8)?;
+					} else {
+						wtr.write_u8(247)?;
+						wtr.write_u16::<BigEndian>(offset_delta as u16)?;
+					}
+					stack.write(wtr, constant_pool, label_pc_map)?;
+				},
+				StackMapFrame::Chop { chopped, .. } => {
+					if !(1..=3).contains(chopped) {
+						return Err(ParserError::other("A chop frame must remove between 1 and 3 locals"));
+					}
+					wtr.write_u8(251 - chopped)?;
+					wtr.write_u16::<BigEndian>(offset_delta as u16)?;
+				},
+				StackMapFrame::Append { locals, .. } => {
+					if !(1..=3).contains(&locals.len()) {
+						return Err(ParserError::other("An append frame must add between 1 and 3 locals"));
+					}
+					wtr.write_u8(251 + locals.len() as u8)?;
+					wtr.write_u16::<BigEndian>(offset_delta as u16)?;
+					for local in locals.iter() {
+						local.write(wtr, constant_pool, label_pc_map)?;
+					}
+				},
+				StackMapFrame::Full { locals, stack, .. } => {
+					wtr.write_u8(255)?;
+					wtr.write_u16::<BigEndian>(offset_delta as u16)?;
+					VerificationType::write_all(wtr, locals, constant_pool, label_pc_map)?;
+					VerificationType::write_all(wtr, stack, constant_pool, label_pc_map)?;
+				}
+			}
+		}
+		Ok(())
+	}
+	
+	/// Rewrites every frame as a full frame, starting from the locals implied by the method
+	/// descriptor (see `VerificationType::initial_locals`)
+	pub fn expand(&mut self, initial_locals: Vec<VerificationType>) -> Result<()> {
+		let mut locals = initial_locals;
+		for frame in self.frames.iter_mut() {
+			let stack = match frame {
+				StackMapFrame::Same { .. } => Vec::new(),
+				StackMapFrame::SameLocals1StackItem { stack, .. } => vec![stack.clone()],
+				StackMapFrame::Chop { chopped, .. } => {
+					let len = locals.len().checked_sub(*chopped as usize)
+						.ok_or_else(|| ParserError::other("Chop frame removes more locals than exist"))?;
+					locals.truncate(len);
+					Vec::new()
+				},
+				StackMapFrame::Append { locals: appended, .. } => {
+					locals.extend(appended.iter().cloned());
+					Vec::new()
+				},
+				StackMapFrame::Full { locals: full, stack, .. } => {
+					locals = full.clone();
+					stack.clone()
+				}
+			};
+			*frame = StackMapFrame::Full {
+				start: frame.start(),
+				locals: locals.clone(),
+				stack
+			};
+		}
+		Ok(())
+	}
+}
+
+impl StackMapFrame {
+	pub fn start(&self) -> LabelInsn {
+		match self {
+			StackMapFrame::Same { start } => *start,
+			StackMapFrame::SameLocals1StackItem { start, .. } => *start,
+			StackMapFrame::Chop { start, .. } => *start,
+			StackMapFrame::Append { start, .. } => *start,
+			StackMapFrame::Full { start, .. } => *start
+		}
+	}
+}
+
+impl VerificationType {
+	pub fn parse<R: Read>(constant_pool: &ConstantPool, buf: &mut R, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<Self> {
+		let tag = buf.read_u8()?;
+		Ok(match tag {
+			0 => VerificationType::Top,
+			1 => VerificationType::Integer,
+			2 => VerificationType::Float,
+			3 => VerificationType::Double,
+			4 => VerificationType::Long,
+			5 => VerificationType::Null,
+			6 => VerificationType::UninitializedThis,
+			7 => {
+				let index = buf.read_u16::<BigEndian>()?;
+				VerificationType::Object(constant_pool.utf8(constant_pool.class(index)?.name_index)?.str.clone())
+			},
+			8 => {
+				let pc = buf.read_u16::<BigEndian>()? as u32;
+				pc_label_map.insert_if_not_present(pc, LabelInsn::new(pc_label_map.len() as u32));
+				VerificationType::Uninitialized(*pc_label_map.get(&pc).ok_or_else(ParserError::unmapped_label)?)
+			},
+			_ => return Err(ParserError::unrecognised("verification type", tag.to_string()))
+		})
+	}
+	
+	fn parse_all<R: Read>(constant_pool: &ConstantPool, buf: &mut R, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<Vec<Self>> {
+		let num_types = buf.read_u16::<BigEndian>()? as usize;
+		let mut types: Vec<VerificationType> = Vec::with_capacity(num_types);
+		for _ in 0..num_types {
+			types.push(VerificationType::parse(constant_pool, buf, pc_label_map)?);
+		}
+		Ok(types)
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter, label_pc_map: &HashMap<LabelInsn, u32>) -> Result<()> {
+		match self {
+			VerificationType::Top => wtr.write_u8(0)?,
+			VerificationType::Integer => wtr.write_u8(1)?,
+			VerificationType::Float => wtr.write_u8(2)?,
+			VerificationType::Double => wtr.write_u8(3)?,
+			VerificationType::Long => wtr.write_u8(4)?,
+			VerificationType::Null => wtr.write_u8(5)?,
+			VerificationType::UninitializedThis => wtr.write_u8(6)?,
+			VerificationType::Object(class) => {
+				wtr.write_u8(7)?;
+				wtr.write_u16::<BigEndian>(constant_pool.class_utf8(class)?)?;
+			},
+			VerificationType::Uninitialized(label) => {
+				wtr.write_u8(8)?;
+				let pc = *label_pc_map.get(label).ok_or_else(ParserError::unmapped_label)?;
+				wtr.write_u16::<BigEndian>(pc as u16)?;
+			}
+		}
+		Ok(())
+	}
+	
+	fn write_all<T: Write>(wtr: &mut T, types: &[VerificationType], constant_pool: &mut ConstantPoolWriter, label_pc_map: &HashMap<LabelInsn, u32>) -> Result<()> {
+		wtr.write_u16::<BigEndian>(types.len() as u16)?;
+		for typ in types.iter() {
+			typ.write(wtr, constant_pool, label_pc_map)?;
+		}
+		Ok(())
+	}
+	
+	/// The locals at the start of a method, before the first frame
+	pub fn initial_locals(this_class: &str, method_name: &str, descriptor: &str, is_static: bool) -> Result<Vec<Self>> {
+		let mut locals: Vec<VerificationType> = Vec::new();
+		if !is_static {
+			locals.push(if method_name == "<init>" && this_class != "java/lang/Object" {
+				VerificationType::UninitializedThis
+			} else {
+				VerificationType::Object(this_class.into())
+			});
+		}
+		let params = descriptor.strip_prefix('(')
+			.and_then(|desc| desc.split(')').next())
+			.ok_or_else(|| ParserError::invalid_descriptor("Method desc must be enclosed in '(' and ')'"))?;
+		let mut chars = params.char_indices();
+		while let Some((start, c)) = chars.next() {
+			let mut c = c;
+			let mut end = start;
+			while c == '[' {
+				let (i, next) = chars.next().ok_or_else(|| ParserError::invalid_descriptor("Array missing element type"))?;
+				c = next;
+				end = i;
+			}
+			if c == 'L' {
+				end = chars.by_ref().find(|(_, c)| *c == ';').ok_or_else(|| ParserError::invalid_descriptor("Type missing ';'"))?.0;
+			}
+			let desc = &params[start..=end];
+			locals.push(match c {
+				// arrays are named by their descriptor
+				_ if desc.starts_with('[') => VerificationType::Object(desc.into()),
+				'L' => VerificationType::Object(desc[1..desc.len() - 1].into()),
+				'B' | 'C' | 'I' | 'S' | 'Z' => VerificationType::Integer,
+				'F' => VerificationType::Float,
+				'J' => VerificationType::Long,
+				'D' => VerificationType::Double,
+				x => return Err(ParserError::invalid_descriptor(format!("Unknown type '{}'", x)))
+			});
+		}
+		Ok(locals)
+	}
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Attribute {
 	ConstantValue(ConstantValueAttribute),
 	Signature(SignatureAttribute),
 	Code(CodeAttribute),
+	/// A method body that has not been decoded yet
+	LazyCode(LazyCodeAttribute),
 	Exceptions(ExceptionsAttribute),
 	SourceFile(SourceFileAttribute),
 	LocalVariableTable(LocalVariableTableAttribute),
+	LineNumberTable(LineNumberTableAttribute),
+	StackMapTable(StackMapTableAttribute),
+	Annotations(AnnotationsAttribute),
+	ParameterAnnotations(ParameterAnnotationsAttribute),
+	AnnotationDefault(AnnotationDefaultAttribute),
+	InnerClasses(InnerClassesAttribute),
+	EnclosingMethod(EnclosingMethodAttribute),
+	NestHost(NestHostAttribute),
+	NestMembers(NestMembersAttribute),
 	Unknown(UnknownAttribute)
 }
 
 impl Attribute {
-	pub fn parse<R: Read>(rdr: &mut R, source: &AttributeSource, version: &ClassVersion, constant_pool: &ConstantPool, pc_label_map: Option<&mut HashMap<u32, LabelInsn>>) -> Result<Attribute> {
+	pub fn parse<R: Read>(rdr: &mut R, source: &AttributeSource, version: &ClassVersion, constant_pool: &ConstantPool, pc_label_map: Option<&mut HashMap<u32, LabelInsn>>, options: &ParseOptions) -> Result<Attribute> {
 		let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
 		let attribute_length = rdr.read_u32::<BigEndian>()? as usize;
 		let buf: Vec<u8> = rdr.read_nbytes(attribute_length as usize)?;
-		let str = name.as_str();
+		Attribute::parse_named(name, buf, source, version, constant_pool, pc_label_map, options)
+	}
+	
+	/// Parses the body of an attribute whose name and length have already been read
+	pub fn parse_named(name: Name, buf: Vec<u8>, source: &AttributeSource, version: &ClassVersion, constant_pool: &ConstantPool, pc_label_map: Option<&mut HashMap<u32, LabelInsn>>, options: &ParseOptions) -> Result<Attribute> {
+		let str = &*name;
 		
 		let attr = match source {
+			AttributeSource::Class | AttributeSource::Field | AttributeSource::Method if str == "RuntimeVisibleAnnotations" || str == "RuntimeInvisibleAnnotations" => {
+				Attribute::Annotations(AnnotationsAttribute::parse(str == "RuntimeVisibleAnnotations", constant_pool, buf)?)
+			},
 			AttributeSource::Class => {
 				if str == "SourceFile" {
 					Attribute::SourceFile(SourceFileAttribute::parse(constant_pool, buf)?)
+				} else if str == "Signature" && version.major >= MajorVersion::JAVA_5 {
+					Attribute::Signature(SignatureAttribute::parse(constant_pool, buf)?)
+				} else if str == "InnerClasses" {
+					Attribute::InnerClasses(InnerClassesAttribute::parse(constant_pool, buf)?)
+				} else if str == "EnclosingMethod" && version.major >= MajorVersion::JAVA_5 {
+					Attribute::EnclosingMethod(EnclosingMethodAttribute::parse(constant_pool, buf)?)
+				} else if str == "NestHost" && version.major >= MajorVersion::JAVA_11 {
+					Attribute::NestHost(NestHostAttribute::parse(constant_pool, buf)?)
+				} else if str == "NestMembers" && version.major >= MajorVersion::JAVA_11 {
+					Attribute::NestMembers(NestMembersAttribute::parse(constant_pool, buf)?)
 				} else {
 					Attribute::Unknown(UnknownAttribute::parse(name, buf)?)
 				}
@@ -291,19 +1080,27 @@ impl Attribute {
 			},
 			AttributeSource::Method => {
 				if str == "Code" {
-					Attribute::Code(CodeAttribute::parse(version, constant_pool, buf)?)
+					Attribute::Code(CodeAttribute::parse(version, constant_pool, buf, options)?)
 				} else if str == "Signature" && version.major >= MajorVersion::JAVA_5 {
 					Attribute::Signature(SignatureAttribute::parse(constant_pool, buf)?)
 				} else if str == "Exceptions" {
 					Attribute::Exceptions(ExceptionsAttribute::parse(constant_pool, buf)?)
+				} else if str == "RuntimeVisibleParameterAnnotations" || str == "RuntimeInvisibleParameterAnnotations" {
+					Attribute::ParameterAnnotations(ParameterAnnotationsAttribute::parse(str == "RuntimeVisibleParameterAnnotations", constant_pool, buf)?)
+				} else if str == "AnnotationDefault" {
+					Attribute::AnnotationDefault(AnnotationDefaultAttribute::parse(constant_pool, buf)?)
 				} else {
 					Attribute::Unknown(UnknownAttribute::parse(name, buf)?)
 				}
 			}
 			AttributeSource::Code => {
-				let pc_label_map = pc_label_map.unwrap();
+				let pc_label_map = pc_label_map.ok_or_else(|| ParserError::other("Attributes of code need the labels of the code"))?;
 				if str == "LocalVariableTable" {
 					Attribute::LocalVariableTable(LocalVariableTableAttribute::parse(constant_pool, buf, pc_label_map)?)
+				} else if str == "LineNumberTable" {
+					Attribute::LineNumberTable(LineNumberTableAttribute::parse(buf, pc_label_map)?)
+				} else if str == "StackMapTable" {
+					Attribute::StackMapTable(StackMapTableAttribute::parse(constant_pool, buf, pc_label_map)?)
 				//} else if str == "LocalVariableTypeTable" && version.major >= MajorVersion::JAVA_5 {
 				
 				} else {
@@ -314,59 +1111,125 @@ impl Attribute {
 		Ok(attr)
 	}
 	
+	/// The name this attribute is stored under in a class file
+	pub fn name(&self) -> &str {
+		match self {
+			Attribute::ConstantValue(_) => "ConstantValue",
+			Attribute::Signature(_) => "Signature",
+			Attribute::Code(_) => "Code",
+			Attribute::LazyCode(_) => "Code",
+			Attribute::Exceptions(_) => "Exceptions",
+			Attribute::SourceFile(_) => "SourceFile",
+			Attribute::LocalVariableTable(_) => "LocalVariableTable",
+			Attribute::LineNumberTable(_) => "LineNumberTable",
+			Attribute::StackMapTable(_) => "StackMapTable",
+			Attribute::Annotations(t) => t.name(),
+			Attribute::ParameterAnnotations(t) => t.name(),
+			Attribute::AnnotationDefault(_) => "AnnotationDefault",
+			Attribute::InnerClasses(_) => "InnerClasses",
+			Attribute::EnclosingMethod(_) => "EnclosingMethod",
+			Attribute::NestHost(_) => "NestHost",
+			Attribute::NestMembers(_) => "NestMembers",
+			Attribute::Unknown(t) => &t.name
+		}
+	}
+	
+	/// Attributes with a known length are written straight to the writer, only those whose length
+	/// depends on how they are encoded are buffered first
 	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter, label_pc_map: &Option<&HashMap<LabelInsn, u32>>) -> Result<()> {
 		match self {
 			Attribute::ConstantValue(t) => {
-				let mut buf: Vec<u8> = Vec::new();
-				wtr.write_u16::<BigEndian>(constant_pool.utf8("ConstantValue"))?;
-				t.write(&mut buf, constant_pool)?;
-				wtr.write_u32::<BigEndian>(buf.len() as u32)?;
-				wtr.write(buf.as_slice())?;
+				Attribute::write_header(wtr, constant_pool, "ConstantValue", 2)?;
+				t.write(wtr, constant_pool)?;
 			},
 			Attribute::Signature(t) => {
+				Attribute::write_header(wtr, constant_pool, "Signature", 2)?;
+				t.write(wtr, constant_pool)?;
+			},
+			Attribute::Code(t) => {
 				let mut buf: Vec<u8> = Vec::new();
-				wtr.write_u16::<BigEndian>(constant_pool.utf8("Signature"))?;
 				t.write(&mut buf, constant_pool)?;
-				wtr.write_u32::<BigEndian>(buf.len() as u32)?;
-				wtr.write(buf.as_slice())?;
+				Attribute::write_header(wtr, constant_pool, "Code", buf.len())?;
+				wtr.write_all(buf.as_slice())?;
 			},
-			Attribute::Code(t) => {
+			Attribute::LazyCode(t) => {
 				let mut buf: Vec<u8> = Vec::new();
-				wtr.write_u16::<BigEndian>(constant_pool.utf8("Code"))?;
 				t.write(&mut buf, constant_pool)?;
-				wtr.write_u32::<BigEndian>(buf.len() as u32)?;
-				wtr.write(buf.as_slice())?;
+				Attribute::write_header(wtr, constant_pool, "Code", buf.len())?;
+				wtr.write_all(buf.as_slice())?;
 			},
 			Attribute::Exceptions(t) => {
+				Attribute::write_header(wtr, constant_pool, "Exceptions", 2 + 2 * t.exceptions.len())?;
+				t.write(wtr, constant_pool)?;
+			},
+			Attribute::SourceFile(t) => {
+				Attribute::write_header(wtr, constant_pool, "SourceFile", 2)?;
+				t.write(wtr, constant_pool)?;
+			},
+			Attribute::LocalVariableTable(t) => {
+				let label_pc_map = label_pc_map.ok_or_else(ParserError::unmapped_label)?;
+				Attribute::write_header(wtr, constant_pool, "LocalVariableTable", 2 + 10 * t.variables.len())?;
+				t.write(wtr, constant_pool, label_pc_map)?;
+			},
+			Attribute::LineNumberTable(t) => {
+				let label_pc_map = label_pc_map.ok_or_else(ParserError::unmapped_label)?;
+				Attribute::write_header(wtr, constant_pool, "LineNumberTable", 2 + 4 * t.lines.len())?;
+				t.write(wtr, label_pc_map)?;
+			},
+			Attribute::StackMapTable(t) => {
+				let label_pc_map = label_pc_map.ok_or_else(ParserError::unmapped_label)?;
+				let mut buf: Vec<u8> = Vec::new();
+				t.write(&mut buf, constant_pool, label_pc_map)?;
+				Attribute::write_header(wtr, constant_pool, "StackMapTable", buf.len())?;
+				wtr.write_all(buf.as_slice())?;
+			},
+			Attribute::Annotations(t) => {
 				let mut buf: Vec<u8> = Vec::new();
-				wtr.write_u16::<BigEndian>(constant_pool.utf8("Exceptions"))?;
 				t.write(&mut buf, constant_pool)?;
-				wtr.write_u32::<BigEndian>(buf.len() as u32)?;
-				wtr.write(buf.as_slice())?;
+				Attribute::write_header(wtr, constant_pool, t.name(), buf.len())?;
+				wtr.write_all(buf.as_slice())?;
 			},
-			Attribute::SourceFile(t) => {
+			Attribute::ParameterAnnotations(t) => {
 				let mut buf: Vec<u8> = Vec::new();
-				wtr.write_u16::<BigEndian>(constant_pool.utf8("SourceFile"))?;
 				t.write(&mut buf, constant_pool)?;
-				wtr.write_u32::<BigEndian>(buf.len() as u32)?;
-				wtr.write(buf.as_slice())?;
+				Attribute::write_header(wtr, constant_pool, t.name(), buf.len())?;
+				wtr.write_all(buf.as_slice())?;
 			},
-			Attribute::LocalVariableTable(t) => {
-				let label_pc_map = label_pc_map.unwrap();
+			Attribute::AnnotationDefault(t) => {
 				let mut buf: Vec<u8> = Vec::new();
-				wtr.write_u16::<BigEndian>(constant_pool.utf8("LocalVariableTable"))?;
-				t.write(&mut buf, constant_pool, label_pc_map)?;
-				wtr.write_u32::<BigEndian>(buf.len() as u32)?;
-				wtr.write(buf.as_slice())?;
+				t.write(&mut buf, constant_pool)?;
+				Attribute::write_header(wtr, constant_pool, "AnnotationDefault", buf.len())?;
+				wtr.write_all(buf.as_slice())?;
+			},
+			Attribute::InnerClasses(t) => {
+				Attribute::write_header(wtr, constant_pool, "InnerClasses", 2 + 8 * t.classes.len())?;
+				t.write(wtr, constant_pool)?;
+			},
+			Attribute::EnclosingMethod(t) => {
+				Attribute::write_header(wtr, constant_pool, "EnclosingMethod", 4)?;
+				t.write(wtr, constant_pool)?;
+			},
+			Attribute::NestHost(t) => {
+				Attribute::write_header(wtr, constant_pool, "NestHost", 2)?;
+				t.write(wtr, constant_pool)?;
+			},
+			Attribute::NestMembers(t) => {
+				Attribute::write_header(wtr, constant_pool, "NestMembers", 2 + 2 * t.members.len())?;
+				t.write(wtr, constant_pool)?;
 			},
 			Attribute::Unknown(t) => {
-				wtr.write_u16::<BigEndian>(constant_pool.utf8(t.name.clone()))?;
-				wtr.write_u32::<BigEndian>(t.len() as u32)?;
+				Attribute::write_header(wtr, constant_pool, &t.name, t.len())?;
 				t.write(wtr, constant_pool)?;
 			}
 		};
 		Ok(())
 	}
+	
+	fn write_header<T: Write>(wtr: &mut T, constant_pool: &mut ConstantPoolWriter, name: &str, length: usize) -> Result<()> {
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(name)?)?;
+		wtr.write_u32::<BigEndian>(length as u32)?;
+		Ok(())
+	}
 }
 
 #[allow(dead_code)]
@@ -3,12 +3,17 @@ use std::fs::File;
 use std::io::{BufReader, BufWriter, Cursor};
 use std::env;
 
-use classfile::classfile::ClassFile;
+use classfile::classfile::{ClassFile, ParseOptions};
+use classfile::archive::Archive;
 
 fn main() {
 	let args: Vec<String> = env::args().collect();
 	
 	if let Some(file) = args.get(1) {
+		if file.ends_with(".jar") || file.ends_with(".zip") {
+			return dissasemble_archive(file, args.get(2));
+		}
+		
 		// Read
 		let start = Instant::now();
 		let class = {
@@ -32,3 +37,19 @@ fn main() {
 		panic!("Please provide a file to dissasemble");
 	}
 }
+
+fn dissasemble_archive(file: &str, output: Option<&String>) {
+	let start = Instant::now();
+	let mut archive = Archive::open(file).unwrap();
+	for (name, class) in archive.parse_classes(&ParseOptions::default()) {
+		println!("{}: {:#x?}", name, class);
+	}
+	println!("Finished parsing {} in {:#?}", file, start.elapsed());
+	
+	if let Some(output) = output {
+		for (name, err) in archive.transform(&ParseOptions::default(), |_, _| Ok(())) {
+			println!("Copying {} unchanged: {}", name, err);
+		}
+		archive.save(output).unwrap();
+	}
+}
@@ -0,0 +1,295 @@
+use crate::Serializable;
+use crate::version::ClassVersion;
+use crate::constantpool::{ConstantPool, ConstantType, CPIndex};
+use crate::access::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use crate::attributes::{Attribute, AttributeSource};
+use crate::code::{CodeAttribute, read_bootstrap_methods};
+use crate::classfile::{ClassFile, ParseOptions};
+use crate::error::{Result, ParserError};
+use crate::intern::Name;
+use crate::ast::LabelInsn;
+use byteorder::{ReadBytesExt, BigEndian};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+/// A class parsed directly out of a byte slice.
+/// Names borrow from the slice wherever they are valid utf8 and attribute bodies are never copied,
+/// which makes scanning many classes for a few references cheap. Use `to_class_file` to get a
+/// fully decoded, owned `ClassFile`
+#[derive(Clone, Debug)]
+pub struct BorrowedClassFile<'a> {
+	bytes: &'a [u8],
+	pub version: ClassVersion,
+	pub constant_pool: BorrowedConstantPool<'a>,
+	pub access_flags: ClassAccessFlags,
+	pub this_class: Cow<'a, str>,
+	/// Can only be None for java/lang/Object
+	pub super_class: Option<Cow<'a, str>>,
+	pub interfaces: Vec<Cow<'a, str>>,
+	pub fields: Vec<BorrowedField<'a>>,
+	pub methods: Vec<BorrowedMethod<'a>>,
+	pub attributes: Vec<BorrowedAttribute<'a>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorrowedField<'a> {
+	pub access_flags: FieldAccessFlags,
+	pub name: Cow<'a, str>,
+	pub descriptor: Cow<'a, str>,
+	pub attributes: Vec<BorrowedAttribute<'a>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorrowedMethod<'a> {
+	pub access_flags: MethodAccessFlags,
+	pub name: Cow<'a, str>,
+	pub descriptor: Cow<'a, str>,
+	pub attributes: Vec<BorrowedAttribute<'a>>
+}
+
+/// An attribute whose body is left undecoded in the class bytes
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorrowedAttribute<'a> {
+	pub name: Cow<'a, str>,
+	pub data: &'a [u8]
+}
+
+/// A constant pool that only records where each entry starts in the class bytes.
+/// Entries are decoded when they are looked up
+#[derive(Clone, Debug)]
+pub struct BorrowedConstantPool<'a> {
+	/// the pool including its leading entry count
+	bytes: &'a [u8],
+	/// offset of each entry's tag within `bytes`, None for index 0 and the second slot of longs and doubles
+	offsets: Vec<Option<u32>>
+}
+
+impl <'a> BorrowedClassFile<'a> {
+	pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+		let mut rdr = bytes;
+		let magic = rdr.read_u32::<BigEndian>()?;
+		if magic != 0xCAFEBABE {
+			return Err(ParserError::unrecognised("header", magic.to_string()));
+		}
+		let version = ClassVersion::parse(&mut rdr)?;
+		let constant_pool = BorrowedConstantPool::parse(&mut rdr)?;
+		let access_flags = ClassAccessFlags::parse(&mut rdr)?;
+		let this_class = constant_pool.class(rdr.read_u16::<BigEndian>()?)?;
+		let super_class = match rdr.read_u16::<BigEndian>()? {
+			0 => None,
+			i => Some(constant_pool.class(i)?)
+		};
+
+		let num_interfaces = rdr.read_u16::<BigEndian>()? as usize;
+		let mut interfaces: Vec<Cow<'a, str>> = Vec::with_capacity(num_interfaces);
+		for _ in 0..num_interfaces {
+			interfaces.push(constant_pool.class(rdr.read_u16::<BigEndian>()?)?);
+		}
+
+		let num_fields = rdr.read_u16::<BigEndian>()? as usize;
+		let mut fields: Vec<BorrowedField<'a>> = Vec::with_capacity(num_fields);
+		for _ in 0..num_fields {
+			fields.push(BorrowedField {
+				access_flags: FieldAccessFlags::parse(&mut rdr)?,
+				name: constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?,
+				descriptor: constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?,
+				attributes: BorrowedAttribute::parse_all(&mut rdr, &constant_pool)?
+			});
+		}
+
+		let num_methods = rdr.read_u16::<BigEndian>()? as usize;
+		let mut methods: Vec<BorrowedMethod<'a>> = Vec::with_capacity(num_methods);
+		for _ in 0..num_methods {
+			methods.push(BorrowedMethod {
+				access_flags: MethodAccessFlags::parse(&mut rdr)?,
+				name: constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?,
+				descriptor: constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?,
+				attributes: BorrowedAttribute::parse_all(&mut rdr, &constant_pool)?
+			});
+		}
+
+		let attributes = BorrowedAttribute::parse_all(&mut rdr, &constant_pool)?;
+
+		Ok(BorrowedClassFile {
+			bytes,
+			version,
+			constant_pool,
+			access_flags,
+			this_class,
+			super_class,
+			interfaces,
+			fields,
+			methods,
+			attributes
+		})
+	}
+
+	/// The bytes this class was parsed from
+	pub fn bytes(&self) -> &'a [u8] {
+		self.bytes
+	}
+
+	/// Decodes the whole class into an owned `ClassFile`
+	pub fn to_class_file(&self) -> Result<ClassFile> {
+		ClassFile::parse(&mut &*self.bytes)
+	}
+}
+
+impl <'a> BorrowedMethod<'a> {
+	pub fn code(&self) -> Option<&BorrowedAttribute<'a>> {
+		self.attributes.iter().find(|attribute| attribute.name == "Code")
+	}
+
+	/// Decodes the body of this method, if it has one, resolving invokedynamic instructions against
+	/// the bootstrap methods of the class it was read from
+	pub fn decode_code(&self, class: &BorrowedClassFile<'a>, constant_pool: &ConstantPool, options: &ParseOptions) -> Option<Result<CodeAttribute>> {
+		self.code().map(|code| {
+			let bootstrap_methods = match class.attributes.iter().find(|attribute| attribute.name == "BootstrapMethods") {
+				Some(attribute) => read_bootstrap_methods(attribute.data, constant_pool)?,
+				None => Arc::from(Vec::new())
+			};
+			CodeAttribute::parse_with_bootstrap_methods(&class.version, constant_pool, &bootstrap_methods, code.data.to_vec(), options)
+		})
+	}
+}
+
+impl <'a> BorrowedAttribute<'a> {
+	fn parse_all(rdr: &mut &'a [u8], constant_pool: &BorrowedConstantPool<'a>) -> Result<Vec<Self>> {
+		let num_attributes = rdr.read_u16::<BigEndian>()? as usize;
+		let mut attributes: Vec<BorrowedAttribute<'a>> = Vec::with_capacity(num_attributes);
+		for _ in 0..num_attributes {
+			let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?;
+			let attribute_length = rdr.read_u32::<BigEndian>()? as usize;
+			attributes.push(BorrowedAttribute {
+				name,
+				data: take(rdr, attribute_length)?
+			});
+		}
+		Ok(attributes)
+	}
+
+	/// Decodes this attribute. Code attributes should be decoded through `BorrowedMethod::decode_code`,
+	/// and the attributes of code need the labels of the instructions they refer to, keyed by pc,
+	/// which are added to as new pcs are referred to
+	pub fn decode(&self, source: AttributeSource, version: &ClassVersion, constant_pool: &ConstantPool, pc_label_map: Option<&mut HashMap<u32, LabelInsn>>) -> Result<Attribute> {
+		Attribute::parse_named(Name::from(&*self.name), self.data.to_vec(), &source, version, constant_pool, pc_label_map, &ParseOptions::default())
+	}
+}
+
+impl <'a> BorrowedConstantPool<'a> {
+	pub fn parse(rdr: &mut &'a [u8]) -> Result<Self> {
+		let start: &'a [u8] = rdr;
+		let size = rdr.read_u16::<BigEndian>()? as usize;
+		let mut offsets: Vec<Option<u32>> = vec![None; size];
+		let mut i = 1;
+		while i < size {
+			offsets[i] = Some((start.len() - rdr.len()) as u32);
+			let tag = rdr.read_u8()?;
+			let (length, slots) = match tag {
+				ConstantType::CONSTANT_Utf8 => (rdr.read_u16::<BigEndian>()? as usize, 1),
+				ConstantType::CONSTANT_Class | ConstantType::CONSTANT_String | ConstantType::CONSTANT_MethodType
+					| ConstantType::CONSTANT_Module | ConstantType::CONSTANT_Package => (2, 1),
+				ConstantType::CONSTANT_MethodHandle => (3, 1),
+				ConstantType::CONSTANT_Integer | ConstantType::CONSTANT_Float | ConstantType::CONSTANT_Fieldref
+					| ConstantType::CONSTANT_Methodref | ConstantType::CONSTANT_InterfaceMethodref
+					| ConstantType::CONSTANT_NameAndType | ConstantType::CONSTANT_Dynamic
+					| ConstantType::CONSTANT_InvokeDynamic => (4, 1),
+				ConstantType::CONSTANT_Long | ConstantType::CONSTANT_Double => (8, 2),
+				_ => return Err(ParserError::unrecognised("constant tag", tag.to_string()))
+			};
+			// the second slot of a long or double must still be within the pool
+			if i + slots > size {
+				return Err(ParserError::bad_cp_index(i as CPIndex));
+			}
+			take(rdr, length)?;
+			i += slots;
+		}
+		Ok(BorrowedConstantPool {
+			bytes: &start[..start.len() - rdr.len()],
+			offsets
+		})
+	}
+
+	/// The number of usable slots, as `ConstantPool::len` counts them
+	pub fn len(&self) -> usize {
+		self.offsets.len().saturating_sub(1)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	fn entry(&self, index: CPIndex) -> Result<&'a [u8]> {
+		match self.offsets.get(index as usize) {
+			Some(Some(offset)) => Ok(&self.bytes[*offset as usize..]),
+			_ => Err(ParserError::bad_cp_index(index))
+		}
+	}
+
+	/// The tag of the constant at the given index
+	pub fn tag(&self, index: CPIndex) -> Result<u8> {
+		Ok(self.entry(index)?[0])
+	}
+
+	/// Decodes the constant at the given index
+	pub fn get(&self, index: CPIndex) -> Result<ConstantType> {
+		ConstantType::parse(&mut self.entry(index)?)
+	}
+
+	/// Iterates over the indexes and tags of every constant
+	pub fn tags(&self) -> impl Iterator<Item = (CPIndex, u8)> + '_ {
+		self.offsets.iter().enumerate()
+			.filter_map(move |(i, offset)| offset.map(|offset| (i as CPIndex, self.bytes[offset as usize])))
+	}
+
+	fn expect(&self, index: CPIndex, tag: u8, expected: &'static str) -> Result<&'a [u8]> {
+		let entry = self.entry(index)?;
+		if entry[0] != tag {
+			return Err(ParserError::incomp_cp(expected, &self.get(index)?, index as usize));
+		}
+		Ok(&entry[1..])
+	}
+
+	/// The string at the given index, only allocated if its modified utf8 differs from utf8
+	pub fn utf8(&self, index: CPIndex) -> Result<Cow<'a, str>> {
+		let mut entry = self.expect(index, ConstantType::CONSTANT_Utf8, "Utf8")?;
+		let length = entry.read_u16::<BigEndian>()? as usize;
+		let bytes = &entry[..length];
+		Ok(match mutf8::mutf8_to_utf8(bytes) {
+			Cow::Borrowed(_data) => match std::str::from_utf8(bytes) {
+				Ok(str) => Cow::Borrowed(str),
+				Err(_) => Cow::Owned(String::from_utf8_lossy(bytes).into_owned())
+			},
+			Cow::Owned(data) => Cow::Owned(String::from_utf8_lossy(&data).into_owned())
+		})
+	}
+
+	/// The name of the class at the given index
+	pub fn class(&self, index: CPIndex) -> Result<Cow<'a, str>> {
+		let mut entry = self.expect(index, ConstantType::CONSTANT_Class, "Class")?;
+		self.utf8(entry.read_u16::<BigEndian>()?)
+	}
+
+	/// Iterates over the names of every class referenced by the pool
+	pub fn class_names(&self) -> impl Iterator<Item = Result<Cow<'a, str>>> + '_ {
+		self.tags()
+			.filter(|(_, tag)| *tag == ConstantType::CONSTANT_Class)
+			.map(move |(index, _)| self.class(index))
+	}
+
+	/// Fully decodes this pool
+	pub fn to_constant_pool(&self) -> Result<ConstantPool> {
+		ConstantPool::parse(&mut &*self.bytes)
+	}
+}
+
+fn take<'a>(rdr: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+	if rdr.len() < len {
+		return Err(ParserError::io(io::Error::from(io::ErrorKind::UnexpectedEof)));
+	}
+	let (head, tail) = rdr.split_at(len);
+	*rdr = tail;
+	Ok(head)
+}
@@ -0,0 +1,244 @@
+use crate::classfile::ClassFile;
+use crate::access::{ClassAccessFlags, MethodAccessFlags};
+use crate::ast::{Insn, InvokeType, BootstrapArgument};
+use crate::constantpool::MethodHandleKind;
+use crate::hierarchy::{InheritanceGraph, MethodRef};
+use crate::classpath::OBJECT;
+use crate::intern::Name;
+use crate::error::Result;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+/// How a call site reaches its target
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CallKind {
+	Static,
+	/// Constructors, private methods and super calls
+	Special,
+	Virtual,
+	Interface,
+	/// A method handle passed to an invokedynamic bootstrap method, such as the body of a lambda or
+	/// a method reference
+	Dynamic
+}
+
+impl Display for CallKind {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			CallKind::Static => "static",
+			CallKind::Special => "special",
+			CallKind::Virtual => "virtual",
+			CallKind::Interface => "interface",
+			CallKind::Dynamic => "dynamic"
+		})
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Call {
+	pub caller: MethodRef,
+	pub callee: MethodRef,
+	pub kind: CallKind
+}
+
+/// The calls between the methods of a set of classes.
+///
+/// Virtual and interface calls are resolved by class hierarchy analysis: a call may reach the
+/// method selected by the receiver type or any concrete class extending it. Calls that cannot be
+/// resolved within the classes, such as calls into the JDK, point at the method as it is named at
+/// the call site
+#[derive(Clone, Debug, Default)]
+pub struct CallGraph {
+	/// The calls made by each method, in the order they first appear in its code
+	callees: BTreeMap<MethodRef, Vec<Call>>,
+	/// The calls reaching each method, sorted
+	callers: HashMap<MethodRef, Vec<Call>>
+}
+
+impl CallGraph {
+	/// Builds the call graph of the given classes, which should be the classes the hierarchy was
+	/// built from. Code that was parsed lazily is decoded
+	pub fn build<'a, I: IntoIterator<Item = &'a ClassFile>>(classes: I, hierarchy: &InheritanceGraph) -> Result<Self> {
+		let mut graph = CallGraph::default();
+		for class in classes {
+			for method in class.methods.iter() {
+				let caller = MethodRef::new(class.this_class.clone(), method.name.clone(), method.descriptor.clone());
+				let code = match method.decode_code()? {
+					Some(x) => x,
+					None => continue
+				};
+				let mut calls = Vec::new();
+				let mut seen = HashSet::new();
+				let mut add = |callee: MethodRef, kind: CallKind| {
+					let call = Call {
+						caller: caller.clone(),
+						callee,
+						kind
+					};
+					if seen.insert(call.clone()) {
+						calls.push(call);
+					}
+				};
+				for insn in code.insns.iter() {
+					match insn {
+						Insn::Invoke(x) => {
+							let kind = match x.kind {
+								InvokeType::Static => CallKind::Static,
+								InvokeType::Special => CallKind::Special,
+								InvokeType::Interface => CallKind::Interface,
+								InvokeType::Instance if x.interface_method => CallKind::Interface,
+								InvokeType::Instance => CallKind::Virtual
+							};
+							for callee in resolve(hierarchy, &x.class, &x.name, &x.descriptor, kind) {
+								add(callee, kind);
+							}
+						},
+						Insn::InvokeDynamic(x) => {
+							for argument in x.bootstrap_arguments.iter() {
+								let handle = match argument {
+									BootstrapArgument::MethodHandle(x) => x,
+									_ => continue
+								};
+								let kind = match handle.kind {
+									MethodHandleKind::InvokeStatic => CallKind::Static,
+									MethodHandleKind::InvokeSpecial | MethodHandleKind::NewInvokeSpecial => CallKind::Special,
+									MethodHandleKind::InvokeVirtual => CallKind::Virtual,
+									MethodHandleKind::InvokeInterface => CallKind::Interface,
+									_ => continue
+								};
+								for callee in resolve(hierarchy, &handle.owner, &handle.name, &handle.descriptor, kind) {
+									add(callee, CallKind::Dynamic);
+								}
+							}
+						},
+						_ => {}
+					}
+				}
+				graph.callees.insert(caller, calls);
+			}
+		}
+		for call in graph.callees.values().flatten() {
+			graph.callers.entry(call.callee.clone()).or_default().push(call.clone());
+		}
+		for calls in graph.callers.values_mut() {
+			calls.sort();
+		}
+		Ok(graph)
+	}
+	
+	/// Every method with code, sorted
+	pub fn methods(&self) -> impl Iterator<Item = &MethodRef> {
+		self.callees.keys()
+	}
+	
+	/// Every call, sorted by caller
+	pub fn calls(&self) -> impl Iterator<Item = &Call> {
+		self.callees.values().flatten()
+	}
+	
+	/// The calls made by the given method, in the order they appear in its code
+	pub fn callees(&self, method: &MethodRef) -> &[Call] {
+		match self.callees.get(method) {
+			Some(x) => x,
+			None => &[]
+		}
+	}
+	
+	/// The calls reaching the given method, sorted
+	pub fn callers(&self, method: &MethodRef) -> &[Call] {
+		match self.callers.get(method) {
+			Some(x) => x,
+			None => &[]
+		}
+	}
+	
+	/// Every method reachable from the given entry points, including the entry points themselves, in
+	/// the order they are discovered
+	pub fn reachable<'a, I: IntoIterator<Item = &'a MethodRef>>(&self, entry_points: I) -> Vec<MethodRef> {
+		let mut result = Vec::new();
+		let mut seen = HashSet::new();
+		let mut queue: VecDeque<&MethodRef> = entry_points.into_iter().collect();
+		while let Some(method) = queue.pop_front() {
+			if !seen.insert(method) {
+				continue;
+			}
+			result.push(method.clone());
+			queue.extend(self.callees(method).iter().map(|call| &call.callee));
+		}
+		result
+	}
+	
+	/// Writes the graph in the DOT language of Graphviz, with a node for each method and an edge
+	/// labelled with its kind for each call
+	pub fn write_dot<W: Write>(&self, wtr: &mut W) -> Result<()> {
+		writeln!(wtr, "digraph calls {{")?;
+		let mut nodes: Vec<&MethodRef> = self.callees.keys()
+			.chain(self.callers.keys())
+			.collect();
+		nodes.sort();
+		nodes.dedup();
+		for node in nodes {
+			writeln!(wtr, "\t{};", dot_id(node))?;
+		}
+		for call in self.calls() {
+			writeln!(wtr, "\t{} -> {} [label=\"{}\"];", dot_id(&call.caller), dot_id(&call.callee), call.kind)?;
+		}
+		writeln!(wtr, "}}")?;
+		Ok(())
+	}
+	
+	pub fn to_dot(&self) -> String {
+		let mut buf = Vec::new();
+		// writing to a vec cannot fail
+		self.write_dot(&mut buf).unwrap();
+		String::from_utf8(buf).unwrap()
+	}
+}
+
+fn dot_id(method: &MethodRef) -> String {
+	format!("\"{}\"", method.to_string().replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The methods a call of the given kind may reach
+fn resolve(hierarchy: &InheritanceGraph, class: &Name, name: &Name, descriptor: &Name, kind: CallKind) -> Vec<MethodRef> {
+	let declared = || vec![MethodRef::new(class.clone(), name.clone(), descriptor.clone())];
+	if kind != CallKind::Virtual && kind != CallKind::Interface {
+		return hierarchy.resolve_method(class, name, descriptor)
+			.map(|method| vec![method])
+			.unwrap_or_else(declared);
+	}
+	// arrays only inherit the methods of Object
+	let class = if class.starts_with('[') {
+		Name::from(OBJECT)
+	} else {
+		class.clone()
+	};
+	let mut targets = Vec::new();
+	for receiver in std::iter::once(class.clone()).chain(hierarchy.all_subtypes(&class)) {
+		let concrete = match hierarchy.class(&receiver) {
+			Some(x) => !x.access_flags.intersects(ClassAccessFlags::INTERFACE | ClassAccessFlags::ABSTRACT),
+			None => false
+		};
+		if !concrete {
+			continue;
+		}
+		// a receiver that finds no declaration inherits one from outside the classes
+		let target = hierarchy.resolve_method(&receiver, name, descriptor)
+			.unwrap_or_else(|| MethodRef::new(class.clone(), name.clone(), descriptor.clone()));
+		let implemented = match hierarchy.method(&target) {
+			Some(method) => !method.access_flags.contains(MethodAccessFlags::ABSTRACT),
+			None => true
+		};
+		if implemented && !targets.contains(&target) {
+			targets.push(target);
+		}
+	}
+	if targets.is_empty() {
+		return hierarchy.resolve_method(&class, name, descriptor)
+			.map(|method| vec![method])
+			.unwrap_or_else(declared);
+	}
+	targets.sort();
+	targets
+}
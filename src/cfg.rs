@@ -0,0 +1,340 @@
+use crate::code::CodeAttribute;
+use crate::ast::{Insn, LabelInsn};
+use crate::intern::Name;
+use crate::error::{Result, ParserError};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How control moves from one block to another
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+	/// Execution continues with the next block in code order, including when a conditional jump is
+	/// not taken
+	FallThrough,
+	/// A jump, a conditional jump that is taken, or a switch
+	Jump,
+	/// An exception thrown within the block is caught by a handler for the given type, None catching
+	/// any exception
+	Exception(Option<Name>)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Edge {
+	pub from: usize,
+	pub to: usize,
+	pub kind: EdgeKind
+}
+
+/// A run of instructions that is only entered at its first instruction and only left after its last
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct BasicBlock {
+	/// The index of the first instruction in the `InsnList`
+	pub start: usize,
+	/// The index after the last instruction (exclusive)
+	pub end: usize,
+	/// Edges leaving the block, normal edges in the order of the instruction's targets followed by
+	/// exceptional edges in the order of the exception table
+	pub successors: Vec<Edge>,
+	pub predecessors: Vec<Edge>
+}
+
+/// The basic blocks of a method's code and the edges between them, blocks being numbered in code
+/// order with the entry block first.
+///
+/// Blocks start at labels, so that every jump target and every bound of an exception handler starts
+/// a block, and after jumps, switches, returns and throws. Consecutive labels share a block. An
+/// exception may be thrown anywhere within a block, so a block covered by a handler has an edge to
+/// it
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ControlFlowGraph {
+	pub blocks: Vec<BasicBlock>,
+	/// The block of each instruction
+	block_of: Vec<usize>
+}
+
+impl ControlFlowGraph {
+	/// Builds the graph of the given code, failing if a jump or handler refers to a label that is
+	/// not placed
+	pub fn build(code: &CodeAttribute) -> Result<Self> {
+		let insns = &code.insns.insns;
+		let mut graph = ControlFlowGraph::default();
+		// whether the block being built holds anything other than labels
+		let mut has_insns = false;
+		for (index, insn) in insns.iter().enumerate() {
+			let starts_block = match insn {
+				Insn::Label(_) => has_insns,
+				_ => index > 0 && ends_block(&insns[index - 1])
+			};
+			if graph.blocks.is_empty() || starts_block {
+				if let Some(block) = graph.blocks.last_mut() {
+					block.end = index;
+				}
+				graph.blocks.push(BasicBlock {
+					start: index,
+					..BasicBlock::default()
+				});
+				has_insns = false;
+			}
+			has_insns |= !matches!(insn, Insn::Label(_));
+			graph.block_of.push(graph.blocks.len() - 1);
+		}
+		if let Some(block) = graph.blocks.last_mut() {
+			block.end = insns.len();
+		}
+	
+		let labels: HashMap<LabelInsn, usize> = insns.iter()
+			.enumerate()
+			.filter_map(|(index, insn)| match insn {
+				Insn::Label(label) => Some((*label, graph.block_of[index])),
+				_ => None
+			})
+			.collect();
+		let block_of_label = |label: &LabelInsn| labels.get(label).copied().ok_or_else(ParserError::unmapped_label);
+		let mut edges = Vec::new();
+		for (from, block) in graph.blocks.iter().enumerate() {
+			let last = insns[block.start..block.end].iter().rev().find(|insn| !matches!(insn, Insn::Label(_)));
+			let mut add = |to: usize, kind: EdgeKind| {
+				let edge = Edge {
+					from,
+					to,
+					kind
+				};
+				if !edges.contains(&edge) {
+					edges.push(edge);
+				}
+			};
+			let fall_through = from + 1 < graph.blocks.len();
+			match last {
+				Some(Insn::Jump(x)) => add(block_of_label(&x.jump_to)?, EdgeKind::Jump),
+				Some(Insn::ConditionalJump(x)) => {
+					if fall_through {
+						add(from + 1, EdgeKind::FallThrough);
+					}
+					add(block_of_label(&x.jump_to)?, EdgeKind::Jump);
+				},
+				Some(Insn::LookupSwitch(x)) => {
+					for label in std::iter::once(&x.default).chain(x.cases.values()) {
+						add(block_of_label(label)?, EdgeKind::Jump);
+					}
+				},
+				Some(Insn::TableSwitch(x)) => {
+					for label in std::iter::once(&x.default).chain(x.cases.iter()) {
+						add(block_of_label(label)?, EdgeKind::Jump);
+					}
+				},
+				Some(Insn::Return(_)) | Some(Insn::Throw(_)) => {},
+				_ if fall_through => add(from + 1, EdgeKind::FallThrough),
+				_ => {}
+			}
+		}
+		for handler in code.exceptions.iter() {
+			let start = block_of_label(&handler.start)?;
+			let end = block_of_label(&handler.end)?;
+			let target = block_of_label(&handler.handler)?;
+			for from in start..end {
+				let edge = Edge {
+					from,
+					to: target,
+					kind: EdgeKind::Exception(handler.catch_type.clone())
+				};
+				if !edges.contains(&edge) {
+					edges.push(edge);
+				}
+			}
+		}
+		// normal edges come before exceptional ones, as the exception table was read last
+		for edge in edges {
+			graph.blocks[edge.to].predecessors.push(edge.clone());
+			graph.blocks[edge.from].successors.push(edge);
+		}
+		Ok(graph)
+	}
+	
+	/// The block holding the instruction at the given index of the `InsnList`
+	pub fn block_of(&self, insn: usize) -> usize {
+		self.block_of[insn]
+	}
+	
+	/// The blocks reachable from the entry block, each before its successors unless the edge between
+	/// them closes a cycle. Unreachable blocks are left out
+	pub fn reverse_post_order(&self) -> Vec<usize> {
+		let mut order = Vec::with_capacity(self.blocks.len());
+		if self.blocks.is_empty() {
+			return order;
+		}
+		let mut visited = vec![false; self.blocks.len()];
+		// each block along with how many of its successors have been visited
+		let mut stack = vec![(0, 0)];
+		visited[0] = true;
+		while let Some((block, next)) = stack.last_mut() {
+			match self.blocks[*block].successors.get(*next) {
+				Some(edge) => {
+					*next += 1;
+					if !visited[edge.to] {
+						visited[edge.to] = true;
+						stack.push((edge.to, 0));
+					}
+				},
+				None => {
+					order.push(*block);
+					stack.pop();
+				}
+			}
+		}
+		order.reverse();
+		order
+	}
+	
+	/// Computes which blocks dominate which, following exceptional edges as well as normal ones
+	pub fn dominators(&self) -> DominatorTree {
+		// the algorithm of Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm"
+		let order = self.reverse_post_order();
+		let mut position = vec![usize::MAX; self.blocks.len()];
+		for (i, block) in order.iter().enumerate() {
+			position[*block] = i;
+		}
+		let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+		if let Some(entry) = order.first() {
+			idom[*entry] = Some(*entry);
+		}
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for block in order.iter().skip(1) {
+				let mut new_idom = None;
+				for edge in self.blocks[*block].predecessors.iter() {
+					if idom[edge.from].is_none() {
+						continue;
+					}
+					new_idom = Some(match new_idom {
+						None => edge.from,
+						Some(mut other) => {
+							let mut finger = edge.from;
+							while finger != other {
+								while position[finger] > position[other] {
+									finger = idom[finger].unwrap();
+								}
+								while position[other] > position[finger] {
+									other = idom[other].unwrap();
+								}
+							}
+							finger
+						}
+					});
+				}
+				if idom[*block] != new_idom {
+					idom[*block] = new_idom;
+					changed = true;
+				}
+			}
+		}
+	
+		let mut children = vec![Vec::new(); self.blocks.len()];
+		for (block, dominator) in idom.iter().enumerate() {
+			match dominator {
+				Some(dominator) if *dominator != block => children[*dominator].push(block),
+				_ => {}
+			}
+		}
+		DominatorTree {
+			idom,
+			children
+		}
+	}
+	
+	/// Finds the natural loops of the code, one per header, sorted by header. A loop is closed by a
+	/// back edge, which goes to a block that dominates its source. Loops entered at more than one
+	/// block, which javac does not emit, are not found
+	pub fn loops(&self) -> Vec<Loop> {
+		let dominators = self.dominators();
+		let mut loops: BTreeMap<usize, Loop> = BTreeMap::new();
+		for block in self.blocks.iter() {
+			for edge in block.successors.iter() {
+				if !dominators.dominates(edge.to, edge.from) {
+					continue;
+				}
+				let found = loops.entry(edge.to).or_insert_with(|| Loop {
+					header: edge.to,
+					blocks: std::iter::once(edge.to).collect(),
+					back_edges: Vec::new()
+				});
+				found.back_edges.push(edge.clone());
+				// every block that reaches the source without passing through the header
+				let mut queue = vec![edge.from];
+				while let Some(block) = queue.pop() {
+					if found.blocks.insert(block) {
+						queue.extend(self.blocks[block].predecessors.iter()
+							.map(|edge| edge.from)
+							.filter(|from| dominators.is_reachable(*from)));
+					}
+				}
+			}
+		}
+		loops.into_values().collect()
+	}
+}
+
+/// Whether control never continues to the following instruction
+fn ends_block(insn: &Insn) -> bool {
+	matches!(insn, Insn::Jump(_) | Insn::ConditionalJump(_) | Insn::LookupSwitch(_) | Insn::TableSwitch(_)
+		| Insn::Return(_) | Insn::Throw(_))
+}
+
+/// The dominators of the blocks of a graph: a block dominates another if every path from the entry
+/// to the other passes through it. Every block dominates itself
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DominatorTree {
+	/// The entry block is its own immediate dominator, unreachable blocks have none
+	idom: Vec<Option<usize>>,
+	children: Vec<Vec<usize>>
+}
+
+impl DominatorTree {
+	/// The closest block that dominates the given one, other than itself. None for the entry block
+	/// and for unreachable blocks
+	pub fn immediate_dominator(&self, block: usize) -> Option<usize> {
+		self.idom[block].filter(|x| *x != block)
+	}
+	
+	/// The blocks that the given block immediately dominates, in code order
+	pub fn children(&self, block: usize) -> &[usize] {
+		&self.children[block]
+	}
+	
+	pub fn is_reachable(&self, block: usize) -> bool {
+		self.idom[block].is_some()
+	}
+	
+	/// Whether every path from the entry to `block` passes through `dominator`. Unreachable blocks
+	/// are not dominated by any block
+	pub fn dominates(&self, dominator: usize, block: usize) -> bool {
+		if !self.is_reachable(block) {
+			return false;
+		}
+		let mut current = block;
+		loop {
+			if current == dominator {
+				return true;
+			}
+			match self.immediate_dominator(current) {
+				Some(x) => current = x,
+				None => return false
+			}
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop {
+	/// The block that dominates the rest of the loop, through which it is entered
+	pub header: usize,
+	/// Every block of the loop, including the header and those of nested loops
+	pub blocks: BTreeSet<usize>,
+	/// The edges going back to the header
+	pub back_edges: Vec<Edge>
+}
+
+impl Loop {
+	pub fn contains(&self, block: usize) -> bool {
+		self.blocks.contains(&block)
+	}
+}
@@ -3,11 +3,14 @@ use byteorder::{ReadBytesExt, BigEndian, WriteBytesExt};
 use crate::Serializable;
 use crate::version::ClassVersion;
 use crate::constantpool::{ConstantPool, ConstantPoolWriter};
-use crate::access::ClassAccessFlags;
+use crate::access::{ClassAccessFlags, MethodAccessFlags};
 use crate::field::{Field, Fields};
 use crate::method::{Methods, Method};
 use crate::error::{Result, ParserError};
-use crate::attributes::{Attribute, Attributes, AttributeSource};
+use crate::attributes::{Attribute, Attributes, AttributeSource, UnknownAttribute};
+use crate::code::{parse_bootstrap_methods, write_bootstrap_methods};
+use std::sync::Arc;
+use crate::intern::{Name, Interner};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ClassFile {
@@ -15,23 +18,93 @@ pub struct ClassFile {
 	pub magic: u32,
 	pub version: ClassVersion,
 	pub access_flags: ClassAccessFlags,
-	pub this_class: String,
+	pub this_class: Name,
 	/// Can be None for example for java/lang/Object
-	pub super_class: Option<String>,
-	pub interfaces: Vec<String>,
+	pub super_class: Option<Name>,
+	pub interfaces: Vec<Name>,
 	pub fields: Vec<Field>,
 	pub methods: Vec<Method>,
-	pub attributes: Vec<Attribute>
+	pub attributes: Vec<Attribute>,
+	/// The constant pool this class was parsed from, if it was retained.
+	/// When present the pool layout is kept on write, with any new constants appended at the end
+	pub constant_pool: Option<Arc<ConstantPool>>
+}
+
+/// Controls which parts of a class are decoded, mirroring the flags of ASM's ClassReader
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+	/// Drop the code of every method
+	pub skip_code: bool,
+	/// Drop LineNumberTable, LocalVariableTable, LocalVariableTypeTable, SourceFile and
+	/// SourceDebugExtension attributes
+	pub skip_debug: bool,
+	/// Drop StackMapTable attributes
+	pub skip_frames: bool,
+	/// Expand every stack map frame into a full frame. Lazy code is expanded when it is decoded
+	pub expand_frames: bool,
+	/// Keep the code of every method undecoded until it is accessed, see `ClassFile::parse_lazy`
+	pub lazy_code: bool,
+	/// Retain the constant pool, see `ClassFile::parse_preserving_pool`
+	pub keep_constant_pool: bool,
+	/// Share names with every other class parsed with the same interner
+	pub interner: Option<Interner>
+}
+
+impl ParseOptions {
+	/// Whether an attribute with the given name is dropped while parsing
+	pub fn skips(&self, name: &str) -> bool {
+		match name {
+			"Code" => self.skip_code,
+			"LineNumberTable" | "LocalVariableTable" | "LocalVariableTypeTable" | "SourceFile"
+				| "SourceDebugExtension" => self.skip_debug,
+			"StackMapTable" => self.skip_frames,
+			_ => false
+		}
+	}
 }
 
 impl ClassFile {
 	pub fn parse<R: Read>(rdr: &mut R) -> Result<Self> {
+		ClassFile::parse_with_options(rdr, &ParseOptions::default())
+	}
+	
+	pub fn parse_with_options<R: Read>(rdr: &mut R, options: &ParseOptions) -> Result<Self> {
+		ClassFile::parse_inner(rdr, options, |_| Ok(()))
+	}
+	
+	/// Parses the class while retaining its constant pool, so that an unmodified class is written
+	/// back byte for byte
+	pub fn parse_preserving_pool<R: Read>(rdr: &mut R) -> Result<Self> {
+		ClassFile::parse_with_options(rdr, &ParseOptions {
+			keep_constant_pool: true,
+			..ParseOptions::default()
+		})
+	}
+	
+	/// Parses the class without decoding any method bodies, which are instead decoded by
+	/// `Method::code`. The constant pool is retained, so bodies that are never decoded are written
+	/// back unchanged
+	pub fn parse_lazy<R: Read>(rdr: &mut R) -> Result<Self> {
+		ClassFile::parse_with_options(rdr, &ParseOptions {
+			keep_constant_pool: true,
+			lazy_code: true,
+			..ParseOptions::default()
+		})
+	}
+	
+	fn parse_inner<R: Read, F>(rdr: &mut R, options: &ParseOptions, edit_pool: F) -> Result<Self>
+		where F: FnOnce(&mut ConstantPool) -> Result<()> {
 		let magic = rdr.read_u32::<BigEndian>()?;
 		if magic != 0xCAFEBABE {
 			return Err(ParserError::unrecognised("header", magic.to_string()));
 		}
 		let version = ClassVersion::parse(rdr)?;
-		let constant_pool = ConstantPool::parse(rdr)?;
+		let mut constant_pool = ConstantPool::parse(rdr)?;
+		edit_pool(&mut constant_pool)?;
+		if let Some(interner) = &options.interner {
+			constant_pool.intern(interner);
+		}
+		let constant_pool = Arc::new(constant_pool);
 		let access_flags = ClassAccessFlags::parse(rdr)?;
 		let this_class = constant_pool.utf8(constant_pool.class(rdr.read_u16::<BigEndian>()?)?.name_index)?.str.clone();
 		let super_class = match rdr.read_u16::<BigEndian>()? {
@@ -40,14 +113,33 @@ impl ClassFile {
 		};
 		
 		let num_interfaces = rdr.read_u16::<BigEndian>()? as usize;
-		let mut interfaces: Vec<String> = Vec::with_capacity(num_interfaces);
+		let mut interfaces: Vec<Name> = Vec::with_capacity(num_interfaces);
 		for _ in 0..num_interfaces {
 			interfaces.push(constant_pool.utf8(constant_pool.class(rdr.read_u16::<BigEndian>()?)?.name_index)?.str.clone());
 		}
 		
-		let fields = Fields::parse(rdr, &version, &constant_pool)?;
-		let methods = Methods::parse(rdr, &version, &constant_pool)?;
-		let attributes = Attributes::parse(rdr, AttributeSource::Class, &version, &constant_pool, &mut None)?;
+		let fields = Fields::parse(rdr, &version, &constant_pool, options)?;
+		// invokedynamic instructions refer to the bootstrap methods of the class, which are only read
+		// after the methods, so every body is kept undecoded until then
+		let method_options = ParseOptions {
+			lazy_code: true,
+			..options.clone()
+		};
+		let mut methods = Methods::parse(rdr, &version, &constant_pool, &method_options)?;
+		let attributes = Attributes::parse(rdr, AttributeSource::Class, &version, &constant_pool, &mut None, options)?;
+		let bootstrap_methods = parse_bootstrap_methods(&attributes, &constant_pool)?;
+		for method in methods.iter_mut() {
+			let is_static = method.access_flags.contains(MethodAccessFlags::STATIC);
+			for attr in method.attributes.iter_mut() {
+				if let Attribute::LazyCode(code) = attr {
+					code.set_bootstrap_methods(bootstrap_methods.clone());
+					code.set_method(&this_class, &method.name, &method.descriptor, is_static);
+					if !options.lazy_code {
+						*attr = Attribute::Code(code.decode()?);
+					}
+				}
+			}
+		}
 		
 		Ok(ClassFile {
 			magic,
@@ -58,47 +150,113 @@ impl ClassFile {
 			interfaces,
 			fields,
 			methods,
-			attributes
+			attributes,
+			constant_pool: if options.keep_constant_pool { Some(constant_pool) } else { None }
 		})
 	}
 	
+	/// Edits the constant pool this class is written with, then resolves the class against the
+	/// edited pool. Every use of a replaced entry observes the new value, and the edited pool is
+	/// retained for subsequent writes
+	pub fn edit_constant_pool<F>(&mut self, op: F) -> Result<()>
+		where F: FnOnce(&mut ConstantPool) -> Result<()> {
+		let mut buf: Vec<u8> = Vec::new();
+		self.write(&mut buf)?;
+		let options = ParseOptions {
+			keep_constant_pool: true,
+			lazy_code: self.methods.iter()
+				.any(|method| method.attributes.iter().any(|attr| matches!(attr, Attribute::LazyCode(_)))),
+			..ParseOptions::default()
+		};
+		*self = ClassFile::parse_inner(&mut buf.as_slice(), &options, |pool| {
+			op(pool)?;
+			pool.validate()
+		})?;
+		Ok(())
+	}
+	
+	/// Returns how many times each constant pool index is referenced when this class is written,
+	/// counting references from both the class structure and other constants
+	pub fn constant_usages(&self) -> Result<Vec<u32>> {
+		// lazy method bodies are decoded rather than copied so that their references are counted
+		let mut constant_pool = match &self.constant_pool {
+			Some(pool) => ConstantPoolWriter::from_pool(pool),
+			None => ConstantPoolWriter::new()
+		};
+		self.write_body(&mut std::io::sink(), &mut constant_pool)?;
+		Ok(constant_pool.usages().to_vec())
+	}
+	
+	fn constant_pool_writer(&self) -> ConstantPoolWriter {
+		match &self.constant_pool {
+			Some(pool) => {
+				let mut writer = ConstantPoolWriter::from_shared_pool(pool);
+				// bootstrap methods with arguments that cannot be represented are not kept
+				if let Ok(methods) = parse_bootstrap_methods(&self.attributes, pool) {
+					if let Some(methods) = methods.iter().cloned().collect() {
+						writer.seed_bootstrap_methods(methods);
+					}
+				}
+				writer
+			},
+			None => ConstantPoolWriter::new()
+		}
+	}
+	
 	pub fn write<W: Write>(&self, wtr: &mut W) -> Result<()> {
 		wtr.write_u32::<BigEndian>(self.magic)?;
 		self.version.write(wtr)?;
 		
-		let mut constant_pool = ConstantPoolWriter::new();
+		let mut constant_pool = self.constant_pool_writer();
 		
 		// we need to write fields/methods etc after the constant pool, however they rely upon
 		// mutable access to the constant pool. therefore we will write them to memory and then to
 		// the wtr parameter
 		let buf: Vec<u8> = Vec::with_capacity(2 + (self.fields.len() * 8) + (self.methods.len() * 8));
 		let mut cursor = Cursor::new(buf);
-		self.access_flags.write(&mut cursor)?;
+		self.write_body(&mut cursor, &mut constant_pool)?;
+		
+		constant_pool.write(wtr)?;
+		wtr.write_all(cursor.get_ref().as_slice())?;
+		
+		Ok(())
+	}
+	
+	/// Writes everything following the constant pool
+	fn write_body<W: Write>(&self, wtr: &mut W, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		self.access_flags.write(wtr)?;
 		
 		// this class
-		let utf = constant_pool.utf8(self.this_class.clone());
-		cursor.write_u16::<BigEndian>(constant_pool.class(utf))?;
+		wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&self.this_class)?)?;
 		// super class
 		if let Some(x) = &self.super_class {
-			let utf = constant_pool.utf8(x.clone());
-			cursor.write_u16::<BigEndian>(constant_pool.class(utf))?;
+			wtr.write_u16::<BigEndian>(constant_pool.class_utf8(x)?)?;
 		} else {
-			cursor.write_u16::<BigEndian>(0)?;
+			wtr.write_u16::<BigEndian>(0)?;
 		}
 		// interfaces
-		cursor.write_u16::<BigEndian>(self.interfaces.len() as u16)?;
+		wtr.write_u16::<BigEndian>(self.interfaces.len() as u16)?;
 		for interface in self.interfaces.iter() {
-			let utf = constant_pool.utf8(interface.clone());
-			cursor.write_u16::<BigEndian>(constant_pool.class(utf))?;
+			wtr.write_u16::<BigEndian>(constant_pool.class_utf8(interface)?)?;
 		}
 		
-		Fields::write(&mut cursor, &self.fields, &mut constant_pool)?;
-		Methods::write(&mut cursor, &self.methods, &mut constant_pool)?;
-		Attributes::write(&mut cursor, &self.attributes, &mut constant_pool, None)?;
-		
-		constant_pool.write(wtr)?;
-		wtr.write_all(cursor.get_ref().as_slice())?;
-		
-		Ok(())
+		Fields::write(wtr, &self.fields, constant_pool)?;
+		Methods::write(wtr, &self.methods, constant_pool)?;
+		write_class_attributes(wtr, &self.attributes, constant_pool)
+	}
+}
+
+/// Writes the attributes of a class after its methods, along with the BootstrapMethods attribute
+/// rebuilt from the invokedynamic instructions written
+pub(crate) fn write_class_attributes<W: Write>(wtr: &mut W, attributes: &Vec<Attribute>, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+	if constant_pool.bootstrap_methods().is_empty() {
+		return Attributes::write(wtr, attributes, constant_pool, None);
+	}
+	let bootstrap_methods = Attribute::Unknown(UnknownAttribute::new(Name::from("BootstrapMethods"), write_bootstrap_methods(constant_pool)?));
+	let mut attributes = attributes.clone();
+	match attributes.iter().position(|attr| matches!(attr, Attribute::Unknown(x) if x.name == "BootstrapMethods")) {
+		Some(index) => attributes[index] = bootstrap_methods,
+		None => attributes.push(bootstrap_methods)
 	}
+	Attributes::write(wtr, &attributes, constant_pool, None)
 }
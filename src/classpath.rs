@@ -0,0 +1,354 @@
+use crate::classfile::{ClassFile, ParseOptions};
+use crate::access::ClassAccessFlags;
+use crate::archive::Archive;
+use crate::intern::Name;
+use crate::error::{Result, ParserError};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+pub const OBJECT: &str = "java/lang/Object";
+
+/// Somewhere classes can be loaded from by internal name
+pub trait ClassProvider: Send + Sync {
+	/// Returns the bytes of the class with the given internal name, if this provider has it
+	fn find(&self, name: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// Loads classes from a directory laid out by package, such as a compiler's output directory
+pub struct DirectoryProvider {
+	root: PathBuf
+}
+
+impl DirectoryProvider {
+	pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+		DirectoryProvider {
+			root: root.into()
+		}
+	}
+}
+
+impl ClassProvider for DirectoryProvider {
+	fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+		let path = self.root.join(format!("{}.class", name));
+		if path.is_file() {
+			Ok(Some(fs::read(path)?))
+		} else {
+			Ok(None)
+		}
+	}
+}
+
+/// Loads classes from a jar, using the entries a JVM of the given release would load
+pub struct ArchiveProvider {
+	archive: Archive,
+	/// Index of the entry holding each class
+	classes: HashMap<String, usize>
+}
+
+impl ArchiveProvider {
+	pub fn new(archive: Archive, release: u32) -> Self {
+		let classes = archive.release_indices(release)
+			.into_iter()
+			.filter_map(|index| archive.entries[index].class_name().map(|name| (name.to_string(), index)))
+			.collect();
+		ArchiveProvider {
+			archive,
+			classes
+		}
+	}
+}
+
+impl ClassProvider for ArchiveProvider {
+	fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+		Ok(self.classes.get(name).map(|index| self.archive.entries[*index].data.clone()))
+	}
+}
+
+/// The parts of a class that hierarchy queries need
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassHeader {
+	pub name: Name,
+	pub access_flags: ClassAccessFlags,
+	pub super_class: Option<Name>,
+	pub interfaces: Vec<Name>
+}
+
+impl ClassHeader {
+	pub fn new<T: Into<Name>>(name: T, access_flags: ClassAccessFlags, super_class: Option<&str>, interfaces: &[&str]) -> Self {
+		ClassHeader {
+			name: name.into(),
+			access_flags,
+			super_class: super_class.map(Name::from),
+			interfaces: interfaces.iter().map(|x| Name::from(*x)).collect()
+		}
+	}
+	
+	pub fn of(class: &ClassFile) -> Self {
+		ClassHeader {
+			name: class.this_class.clone(),
+			access_flags: class.access_flags,
+			super_class: class.super_class.clone(),
+			interfaces: class.interfaces.clone()
+		}
+	}
+	
+	pub fn is_interface(&self) -> bool {
+		self.access_flags.contains(ClassAccessFlags::INTERFACE)
+	}
+}
+
+/// Describes classes that are not found by any provider, usually those of the JDK
+pub type Fallback = Box<dyn Fn(&str) -> Option<ClassHeader> + Send + Sync>;
+
+/// Finds classes by internal name across directories, jars and in memory classes, loading each
+/// class the first time it is needed and answering questions about the class hierarchy.
+///
+/// Providers are searched in the order they were added, then the fallback is asked. Arrays are
+/// named by their descriptor, such as `[Ljava/lang/String;`
+pub struct ClassPath {
+	providers: Vec<Box<dyn ClassProvider>>,
+	fallback: Option<Fallback>,
+	options: ParseOptions,
+	/// Every class that has been looked up, None if it was not found
+	classes: RwLock<HashMap<Name, Option<Arc<ClassFile>>>>,
+	/// Every header that has been looked up, None if it was not found
+	headers: RwLock<HashMap<Name, Option<Arc<ClassHeader>>>>
+}
+
+impl Default for ClassPath {
+	fn default() -> Self {
+		ClassPath::new()
+	}
+}
+
+impl ClassPath {
+	pub fn new() -> Self {
+		ClassPath {
+			providers: Vec::new(),
+			fallback: None,
+			options: ParseOptions::default(),
+			classes: RwLock::new(HashMap::new()),
+			headers: RwLock::new(HashMap::new())
+		}
+	}
+	
+	/// Sets the options classes are parsed with when they are loaded
+	pub fn with_options(mut self, options: ParseOptions) -> Self {
+		self.options = options;
+		self
+	}
+	
+	/// Sets the fallback used for classes that no provider has, see `jdk_fallback`
+	pub fn with_fallback(mut self, fallback: Fallback) -> Self {
+		self.fallback = Some(fallback);
+		self
+	}
+	
+	pub fn add_provider<P: ClassProvider + 'static>(&mut self, provider: P) {
+		self.providers.push(Box::new(provider));
+	}
+	
+	pub fn add_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+		self.add_provider(DirectoryProvider::new(dir));
+	}
+	
+	/// Adds the classes of a jar that a JVM of the given release would load
+	pub fn add_archive(&mut self, archive: Archive, release: u32) {
+		self.add_provider(ArchiveProvider::new(archive, release));
+	}
+	
+	pub fn add_jar<P: AsRef<Path>>(&mut self, path: P, release: u32) -> Result<()> {
+		self.add_archive(Archive::open(path)?, release);
+		Ok(())
+	}
+	
+	/// Adds an already parsed class, which takes precedence over every provider
+	pub fn add_class(&mut self, class: ClassFile) {
+		let name = class.this_class.clone();
+		self.headers.get_mut().unwrap().insert(name.clone(), Some(Arc::new(ClassHeader::of(&class))));
+		self.classes.get_mut().unwrap().insert(name, Some(Arc::new(class)));
+	}
+	
+	/// Loads the class with the given name from the first provider that has it
+	pub fn class(&self, name: &str) -> Result<Option<Arc<ClassFile>>> {
+		if let Some(class) = self.classes.read().unwrap().get(name) {
+			return Ok(class.clone());
+		}
+		let mut class = None;
+		for provider in self.providers.iter() {
+			if let Some(bytes) = provider.find(name)? {
+				class = Some(Arc::new(ClassFile::parse_with_options(&mut bytes.as_slice(), &self.options)?));
+				break;
+			}
+		}
+		let mut classes = self.classes.write().unwrap();
+		// another thread may have loaded the class in the meantime
+		Ok(classes.entry(Name::from(name)).or_insert(class).clone())
+	}
+	
+	/// Like `class`, but returns an error if the class is not found
+	pub fn require(&self, name: &str) -> Result<Arc<ClassFile>> {
+		self.class(name)?.ok_or_else(|| ParserError::class_not_found(name))
+	}
+	
+	/// The header of the given class, taken from the class itself or from the fallback
+	pub fn header(&self, name: &str) -> Result<Option<Arc<ClassHeader>>> {
+		if let Some(header) = self.headers.read().unwrap().get(name) {
+			return Ok(header.clone());
+		}
+		let header = if name.starts_with('[') {
+			// arrays extend Object and implement Cloneable and Serializable
+			Some(ClassHeader::new(name, ClassAccessFlags::PUBLIC | ClassAccessFlags::FINAL, Some(OBJECT), &["java/lang/Cloneable", "java/io/Serializable"]))
+		} else {
+			match self.class(name)? {
+				Some(class) => Some(ClassHeader::of(&class)),
+				None => self.fallback.as_ref().and_then(|fallback| fallback(name))
+			}
+		};
+		let mut headers = self.headers.write().unwrap();
+		Ok(headers.entry(Name::from(name)).or_insert_with(|| header.map(Arc::new)).clone())
+	}
+	
+	fn require_header(&self, name: &str) -> Result<Arc<ClassHeader>> {
+		self.header(name)?.ok_or_else(|| ParserError::class_not_found(name))
+	}
+	
+	pub fn is_interface(&self, name: &str) -> Result<bool> {
+		Ok(self.require_header(name)?.is_interface())
+	}
+	
+	/// The super classes of the given class, starting with its direct super class and ending with
+	/// `java/lang/Object`
+	pub fn super_classes(&self, name: &str) -> Result<Vec<Name>> {
+		let mut supers: Vec<Name> = Vec::new();
+		let mut current = self.require_header(name)?;
+		while let Some(super_class) = current.super_class.clone() {
+			if super_class == name || supers.contains(&super_class) {
+				return Err(ParserError::other(format!("Circular class hierarchy at {}", super_class)));
+			}
+			current = self.require_header(&super_class)?;
+			supers.push(super_class);
+		}
+		Ok(supers)
+	}
+	
+	/// Every interface the given class implements, directly or through its super classes and
+	/// super interfaces, in breadth first order
+	pub fn all_interfaces(&self, name: &str) -> Result<Vec<Name>> {
+		let mut classes: Vec<Name> = vec![Name::from(name)];
+		classes.extend(self.super_classes(name)?);
+		let mut seen: HashSet<Name> = HashSet::new();
+		let mut queue: Vec<Name> = Vec::new();
+		for class in classes.iter() {
+			for interface in self.require_header(class)?.interfaces.iter() {
+				if seen.insert(interface.clone()) {
+					queue.push(interface.clone());
+				}
+			}
+		}
+		let mut i = 0;
+		while i < queue.len() {
+			let header = self.require_header(&queue[i])?;
+			for interface in header.interfaces.iter() {
+				if seen.insert(interface.clone()) {
+					queue.push(interface.clone());
+				}
+			}
+			i += 1;
+		}
+		Ok(queue)
+	}
+	
+	/// Whether a value of type `from` can be assigned to a variable of type `to`
+	pub fn is_assignable_from(&self, to: &str, from: &str) -> Result<bool> {
+		if to == from || to == OBJECT {
+			return Ok(true);
+		}
+		if let (Some(to), Some(from)) = (to.strip_prefix('['), from.strip_prefix('[')) {
+			// arrays of references are covariant, arrays of primitives only match themselves
+			return match (array_element(to), array_element(from)) {
+				(Some(to), Some(from)) => self.is_assignable_from(to, from),
+				_ => Ok(to == from)
+			};
+		}
+		if self.super_classes(from)?.iter().any(|x| x == to) {
+			return Ok(true);
+		}
+		if self.is_interface(to)? {
+			return Ok(self.all_interfaces(from)?.iter().any(|x| x == to));
+		}
+		Ok(false)
+	}
+	
+	/// The most specific class both given classes extend, as needed when merging stack map frames.
+	/// Interfaces are treated as `java/lang/Object`
+	pub fn common_super_class(&self, a: &str, b: &str) -> Result<Name> {
+		if self.is_assignable_from(a, b)? {
+			return Ok(Name::from(a));
+		}
+		if self.is_assignable_from(b, a)? {
+			return Ok(Name::from(b));
+		}
+		if self.is_interface(a)? || self.is_interface(b)? {
+			return Ok(Name::from(OBJECT));
+		}
+		for super_class in self.super_classes(a)? {
+			if self.is_assignable_from(&super_class, b)? {
+				return Ok(super_class);
+			}
+		}
+		Ok(Name::from(OBJECT))
+	}
+}
+
+/// The element type of an array descriptor with its leading `[` removed, if it is a reference
+fn array_element(desc: &str) -> Option<&str> {
+	if desc.starts_with('[') {
+		Some(desc)
+	} else {
+		desc.strip_prefix('L').and_then(|desc| desc.strip_suffix(';'))
+	}
+}
+
+/// A fallback describing the most commonly referenced classes of `java.lang`, `java.io` and
+/// `java.util`, for class paths that do not include the JDK
+pub fn jdk_fallback() -> Fallback {
+	Box::new(|name| {
+		let public = ClassAccessFlags::PUBLIC;
+		let interface = ClassAccessFlags::PUBLIC | ClassAccessFlags::INTERFACE | ClassAccessFlags::ABSTRACT;
+		let (access, super_class, interfaces): (ClassAccessFlags, Option<&str>, &[&str]) = match name {
+			OBJECT => (public, None, &[]),
+			"java/lang/String" => (public | ClassAccessFlags::FINAL, Some(OBJECT), &["java/io/Serializable", "java/lang/Comparable", "java/lang/CharSequence"]),
+			"java/lang/Class" => (public | ClassAccessFlags::FINAL, Some(OBJECT), &["java/io/Serializable"]),
+			"java/lang/Enum" => (public | ClassAccessFlags::ABSTRACT, Some(OBJECT), &["java/lang/Comparable", "java/io/Serializable"]),
+			"java/lang/Record" => (public | ClassAccessFlags::ABSTRACT, Some(OBJECT), &[]),
+			"java/lang/Number" => (public | ClassAccessFlags::ABSTRACT, Some(OBJECT), &["java/io/Serializable"]),
+			"java/lang/Integer" | "java/lang/Long" | "java/lang/Short" | "java/lang/Byte" | "java/lang/Float"
+				| "java/lang/Double" => (public | ClassAccessFlags::FINAL, Some("java/lang/Number"), &["java/lang/Comparable"]),
+			"java/lang/Boolean" | "java/lang/Character" => (public | ClassAccessFlags::FINAL, Some(OBJECT), &["java/io/Serializable", "java/lang/Comparable"]),
+			"java/lang/Throwable" => (public, Some(OBJECT), &["java/io/Serializable"]),
+			"java/lang/Exception" | "java/lang/Error" => (public, Some("java/lang/Throwable"), &[]),
+			"java/lang/RuntimeException" => (public, Some("java/lang/Exception"), &[]),
+			"java/lang/IllegalArgumentException" | "java/lang/IllegalStateException" | "java/lang/NullPointerException"
+				| "java/lang/ArithmeticException" | "java/lang/ClassCastException"
+				| "java/lang/UnsupportedOperationException" => (public, Some("java/lang/RuntimeException"), &[]),
+			"java/lang/IndexOutOfBoundsException" => (public, Some("java/lang/RuntimeException"), &[]),
+			"java/lang/ArrayIndexOutOfBoundsException" => (public, Some("java/lang/IndexOutOfBoundsException"), &[]),
+			"java/io/IOException" | "java/lang/InterruptedException" => (public, Some("java/lang/Exception"), &[]),
+			"java/lang/StringBuilder" => (public | ClassAccessFlags::FINAL, Some(OBJECT), &["java/io/Serializable", "java/lang/CharSequence"]),
+			"java/io/Serializable" | "java/lang/Comparable" | "java/lang/CharSequence" | "java/lang/Cloneable"
+				| "java/lang/Runnable" | "java/lang/Iterable" | "java/lang/AutoCloseable" | "java/util/Map"
+				| "java/util/Iterator" => (interface, Some(OBJECT), &[]),
+			"java/io/Closeable" => (interface, Some(OBJECT), &["java/lang/AutoCloseable"]),
+			"java/util/Collection" => (interface, Some(OBJECT), &["java/lang/Iterable"]),
+			"java/util/List" | "java/util/Set" => (interface, Some(OBJECT), &["java/util/Collection"]),
+			"java/util/ArrayList" => (public, Some(OBJECT), &["java/util/List", "java/lang/Cloneable", "java/io/Serializable"]),
+			"java/util/HashMap" => (public, Some(OBJECT), &["java/util/Map", "java/lang/Cloneable", "java/io/Serializable"]),
+			"java/util/HashSet" => (public, Some(OBJECT), &["java/util/Set", "java/lang/Cloneable", "java/io/Serializable"]),
+			_ => return None
+		};
+		Some(ClassHeader::new(name, access, super_class, interfaces))
+	})
+}
@@ -1,16 +1,24 @@
-use crate::attributes::{Attribute, AttributeSource, Attributes};
-use crate::constantpool::{ConstantPool, ConstantType, CPIndex, ConstantPoolWriter};
+use crate::attributes::{Attribute, AttributeSource, Attributes, VerificationType};
+use crate::constantpool::{ConstantPool, ConstantType, CPIndex, ConstantPoolWriter, MethodHandleKind};
 use crate::version::ClassVersion;
+use crate::classfile::ParseOptions;
 use crate::error::{Result, ParserError};
 use crate::ast::*;
 use crate::insnlist::InsnList;
-use crate::utils::{ReadUtils, MapUtils};
+use crate::intern::Name;
+use crate::utils::ReadUtils;
 use crate::types::{Type, parse_method_desc};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use std::io::{Read, Write, Cursor, Seek, SeekFrom};
-use std::collections::HashMap;
+use std::io::{Read, Write, Cursor};
+use std::collections::{HashMap, HashSet};
 use derive_more::Constructor;
 use std::convert::TryFrom;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// The entries of the BootstrapMethods attribute of a class by index, None for those with
+/// arguments that cannot be represented yet
+pub(crate) type BootstrapMethods = Arc<[Option<BootstrapMethod>]>;
 
 #[derive(Constructor, Clone, Debug, PartialEq)]
 pub struct CodeAttribute {
@@ -21,6 +29,97 @@ pub struct CodeAttribute {
 	pub attributes: Vec<Attribute>
 }
 
+/// The body of a method which is only decoded when it is accessed through `Method::code`.
+/// If it is never decoded, its original bytes are written back untouched as long as the class is
+/// written with the constant pool it was parsed with
+#[derive(Clone, PartialEq)]
+pub struct LazyCodeAttribute {
+	version: ClassVersion,
+	constant_pool: Arc<ConstantPool>,
+	bytes: Vec<u8>,
+	options: ParseOptions,
+	bootstrap_methods: BootstrapMethods,
+	/// The method the code belongs to, which its frames are expanded from
+	method: Option<MethodContext>
+}
+
+#[derive(Clone, PartialEq)]
+struct MethodContext {
+	this_class: Name,
+	name: Name,
+	descriptor: Name,
+	is_static: bool
+}
+
+impl LazyCodeAttribute {
+	/// The options are applied when the code is decoded
+	pub fn new(version: ClassVersion, constant_pool: Arc<ConstantPool>, bytes: Vec<u8>, options: ParseOptions) -> Self {
+		LazyCodeAttribute {
+			version,
+			constant_pool,
+			bytes,
+			options,
+			bootstrap_methods: Arc::from(Vec::new()),
+			method: None
+		}
+	}
+	
+	/// Lets the frames be expanded when the code is decoded, if the options ask for it
+	pub(crate) fn set_method(&mut self, this_class: &Name, name: &Name, descriptor: &Name, is_static: bool) {
+		self.method = Some(MethodContext {
+			this_class: this_class.clone(),
+			name: name.clone(),
+			descriptor: descriptor.clone(),
+			is_static
+		});
+	}
+	
+	/// Lets invokedynamic instructions be resolved against the bootstrap methods of the class
+	pub(crate) fn set_bootstrap_methods(&mut self, bootstrap_methods: BootstrapMethods) {
+		self.bootstrap_methods = bootstrap_methods;
+	}
+	
+	/// The undecoded body of the attribute
+	pub fn bytes(&self) -> &[u8] {
+		self.bytes.as_slice()
+	}
+	
+	pub fn max_stack(&self) -> Result<u16> {
+		Ok(self.bytes.as_slice().read_u16::<BigEndian>()?)
+	}
+	
+	pub fn max_locals(&self) -> Result<u16> {
+		Ok(self.bytes.get(2..).unwrap_or_default().read_u16::<BigEndian>()?)
+	}
+	
+	pub fn decode(&self) -> Result<CodeAttribute> {
+		let mut code = CodeAttribute::parse_with_bootstrap_methods(&self.version, &self.constant_pool, &self.bootstrap_methods, self.bytes.clone(), &self.options)?;
+		if let (true, Some(method)) = (self.options.expand_frames, &self.method) {
+			code.expand_frames(&method.this_class, &method.name, &method.descriptor, method.is_static)?;
+		}
+		Ok(code)
+	}
+	
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		// the original bytes still contain any attributes the options would have removed
+		let drops_attributes = self.options.skip_debug || self.options.skip_frames;
+		if !drops_attributes && constant_pool.is_seeded_from(&self.constant_pool) {
+			wtr.write_all(self.bytes.as_slice())?;
+			Ok(())
+		} else {
+			self.decode()?.write(wtr, constant_pool)
+		}
+	}
+}
+
+impl Debug for LazyCodeAttribute {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("LazyCodeAttribute")
+			.field("length", &self.bytes.len())
+			.finish()
+	}
+}
+
 impl CodeAttribute {
 	pub fn empty() -> Self {
 		CodeAttribute {
@@ -32,7 +131,13 @@ impl CodeAttribute {
 		}
 	}
 	
-	pub fn parse(version: &ClassVersion, constant_pool: &ConstantPool, buf: Vec<u8>) -> Result<Self> {
+	/// Invokedynamic instructions are not resolved, as the bootstrap methods of the class are unknown.
+	/// Parse the whole class to resolve them
+	pub fn parse(version: &ClassVersion, constant_pool: &ConstantPool, buf: Vec<u8>, options: &ParseOptions) -> Result<Self> {
+		CodeAttribute::parse_with_bootstrap_methods(version, constant_pool, &[], buf, options)
+	}
+	
+	pub(crate) fn parse_with_bootstrap_methods(version: &ClassVersion, constant_pool: &ConstantPool, bootstrap_methods: &[Option<BootstrapMethod>], buf: Vec<u8>, options: &ParseOptions) -> Result<Self> {
 		let mut buf = Cursor::new(buf);
 		
 		let max_stack = buf.read_u16::<BigEndian>()?;
@@ -43,21 +148,20 @@ impl CodeAttribute {
 		let code: Vec<u8> = buf.read_nbytes(code_length as usize)?;
 		let mut code = Cursor::new(code);
 		
+		// the exceptions and attributes are read first so that the labels they reference can be placed
+		// while decoding the instructions
 		let mut pc_label_map: HashMap<u32, LabelInsn> = HashMap::new();
-		InsnParser::find_insn_refs(&mut code, code_length, &mut pc_label_map)?;
-		
 		let num_exceptions = buf.read_u16::<BigEndian>()?;
 		let mut exceptions: Vec<ExceptionHandler> = Vec::with_capacity(num_exceptions as usize);
 		for _ in 0..num_exceptions {
-			exceptions.push(ExceptionHandler::parse(constant_pool, &mut buf)?);
+			exceptions.push(ExceptionHandler::parse(constant_pool, &mut buf, &mut pc_label_map)?);
 		}
 		
 		let mut pc_label_map = Some(pc_label_map);
-		let attributes = Attributes::parse(&mut buf, AttributeSource::Code, version, constant_pool, &mut pc_label_map)?;
+		let attributes = Attributes::parse(&mut buf, AttributeSource::Code, version, constant_pool, &mut pc_label_map, options)?;
 		let mut pc_label_map = pc_label_map.unwrap();
 		
-		code.set_position(0);
-		let code = InsnParser::parse_insns(constant_pool, &mut code, code_length, &mut pc_label_map)?;
+		let code = InsnParser::parse_insns(constant_pool, bootstrap_methods, &mut code, code_length, &mut pc_label_map)?;
 		
 		Ok(CodeAttribute {
 			max_stack,
@@ -68,6 +172,16 @@ impl CodeAttribute {
 		})
 	}
 	
+	/// Expands every stack map frame into a full frame, given the method this code belongs to
+	pub fn expand_frames(&mut self, this_class: &str, method_name: &str, descriptor: &str, is_static: bool) -> Result<()> {
+		for attr in self.attributes.iter_mut() {
+			if let Attribute::StackMapTable(table) = attr {
+				table.expand(VerificationType::initial_locals(this_class, method_name, descriptor, is_static)?)?;
+			}
+		}
+		Ok(())
+	}
+	
 	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
 		wtr.write_u16::<BigEndian>(self.max_stack)?;
 		wtr.write_u16::<BigEndian>(self.max_locals)?;
@@ -76,7 +190,7 @@ impl CodeAttribute {
 		wtr.write_all(code_bytes.as_slice())?;
 		wtr.write_u16::<BigEndian>(self.exceptions.len() as u16)?;
 		for excep in self.exceptions.iter() {
-			excep.write(wtr, constant_pool)?;
+			excep.write(wtr, constant_pool, &label_pc_map)?;
 		}
 		Attributes::write(wtr, &self.attributes, constant_pool, Some(&label_pc_map))?;
 		Ok(())
@@ -86,18 +200,20 @@ impl CodeAttribute {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExceptionHandler {
-	pub start_pc: u16,
-	pub end_pc: u16,
-	pub handler_pc: u16,
-	pub catch_type: Option<String>
+	/// The first instruction covered by this handler
+	pub start: LabelInsn,
+	/// The end of the covered range (exclusive)
+	pub end: LabelInsn,
+	pub handler: LabelInsn,
+	/// None catches any exception
+	pub catch_type: Option<Name>
 }
 
 impl ExceptionHandler {
-	// TODO: exception handlers should use labels
-	pub fn parse<T: Read>(constant_pool: &ConstantPool, buf: &mut T) -> Result<Self> {
-		let start_pc = buf.read_u16::<BigEndian>()?;
-		let end_pc = buf.read_u16::<BigEndian>()?;
-		let handler_pc = buf.read_u16::<BigEndian>()?;
+	pub fn parse<T: Read>(constant_pool: &ConstantPool, buf: &mut T, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<Self> {
+		let start_pc = buf.read_u16::<BigEndian>()? as u32;
+		let end_pc = buf.read_u16::<BigEndian>()? as u32;
+		let handler_pc = buf.read_u16::<BigEndian>()? as u32;
 		let catch_index = buf.read_u16::<BigEndian>()?;
 		let catch_type = if catch_index > 0 {
 			Some(constant_pool.utf8(constant_pool.class(catch_index)?.name_index)?.str.clone())
@@ -106,19 +222,20 @@ impl ExceptionHandler {
 		};
 		
 		Ok(ExceptionHandler {
-			start_pc,
-			end_pc,
-			handler_pc,
+			start: InsnParser::label_at(pc_label_map, start_pc),
+			end: InsnParser::label_at(pc_label_map, end_pc),
+			handler: InsnParser::label_at(pc_label_map, handler_pc),
 			catch_type
 		})
 	}
 	
-	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
-		wtr.write_u16::<BigEndian>(self.start_pc)?;
-		wtr.write_u16::<BigEndian>(self.end_pc)?;
-		wtr.write_u16::<BigEndian>(self.handler_pc)?;
-		let catch_type = match self.catch_type.clone() {
-			Some(x) => constant_pool.class_utf8(x),
+	pub fn write<T: Write>(&self, wtr: &mut T, constant_pool: &mut ConstantPoolWriter, label_pc_map: &HashMap<LabelInsn, u32>) -> Result<()> {
+		for label in [self.start, self.end, self.handler].iter() {
+			let pc = *label_pc_map.get(label).ok_or_else(ParserError::unmapped_label)?;
+			wtr.write_u16::<BigEndian>(pc as u16)?;
+		}
+		let catch_type = match &self.catch_type {
+			Some(x) => constant_pool.class_utf8(x)?,
 			None => 0
 		};
 		wtr.write_u16::<BigEndian>(catch_type)?;
@@ -336,226 +453,14 @@ impl InsnParser {
 	const TABLESWITCH: u8 = 0xAA;
 	const WIDE: u8 = 0xC4;
 	
-	/// Iterate all instructions and collect any pcs that are referenced - i.e. need to have relevant Labels
-	fn find_insn_refs<T: Read + Seek>(rdr: &mut T, length: u32, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<()> {
-		let mut pc: u32 = 0;
-		while pc < length {
-			let this_pc = pc;
-			let opcode = rdr.read_u8()?;
-			pc += 1;
-			
-			match opcode {
-				InsnParser::GOTO => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::GOTO_W => {
-					let to = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 4;
-				}
-				InsnParser::IF_ACMPEQ => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IF_ACMPNE => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IF_ICMPEQ => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IF_ICMPGE => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IF_ICMPGT => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IF_ICMPLE => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IF_ICMPLT => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IF_ICMPNE => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFEQ => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFGE => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFGT => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFLE => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFLT => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFNE => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFNONNULL => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::IFNULL => {
-					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(to, LabelInsn::new(pc_label_map.len() as u32));
-					pc += 2;
-				}
-				InsnParser::LOOKUPSWITCH => {
-					let pad = 3 - (this_pc % 4);
-					rdr.seek(SeekFrom::Current(pad as i64))?;
-					
-					let default = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(default, LabelInsn::new(pc_label_map.len() as u32));
-					let npairs = rdr.read_i32::<BigEndian>()? as u32;
-					
-					for i in 0..npairs {
-						let matc = rdr.read_i32::<BigEndian>()?;
-						let jump = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
-						pc_label_map.insert_if_not_present(jump, LabelInsn::new(pc_label_map.len() as u32));
-					}
-					
-					pc += pad + (2 * 4) + (npairs * 2 * 4);
-				}
-				InsnParser::TABLESWITCH => {
-					let pad = 3 - (this_pc % 4);
-					rdr.seek(SeekFrom::Current(pad as i64))?;
-					
-					let default = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
-					pc_label_map.insert_if_not_present(default, LabelInsn::new(pc_label_map.len() as u32));
-					
-					let low = rdr.read_i32::<BigEndian>()?;
-					let high = rdr.read_i32::<BigEndian>()?;
-					let num_cases = (high - low + 1) as u32;
-					for i in 0..num_cases {
-						let case = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
-						pc_label_map.insert_if_not_present(case, LabelInsn::new(pc_label_map.len() as u32));
-					}
-					
-					pc += pad + ((3 + num_cases) * 4);
-				},
-				InsnParser::AALOAD | InsnParser::AASTORE | InsnParser::ACONST_NULL |
-				InsnParser::ALOAD_0 | InsnParser::ALOAD_1 | InsnParser::ALOAD_2 |
-				InsnParser::ALOAD_3 | InsnParser::ARETURN | InsnParser::ARRAYLENGTH |
-				InsnParser::ASTORE_0 | InsnParser::ASTORE_2 | InsnParser::ASTORE_3 |
-				InsnParser::ATHROW | InsnParser::BALOAD | InsnParser::BASTORE |
-				InsnParser::BREAKPOINT | InsnParser::CALOAD | InsnParser::CASTORE |
-				InsnParser::D2F | InsnParser::D2I | InsnParser::D2L | InsnParser::DADD |
-				InsnParser::DALOAD | InsnParser::DASTORE | InsnParser::DCMPG | InsnParser::DCMPL |
-				InsnParser::DCONST_0 | InsnParser::DCONST_1 | InsnParser::DDIV |
-				InsnParser::DLOAD_0 | InsnParser::DLOAD_1 | InsnParser::DLOAD_2 |
-				InsnParser::DLOAD_3 | InsnParser::DMUL | InsnParser::DNEG | InsnParser::DREM |
-				InsnParser::DRETURN | InsnParser::DSTORE_0 | InsnParser::DSTORE_1 |
-				InsnParser::DSTORE_2 | InsnParser::DSTORE_3 | InsnParser::DSUB | InsnParser::DUP |
-				InsnParser::DUP_X1 | InsnParser::DUP_X2 | InsnParser::DUP2 | InsnParser::DUP2_X1 |
-				InsnParser::DUP2_X2 | InsnParser::F2D | InsnParser::F2I | InsnParser::F2L |
-				InsnParser::FADD | InsnParser::FALOAD | InsnParser::FASTORE | InsnParser::FCMPG |
-				InsnParser::FCMPL | InsnParser::FCONST_0 | InsnParser::FCONST_1 |
-				InsnParser::FCONST_2 | InsnParser::FDIV | InsnParser::FLOAD_0 |
-				InsnParser::FLOAD_1 | InsnParser::FLOAD_2 | InsnParser::FLOAD_3 | InsnParser::FMUL |
-				InsnParser::FNEG | InsnParser::FREM | InsnParser::FRETURN | InsnParser::FSTORE_0 |
-				InsnParser::FSTORE_1 | InsnParser::FSTORE_2 | InsnParser::FSTORE_3 |
-				InsnParser::FSUB | InsnParser::I2B | InsnParser::I2C | InsnParser::I2D |
-				InsnParser::I2F | InsnParser::I2L | InsnParser::I2S | InsnParser::IADD |
-				InsnParser::IALOAD | InsnParser::IAND | InsnParser::IASTORE |
-				InsnParser::ICONST_M1 | InsnParser::ICONST_0 | InsnParser::ICONST_1 |
-				InsnParser::ICONST_2 | InsnParser::ICONST_3 | InsnParser::ICONST_4 |
-				InsnParser::ICONST_5 | InsnParser::IDIV | InsnParser::ILOAD_0 |
-				InsnParser::ILOAD_1 | InsnParser::ILOAD_2 | InsnParser::ILOAD_3 |
-				InsnParser::IMPDEP1 | InsnParser::IMPDEP2 | InsnParser::IMUL | InsnParser::INEG |
-				InsnParser::IOR | InsnParser::IREM | InsnParser::IRETURN | InsnParser::ISHL |
-				InsnParser::ISHR | InsnParser::ISTORE_0 | InsnParser::ISTORE_1 |
-				InsnParser::ISTORE_2 | InsnParser::ISTORE_3 | InsnParser::ISUB | InsnParser::IUSHR |
-				InsnParser::IXOR | InsnParser::L2D | InsnParser::L2F | InsnParser::L2I |
-				InsnParser::LADD | InsnParser::LALOAD | InsnParser::LAND | InsnParser::LASTORE |
-				InsnParser::LCMP | InsnParser::LCONST_0 | InsnParser::LCONST_1 | InsnParser::LDIV |
-				InsnParser::LLOAD_0 | InsnParser::LLOAD_1 | InsnParser::LLOAD_2 |
-				InsnParser::LLOAD_3 | InsnParser::LMUL | InsnParser::LNEG | InsnParser::LOR |
-				InsnParser::LREM | InsnParser::LRETURN | InsnParser::LSHL | InsnParser::LSHR |
-				InsnParser::LSTORE_0 | InsnParser::LSTORE_1 | InsnParser::LSTORE_2 |
-				InsnParser::LSTORE_3 | InsnParser::LSUB | InsnParser::LUSHR | InsnParser::LXOR |
-				InsnParser::MONITORENTER | InsnParser::MONITOREXIT | InsnParser::NOP |
-				InsnParser::POP | InsnParser::POP2 | InsnParser::RETURN | InsnParser::SALOAD |
-				InsnParser::SASTORE | InsnParser::SWAP => {},
-				InsnParser::ALOAD | InsnParser::ASTORE | InsnParser::BIPUSH | InsnParser::DLOAD |
-				InsnParser::DSTORE | InsnParser::FLOAD | InsnParser::FSTORE | InsnParser::ILOAD |
-				InsnParser::ISTORE | InsnParser::LDC | InsnParser::LLOAD | InsnParser::LSTORE |
-				InsnParser::NEWARRAY => {
-					pc += 1;
-					rdr.seek(SeekFrom::Current(1))?;
-				}
-				InsnParser::ANEWARRAY | InsnParser::CHECKCAST | InsnParser::GETFIELD |
-				InsnParser::GETSTATIC | InsnParser::IINC | InsnParser::INSTANCEOF |
-				InsnParser::INVOKESPECIAL | InsnParser::INVOKESTATIC | InsnParser::INVOKEVIRTUAL |
-				InsnParser::LDC_W | InsnParser::LDC2_W | InsnParser::NEW | InsnParser::PUTFIELD |
-				InsnParser::PUTSTATIC | InsnParser::SIPUSH => {
-					pc += 2;
-					rdr.seek(SeekFrom::Current(2))?;
-				}
-				InsnParser::MULTIANEWARRAY => {
-					pc += 3;
-					rdr.seek(SeekFrom::Current(3))?;
-				}
-				InsnParser::INVOKEDYNAMIC | InsnParser::INVOKEINTERFACE => {
-					pc += 4;
-					rdr.seek(SeekFrom::Current(4))?;
-				}
-				InsnParser::WIDE => match rdr.read_u8()? {
-					InsnParser::ILOAD | InsnParser::FLOAD | InsnParser::ALOAD | InsnParser::LLOAD |
-					InsnParser::DLOAD | InsnParser::ISTORE | InsnParser::FSTORE |
-					InsnParser::LSTORE | InsnParser::DSTORE => {
-						pc += 3;
-						rdr.seek(SeekFrom::Current(3))?;
-					}
-					InsnParser::IINC => {
-						pc += 5;
-						rdr.seek(SeekFrom::Current(5))?;
-					}
-					_ => return Err(ParserError::invalid_insn(this_pc, format!("Invalid wide opcode {:x}", opcode)))
-				},
-				_ => return Err(ParserError::unknown_insn(opcode))
-			}
-		}
-		Ok(())
-	}
-	
-	fn parse_insns<T: Read>(constant_pool: &ConstantPool, mut rdr: T, length: u32, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<InsnList> {
+	/// Decodes every instruction in a single pass.
+	/// Labels are placed as soon as the decoder reaches their pc, only backwards jumps to a pc
+	/// that had no label yet need to be inserted afterwards
+	fn parse_insns<T: Read>(constant_pool: &ConstantPool, bootstrap_methods: &[Option<BootstrapMethod>], mut rdr: T, length: u32, pc_label_map: &mut HashMap<u32, LabelInsn>) -> Result<InsnList> {
 		let num_insns_estimate = length as usize / 3; // estimate an average 3 bytes per insn
 		let mut insns: Vec<Insn> = Vec::with_capacity(num_insns_estimate);
+		let mut pcs: Vec<u32> = Vec::with_capacity(num_insns_estimate);
+		let mut labels = CodeLabels::new(pc_label_map, length)?;
 		
 		let mut pc: u32 = 0;
 		while pc < length {
@@ -563,9 +468,9 @@ impl InsnParser {
 			let opcode = rdr.read_u8()?;
 			pc += 1;
 			
-			// does this pc need an associated label?
-			if let Some(lbl) = pc_label_map.get(&this_pc) {
-				insns.push(Insn::Label(*lbl));
+			if let Some(label) = labels.at(this_pc) {
+				insns.push(Insn::Label(label));
+				pcs.push(this_pc);
 			}
 			
 			let insn = match opcode {
@@ -710,12 +615,12 @@ impl InsnParser {
 				InsnParser::GOTO => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::Jump(JumpInsn::new(*pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::Jump(JumpInsn::new(labels.target(this_pc, to)?))
 				},
 				InsnParser::GOTO_W => {
 					let to = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
 					pc += 4;
-					Insn::Jump(JumpInsn::new(*pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::Jump(JumpInsn::new(labels.target(this_pc, to)?))
 				},
 				InsnParser::I2B => Insn::Convert(ConvertInsn::new(PrimitiveType::Int, PrimitiveType::Byte)),
 				InsnParser::I2C => Insn::Convert(ConvertInsn::new(PrimitiveType::Int, PrimitiveType::Char)),
@@ -738,82 +643,82 @@ impl InsnParser {
 				InsnParser::IF_ACMPEQ => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::ReferencesEqual, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::ReferencesEqual, labels.target(this_pc, to)?))
 				},
 				InsnParser::IF_ACMPNE => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::ReferencesNotEqual, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::ReferencesNotEqual, labels.target(this_pc, to)?))
 				},
 				InsnParser::IF_ICMPEQ => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsEq, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsEq, labels.target(this_pc, to)?))
 				},
 				InsnParser::IF_ICMPGE => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsGreaterThanOrEq, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsGreaterThanOrEq, labels.target(this_pc, to)?))
 				},
 				InsnParser::IF_ICMPGT => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsGreaterThan, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsGreaterThan, labels.target(this_pc, to)?))
 				},
 				InsnParser::IF_ICMPLE => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsLessThanOrEq, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsLessThanOrEq, labels.target(this_pc, to)?))
 				},
 				InsnParser::IF_ICMPLT => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsLessThan, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsLessThan, labels.target(this_pc, to)?))
 				},
 				InsnParser::IF_ICMPNE => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsNotEq, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntsNotEq, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFEQ => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntEqZero, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntEqZero, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFGE => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntGreaterThanOrEqZero, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntGreaterThanOrEqZero, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFGT => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntGreaterThanZero, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntGreaterThanZero, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFLE => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntLessThanOrEqZero, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntLessThanOrEqZero, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFLT => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntLessThanZero, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntLessThanZero, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFNE => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntNotEqZero, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntNotEqZero, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFNONNULL => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::NotNull, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::NotNull, labels.target(this_pc, to)?))
 				},
 				InsnParser::IFNULL => {
 					let to = (rdr.read_i16::<BigEndian>()? as i32 + this_pc as i32) as u32;
 					pc += 2;
-					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IsNull, *pc_label_map.get(&to).ok_or_else(ParserError::unmapped_label)?))
+					Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IsNull, labels.target(this_pc, to)?))
 				},
 				InsnParser::IINC => {
 					let index = rdr.read_u8()?;
@@ -843,12 +748,20 @@ impl InsnParser {
 					let dyn_info = constant_pool.invokedynamicinfo(rdr.read_u16::<BigEndian>()?)?;
 					rdr.read_u16::<BigEndian>()?;
 					pc += 4;
-					// TODO: Resolve bootstrap methods
 					
 					let name_and_type = constant_pool.nameandtype(dyn_info.name_and_type_index)?;
 					let name = constant_pool.utf8(name_and_type.name_index)?.str.clone();
 					let descriptor = constant_pool.utf8(name_and_type.descriptor_index)?.str.clone();
-					Insn::InvokeDynamic(InvokeDynamicInsn::new(name, descriptor, BootstrapMethodType::InvokeStatic, String::from("Unimplemented"), String::from("Unimplemented"), String::from("Unimplemented"), Vec::new()))
+					let bootstrap = bootstrap_methods.get(dyn_info.bootstrap_method_attr_index as usize).and_then(Option::as_ref);
+					Insn::InvokeDynamic(match bootstrap {
+						Some(bootstrap) => InvokeDynamicInsn::new(name, descriptor, bootstrap.handle.clone(), bootstrap.arguments.clone()),
+						// TODO: Constant_Dynamic arguments
+						None => {
+							let unresolved = Name::from("Unimplemented");
+							let handle = Handle::new(MethodHandleKind::InvokeStatic, unresolved.clone(), unresolved.clone(), unresolved, false);
+							InvokeDynamicInsn::new(name, descriptor, handle, Vec::new())
+						}
+					})
 				},
 				InsnParser::INVOKEINTERFACE => {
 					let method = constant_pool.interfacemethodref(rdr.read_u16::<BigEndian>()?)?;
@@ -946,7 +859,7 @@ impl InsnParser {
 				InsnParser::LLOAD => {
 					let index = rdr.read_u8()?;
 					pc += 1;
-					Insn::LocalLoad(LocalLoadInsn::new(OpType::Double, index as u16))
+					Insn::LocalLoad(LocalLoadInsn::new(OpType::Long, index as u16))
 				},
 				InsnParser::LLOAD_0 => Insn::LocalLoad(LocalLoadInsn::new(OpType::Long, 0)),
 				InsnParser::LLOAD_1 => Insn::LocalLoad(LocalLoadInsn::new(OpType::Long, 1)),
@@ -961,12 +874,12 @@ impl InsnParser {
 					let default = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
 					let npairs = rdr.read_i32::<BigEndian>()? as u32;
 					
-					let mut insn = LookupSwitchInsn::new(*pc_label_map.get(&default).ok_or_else(ParserError::unmapped_label)?);
+					let mut insn = LookupSwitchInsn::new(labels.target(this_pc, default)?);
 					
 					for i in 0..npairs {
 						let matc = rdr.read_i32::<BigEndian>()?;
 						let jump = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
-						insn.cases.insert(matc, *pc_label_map.get(&jump).ok_or_else(ParserError::unmapped_label)?);
+						insn.cases.insert(matc, labels.target(this_pc, jump)?);
 					}
 					
 					pc += pad + (2 * 4) + (npairs * 2 * 4);
@@ -1062,13 +975,13 @@ impl InsnParser {
 					let mut cases: Vec<LabelInsn> = Vec::with_capacity(num_cases as usize);
 					for i in 0..num_cases {
 						let case = (rdr.read_i32::<BigEndian>()? + this_pc as i32) as u32;
-						cases.push(*pc_label_map.get(&case).ok_or_else(ParserError::unmapped_label)?);
+						cases.push(labels.target(this_pc, case)?);
 					}
 					
 					pc += pad + ((3 + num_cases) * 4);
 					
 					Insn::TableSwitch(TableSwitchInsn {
-						default: *pc_label_map.get(&default).ok_or_else(ParserError::unmapped_label)?,
+						default: labels.target(this_pc, default)?,
 						low,
 						cases
 					})
@@ -1135,42 +1048,26 @@ impl InsnParser {
 				_ => return Err(ParserError::unknown_insn(opcode))
 			};
 			insns.push(insn);
+			pcs.push(this_pc);
 		}
 		
 		// there can be a label at the end of the code space, e.g. for an end exception handler
-		if let Some(lbl) = pc_label_map.get(&pc) {
-			insns.push(Insn::Label(*lbl));
+		if let Some(label) = labels.at(length) {
+			insns.push(Insn::Label(label));
+			pcs.push(length);
 		}
+		labels.place_late(&mut insns, &pcs)?;
 		
-		let list = InsnList {
+		Ok(InsnList {
 			insns,
 			labels: pc_label_map.len() as u32
-		};
-		
-		Ok(list)
+		})
 	}
 	
-	fn remap_label_nodes(x: &mut LabelInsn, list: &mut InsnList, pc_index_map: &HashMap<u32, u32>, insert: &mut HashMap<usize, Vec<Insn>>) -> Result<()> {
-		let mut insert_into = *match pc_index_map.get(&x.id) {
-			Some(x) => x,
-			_ => return Err(ParserError::out_of_bounds_jump(x.id as i32))
-		};
-		
-		for (i, insns) in insert.iter() {
-			for _ in 0..insns.len() {
-				if insert_into as usize > *i {
-					insert_into += 1;
-				}
-			}
-		}
-		
-		let jump_to = list.new_label();
-		x.id = jump_to.id;
-		
-		insert.entry(insert_into as usize)
-			.or_insert(Vec::with_capacity(1))
-			.push(Insn::Label(jump_to));
-		Ok(())
+	/// Returns the label of the given pc, creating it if this is the first reference to the pc
+	fn label_at(pc_label_map: &mut HashMap<u32, LabelInsn>, pc: u32) -> LabelInsn {
+		let id = pc_label_map.len() as u32;
+		*pc_label_map.entry(pc).or_insert_with(|| LabelInsn::new(id))
 	}
 	
 	fn parse_ldc(index: CPIndex, constant_pool: &ConstantPool) -> Result<Insn> {
@@ -1194,739 +1091,705 @@ impl InsnParser {
 		Ok(Insn::Ldc(LdcInsn::new(ldc_type)))
 	}
 	
+	fn parse_handle(index: CPIndex, constant_pool: &ConstantPool) -> Result<Handle> {
+		let info = constant_pool.methodhandle(index)?;
+		let reference = constant_pool.get(info.reference)?;
+		let (class_index, name_and_type_index, interface) = match reference {
+			ConstantType::Fieldref(x) => (x.class_index, x.name_and_type_index, false),
+			ConstantType::Methodref(x) => (x.class_index, x.name_and_type_index, false),
+			ConstantType::InterfaceMethodref(x) => (x.class_index, x.name_and_type_index, true),
+			x => return Err(ParserError::incomp_cp(
+				"Fieldref or Methodref",
+				x,
+				info.reference as usize
+			))
+		};
+		let name_and_type = constant_pool.nameandtype(name_and_type_index)?;
+		Ok(Handle::new(
+			info.kind,
+			constant_pool.utf8(constant_pool.class(class_index)?.name_index)?.str.clone(),
+			constant_pool.utf8(name_and_type.name_index)?.str.clone(),
+			constant_pool.utf8(name_and_type.descriptor_index)?.str.clone(),
+			interface
+		))
+	}
+	
+	/// None for dynamic constants, which cannot be represented yet
+	fn parse_bootstrap_argument(index: CPIndex, constant_pool: &ConstantPool) -> Result<Option<BootstrapArgument>> {
+		let constant = constant_pool.get(index)?;
+		Ok(Some(match constant {
+			ConstantType::Integer(x) => BootstrapArgument::Int(x.inner()),
+			ConstantType::Float(x) => BootstrapArgument::Float(x.inner()),
+			ConstantType::Long(x) => BootstrapArgument::Long(x.inner()),
+			ConstantType::Double(x) => BootstrapArgument::Double(x.inner()),
+			ConstantType::Class(x) => BootstrapArgument::Class(constant_pool.utf8(x.name_index)?.str.clone()),
+			ConstantType::String(x) => BootstrapArgument::String(constant_pool.utf8(x.utf_index)?.str.clone()),
+			ConstantType::MethodType(x) => BootstrapArgument::MethodType(constant_pool.utf8(x.descriptor_index)?.str.clone()),
+			ConstantType::MethodHandle(_) => BootstrapArgument::MethodHandle(InsnParser::parse_handle(index, constant_pool)?),
+			ConstantType::Dynamic(_) => return Ok(None),
+			x => return Err(ParserError::incomp_cp(
+				"Bootstrap Argument",
+				x,
+				index as usize
+			))
+		}))
+	}
+	
+	/// Writes the bytecode of the given code, returning it along with the pc of every label.
+	/// Jumps use 16 bit offsets unless their target turns out to be too far away, in which case the
+	/// code is laid out again with those jumps widened
 	fn write_insns(code: &CodeAttribute, constant_pool: &mut ConstantPoolWriter) -> Result<(Vec<u8>, HashMap<LabelInsn, u32>)> {
-		let mut wtr: Cursor<Vec<u8>> = Cursor::new(Vec::with_capacity(code.insns.len()));
-		
-		let mut label_pc_map: HashMap<LabelInsn, u32> = HashMap::new();
-		
-		enum ReferenceType {
-			/// 0: GOTO
-			/// 1: indexbyte_1
-			/// 2: indexbyte_2
-			/// 3: NOP
-			/// 4: NOP
-			Jump(u32),
-			/// 0: OPCODE (IF_IMPEQ, IFEQ...)
-			/// 1: indexbyte_1
-			/// 2: indexbyte_2
-			/// 3: NOP
-			/// 4: NOP
-			/// 5: NOP
-			/// 6: NOP
-			/// 7: NOP
-			Conditional(u32),
-			/// 0: indexbyte_1
-			/// 1: indexbyte_2
-			/// 2: indexbyte_3
-			/// 3: indexbyte_4
-			Direct(u32)
-		}
-		
-		let mut forward_references: HashMap<LabelInsn, Vec<ReferenceType>> = HashMap::new();
-		
-		let mut pc = 0u32;
-		for insn in code.insns.iter() {
-			match insn {
-				Insn::Label(x) => {
-					label_pc_map.insert(x.clone(), pc);
-					if let Some(refs) = forward_references.get(x) {
-						let vec_mut = wtr.get_mut();
-						for ref_t in refs.iter() {
-							match ref_t {
-								ReferenceType::Jump(at) => {
-									let i = *at as usize;
-									let offset: i32 = pc as i32 - i as i32;
-									let off_bytes = offset.to_be_bytes();
-									if off_bytes[0] == 0 && off_bytes[1] == 0 {
-										vec_mut[i + 1] = off_bytes[2];
-										vec_mut[i + 2] = off_bytes[3];
-									} else {
-										// need to replace with a GOTO_W
-										vec_mut[i] = InsnParser::GOTO_W;
-										vec_mut[i + 1] = off_bytes[0];
-										vec_mut[i + 2] = off_bytes[1];
-										vec_mut[i + 3] = off_bytes[2];
-										vec_mut[i + 4] = off_bytes[3];
-									}
-								}
-								ReferenceType::Conditional(at) => {
-									let i = *at as usize;
-									let offset_1: i32 = pc as i32 - i as i32;
-									let off_bytes = offset_1.to_be_bytes();
-									if off_bytes[0] == 0 && off_bytes[1] == 0 {
-										vec_mut[i + 1] = off_bytes[2];
-										vec_mut[i + 2] = off_bytes[3];
-									} else {
-										// need to add a a GOTO_W
-										let off_bytes_1 = 3i32.to_be_bytes();
-										vec_mut[i + 1] = off_bytes_1[2];
-										vec_mut[i + 2] = off_bytes_1[3];
-										let offset_2: i32 = pc as i32 - i as i32 - 3;
-										let off_bytes_2 = offset_2.to_be_bytes();
-										vec_mut[i + 3] = InsnParser::GOTO_W;
-										vec_mut[i + 4] = off_bytes_2[0];
-										vec_mut[i + 5] = off_bytes_2[1];
-										vec_mut[i + 6] = off_bytes_2[2];
-										vec_mut[i + 7] = off_bytes_2[3];
-									}
-								}
-								ReferenceType::Direct(at) => {
-									let i = *at as usize;
-									let offset: i32 = pc as i32 - i as i32;
-									let off_bytes = offset.to_be_bytes();
-									vec_mut[i + 0] = off_bytes[0];
-									vec_mut[i + 1] = off_bytes[1];
-									vec_mut[i + 2] = off_bytes[2];
-									vec_mut[i + 3] = off_bytes[3];
-								}
-							}
-						}
+		let mut wide_jumps: HashSet<usize> = HashSet::new();
+		// the bytes of each instruction of the previous layout, which are reused so that only the first
+		// layout touches the constant pool
+		let mut previous: Option<(Vec<u8>, Vec<InsnSpan>)> = None;
+		loop {
+			let mut wtr: Vec<u8> = Vec::with_capacity(code.insns.len() * 2);
+			let mut spans: Vec<InsnSpan> = Vec::with_capacity(code.insns.len());
+			let mut label_pc_map: HashMap<LabelInsn, u32> = HashMap::with_capacity(code.insns.labels as usize);
+			let mut jumps: Vec<JumpRef> = Vec::new();
+			
+			for (index, insn) in code.insns.iter().enumerate() {
+				let start = wtr.len();
+				let pc = start as u32;
+				match insn {
+					Insn::Label(x) => {
+						label_pc_map.insert(*x, pc);
 					}
-				}
-				Insn::ArrayLoad(x) => {
-					wtr.write_u8(match &x.kind {
-						Type::Reference(x) => InsnParser::AALOAD,
-						Type::Byte | Type::Boolean => InsnParser::BALOAD,
-						Type::Char => InsnParser::CALOAD,
-						Type::Short => InsnParser::SALOAD,
-						Type::Int => InsnParser::IALOAD,
-						Type::Long => InsnParser::LALOAD,
-						Type::Float => InsnParser::FALOAD,
-						Type::Double => InsnParser::DALOAD,
-						Type::Void => return Err(ParserError::invalid_insn(pc, "Cannot use type Void in array load"))
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::ArrayStore(x) => {
-					wtr.write_u8(match &x.kind {
-						Type::Reference(x) => InsnParser::AASTORE,
-						Type::Byte | Type::Boolean => InsnParser::BASTORE,
-						Type::Char => InsnParser::CASTORE,
-						Type::Short => InsnParser::SASTORE,
-						Type::Int => InsnParser::IASTORE,
-						Type::Long => InsnParser::LASTORE,
-						Type::Float => InsnParser::FASTORE,
-						Type::Double => InsnParser::DASTORE,
-						Type::Void => return Err(ParserError::invalid_insn(pc, "Cannot use type Void in array store"))
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Ldc(x) => {
-					pc = pc.checked_add(match &x.constant {
-						LdcType::Null => {
-							wtr.write_u8(InsnParser::ACONST_NULL)?;
-							1
-						}
-						LdcType::String(x) => InsnParser::write_ldc(&mut wtr, constant_pool.string_utf(x.clone()), false)?,
-						LdcType::Int(x) => InsnParser::write_ldc(&mut wtr, constant_pool.integer(*x), false)?,
-						LdcType::Float(x) => InsnParser::write_ldc(&mut wtr, constant_pool.float(*x), false)?,
-						LdcType::Long(x) => InsnParser::write_ldc(&mut wtr, constant_pool.long(*x), false)?,
-						LdcType::Double(x) => InsnParser::write_ldc(&mut wtr, constant_pool.double(*x), false)?,
-						LdcType::Class(x) => InsnParser::write_ldc(&mut wtr, constant_pool.class_utf8(x.clone()), false)?,
-						LdcType::MethodType(x) => InsnParser::write_ldc(&mut wtr, constant_pool.methodtype_utf8(x.clone()), false)?,
-						LdcType::MethodHandle() => return Err(ParserError::invalid_insn(pc, "MethodHandle LDC")),
-						LdcType::Dynamic() => return Err(ParserError::invalid_insn(pc, "Dynamic LDC")),
-					}).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::LocalLoad(x) => {
-					let (op0, op1, op2, op3, opx) = match &x.kind {
-						OpType::Reference => (InsnParser::ALOAD_0, InsnParser::ALOAD_1, InsnParser::ALOAD_2, InsnParser::ALOAD_3, InsnParser::ALOAD),
-						OpType::Short | OpType::Char | OpType::Byte | OpType::Boolean | OpType::Int => (InsnParser::ILOAD_0, InsnParser::ILOAD_1, InsnParser::ILOAD_2, InsnParser::ILOAD_3, InsnParser::ILOAD),
-						OpType::Float => (InsnParser::FLOAD_0, InsnParser::FLOAD_1, InsnParser::FLOAD_2, InsnParser::FLOAD_3, InsnParser::FLOAD),
-						OpType::Double => (InsnParser::DLOAD_0, InsnParser::DLOAD_1, InsnParser::DLOAD_2, InsnParser::DLOAD_3, InsnParser::DLOAD),
-						OpType::Long => (InsnParser::LLOAD_0, InsnParser::LLOAD_1, InsnParser::LLOAD_2, InsnParser::LLOAD_3, InsnParser::LLOAD),
-					};
-					match x.index {
-						0 => {
-							wtr.write_u8(op0)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						1 => {
-							wtr.write_u8(op1)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						2 => {
-							wtr.write_u8(op2)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						3 => {
-							wtr.write_u8(op3)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						index => {
-							if index <= 0xFF {
-								wtr.write_u8(opx)?;
-								wtr.write_u8(index as u8)?;
-								pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-							} else {
-								wtr.write_u8(InsnParser::WIDE)?;
-								wtr.write_u8(opx)?;
-								wtr.write_u16::<BigEndian>(index)?;
-								pc = pc.checked_add(4).ok_or_else(ParserError::too_many_instructions)?;
-							}
+					Insn::Jump(x) => {
+						if wide_jumps.contains(&index) {
+							wtr.push(InsnParser::GOTO_W);
+							jumps.push(JumpRef::new(index, pc, wtr.len(), x.jump_to, true));
+							wtr.write_i32::<BigEndian>(0)?;
+						} else {
+							wtr.push(InsnParser::GOTO);
+							jumps.push(JumpRef::new(index, pc, wtr.len(), x.jump_to, false));
+							wtr.write_i16::<BigEndian>(0)?;
 						}
 					}
-				}
-				Insn::LocalStore(x) => {
-					let (op0, op1, op2, op3, opx) = match &x.kind {
-						OpType::Reference => (InsnParser::ASTORE_0, InsnParser::ASTORE_1, InsnParser::ASTORE_2, InsnParser::ASTORE_3, InsnParser::ASTORE),
-						OpType::Boolean | OpType::Byte | OpType::Char | OpType::Short | OpType::Int => (InsnParser::ISTORE_0, InsnParser::ISTORE_1, InsnParser::ISTORE_2, InsnParser::ISTORE_3, InsnParser::ISTORE),
-						OpType::Float => (InsnParser::FSTORE_0, InsnParser::FSTORE_1, InsnParser::FSTORE_2, InsnParser::FSTORE_3, InsnParser::FSTORE),
-						OpType::Double => (InsnParser::DSTORE_0, InsnParser::DSTORE_1, InsnParser::DSTORE_2, InsnParser::DSTORE_3, InsnParser::DSTORE),
-						OpType::Long => (InsnParser::LSTORE_0, InsnParser::LSTORE_1, InsnParser::LSTORE_2, InsnParser::LSTORE_3, InsnParser::LSTORE)
-					};
-					match x.index {
-						0 => {
-							wtr.write_u8(op0)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						1 => {
-							wtr.write_u8(op1)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						2 => {
-							wtr.write_u8(op2)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						3 => {
-							wtr.write_u8(op3)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						index => {
-							if index <= 0xFF {
-								wtr.write_u8(opx)?;
-								wtr.write_u8(index as u8)?;
-								pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-							} else {
-								wtr.write_u8(InsnParser::WIDE)?;
-								wtr.write_u8(opx)?;
-								wtr.write_u16::<BigEndian>(index)?;
-								pc = pc.checked_add(4).ok_or_else(ParserError::too_many_instructions)?;
-							}
+					Insn::ConditionalJump(x) => {
+						let opcode = InsnParser::condition_opcode(x.condition);
+						if wide_jumps.contains(&index) {
+							// jump over a wide goto if the condition does not hold
+							wtr.push(InsnParser::negate_condition(opcode));
+							wtr.write_i16::<BigEndian>(8)?;
+							wtr.push(InsnParser::GOTO_W);
+							jumps.push(JumpRef::new(index, pc + 3, wtr.len(), x.jump_to, true));
+							wtr.write_i32::<BigEndian>(0)?;
+						} else {
+							wtr.push(opcode);
+							jumps.push(JumpRef::new(index, pc, wtr.len(), x.jump_to, false));
+							wtr.write_i16::<BigEndian>(0)?;
 						}
 					}
-				}
-				Insn::NewArray(x) => {
-					match &x.kind {
-						Type::Reference(x) => {
-							let cls = if let Some(cls) = x {
-								cls.clone()
-							} else {
-								// technically this should be invalid and we could throw an error
-								// but it's better to just assume the user wants an Object
-								String::from("java/lang/Object")
-							};
-							wtr.write_u8(InsnParser::ANEWARRAY)?;
-							wtr.write_u16::<BigEndian>(constant_pool.class_utf8(cls))?;
-							pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						Type::Boolean => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(4)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						Type::Byte => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(8)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						Type::Char => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(5)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						Type::Short => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(9)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						Type::Int => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(10)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						Type::Long => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(11)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						Type::Float => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(6)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
+					Insn::LookupSwitch(x) => {
+						wtr.push(InsnParser::LOOKUPSWITCH);
+						InsnParser::write_switch_padding(&mut wtr);
+						jumps.push(JumpRef::new(index, pc, wtr.len(), x.default, true));
+						wtr.write_i32::<BigEndian>(0)?;
+						wtr.write_i32::<BigEndian>(x.cases.len() as i32)?;
+						for (case, to) in x.cases.iter() {
+							wtr.write_i32::<BigEndian>(*case)?;
+							jumps.push(JumpRef::new(index, pc, wtr.len(), *to, true));
+							wtr.write_i32::<BigEndian>(0)?;
 						}
-						Type::Double => {
-							wtr.write_u8(InsnParser::NEWARRAY)?;
-							wtr.write_u8(7)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						},
-						Type::Void => return Err(ParserError::invalid_insn(pc, "Cannot use type Void in newarray"))
 					}
-				}
-				Insn::Return(x) => {
-					match &x.kind {
-						ReturnType::Void => wtr.write_u8(InsnParser::RETURN)?,
-						ReturnType::Reference => wtr.write_u8(InsnParser::ARETURN)?,
-						// boolean, byte, char and short all use the int return (same size)
-						ReturnType::Boolean => wtr.write_u8(InsnParser::IRETURN)?,
-						ReturnType::Byte => wtr.write_u8(InsnParser::IRETURN)?,
-						ReturnType::Char => wtr.write_u8(InsnParser::IRETURN)?,
-						ReturnType::Short => wtr.write_u8(InsnParser::IRETURN)?,
-						ReturnType::Int => wtr.write_u8(InsnParser::IRETURN)?,
-						ReturnType::Long => wtr.write_u8(InsnParser::LRETURN)?,
-						ReturnType::Float => wtr.write_u8(InsnParser::FRETURN)?,
-						ReturnType::Double => wtr.write_u8(InsnParser::DRETURN)?,
-					}
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::ArrayLength(x) => {
-					wtr.write_u8(InsnParser::ARRAYLENGTH)?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Throw(x) => {
-					wtr.write_u8(InsnParser::ATHROW)?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::CheckCast(x) => {
-					wtr.write_u8(InsnParser::CHECKCAST)?;
-					wtr.write_u16::<BigEndian>(constant_pool.class_utf8(x.kind.clone()))?;
-					pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Convert(x) => {
-					match &x.from {
-						PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => {
-							wtr.write_u8(match &x.to {
-								PrimitiveType::Boolean | PrimitiveType::Byte => InsnParser::I2B,
-								PrimitiveType::Char => InsnParser::I2C,
-								PrimitiveType::Short => InsnParser::I2S,
-								PrimitiveType::Int => InsnParser::NOP,
-								PrimitiveType::Long => InsnParser::I2L,
-								PrimitiveType::Float => InsnParser::I2F,
-								PrimitiveType::Double => InsnParser::I2D
-							})?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
+					Insn::TableSwitch(x) => {
+						if x.cases.is_empty() {
+							return Err(ParserError::invalid_insn(pc, "TableSwitch must have at least one case"));
 						}
-						PrimitiveType::Long => {
-							wtr.write_u8(match &x.to {
-								PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => InsnParser::L2I,
-								PrimitiveType::Long => InsnParser::NOP,
-								PrimitiveType::Float => InsnParser::L2F,
-								PrimitiveType::Double => InsnParser::L2D
-							})?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						PrimitiveType::Float => {
-							wtr.write_u8(match &x.to {
-								PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => InsnParser::F2I,
-								PrimitiveType::Long => InsnParser::F2L,
-								PrimitiveType::Float => InsnParser::NOP,
-								PrimitiveType::Double => InsnParser::F2D
-							})?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						PrimitiveType::Double => {
-							wtr.write_u8(match &x.to {
-								PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => InsnParser::D2I,
-								PrimitiveType::Long => InsnParser::D2L,
-								PrimitiveType::Float => InsnParser::D2F,
-								PrimitiveType::Double => InsnParser::NOP
-							})?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
+						wtr.push(InsnParser::TABLESWITCH);
+						InsnParser::write_switch_padding(&mut wtr);
+						jumps.push(JumpRef::new(index, pc, wtr.len(), x.default, true));
+						wtr.write_i32::<BigEndian>(0)?;
+						wtr.write_i32::<BigEndian>(x.low)?;
+						wtr.write_i32::<BigEndian>(x.low + x.cases.len() as i32 - 1)?;
+						for to in x.cases.iter() {
+							jumps.push(JumpRef::new(index, pc, wtr.len(), *to, true));
+							wtr.write_i32::<BigEndian>(0)?;
 						}
 					}
-				}
-				Insn::Add(x) => {
-					wtr.write_u8(match &x.kind {
-						PrimitiveType::Boolean => InsnParser::IADD,
-						PrimitiveType::Byte => InsnParser::IADD,
-						PrimitiveType::Char => InsnParser::IADD,
-						PrimitiveType::Short => InsnParser::IADD,
-						PrimitiveType::Int => InsnParser::IADD,
-						PrimitiveType::Long => InsnParser::LADD,
-						PrimitiveType::Float => InsnParser::FADD,
-						PrimitiveType::Double => InsnParser::DADD
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Compare(x) => {
-					match &x.kind {
-						PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int => {
-							// there's no int comparison opcode, but we can use long comparison
-							wtr.write_u8(InsnParser::I2L)?;
-							wtr.write_u8(InsnParser::LCMP)?;
-							pc = pc.checked_add(2).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						PrimitiveType::Long => {
-							wtr.write_u8(InsnParser::LCMP)?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						PrimitiveType::Float => {
-							wtr.write_u8(if x.pos_on_nan { InsnParser::FCMPG } else { InsnParser::FCMPL })?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-						}
-						PrimitiveType::Double => {
-							wtr.write_u8(if x.pos_on_nan { InsnParser::DCMPG } else { InsnParser::DCMPL })?;
-							pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
+					insn => match &previous {
+						Some((bytes, spans)) => {
+							let (from, to) = spans[index];
+							wtr.extend_from_slice(&bytes[from..to]);
 						}
+						None => InsnParser::write_insn(insn, pc, &mut wtr, constant_pool)?
 					}
 				}
-				Insn::Divide(x) => {
-					wtr.write_u8(match &x.kind {
-						PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int => InsnParser::IDIV,
-						PrimitiveType::Long => InsnParser::LDIV,
-						PrimitiveType::Float => InsnParser::FDIV,
-						PrimitiveType::Double => InsnParser::DDIV
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Multiply(x) => {
-					wtr.write_u8(match &x.kind {
-						PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int => InsnParser::IMUL,
-						PrimitiveType::Long => InsnParser::LMUL,
-						PrimitiveType::Float => InsnParser::FMUL,
-						PrimitiveType::Double => InsnParser::DMUL
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Negate(x) => {
-					wtr.write_u8(match &x.kind {
-						PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int => InsnParser::INEG,
-						PrimitiveType::Long => InsnParser::LNEG,
-						PrimitiveType::Float => InsnParser::FNEG,
-						PrimitiveType::Double => InsnParser::DNEG
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Remainder(x) => {
-					wtr.write_u8(match &x.kind {
-						PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int => InsnParser::IREM,
-						PrimitiveType::Long => InsnParser::LREM,
-						PrimitiveType::Float => InsnParser::FREM,
-						PrimitiveType::Double => InsnParser::DREM
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Subtract(x) => {
-					wtr.write_u8(match &x.kind {
-						PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int => InsnParser::ISUB,
-						PrimitiveType::Long => InsnParser::LSUB,
-						PrimitiveType::Float => InsnParser::FSUB,
-						PrimitiveType::Double => InsnParser::DSUB
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::And(x) => {
-					wtr.write_u8(match &x.kind {
-						IntegerType::Int => InsnParser::IAND,
-						IntegerType::Long => InsnParser::LAND
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Or(x) => {
-					wtr.write_u8(match &x.kind {
-						IntegerType::Int => InsnParser::IOR,
-						IntegerType::Long => InsnParser::LOR
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Xor(x) => {
-					wtr.write_u8(match &x.kind {
-						IntegerType::Int => InsnParser::IXOR,
-						IntegerType::Long => InsnParser::LXOR
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::ShiftLeft(x) => {
-					wtr.write_u8(match &x.kind {
-						IntegerType::Int => InsnParser::ISHL,
-						IntegerType::Long => InsnParser::LSHL
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::ShiftRight(x) => {
-					wtr.write_u8(match &x.kind {
-						IntegerType::Int => InsnParser::ISHR,
-						IntegerType::Long => InsnParser::LSHR
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::LogicalShiftRight(x) => {
-					wtr.write_u8(match &x.kind {
-						IntegerType::Int => InsnParser::IUSHR,
-						IntegerType::Long => InsnParser::LUSHR
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Dup(x) => {
-					wtr.write_u8(match x.num {
-						1 => {
-							match x.down {
-								0 => InsnParser::DUP,
-								1 => InsnParser::DUP_X1,
-								2 => InsnParser::DUP_X2,
-								_ => return Err(ParserError::invalid_insn(pc, "DupInsn::down must not be larger than 2"))
-							}
-						}
-						2 => {
-							match x.down {
-								0 => InsnParser::DUP2,
-								1 => InsnParser::DUP2_X1,
-								2 => InsnParser::DUP2_X2,
-								_ => return Err(ParserError::invalid_insn(pc, "DupInsn::down must not be larger than 2"))
-							}
-						}
-						_ => return Err(ParserError::invalid_insn(pc, "DupInsn::num must be in the range 1-2"))
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::Pop(x) => {
-					wtr.write_u8(match x.pop_two {
-						false => InsnParser::POP,
-						true => InsnParser::POP2,
-					})?;
-					pc = pc.checked_add(1).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::GetField(x) => {
-					wtr.write_u8(if x.instance { InsnParser::GETFIELD } else { InsnParser::GETSTATIC })?;
-					let class_ref = constant_pool.class_utf8(x.class.clone());
-					let name_ref = constant_pool.utf8(x.name.clone());
-					let desc_ref = constant_pool.utf8(x.descriptor.clone());
-					let nametype_ref = constant_pool.nameandtype(name_ref, desc_ref);
-					wtr.write_u16::<BigEndian>(constant_pool.fieldref(class_ref, nametype_ref))?;
-					pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
+				spans.push((start, wtr.len()));
+			}
+			
+			// code_length must be less than 65536
+			if wtr.len() > u16::MAX as usize {
+				return Err(ParserError::too_many_instructions());
+			}
+			
+			let mut widened = false;
+			for jump in jumps.iter() {
+				let to = *label_pc_map.get(&jump.to).ok_or_else(ParserError::unmapped_label)?;
+				let offset = to as i32 - jump.from as i32;
+				if jump.wide {
+					wtr[jump.at..jump.at + 4].copy_from_slice(&offset.to_be_bytes());
+				} else if let Ok(offset) = i16::try_from(offset) {
+					wtr[jump.at..jump.at + 2].copy_from_slice(&offset.to_be_bytes());
+				} else {
+					wide_jumps.insert(jump.insn);
+					widened = true;
 				}
-				Insn::PutField(x) => {
-					wtr.write_u8(if x.instance { InsnParser::PUTFIELD } else { InsnParser::PUTSTATIC })?;
-					let class_ref = constant_pool.class_utf8(x.class.clone());
-					let name_ref = constant_pool.utf8(x.name.clone());
-					let desc_ref = constant_pool.utf8(x.descriptor.clone());
-					let nametype_ref = constant_pool.nameandtype(name_ref, desc_ref);
-					wtr.write_u16::<BigEndian>(constant_pool.fieldref(class_ref, nametype_ref))?;
-					pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
+			}
+			if !widened {
+				InsnParser::check_widened_frames(code, &wide_jumps, &spans, &label_pc_map)?;
+				return Ok((wtr, label_pc_map));
+			}
+			previous = Some((wtr, spans));
+		}
+	}
+	
+	/// A widened conditional jump branches over its wide goto to the next instruction, which the
+	/// stack map table then needs a frame for. That frame can't be worked out here, so code with a
+	/// stack map table is rejected unless the next instruction already has one
+	fn check_widened_frames(code: &CodeAttribute, wide_jumps: &HashSet<usize>, spans: &[InsnSpan], label_pc_map: &HashMap<LabelInsn, u32>) -> Result<()> {
+		let mut frame_pcs: Option<HashSet<u32>> = None;
+		for attr in code.attributes.iter() {
+			if let Attribute::StackMapTable(table) = attr {
+				let pcs = frame_pcs.get_or_insert_with(HashSet::new);
+				for frame in table.frames.iter() {
+					pcs.insert(*label_pc_map.get(&frame.start()).ok_or_else(ParserError::unmapped_label)?);
 				}
-				Insn::Jump(x) => {
-					if let Some(to) = label_pc_map.get(&x.jump_to) {
-						let offset: i32 = pc as i32 - (*to) as i32;
-						let off_bytes = offset.to_be_bytes();
-						// backwards reference
-						if off_bytes[0] == 0 && off_bytes[1] == 0 {
-							wtr.write_u8(InsnParser::GOTO)?;
-							wtr.write_i16::<BigEndian>(offset as i16)?;
-							pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
-						} else {
-							wtr.write_u8(InsnParser::GOTO_W)?;
-							wtr.write_i32::<BigEndian>(offset)?;
-							pc = pc.checked_add(5).ok_or_else(ParserError::too_many_instructions)?;
-						}
-					} else {
-						if let Some(vec) = forward_references.get_mut(&x.jump_to) {
-							vec.push(ReferenceType::Jump(pc));
-						} else {
-							let mut vec = Vec::new();
-							vec.push(ReferenceType::Jump(pc));
-							forward_references.insert(x.jump_to.clone(), vec);
-						}
-						wtr.write_u8(InsnParser::GOTO)?;
-						wtr.write_u16::<BigEndian>(0)?;
-						wtr.write_u8(InsnParser::NOP)?;
-						wtr.write_u8(InsnParser::NOP)?;
-						pc = pc.checked_add(8).ok_or_else(ParserError::too_many_instructions)?;
-					}
+			}
+		}
+		let frame_pcs = match frame_pcs {
+			Some(x) => x,
+			None => return Ok(())
+		};
+		for index in wide_jumps.iter() {
+			if let Insn::ConditionalJump(_) = code.insns.insns[*index] {
+				let (start, end) = spans[*index];
+				if !frame_pcs.contains(&(end as u32)) {
+					return Err(ParserError::invalid_insn(start as u32, "Widened conditional jump needs a stack map frame at the instruction after it"));
 				}
-				Insn::ConditionalJump(x) => {
-					let opcode = match x.condition {
-						JumpCondition::IsNull => InsnParser::IFNULL,
-						JumpCondition::NotNull => InsnParser::IFNONNULL,
-						JumpCondition::ReferencesEqual => InsnParser::IF_ACMPEQ,
-						JumpCondition::ReferencesNotEqual => InsnParser::IF_ACMPNE,
-						JumpCondition::IntsEq => InsnParser::IF_ICMPEQ,
-						JumpCondition::IntsNotEq => InsnParser::IF_ICMPNE,
-						JumpCondition::IntsLessThan => InsnParser::IF_ICMPLT,
-						JumpCondition::IntsLessThanOrEq => InsnParser::IF_ICMPLE,
-						JumpCondition::IntsGreaterThan => InsnParser::IF_ICMPGT,
-						JumpCondition::IntsGreaterThanOrEq => InsnParser::IF_ICMPGE,
-						JumpCondition::IntEqZero => InsnParser::IFEQ,
-						JumpCondition::IntNotEqZero => InsnParser::IFNE,
-						JumpCondition::IntLessThanZero => InsnParser::IFLT,
-						JumpCondition::IntLessThanOrEqZero => InsnParser::IFLE,
-						JumpCondition::IntGreaterThanZero => InsnParser::IFGT,
-						JumpCondition::IntGreaterThanOrEqZero => InsnParser::IFGE
-					};
-					
-					if let Some(to) = label_pc_map.get(&x.jump_to) {
-						let offset: i32 = pc as i32 - (*to) as i32;
-						let off_bytes = offset.to_be_bytes();
-						// backwards reference
-						if off_bytes[0] == 0 && off_bytes[1] == 0 {
-							wtr.write_u8(opcode)?;
-							wtr.write_i16::<BigEndian>(offset as i16)?;
-							pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
-						} else {
-							wtr.write_u8(opcode)?;
-							wtr.write_u16::<BigEndian>(3)?;
-							wtr.write_u8(InsnParser::GOTO_W)?;
-							wtr.write_i32::<BigEndian>(offset - 3)?;
-							pc = pc.checked_add(8).ok_or_else(ParserError::too_many_instructions)?;
-						}
-					} else {
-						if let Some(vec) = forward_references.get_mut(&x.jump_to) {
-							vec.push(ReferenceType::Conditional(pc));
+			}
+		}
+		Ok(())
+	}
+	
+	/// Pads a switch so that its operands start at a multiple of 4 bytes into the code
+	fn write_switch_padding(wtr: &mut Vec<u8>) {
+		let padding = (4 - wtr.len() % 4) % 4;
+		wtr.resize(wtr.len() + padding, 0);
+	}
+	
+	fn condition_opcode(condition: JumpCondition) -> u8 {
+		match condition {
+			JumpCondition::IsNull => InsnParser::IFNULL,
+			JumpCondition::NotNull => InsnParser::IFNONNULL,
+			JumpCondition::ReferencesEqual => InsnParser::IF_ACMPEQ,
+			JumpCondition::ReferencesNotEqual => InsnParser::IF_ACMPNE,
+			JumpCondition::IntsEq => InsnParser::IF_ICMPEQ,
+			JumpCondition::IntsNotEq => InsnParser::IF_ICMPNE,
+			JumpCondition::IntsLessThan => InsnParser::IF_ICMPLT,
+			JumpCondition::IntsLessThanOrEq => InsnParser::IF_ICMPLE,
+			JumpCondition::IntsGreaterThan => InsnParser::IF_ICMPGT,
+			JumpCondition::IntsGreaterThanOrEq => InsnParser::IF_ICMPGE,
+			JumpCondition::IntEqZero => InsnParser::IFEQ,
+			JumpCondition::IntNotEqZero => InsnParser::IFNE,
+			JumpCondition::IntLessThanZero => InsnParser::IFLT,
+			JumpCondition::IntLessThanOrEqZero => InsnParser::IFLE,
+			JumpCondition::IntGreaterThanZero => InsnParser::IFGT,
+			JumpCondition::IntGreaterThanOrEqZero => InsnParser::IFGE
+		}
+	}
+	
+	/// The conditional jump opcode that jumps exactly when the given one does not
+	fn negate_condition(opcode: u8) -> u8 {
+		match opcode {
+			InsnParser::IFNULL => InsnParser::IFNONNULL,
+			InsnParser::IFNONNULL => InsnParser::IFNULL,
+			InsnParser::IF_ACMPEQ => InsnParser::IF_ACMPNE,
+			InsnParser::IF_ACMPNE => InsnParser::IF_ACMPEQ,
+			InsnParser::IF_ICMPEQ => InsnParser::IF_ICMPNE,
+			InsnParser::IF_ICMPNE => InsnParser::IF_ICMPEQ,
+			InsnParser::IF_ICMPLT => InsnParser::IF_ICMPGE,
+			InsnParser::IF_ICMPGE => InsnParser::IF_ICMPLT,
+			InsnParser::IF_ICMPGT => InsnParser::IF_ICMPLE,
+			InsnParser::IF_ICMPLE => InsnParser::IF_ICMPGT,
+			InsnParser::IFEQ => InsnParser::IFNE,
+			InsnParser::IFNE => InsnParser::IFEQ,
+			InsnParser::IFLT => InsnParser::IFGE,
+			InsnParser::IFGE => InsnParser::IFLT,
+			InsnParser::IFGT => InsnParser::IFLE,
+			InsnParser::IFLE => InsnParser::IFGT,
+			x => x
+		}
+	}
+	
+	/// Writes any instruction that does not refer to a label
+	fn write_insn(insn: &Insn, pc: u32, wtr: &mut Vec<u8>, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
+		match insn {
+			Insn::ArrayLoad(x) => {
+				wtr.push(match &x.kind {
+					Type::Reference(_) => InsnParser::AALOAD,
+					Type::Byte | Type::Boolean => InsnParser::BALOAD,
+					Type::Char => InsnParser::CALOAD,
+					Type::Short => InsnParser::SALOAD,
+					Type::Int => InsnParser::IALOAD,
+					Type::Long => InsnParser::LALOAD,
+					Type::Float => InsnParser::FALOAD,
+					Type::Double => InsnParser::DALOAD,
+					Type::Void => return Err(ParserError::invalid_insn(pc, "Cannot use type Void in array load"))
+				});
+			}
+			Insn::ArrayStore(x) => {
+				wtr.push(match &x.kind {
+					Type::Reference(_) => InsnParser::AASTORE,
+					Type::Byte | Type::Boolean => InsnParser::BASTORE,
+					Type::Char => InsnParser::CASTORE,
+					Type::Short => InsnParser::SASTORE,
+					Type::Int => InsnParser::IASTORE,
+					Type::Long => InsnParser::LASTORE,
+					Type::Float => InsnParser::FASTORE,
+					Type::Double => InsnParser::DASTORE,
+					Type::Void => return Err(ParserError::invalid_insn(pc, "Cannot use type Void in array store"))
+				});
+			}
+			Insn::Ldc(x) => {
+				match &x.constant {
+					LdcType::Null => wtr.push(InsnParser::ACONST_NULL),
+					LdcType::Int(x) => match *x {
+						-1 => wtr.push(InsnParser::ICONST_M1),
+						0 => wtr.push(InsnParser::ICONST_0),
+						1 => wtr.push(InsnParser::ICONST_1),
+						2 => wtr.push(InsnParser::ICONST_2),
+						3 => wtr.push(InsnParser::ICONST_3),
+						4 => wtr.push(InsnParser::ICONST_4),
+						5 => wtr.push(InsnParser::ICONST_5),
+						x => if let Ok(byte) = i8::try_from(x) {
+							wtr.push(InsnParser::BIPUSH);
+							wtr.write_i8(byte)?;
+						} else if let Ok(short) = i16::try_from(x) {
+							wtr.push(InsnParser::SIPUSH);
+							wtr.write_i16::<BigEndian>(short)?;
 						} else {
-							let mut vec = Vec::new();
-							vec.push(ReferenceType::Conditional(pc));
-							forward_references.insert(x.jump_to.clone(), vec);
-						}
-						wtr.write_u8(opcode)?;
-						wtr.write_u16::<BigEndian>(0)?;
-						wtr.write_u8(InsnParser::NOP)?;
-						wtr.write_u8(InsnParser::NOP)?;
-						wtr.write_u8(InsnParser::NOP)?;
-						wtr.write_u8(InsnParser::NOP)?;
-						wtr.write_u8(InsnParser::NOP)?;
-						pc = pc.checked_add(8).ok_or_else(ParserError::too_many_instructions)?;
-					}
-				}
-				Insn::IncrementInt(x) => {
-					let index = x.index;
-					let amount = x.amount;
-					// need to check if we can fit the amount into 1 byte
-					if let (Ok(index), Ok(amount)) = (u8::try_from(index), i8::try_from(amount)) {
-						wtr.write_u8(InsnParser::IINC)?;
-						wtr.write_u8(index)?;
-						wtr.write_i8(amount)?;
-						pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
-					} else {
-						wtr.write_u8(InsnParser::WIDE)?;
-						wtr.write_u8(InsnParser::IINC)?;
-						wtr.write_u16::<BigEndian>(index)?;
-						wtr.write_i16::<BigEndian>(amount)?;
-						pc = pc.checked_add(6).ok_or_else(ParserError::too_many_instructions)?;
-					}
-				}
-				Insn::InstanceOf(x) => {
-					wtr.write_u8(InsnParser::INSTANCEOF)?;
-					wtr.write_u16::<BigEndian>(constant_pool.class_utf8(x.class.clone()))?;
-					pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
-				}
-				Insn::InvokeDynamic(x) => {
-					return Err(ParserError::unimplemented("Invokedynamic writing unimplemented"));
-				}
-				Insn::Invoke(x) => {
-					let opcode = match x.kind {
-						InvokeType::Instance => InsnParser::INVOKEVIRTUAL,
-						InvokeType::Static => InsnParser::INVOKESTATIC,
-						InvokeType::Interface => InsnParser::INVOKEINTERFACE,
-						InvokeType::Special => InsnParser::INVOKESPECIAL
-					};
-					wtr.write_u8(opcode)?;
-					if opcode == InsnParser::INVOKEINTERFACE {
-						let class = constant_pool.class_utf8(x.class.clone());
-						let name = constant_pool.utf8(x.name.clone());
-						let desc = constant_pool.utf8(x.descriptor.clone());
-						let nandt = constant_pool.nameandtype(name, desc);
-						wtr.write_u16::<BigEndian>(constant_pool.interfacemethodref(class, nandt))?;
-						// The count operand of an invokeinterface instruction is valid if it is
-						// the difference between the size of the operand stack before and after the instruction
-						// executes.
-						let mut count = 1; // interface methods are virtual so there is always at least one
-						let (args, _) = parse_method_desc(&x.descriptor)?;
-						for arg in args.iter() {
-							count += arg.size();
+							InsnParser::write_ldc(wtr, constant_pool.integer(x)?, false)?;
 						}
-						wtr.write_u8(count)?;
-						wtr.write_u8(0)?;
-						pc = pc.checked_add(5).ok_or_else(ParserError::too_many_instructions)?;
-					} else {
-						let class = constant_pool.class_utf8(x.class.clone());
-						let name = constant_pool.utf8(x.name.clone());
-						let desc = constant_pool.utf8(x.descriptor.clone());
-						let nandt = constant_pool.nameandtype(name, desc);
-						wtr.write_u16::<BigEndian>(constant_pool.methodref(class, nandt))?;
-						pc = pc.checked_add(3).ok_or_else(ParserError::too_many_instructions)?;
-					}
+					},
+					// compare bits so that -0.0 is not written as 0.0
+					LdcType::Float(x) if x.to_bits() == 0f32.to_bits() => wtr.push(InsnParser::FCONST_0),
+					LdcType::Float(x) if x.to_bits() == 1f32.to_bits() => wtr.push(InsnParser::FCONST_1),
+					LdcType::Float(x) if x.to_bits() == 2f32.to_bits() => wtr.push(InsnParser::FCONST_2),
+					LdcType::Float(x) => InsnParser::write_ldc(wtr, constant_pool.float(*x)?, false)?,
+					LdcType::Long(0) => wtr.push(InsnParser::LCONST_0),
+					LdcType::Long(1) => wtr.push(InsnParser::LCONST_1),
+					LdcType::Long(x) => InsnParser::write_ldc(wtr, constant_pool.long(*x)?, true)?,
+					LdcType::Double(x) if x.to_bits() == 0f64.to_bits() => wtr.push(InsnParser::DCONST_0),
+					LdcType::Double(x) if x.to_bits() == 1f64.to_bits() => wtr.push(InsnParser::DCONST_1),
+					LdcType::Double(x) => InsnParser::write_ldc(wtr, constant_pool.double(*x)?, true)?,
+					LdcType::String(x) => InsnParser::write_ldc(wtr, constant_pool.string_utf(x)?, false)?,
+					LdcType::Class(x) => InsnParser::write_ldc(wtr, constant_pool.class_utf8(x)?, false)?,
+					LdcType::MethodType(x) => InsnParser::write_ldc(wtr, constant_pool.methodtype_utf8(x)?, false)?,
+					LdcType::MethodHandle() => return Err(ParserError::invalid_insn(pc, "MethodHandle LDC")),
+					LdcType::Dynamic() => return Err(ParserError::invalid_insn(pc, "Dynamic LDC")),
 				}
-				Insn::LookupSwitch(x) => {
-					wtr.write_u8(InsnParser::LOOKUPSWITCH)?;
-					let pad = (4 - (pc % 4)) % 4;
-					for i in 0..pad {
-						wtr.write_u8(0)?;
-					}
-					
-					if let Some(at) = label_pc_map.get(&x.default) {
-						let offset: i32 = pc as i32 - (*at) as i32;
-						wtr.write_i32::<BigEndian>(offset)?;
-					} else {
-						if let Some(vec) = forward_references.get_mut(&x.default) {
-							vec.push(ReferenceType::Direct(pc + 2));
-						} else {
-							let mut vec = Vec::new();
-							vec.push(ReferenceType::Direct(pc + 2));
-							forward_references.insert(x.default.clone(), vec);
-						}
-						wtr.write_i32::<BigEndian>(0)?;
+			}
+			Insn::LocalLoad(x) => {
+				let ops = match &x.kind {
+					OpType::Reference => [InsnParser::ALOAD_0, InsnParser::ALOAD_1, InsnParser::ALOAD_2, InsnParser::ALOAD_3, InsnParser::ALOAD],
+					OpType::Short | OpType::Char | OpType::Byte | OpType::Boolean | OpType::Int => [InsnParser::ILOAD_0, InsnParser::ILOAD_1, InsnParser::ILOAD_2, InsnParser::ILOAD_3, InsnParser::ILOAD],
+					OpType::Float => [InsnParser::FLOAD_0, InsnParser::FLOAD_1, InsnParser::FLOAD_2, InsnParser::FLOAD_3, InsnParser::FLOAD],
+					OpType::Double => [InsnParser::DLOAD_0, InsnParser::DLOAD_1, InsnParser::DLOAD_2, InsnParser::DLOAD_3, InsnParser::DLOAD],
+					OpType::Long => [InsnParser::LLOAD_0, InsnParser::LLOAD_1, InsnParser::LLOAD_2, InsnParser::LLOAD_3, InsnParser::LLOAD],
+				};
+				InsnParser::write_local(wtr, ops, x.index)?;
+			}
+			Insn::LocalStore(x) => {
+				let ops = match &x.kind {
+					OpType::Reference => [InsnParser::ASTORE_0, InsnParser::ASTORE_1, InsnParser::ASTORE_2, InsnParser::ASTORE_3, InsnParser::ASTORE],
+					OpType::Boolean | OpType::Byte | OpType::Char | OpType::Short | OpType::Int => [InsnParser::ISTORE_0, InsnParser::ISTORE_1, InsnParser::ISTORE_2, InsnParser::ISTORE_3, InsnParser::ISTORE],
+					OpType::Float => [InsnParser::FSTORE_0, InsnParser::FSTORE_1, InsnParser::FSTORE_2, InsnParser::FSTORE_3, InsnParser::FSTORE],
+					OpType::Double => [InsnParser::DSTORE_0, InsnParser::DSTORE_1, InsnParser::DSTORE_2, InsnParser::DSTORE_3, InsnParser::DSTORE],
+					OpType::Long => [InsnParser::LSTORE_0, InsnParser::LSTORE_1, InsnParser::LSTORE_2, InsnParser::LSTORE_3, InsnParser::LSTORE]
+				};
+				InsnParser::write_local(wtr, ops, x.index)?;
+			}
+			Insn::NewArray(x) => {
+				let atype = match &x.kind {
+					Type::Reference(x) => {
+						// technically this should be invalid and we could throw an error
+						// but it's better to just assume the user wants an Object
+						let cls = x.as_deref().unwrap_or("java/lang/Object");
+						wtr.push(InsnParser::ANEWARRAY);
+						wtr.write_u16::<BigEndian>(constant_pool.class_utf8(cls)?)?;
+						return Ok(());
 					}
-					
-					wtr.write_i32::<BigEndian>(x.cases.len() as i32)?;
-					
-					pc = pc.checked_add(10).ok_or_else(ParserError::too_many_instructions)?;
-					
-					for (case, to) in x.cases.iter() {
-						wtr.write_i32::<BigEndian>(*case)?;
-						if let Some(at) = label_pc_map.get(to) {
-							let offset: i32 = (pc + 4) as i32 - (*at) as i32;
-							wtr.write_i32::<BigEndian>(offset)?;
-						} else {
-							if let Some(vec) = forward_references.get_mut(to) {
-								vec.push(ReferenceType::Direct(pc + 4));
-							} else {
-								let mut vec = Vec::new();
-								vec.push(ReferenceType::Direct(pc + 4));
-								forward_references.insert(to.clone(), vec);
-							}
-							wtr.write_i32::<BigEndian>(0)?;
-						}
-						pc = pc.checked_add(8).ok_or_else(ParserError::too_many_instructions)?;
+					Type::Boolean => 4,
+					Type::Char => 5,
+					Type::Float => 6,
+					Type::Double => 7,
+					Type::Byte => 8,
+					Type::Short => 9,
+					Type::Int => 10,
+					Type::Long => 11,
+					Type::Void => return Err(ParserError::invalid_insn(pc, "Cannot use type Void in newarray"))
+				};
+				wtr.push(InsnParser::NEWARRAY);
+				wtr.push(atype);
+			}
+			Insn::Return(x) => {
+				wtr.push(match &x.kind {
+					ReturnType::Void => InsnParser::RETURN,
+					ReturnType::Reference => InsnParser::ARETURN,
+					// boolean, byte, char and short all use the int return (same size)
+					ReturnType::Boolean | ReturnType::Byte | ReturnType::Char | ReturnType::Short | ReturnType::Int => InsnParser::IRETURN,
+					ReturnType::Long => InsnParser::LRETURN,
+					ReturnType::Float => InsnParser::FRETURN,
+					ReturnType::Double => InsnParser::DRETURN,
+				});
+			}
+			Insn::ArrayLength(_) => wtr.push(InsnParser::ARRAYLENGTH),
+			Insn::Throw(_) => wtr.push(InsnParser::ATHROW),
+			Insn::CheckCast(x) => {
+				wtr.push(InsnParser::CHECKCAST);
+				wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&x.kind)?)?;
+			}
+			Insn::Convert(x) => {
+				wtr.push(match &x.from {
+					PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => match &x.to {
+						PrimitiveType::Boolean | PrimitiveType::Byte => InsnParser::I2B,
+						PrimitiveType::Char => InsnParser::I2C,
+						PrimitiveType::Short => InsnParser::I2S,
+						PrimitiveType::Int => InsnParser::NOP,
+						PrimitiveType::Long => InsnParser::I2L,
+						PrimitiveType::Float => InsnParser::I2F,
+						PrimitiveType::Double => InsnParser::I2D
+					},
+					PrimitiveType::Long => match &x.to {
+						PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => InsnParser::L2I,
+						PrimitiveType::Long => InsnParser::NOP,
+						PrimitiveType::Float => InsnParser::L2F,
+						PrimitiveType::Double => InsnParser::L2D
+					},
+					PrimitiveType::Float => match &x.to {
+						PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => InsnParser::F2I,
+						PrimitiveType::Long => InsnParser::F2L,
+						PrimitiveType::Float => InsnParser::NOP,
+						PrimitiveType::Double => InsnParser::F2D
+					},
+					PrimitiveType::Double => match &x.to {
+						PrimitiveType::Short | PrimitiveType::Char | PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Int => InsnParser::D2I,
+						PrimitiveType::Long => InsnParser::D2L,
+						PrimitiveType::Float => InsnParser::D2F,
+						PrimitiveType::Double => InsnParser::NOP
 					}
+				});
+			}
+			Insn::Add(x) => wtr.push(InsnParser::typed_opcode(x.kind, [InsnParser::IADD, InsnParser::LADD, InsnParser::FADD, InsnParser::DADD])),
+			Insn::Compare(x) => match &x.kind {
+				// there's no int comparison opcode, so both ints are widened and compared as longs. The
+				// first is widened beneath the second, which leaves them swapped
+				PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int =>
+					wtr.extend_from_slice(&[InsnParser::I2L, InsnParser::DUP2_X1, InsnParser::POP2, InsnParser::I2L, InsnParser::LCMP, InsnParser::INEG]),
+				PrimitiveType::Long => wtr.push(InsnParser::LCMP),
+				PrimitiveType::Float => wtr.push(if x.pos_on_nan { InsnParser::FCMPG } else { InsnParser::FCMPL }),
+				PrimitiveType::Double => wtr.push(if x.pos_on_nan { InsnParser::DCMPG } else { InsnParser::DCMPL })
+			},
+			Insn::Divide(x) => wtr.push(InsnParser::typed_opcode(x.kind, [InsnParser::IDIV, InsnParser::LDIV, InsnParser::FDIV, InsnParser::DDIV])),
+			Insn::Multiply(x) => wtr.push(InsnParser::typed_opcode(x.kind, [InsnParser::IMUL, InsnParser::LMUL, InsnParser::FMUL, InsnParser::DMUL])),
+			Insn::Negate(x) => wtr.push(InsnParser::typed_opcode(x.kind, [InsnParser::INEG, InsnParser::LNEG, InsnParser::FNEG, InsnParser::DNEG])),
+			Insn::Remainder(x) => wtr.push(InsnParser::typed_opcode(x.kind, [InsnParser::IREM, InsnParser::LREM, InsnParser::FREM, InsnParser::DREM])),
+			Insn::Subtract(x) => wtr.push(InsnParser::typed_opcode(x.kind, [InsnParser::ISUB, InsnParser::LSUB, InsnParser::FSUB, InsnParser::DSUB])),
+			Insn::And(x) => wtr.push(if x.kind == IntegerType::Int { InsnParser::IAND } else { InsnParser::LAND }),
+			Insn::Or(x) => wtr.push(if x.kind == IntegerType::Int { InsnParser::IOR } else { InsnParser::LOR }),
+			Insn::Xor(x) => wtr.push(if x.kind == IntegerType::Int { InsnParser::IXOR } else { InsnParser::LXOR }),
+			Insn::ShiftLeft(x) => wtr.push(if x.kind == IntegerType::Int { InsnParser::ISHL } else { InsnParser::LSHL }),
+			Insn::ShiftRight(x) => wtr.push(if x.kind == IntegerType::Int { InsnParser::ISHR } else { InsnParser::LSHR }),
+			Insn::LogicalShiftRight(x) => wtr.push(if x.kind == IntegerType::Int { InsnParser::IUSHR } else { InsnParser::LUSHR }),
+			Insn::Dup(x) => {
+				wtr.push(match (x.num, x.down) {
+					(1, 0) => InsnParser::DUP,
+					(1, 1) => InsnParser::DUP_X1,
+					(1, 2) => InsnParser::DUP_X2,
+					(2, 0) => InsnParser::DUP2,
+					(2, 1) => InsnParser::DUP2_X1,
+					(2, 2) => InsnParser::DUP2_X2,
+					(1, _) | (2, _) => return Err(ParserError::invalid_insn(pc, "DupInsn::down must not be larger than 2")),
+					_ => return Err(ParserError::invalid_insn(pc, "DupInsn::num must be in the range 1-2"))
+				});
+			}
+			Insn::Pop(x) => wtr.push(if x.pop_two { InsnParser::POP2 } else { InsnParser::POP }),
+			Insn::GetField(x) => {
+				wtr.push(if x.instance { InsnParser::GETFIELD } else { InsnParser::GETSTATIC });
+				wtr.write_u16::<BigEndian>(constant_pool.fieldref_utf8(&x.class, &x.name, &x.descriptor)?)?;
+			}
+			Insn::PutField(x) => {
+				wtr.push(if x.instance { InsnParser::PUTFIELD } else { InsnParser::PUTSTATIC });
+				wtr.write_u16::<BigEndian>(constant_pool.fieldref_utf8(&x.class, &x.name, &x.descriptor)?)?;
+			}
+			Insn::IncrementInt(x) => {
+				// need to check if we can fit the index and amount into 1 byte each
+				if let (Ok(index), Ok(amount)) = (u8::try_from(x.index), i8::try_from(x.amount)) {
+					wtr.push(InsnParser::IINC);
+					wtr.push(index);
+					wtr.write_i8(amount)?;
+				} else {
+					wtr.push(InsnParser::WIDE);
+					wtr.push(InsnParser::IINC);
+					wtr.write_u16::<BigEndian>(x.index)?;
+					wtr.write_i16::<BigEndian>(x.amount)?;
 				}
-				Insn::TableSwitch(x) => {
-					wtr.write_u8(InsnParser::TABLESWITCH)?;
-					let pad = (4 - (pc % 4)) % 4;
-					for i in 0..pad {
-						wtr.write_u8(0)?;
+			}
+			Insn::InstanceOf(x) => {
+				wtr.push(InsnParser::INSTANCEOF);
+				wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&x.class)?)?;
+			}
+			Insn::InvokeDynamic(x) => {
+				// code parsed outside of its class cannot see the bootstrap methods it refers to
+				if x.bootstrap_handle.owner == "Unimplemented" {
+					return Err(ParserError::unimplemented("Writing unresolved invokedynamic"));
+				}
+				let bootstrap = constant_pool.bootstrap_method(&BootstrapMethod::new(x.bootstrap_handle.clone(), x.bootstrap_arguments.clone()))?;
+				wtr.push(InsnParser::INVOKEDYNAMIC);
+				wtr.write_u16::<BigEndian>(constant_pool.invokedynamic_utf8(bootstrap, &x.name, &x.descriptor)?)?;
+				wtr.write_u16::<BigEndian>(0)?;
+			}
+			Insn::Invoke(x) => {
+				// invokevirtual can only refer to class methods, so instance calls of interface methods are invokeinterface
+				let interface = x.kind == InvokeType::Interface || (x.kind == InvokeType::Instance && x.interface_method);
+				let opcode = match x.kind {
+					_ if interface => InsnParser::INVOKEINTERFACE,
+					InvokeType::Instance | InvokeType::Interface => InsnParser::INVOKEVIRTUAL,
+					InvokeType::Static => InsnParser::INVOKESTATIC,
+					InvokeType::Special => InsnParser::INVOKESPECIAL
+				};
+				let method = constant_pool.methodref_utf8(&x.class, &x.name, &x.descriptor, interface || x.interface_method)?;
+				wtr.push(opcode);
+				wtr.write_u16::<BigEndian>(method)?;
+				if interface {
+					// The count operand of an invokeinterface instruction is valid if it is
+					// the difference between the size of the operand stack before and after the instruction
+					// executes.
+					let mut count = 1; // interface methods are virtual so there is always at least one
+					let (args, _) = parse_method_desc(&x.descriptor)?;
+					for arg in args.iter() {
+						count += arg.size();
 					}
+					wtr.push(count);
+					wtr.push(0);
 				}
-				Insn::MonitorEnter(_) => {}
-				Insn::MonitorExit(_) => {}
-				Insn::MultiNewArray(_) => {}
-				Insn::NewObject(_) => {}
-				Insn::Nop(_) => {}
-				Insn::Swap(_) => {}
-				Insn::ImpDep1(_) => {}
-				Insn::ImpDep2(_) => {}
-				Insn::BreakPoint(_) => {}
+			}
+			Insn::MonitorEnter(_) => wtr.push(InsnParser::MONITORENTER),
+			Insn::MonitorExit(_) => wtr.push(InsnParser::MONITOREXIT),
+			Insn::MultiNewArray(x) => {
+				wtr.push(InsnParser::MULTIANEWARRAY);
+				wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&x.kind)?)?;
+				wtr.push(x.dimensions);
+			}
+			Insn::NewObject(x) => {
+				wtr.push(InsnParser::NEW);
+				wtr.write_u16::<BigEndian>(constant_pool.class_utf8(&x.kind)?)?;
+			}
+			Insn::Nop(_) => wtr.push(InsnParser::NOP),
+			Insn::Swap(_) => wtr.push(InsnParser::SWAP),
+			Insn::ImpDep1(_) => wtr.push(InsnParser::IMPDEP1),
+			Insn::ImpDep2(_) => wtr.push(InsnParser::IMPDEP2),
+			Insn::BreakPoint(_) => wtr.push(InsnParser::BREAKPOINT),
+			Insn::Label(_) | Insn::Jump(_) | Insn::ConditionalJump(_) | Insn::LookupSwitch(_) | Insn::TableSwitch(_) => {
+				return Err(ParserError::invalid_insn(pc, "Instructions referring to labels are written by write_insns"));
 			}
 		}
-		
-		Ok((wtr.into_inner(), label_pc_map))
+		Ok(())
+	}
+	
+	/// Picks the int, long, float or double variant of an arithmetic opcode
+	fn typed_opcode(kind: PrimitiveType, ops: [u8; 4]) -> u8 {
+		match kind {
+			PrimitiveType::Boolean | PrimitiveType::Byte | PrimitiveType::Char | PrimitiveType::Short | PrimitiveType::Int => ops[0],
+			PrimitiveType::Long => ops[1],
+			PrimitiveType::Float => ops[2],
+			PrimitiveType::Double => ops[3]
+		}
 	}
 	
-	fn write_ldc<T: Write>(wtr: &mut T, constant: u16, double_size: bool) -> Result<u32> {
+	/// Writes a local load or store, given the opcodes for slots 0 to 3 followed by the indexed opcode
+	fn write_local(wtr: &mut Vec<u8>, ops: [u8; 5], index: u16) -> Result<()> {
+		match index {
+			0..=3 => wtr.push(ops[index as usize]),
+			index => if let Ok(index) = u8::try_from(index) {
+				wtr.push(ops[4]);
+				wtr.push(index);
+			} else {
+				wtr.push(InsnParser::WIDE);
+				wtr.push(ops[4]);
+				wtr.write_u16::<BigEndian>(index)?;
+			}
+		}
+		Ok(())
+	}
+	
+	fn write_ldc(wtr: &mut Vec<u8>, constant: CPIndex, double_size: bool) -> Result<()> {
 		// double sized constants must use LDC2 (only wide variant exists)
 		if double_size {
-			wtr.write_u8(InsnParser::LDC2_W)?;
+			wtr.push(InsnParser::LDC2_W);
 			wtr.write_u16::<BigEndian>(constant)?;
-			Ok(5)
-		} else {
+		} else if let Ok(constant) = u8::try_from(constant) {
 			// If we can fit the constant index into a u8 then use LDC otherwise use LDC_W
-			if constant <= 0xFF {
-				wtr.write_u8(InsnParser::LDC)?;
-				wtr.write_u8(constant as u8)?;
-				Ok(3)
-			} else {
-				wtr.write_u8(InsnParser::LDC_W)?;
-				wtr.write_u16::<BigEndian>(constant)?;
-				Ok(5)
+			wtr.push(InsnParser::LDC);
+			wtr.push(constant);
+		} else {
+			wtr.push(InsnParser::LDC_W);
+			wtr.write_u16::<BigEndian>(constant)?;
+		}
+		Ok(())
+	}
+}
+
+/// The start and end of an instruction's bytes within the written code
+type InsnSpan = (usize, usize);
+
+/// An offset within the written code that points to a label, filled in once every label has a pc
+#[derive(Constructor)]
+struct JumpRef {
+	/// The index of the instruction the offset belongs to
+	insn: usize,
+	/// The pc the offset is relative to
+	from: u32,
+	/// Where the offset is written
+	at: usize,
+	to: LabelInsn,
+	/// Whether the offset is 32 rather than 16 bits
+	wide: bool
+}
+
+/// The labels of a method body while its instructions are decoded, along with a bitmap of the
+/// pcs that they point to
+struct CodeLabels<'m> {
+	pc_label_map: &'m mut HashMap<u32, LabelInsn>,
+	targets: Vec<bool>,
+	placed: usize,
+	/// pcs of labels that were only found after their instruction was decoded
+	late: Vec<u32>
+}
+
+impl <'m> CodeLabels<'m> {
+	fn new(pc_label_map: &'m mut HashMap<u32, LabelInsn>, length: u32) -> Result<Self> {
+		let mut targets = vec![false; length as usize + 1];
+		for pc in pc_label_map.keys() {
+			*targets.get_mut(*pc as usize).ok_or_else(|| ParserError::out_of_bounds_jump(*pc as i32))? = true;
+		}
+		Ok(CodeLabels {
+			pc_label_map,
+			targets,
+			placed: 0,
+			late: Vec::new()
+		})
+	}
+	
+	/// The label that should be placed before the instruction at the given pc
+	fn at(&mut self, pc: u32) -> Option<LabelInsn> {
+		if !self.targets[pc as usize] {
+			return None;
+		}
+		self.placed += 1;
+		self.pc_label_map.get(&pc).copied()
+	}
+	
+	/// Returns the label for a jump from the instruction at `from` to the given pc
+	fn target(&mut self, from: u32, pc: u32) -> Result<LabelInsn> {
+		let target = self.targets.get_mut(pc as usize).ok_or_else(|| ParserError::out_of_bounds_jump(pc as i32))?;
+		if !*target {
+			*target = true;
+			if pc <= from {
+				self.late.push(pc);
+			}
+		}
+		Ok(InsnParser::label_at(self.pc_label_map, pc))
+	}
+	
+	/// Inserts the late labels into the decoded instructions, given the pc of each instruction
+	fn place_late(&mut self, insns: &mut Vec<Insn>, pcs: &[u32]) -> Result<()> {
+		// every label must be at the start of an instruction
+		if let Some(pc) = self.late.iter().find(|pc| pcs.binary_search(pc).is_err()) {
+			return Err(ParserError::out_of_bounds_jump(*pc as i32));
+		}
+		if self.placed + self.late.len() != self.pc_label_map.len() {
+			let pc = self.pc_label_map.keys()
+				.find(|pc| pcs.binary_search(pc).is_err())
+				.ok_or_else(ParserError::unmapped_label)?;
+			return Err(ParserError::out_of_bounds_jump(*pc as i32));
+		}
+		if self.late.is_empty() {
+			return Ok(());
+		}
+		
+		// shift the instructions after each late label back from the end, so that each is moved once
+		self.late.sort_unstable();
+		let mut read = insns.len();
+		insns.resize_with(read + self.late.len(), || Insn::Nop(NopInsn {}));
+		let mut write = insns.len();
+		for pc in self.late.iter().rev() {
+			while read > 0 && pcs[read - 1] >= *pc {
+				read -= 1;
+				write -= 1;
+				insns.swap(read, write);
 			}
+			write -= 1;
+			insns[write] = Insn::Label(self.pc_label_map[pc]);
+		}
+		Ok(())
+	}
+}
+
+/// Reads the BootstrapMethods attribute among the given class attributes
+pub(crate) fn parse_bootstrap_methods(attributes: &[Attribute], constant_pool: &ConstantPool) -> Result<BootstrapMethods> {
+	let buf = attributes.iter().find_map(|attr| match attr {
+		Attribute::Unknown(x) if x.name == "BootstrapMethods" => Some(x.buf.as_slice()),
+		_ => None
+	});
+	match buf {
+		Some(x) => read_bootstrap_methods(x, constant_pool),
+		None => Ok(Arc::from(Vec::new()))
+	}
+}
+
+/// Reads the body of a BootstrapMethods attribute
+pub(crate) fn read_bootstrap_methods(mut rdr: &[u8], constant_pool: &ConstantPool) -> Result<BootstrapMethods> {
+	let num_methods = rdr.read_u16::<BigEndian>()? as usize;
+	let mut methods = Vec::with_capacity(num_methods);
+	for _ in 0..num_methods {
+		let handle = InsnParser::parse_handle(rdr.read_u16::<BigEndian>()?, constant_pool)?;
+		let num_arguments = rdr.read_u16::<BigEndian>()? as usize;
+		let mut arguments = Vec::with_capacity(num_arguments);
+		for _ in 0..num_arguments {
+			arguments.push(InsnParser::parse_bootstrap_argument(rdr.read_u16::<BigEndian>()?, constant_pool)?);
+		}
+		let arguments: Option<Vec<BootstrapArgument>> = arguments.into_iter().collect();
+		methods.push(arguments.map(|arguments| BootstrapMethod::new(handle, arguments)));
+	}
+	Ok(Arc::from(methods))
+}
+
+/// Writes the body of the BootstrapMethods attribute holding every bootstrap method put into the
+/// given writer
+pub(crate) fn write_bootstrap_methods(constant_pool: &mut ConstantPoolWriter) -> Result<Vec<u8>> {
+	let methods = constant_pool.bootstrap_methods().to_vec();
+	let mut wtr = Vec::new();
+	wtr.write_u16::<BigEndian>(methods.len() as u16)?;
+	for method in methods.iter() {
+		wtr.write_u16::<BigEndian>(constant_pool.handle(&method.handle)?)?;
+		wtr.write_u16::<BigEndian>(method.arguments.len() as u16)?;
+		for argument in method.arguments.iter() {
+			let index = match argument {
+				BootstrapArgument::Int(x) => constant_pool.integer(*x)?,
+				BootstrapArgument::Float(x) => constant_pool.float(*x)?,
+				BootstrapArgument::Long(x) => constant_pool.long(*x)?,
+				BootstrapArgument::Double(x) => constant_pool.double(*x)?,
+				BootstrapArgument::Class(x) => constant_pool.class_utf8(x)?,
+				BootstrapArgument::String(x) => constant_pool.string_utf(x)?,
+				BootstrapArgument::MethodType(x) => constant_pool.methodtype_utf8(x)?,
+				BootstrapArgument::MethodHandle(x) => constant_pool.handle(x)?
+			};
+			wtr.write_u16::<BigEndian>(index)?;
 		}
 	}
+	Ok(wtr)
 }
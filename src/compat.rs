@@ -0,0 +1,285 @@
+use crate::classfile::ClassFile;
+use crate::access::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use crate::attributes::{Attribute, ConstantValue};
+use crate::field::Field;
+use crate::method::Method;
+use crate::intern::Name;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+
+/// How badly a change breaks code written against the old version of a library, from least to
+/// most severe
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Breakage {
+	/// Existing code still compiles and links, but behaves differently, such as code that inlined a
+	/// constant whose value changed
+	Semantic,
+	/// Existing binaries still link, but their sources no longer compile
+	Source,
+	/// Existing binaries fail to link or verify, and usually their sources no longer compile either
+	Binary
+}
+
+/// A change to the API of a class, named after the sections of JLS chapter 13 that describe them
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiChange {
+	ClassRemoved,
+	/// A public class is no longer public
+	ClassLessAccessible,
+	ClassNowFinal,
+	ClassNowAbstract,
+	/// A class became an interface, an annotation interface or an enum, or the other way around
+	ClassKindChanged,
+	/// A superclass or superinterface, direct or not, is no longer one
+	SupertypeRemoved(Name),
+	FieldRemoved,
+	/// The field has the given descriptor instead
+	FieldTypeChanged(Name),
+	FieldLessAccessible,
+	FieldNowFinal,
+	/// The field became static or stopped being static
+	FieldStaticChanged,
+	/// The old and new values of a constant, which callers compiled against the old version inlined
+	ConstantValueChanged(ConstantValue, Option<ConstantValue>),
+	MethodRemoved,
+	/// The method was removed, and the only new method of the same name has the given descriptor
+	MethodDescriptorChanged(Name),
+	MethodLessAccessible,
+	MethodNowFinal,
+	MethodNowAbstract,
+	/// The method became static or stopped being static
+	MethodStaticChanged,
+	/// A new abstract method that existing subclasses and implementations do not implement
+	AbstractMethodAdded,
+	/// The given exception was added to the `throws` clause. Its hierarchy is not known, so
+	/// unchecked exceptions are reported as well
+	ExceptionAdded(Name)
+}
+
+impl ApiChange {
+	pub fn breakage(&self) -> Breakage {
+		match self {
+			ApiChange::ConstantValueChanged(..) => Breakage::Semantic,
+			ApiChange::AbstractMethodAdded | ApiChange::ExceptionAdded(_) => Breakage::Source,
+			_ => Breakage::Binary
+		}
+	}
+}
+
+/// A change that breaks code written against the old version of a class
+#[derive(Clone, Debug, PartialEq)]
+pub struct Incompatibility {
+	pub class: Name,
+	/// The name and descriptor of the field or method, None for changes to the class itself
+	pub member: Option<(Name, Name)>,
+	pub change: ApiChange
+}
+
+impl Incompatibility {
+	pub fn breakage(&self) -> Breakage {
+		self.change.breakage()
+	}
+}
+
+impl Display for Incompatibility {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:?} {}", self.breakage(), self.class)?;
+		if let Some((name, descriptor)) = &self.member {
+			write!(f, ".{} {}", name, descriptor)?;
+		}
+		write!(f, ": {:?}", self.change)
+	}
+}
+
+/// The most severe breakage among the given incompatibilities, None if there are none
+pub fn worst_breakage(incompatibilities: &[Incompatibility]) -> Option<Breakage> {
+	incompatibilities.iter().map(|x| x.breakage()).max()
+}
+
+/// Compares the API of two versions of a library, given all of their classes. Only public classes
+/// are part of the API, along with their public members and the protected members of those that are
+/// not final. A member that moved to a superclass is still part of the API, as long as the
+/// superclass is among the new classes.
+///
+/// Incompatibilities are returned in the order of the old classes
+pub fn check_compatibility(old: &[ClassFile], new: &[ClassFile]) -> Vec<Incompatibility> {
+	let old_classes: HashMap<&str, &ClassFile> = old.iter().map(|class| (class.this_class.as_str(), class)).collect();
+	let new_classes: HashMap<&str, &ClassFile> = new.iter().map(|class| (class.this_class.as_str(), class)).collect();
+	let mut incompatibilities = Vec::new();
+	for old_class in old.iter().filter(|class| class.access_flags.contains(ClassAccessFlags::PUBLIC)) {
+		let mut report = |member: Option<(&Name, &Name)>, change: ApiChange| {
+			incompatibilities.push(Incompatibility {
+				class: old_class.this_class.clone(),
+				member: member.map(|(name, descriptor)| (name.clone(), descriptor.clone())),
+				change
+			});
+		};
+		let new_class = match new_classes.get(old_class.this_class.as_str()) {
+			Some(x) => *x,
+			None => {
+				report(None, ApiChange::ClassRemoved);
+				continue;
+			}
+		};
+		if !new_class.access_flags.contains(ClassAccessFlags::PUBLIC) {
+			report(None, ApiChange::ClassLessAccessible);
+			continue;
+		}
+		let kind = ClassAccessFlags::INTERFACE | ClassAccessFlags::ANNOTATION | ClassAccessFlags::ENUM;
+		if old_class.access_flags & kind != new_class.access_flags & kind {
+			report(None, ApiChange::ClassKindChanged);
+		}
+		let added = new_class.access_flags - old_class.access_flags;
+		if added.contains(ClassAccessFlags::FINAL) {
+			report(None, ApiChange::ClassNowFinal);
+		}
+		if added.contains(ClassAccessFlags::ABSTRACT) && !new_class.access_flags.contains(ClassAccessFlags::INTERFACE) {
+			report(None, ApiChange::ClassNowAbstract);
+		}
+		let new_supertypes = supertypes(new_class, &new_classes);
+		for supertype in supertypes(old_class, &old_classes).difference(&new_supertypes) {
+			report(None, ApiChange::SupertypeRemoved(supertype.clone()));
+		}
+	
+		let is_final = old_class.access_flags.contains(ClassAccessFlags::FINAL);
+		for field in old_class.fields.iter().filter(|x| is_api(x.access_flags.bits(), is_final)) {
+			let member = Some((&field.name, &field.descriptor));
+			let new_field = match new_class.fields.iter().find(|x| x.name == field.name) {
+				Some(x) => x,
+				None => {
+					let inherited = new_supertypes.iter()
+						.filter_map(|name| new_classes.get(name.as_str()))
+						.any(|class| class.fields.iter().any(|x| x.name == field.name && x.descriptor == field.descriptor));
+					if !inherited {
+						report(member, ApiChange::FieldRemoved);
+					}
+					continue;
+				}
+			};
+			if new_field.descriptor != field.descriptor {
+				report(member, ApiChange::FieldTypeChanged(new_field.descriptor.clone()));
+			}
+			if visibility(new_field.access_flags.bits()) < visibility(field.access_flags.bits()) {
+				report(member, ApiChange::FieldLessAccessible);
+			}
+			if (new_field.access_flags - field.access_flags).contains(FieldAccessFlags::FINAL) {
+				report(member, ApiChange::FieldNowFinal);
+			}
+			if new_field.access_flags.contains(FieldAccessFlags::STATIC) != field.access_flags.contains(FieldAccessFlags::STATIC) {
+				report(member, ApiChange::FieldStaticChanged);
+			}
+			if let Some(value) = constant_value(field) {
+				let new_value = constant_value(new_field);
+				if new_value != Some(value) {
+					report(member, ApiChange::ConstantValueChanged(value.clone(), new_value.cloned()));
+				}
+			}
+		}
+	
+		let is_method_api = |method: &&Method| {
+			is_api(method.access_flags.bits(), is_final) && method.name != "<clinit>"
+				&& !method.access_flags.intersects(MethodAccessFlags::SYNTHETIC | MethodAccessFlags::BRIDGE)
+		};
+		for method in old_class.methods.iter().filter(is_method_api) {
+			let member = Some((&method.name, &method.descriptor));
+			let new_method = match new_class.methods.iter().find(|x| x.name == method.name && x.descriptor == method.descriptor) {
+				Some(x) => x,
+				None => {
+					let inherited = method.name != "<init>" && new_supertypes.iter()
+						.filter_map(|name| new_classes.get(name.as_str()))
+						.any(|class| class.methods.iter().any(|x| x.name == method.name && x.descriptor == method.descriptor));
+					if inherited {
+						continue;
+					}
+					let mut renamed = new_class.methods.iter()
+						.filter(|x| x.name == method.name && !old_class.methods.iter().any(|y| y.name == x.name && y.descriptor == x.descriptor));
+					match (renamed.next(), renamed.next()) {
+						(Some(x), None) => report(member, ApiChange::MethodDescriptorChanged(x.descriptor.clone())),
+						_ => report(member, ApiChange::MethodRemoved)
+					}
+					continue;
+				}
+			};
+			if visibility(new_method.access_flags.bits()) < visibility(method.access_flags.bits()) {
+				report(member, ApiChange::MethodLessAccessible);
+			}
+			let added = new_method.access_flags - method.access_flags;
+			if added.contains(MethodAccessFlags::FINAL) && !new_class.access_flags.contains(ClassAccessFlags::FINAL) {
+				report(member, ApiChange::MethodNowFinal);
+			}
+			if added.contains(MethodAccessFlags::ABSTRACT) {
+				report(member, ApiChange::MethodNowAbstract);
+			}
+			if new_method.access_flags.contains(MethodAccessFlags::STATIC) != method.access_flags.contains(MethodAccessFlags::STATIC) {
+				report(member, ApiChange::MethodStaticChanged);
+			}
+			let old_exceptions = exceptions(method);
+			for exception in exceptions(new_method).iter().filter(|x| !old_exceptions.contains(x)) {
+				report(member, ApiChange::ExceptionAdded(exception.clone()));
+			}
+		}
+	
+		for method in new_class.methods.iter().filter(is_method_api) {
+			let is_new = !old_class.methods.iter().any(|x| x.name == method.name && x.descriptor == method.descriptor);
+			if is_new && method.access_flags.contains(MethodAccessFlags::ABSTRACT) {
+				report(Some((&method.name, &method.descriptor)), ApiChange::AbstractMethodAdded);
+			}
+		}
+	}
+	incompatibilities
+}
+
+/// Whether a member with these access flags is visible outside of its package, protected members
+/// of final classes being out of reach
+fn is_api(access: u16, final_class: bool) -> bool {
+	match visibility(access) {
+		3 => true,
+		2 => !final_class,
+		_ => false
+	}
+}
+
+/// From private, to package private, protected and then public. The flags share their bits among
+/// classes, fields and methods
+fn visibility(access: u16) -> u8 {
+	if access & MethodAccessFlags::PUBLIC.bits() != 0 {
+		3
+	} else if access & MethodAccessFlags::PROTECTED.bits() != 0 {
+		2
+	} else if access & MethodAccessFlags::PRIVATE.bits() != 0 {
+		0
+	} else {
+		1
+	}
+}
+
+/// Every superclass and superinterface of a class, direct or not. The supertypes of classes
+/// outside the given classes are not known
+fn supertypes(class: &ClassFile, classes: &HashMap<&str, &ClassFile>) -> BTreeSet<Name> {
+	let mut supertypes = BTreeSet::new();
+	let mut queue: Vec<&Name> = class.super_class.iter().chain(class.interfaces.iter()).collect();
+	while let Some(name) = queue.pop() {
+		if supertypes.insert(name.clone()) {
+			if let Some(class) = classes.get(name.as_str()) {
+				queue.extend(class.super_class.iter().chain(class.interfaces.iter()));
+			}
+		}
+	}
+	supertypes
+}
+
+fn constant_value(field: &Field) -> Option<&ConstantValue> {
+	field.attributes.iter().find_map(|attr| match attr {
+		Attribute::ConstantValue(x) => Some(&x.value),
+		_ => None
+	})
+}
+
+fn exceptions(method: &Method) -> &[Name] {
+	method.attributes.iter()
+		.find_map(|attr| match attr {
+			Attribute::Exceptions(x) => Some(x.exceptions.as_slice()),
+			_ => None
+		})
+		.unwrap_or(&[])
+}
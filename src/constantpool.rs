@@ -8,7 +8,12 @@ use derive_more::Constructor;
 use enum_display_derive::DisplayDebug;
 use std::fmt::{Debug, Formatter};
 use linked_hash_map::LinkedHashMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::convert::TryFrom;
 use std::hash::{Hash};
+use crate::intern::{Name, Interner};
+use crate::ast::{BootstrapMethod, Handle};
 
 pub type CPIndex = u16;
 
@@ -188,7 +193,7 @@ impl ConstantPool {
 		}
 	}
 	
-	pub fn utf8_inner(&self, index: CPIndex) -> Result<String> {
+	pub fn utf8_inner(&self, index: CPIndex) -> Result<Name> {
 		let utf8_info = self.utf8(index)?;
 		Ok(utf8_info.str.clone())
 	}
@@ -258,6 +263,186 @@ impl ConstantPool {
 			)),
 		}
 	}
+	
+	/// The number of usable slots in the pool, not counting slot 0. The second slot of a long or
+	/// double is counted
+	pub fn len(&self) -> usize {
+		self.inner.len().saturating_sub(1)
+	}
+	
+	/// Whether the pool holds no constants
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	
+	/// Iterates every present entry along with its index
+	pub fn iter(&self) -> impl Iterator<Item = (CPIndex, &ConstantType)> {
+		self.inner.iter()
+			.enumerate()
+			.filter_map(|(index, constant)| constant.as_ref().map(|c| (index as CPIndex, c)))
+	}
+	
+	/// Overwrites the entry at the given index and returns the previous entry.
+	/// The new entry must occupy the same number of slots as the old one, since changing the size
+	/// would shift every following index
+	pub fn replace(&mut self, index: CPIndex, constant: ConstantType) -> Result<ConstantType> {
+		let old = self.get(index)?;
+		if old.double_size() != constant.double_size() {
+			return Err(ParserError::incomp_cp(
+				if old.double_size() { "Long or Double" } else { "single size constant" },
+				&constant,
+				index as usize
+			));
+		}
+		Ok(self.inner[index as usize].replace(constant).unwrap())
+	}
+
+	/// Replaces every Utf8 entry with the copy held by the given interner, so that names read from
+	/// this pool share their memory with the names of every other pool interned by it
+	pub fn intern(&mut self, interner: &Interner) {
+		for constant in self.inner.iter_mut() {
+			if let Some(ConstantType::Utf8(x)) = constant {
+				x.str = interner.intern_name(&x.str);
+			}
+		}
+	}
+
+	/// Finds the first index holding an entry equal to the given constant
+	pub fn find(&self, constant: &ConstantType) -> Option<CPIndex> {
+		self.iter()
+			.find(|(_, c)| *c == constant)
+			.map(|(index, _)| index)
+	}
+	
+	pub fn find_utf8(&self, str: &str) -> Option<CPIndex> {
+		self.iter()
+			.find(|(_, c)| matches!(c, ConstantType::Utf8(x) if &*x.str == str))
+			.map(|(index, _)| index)
+	}
+	
+	pub fn find_class(&self, name: &str) -> Option<CPIndex> {
+		self.iter()
+			.find(|(_, c)| match c {
+				ConstantType::Class(x) => self.utf8_matches(x.name_index, name),
+				_ => false
+			})
+			.map(|(index, _)| index)
+	}
+	
+	pub fn find_string(&self, str: &str) -> Option<CPIndex> {
+		self.iter()
+			.find(|(_, c)| match c {
+				ConstantType::String(x) => self.utf8_matches(x.utf_index, str),
+				_ => false
+			})
+			.map(|(index, _)| index)
+	}
+	
+	pub fn find_fieldref(&self, class: &str, name: &str, descriptor: &str) -> Option<CPIndex> {
+		self.iter()
+			.find(|(_, c)| match c {
+				ConstantType::Fieldref(x) => self.member_matches(x.class_index, x.name_and_type_index, class, name, descriptor),
+				_ => false
+			})
+			.map(|(index, _)| index)
+	}
+	
+	/// Finds either a Methodref or an InterfaceMethodref
+	pub fn find_methodref(&self, class: &str, name: &str, descriptor: &str) -> Option<CPIndex> {
+		self.iter()
+			.find(|(_, c)| match c {
+				ConstantType::Methodref(x) | ConstantType::InterfaceMethodref(x) =>
+					self.member_matches(x.class_index, x.name_and_type_index, class, name, descriptor),
+				_ => false
+			})
+			.map(|(index, _)| index)
+	}
+	
+	/// Counts how many times each index is referenced by other entries of this pool
+	pub fn references(&self) -> Vec<u32> {
+		let mut counts = vec![0u32; self.inner.len()];
+		for (_, constant) in self.iter() {
+			constant.for_each_reference(|index| {
+				if let Some(count) = counts.get_mut(index as usize) {
+					*count += 1;
+				}
+			});
+		}
+		counts
+	}
+	
+	/// Checks that every entry refers to entries of the correct type
+	pub fn validate(&self) -> Result<()> {
+		for (index, constant) in self.iter() {
+			match constant {
+				ConstantType::Class(x) => self.check_reference(index, x.name_index, "Utf8")?,
+				ConstantType::Fieldref(x) => {
+					self.check_reference(index, x.class_index, "Class")?;
+					self.check_reference(index, x.name_and_type_index, "NameAndType")?;
+				},
+				ConstantType::Methodref(x) | ConstantType::InterfaceMethodref(x) => {
+					self.check_reference(index, x.class_index, "Class")?;
+					self.check_reference(index, x.name_and_type_index, "NameAndType")?;
+				},
+				ConstantType::String(x) => self.check_reference(index, x.utf_index, "Utf8")?,
+				ConstantType::NameAndType(x) => {
+					self.check_reference(index, x.name_index, "Utf8")?;
+					self.check_reference(index, x.descriptor_index, "Utf8")?;
+				},
+				ConstantType::MethodHandle(x) => {
+					let expected = x.kind.reference_type();
+					let valid = match self.get(x.reference) {
+						Ok(ConstantType::Fieldref(_)) => expected == "Fieldref",
+						Ok(ConstantType::Methodref(_)) => expected == "Methodref" || expected == "AnyMethodRef",
+						Ok(ConstantType::InterfaceMethodref(_)) => expected == "InterfaceMethodref" || expected == "AnyMethodRef",
+						_ => false
+					};
+					if !valid {
+						return Err(ParserError::bad_cp_reference(index, x.reference, expected));
+					}
+				},
+				ConstantType::MethodType(x) => self.check_reference(index, x.descriptor_index, "Utf8")?,
+				ConstantType::Dynamic(x) => self.check_reference(index, x.name_and_type_index, "NameAndType")?,
+				ConstantType::InvokeDynamic(x) => self.check_reference(index, x.name_and_type_index, "NameAndType")?,
+				ConstantType::Module(x) => self.check_reference(index, x.name_index, "Utf8")?,
+				ConstantType::Package(x) => self.check_reference(index, x.name_index, "Utf8")?,
+				ConstantType::Integer(_) | ConstantType::Float(_) | ConstantType::Long(_) |
+				ConstantType::Double(_) | ConstantType::Utf8(_) => {}
+			}
+		}
+		Ok(())
+	}
+	
+	fn check_reference(&self, index: CPIndex, reference: CPIndex, expected: &'static str) -> Result<()> {
+		let valid = matches!((self.get(reference), expected),
+			(Ok(ConstantType::Utf8(_)), "Utf8") |
+			(Ok(ConstantType::Class(_)), "Class") |
+			(Ok(ConstantType::NameAndType(_)), "NameAndType")
+		);
+		if valid {
+			Ok(())
+		} else {
+			Err(ParserError::bad_cp_reference(index, reference, expected))
+		}
+	}
+	
+	fn utf8_matches(&self, index: CPIndex, str: &str) -> bool {
+		match self.utf8(index) {
+			Ok(x) => &*x.str == str,
+			Err(_) => false
+		}
+	}
+	
+	fn member_matches(&self, class_index: CPIndex, name_and_type_index: CPIndex, class: &str, name: &str, descriptor: &str) -> bool {
+		let class_matches = match self.class(class_index) {
+			Ok(x) => self.utf8_matches(x.name_index, class),
+			Err(_) => false
+		};
+		class_matches && match self.nameandtype(name_and_type_index) {
+			Ok(x) => self.utf8_matches(x.name_index, name) && self.utf8_matches(x.descriptor_index, descriptor),
+			Err(_) => false
+		}
+	}
 }
 
 impl Serializable for ConstantPool {
@@ -274,16 +459,24 @@ impl Serializable for ConstantPool {
 			}
 			let constant = ConstantType::parse(rdr)?;
 			if constant.double_size() {
+				// the second slot of a long or double must still be within the pool
+				if i + 1 >= size {
+					return Err(ParserError::bad_cp_index(i as CPIndex));
+				}
 				skip = true;
 			}
 			cp.set(i as CPIndex, Some(constant));
 		}
 		
+		cp.validate()?;
 		Ok(cp)
 	}
 	
 	fn write<W: Write>(&self, wtr: &mut W) -> Result<()> {
 		wtr.write_u16::<BigEndian>(self.inner.len() as u16)?;
+		for constant in self.inner.iter().flatten() {
+			constant.write(wtr)?;
+		}
 		Ok(())
 	}
 }
@@ -368,9 +561,17 @@ pub struct NameAndTypeInfo {
 	pub name_index: CPIndex,
 	pub descriptor_index: CPIndex
 }
-#[derive(Constructor, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Utf8Info {
-	pub str: String
+	pub str: Name
+}
+
+impl Utf8Info {
+	pub fn new<T: Into<Name>>(str: T) -> Self {
+		Utf8Info {
+			str: str.into()
+		}
+	}
 }
 
 #[derive(Constructor, Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -392,6 +593,20 @@ pub enum MethodHandleKind {
 	InvokeInterface
 }
 
+impl MethodHandleKind {
+	/// The type of constant a handle of this kind must refer to
+	pub fn reference_type(&self) -> &'static str {
+		match self {
+			MethodHandleKind::GetField | MethodHandleKind::GetStatic |
+			MethodHandleKind::PutField | MethodHandleKind::PutStatic => "Fieldref",
+			MethodHandleKind::InvokeVirtual | MethodHandleKind::NewInvokeSpecial => "Methodref",
+			// since java 8 these may refer to interface methods
+			MethodHandleKind::InvokeStatic | MethodHandleKind::InvokeSpecial => "AnyMethodRef",
+			MethodHandleKind::InvokeInterface => "InterfaceMethodref"
+		}
+	}
+}
+
 #[allow(non_upper_case_globals)]
 impl MethodHandleKind {
 	const REF_getField: u8 = 1;
@@ -452,23 +667,23 @@ pub enum ConstantType {
 
 #[allow(non_upper_case_globals)]
 impl ConstantType {
-	const CONSTANT_Utf8: u8 = 1;
-	const CONSTANT_Integer: u8 = 3;
-	const CONSTANT_Float: u8 = 4;
-	const CONSTANT_Long: u8 = 5;
-	const CONSTANT_Double: u8 = 6;
-	const CONSTANT_Class: u8 = 7;
-	const CONSTANT_String: u8 = 8;
-	const CONSTANT_Fieldref: u8 = 9;
-	const CONSTANT_Methodref: u8 = 10;
-	const CONSTANT_InterfaceMethodref: u8 = 11;
-	const CONSTANT_NameAndType: u8 = 12;
-	const CONSTANT_MethodHandle: u8 = 15;
-	const CONSTANT_MethodType: u8 = 16;
-	const CONSTANT_Dynamic: u8 = 17;
-	const CONSTANT_InvokeDynamic: u8 = 18;
-	const CONSTANT_Module: u8 = 19;
-	const CONSTANT_Package: u8 = 20;
+	pub(crate) const CONSTANT_Utf8: u8 = 1;
+	pub(crate) const CONSTANT_Integer: u8 = 3;
+	pub(crate) const CONSTANT_Float: u8 = 4;
+	pub(crate) const CONSTANT_Long: u8 = 5;
+	pub(crate) const CONSTANT_Double: u8 = 6;
+	pub(crate) const CONSTANT_Class: u8 = 7;
+	pub(crate) const CONSTANT_String: u8 = 8;
+	pub(crate) const CONSTANT_Fieldref: u8 = 9;
+	pub(crate) const CONSTANT_Methodref: u8 = 10;
+	pub(crate) const CONSTANT_InterfaceMethodref: u8 = 11;
+	pub(crate) const CONSTANT_NameAndType: u8 = 12;
+	pub(crate) const CONSTANT_MethodHandle: u8 = 15;
+	pub(crate) const CONSTANT_MethodType: u8 = 16;
+	pub(crate) const CONSTANT_Dynamic: u8 = 17;
+	pub(crate) const CONSTANT_InvokeDynamic: u8 = 18;
+	pub(crate) const CONSTANT_Module: u8 = 19;
+	pub(crate) const CONSTANT_Package: u8 = 20;
 	
 	pub fn parse<R: Read>(rdr: &mut R) -> Result<Self> {
 		let tag = rdr.read_u8()?;
@@ -528,8 +743,7 @@ impl ConstantType {
 				};
 				
 				let str = String::from_utf8_lossy(&utf);
-				let str = String::from(&*str);
-				ConstantType::Utf8 ( Utf8Info { str } )
+				ConstantType::Utf8 ( Utf8Info::new(&*str) )
 			},
 			ConstantType::CONSTANT_MethodHandle => {
 				let kind = match rdr.read_u8()? {
@@ -684,10 +898,49 @@ impl ConstantType {
 			_ => false
 		}
 	}
+	
+	/// Calls the given function with every constant pool index this entry refers to.
+	/// Bootstrap method indices are not constant pool indices and are not included
+	pub fn for_each_reference<F: FnMut(CPIndex)>(&self, mut op: F) {
+		match self {
+			ConstantType::Class(x) => op(x.name_index),
+			ConstantType::Fieldref(x) => {
+				op(x.class_index);
+				op(x.name_and_type_index);
+			},
+			ConstantType::Methodref(x) | ConstantType::InterfaceMethodref(x) => {
+				op(x.class_index);
+				op(x.name_and_type_index);
+			},
+			ConstantType::String(x) => op(x.utf_index),
+			ConstantType::NameAndType(x) => {
+				op(x.name_index);
+				op(x.descriptor_index);
+			},
+			ConstantType::MethodHandle(x) => op(x.reference),
+			ConstantType::MethodType(x) => op(x.descriptor_index),
+			ConstantType::Dynamic(x) => op(x.name_and_type_index),
+			ConstantType::InvokeDynamic(x) => op(x.name_and_type_index),
+			ConstantType::Module(x) => op(x.name_index),
+			ConstantType::Package(x) => op(x.name_index),
+			ConstantType::Integer(_) | ConstantType::Float(_) | ConstantType::Long(_) |
+			ConstantType::Double(_) | ConstantType::Utf8(_) => {}
+		}
+	}
 }
 
 pub struct ConstantPoolWriter {
 	inner: LinkedHashMap<ConstantType, u16>,
+	/// Index of every utf8 entry, so that strings can be looked up without allocating a constant
+	utf8_indices: HashMap<Name, CPIndex>,
+	/// Entries of a parsed pool which are written first, at their original indices
+	seed: Vec<Option<ConstantType>>,
+	/// How many times each index is referenced by the written class
+	usages: Vec<u32>,
+	/// The shared pool the seed was taken from, if any
+	source: Option<Arc<ConstantPool>>,
+	/// The entries of the BootstrapMethods attribute written with the class
+	bootstrap_methods: Vec<BootstrapMethod>,
 	index: CPIndex
 }
 
@@ -695,114 +948,299 @@ impl ConstantPoolWriter {
 	pub fn new() -> ConstantPoolWriter {
 		ConstantPoolWriter {
 			inner: LinkedHashMap::with_capacity(5),
+			utf8_indices: HashMap::new(),
+			seed: Vec::new(),
+			usages: Vec::new(),
+			source: None,
+			bootstrap_methods: Vec::new(),
 			index: 1
 		}
 	}
 	
-	pub fn put(&mut self, constant: ConstantType) -> CPIndex {
-		match self.inner.get(&constant) {
-			Some(x) => *x,
-			None => {
-				let this_index = self.index;
-				self.index += if constant.double_size() { 2	} else { 1 };
-				self.inner.insert(constant, this_index);
-				this_index
+	/// Creates a writer which keeps every entry of the given pool at its original index.
+	/// Lookups of existing constants resolve to their original index, and any new constants are
+	/// appended after the end of the original pool.
+	pub fn from_pool(pool: &ConstantPool) -> ConstantPoolWriter {
+		let mut inner = LinkedHashMap::with_capacity(pool.inner.len());
+		let mut utf8_indices = HashMap::new();
+		// seeded entries are always written, so their own references are always used
+		let usages = pool.references();
+		for (index, constant) in pool.iter() {
+			// duplicate entries resolve to the first occurrence, but are still written
+			if !inner.contains_key(constant) {
+				if let ConstantType::Utf8(x) = constant {
+					utf8_indices.insert(x.str.clone(), index);
+				}
+				inner.insert(constant.clone(), index);
 			}
 		}
+		ConstantPoolWriter {
+			inner,
+			utf8_indices,
+			seed: pool.inner.clone(),
+			usages,
+			source: None,
+			bootstrap_methods: Vec::new(),
+			index: pool.inner.len().max(1) as CPIndex
+		}
+	}
+	
+	/// Like `from_pool`, but also remembers the pool so that bytes which refer to it can be copied
+	/// into the output unchanged
+	pub fn from_shared_pool(pool: &Arc<ConstantPool>) -> ConstantPoolWriter {
+		let mut writer = ConstantPoolWriter::from_pool(pool);
+		writer.source = Some(pool.clone());
+		writer
+	}
+	
+	/// Returns true if every index of the given pool resolves to the same constant in this writer
+	pub fn is_seeded_from(&self, pool: &Arc<ConstantPool>) -> bool {
+		match &self.source {
+			Some(source) => Arc::ptr_eq(source, pool),
+			None => false
+		}
+	}
+	
+	/// Adds a constant if an equal one is not already present, returning its index, which is counted
+	/// as used. The indices the constant refers to were counted when this writer returned them, so
+	/// they are not counted again
+	pub fn put(&mut self, constant: ConstantType) -> Result<CPIndex> {
+		let index = self.insert_with(constant, false)?;
+		self.mark_used(index);
+		Ok(index)
+	}
+	
+	/// Like `put`, but counts the entries the constant refers to if it is new, for indices that
+	/// were found without being counted
+	fn put_referencing(&mut self, constant: ConstantType) -> Result<CPIndex> {
+		let index = self.insert(constant)?;
+		self.mark_used(index);
+		Ok(index)
+	}
+	
+	/// Finds or adds a constant without counting the returned index as used
+	fn insert(&mut self, constant: ConstantType) -> Result<CPIndex> {
+		self.insert_with(constant, true)
+	}
+	
+	fn insert_with(&mut self, constant: ConstantType, count_references: bool) -> Result<CPIndex> {
+		if let Some(index) = self.inner.get(&constant) {
+			return Ok(*index);
+		}
+		let this_index = self.index;
+		// the constant pool count is a u16, so the last usable index is 65534
+		let next = this_index as u32 + if constant.double_size() { 2 } else { 1 };
+		if next > CPIndex::MAX as u32 {
+			return Err(ParserError::too_many_constants());
+		}
+		self.index = next as CPIndex;
+		if count_references {
+			constant.for_each_reference(|index| self.mark_used(index));
+		}
+		if let ConstantType::Utf8(x) = &constant {
+			self.utf8_indices.insert(x.str.clone(), this_index);
+		}
+		self.inner.insert(constant, this_index);
+		Ok(this_index)
+	}
+	
+	fn insert_utf8(&mut self, str: &str) -> Result<CPIndex> {
+		match self.utf8_indices.get(str) {
+			Some(index) => Ok(*index),
+			None => self.insert(ConstantType::Utf8(Utf8Info::new(str)))
+		}
+	}
+	
+	fn insert_class(&mut self, name: &str) -> Result<CPIndex> {
+		let utf = self.insert_utf8(name)?;
+		self.insert(ConstantType::Class(ClassInfo::new(utf)))
+	}
+	
+	fn insert_nameandtype(&mut self, name: &str, descriptor: &str) -> Result<CPIndex> {
+		let name = self.insert_utf8(name)?;
+		let descriptor = self.insert_utf8(descriptor)?;
+		self.insert(ConstantType::NameAndType(NameAndTypeInfo::new(name, descriptor)))
+	}
+	
+	fn mark_used(&mut self, index: CPIndex) {
+		if self.usages.len() <= index as usize {
+			self.usages.resize(index as usize + 1, 0);
+		}
+		self.usages[index as usize] += 1;
 	}
 	
 	pub fn len(&self) -> u16 {
 		self.index
 	}
 	
-	pub fn class(&mut self, name_index: CPIndex) -> CPIndex {
+	/// How many times each index has been referenced, either by the class or by another constant
+	pub fn usages(&self) -> &[u32] {
+		self.usages.as_slice()
+	}
+	
+	pub fn class(&mut self, name_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::Class(ClassInfo::new(name_index)))
 	}
 	
-	pub fn class_utf8<T: Into<String>>(&mut self, str: T) -> CPIndex {
-		let utf = self.utf8(str);
-		self.class(utf)
+	pub fn class_utf8<T: AsRef<str>>(&mut self, str: T) -> Result<CPIndex> {
+		let utf = self.insert_utf8(str.as_ref())?;
+		self.put_referencing(ConstantType::Class(ClassInfo::new(utf)))
 	}
 	
-	pub fn fieldref(&mut self, class_index: CPIndex, name_and_type_index: CPIndex) -> CPIndex {
+	pub fn fieldref(&mut self, class_index: CPIndex, name_and_type_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::Fieldref(FieldRefInfo::new(class_index, name_and_type_index)))
 	}
 	
-	pub fn methodref(&mut self, class_index: CPIndex, name_and_type_index: CPIndex) -> CPIndex {
+	/// Puts a field reference along with the class and name and type it refers to
+	pub fn fieldref_utf8<T: AsRef<str>>(&mut self, class: T, name: T, descriptor: T) -> Result<CPIndex> {
+		let class = self.insert_class(class.as_ref())?;
+		let name_and_type = self.insert_nameandtype(name.as_ref(), descriptor.as_ref())?;
+		self.put_referencing(ConstantType::Fieldref(FieldRefInfo::new(class, name_and_type)))
+	}
+	
+	pub fn methodref(&mut self, class_index: CPIndex, name_and_type_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::Methodref(MethodRefInfo::new(class_index, name_and_type_index)))
 	}
 	
-	pub fn interfacemethodref(&mut self, class_index: CPIndex, name_and_type_index: CPIndex) -> CPIndex {
+	pub fn interfacemethodref(&mut self, class_index: CPIndex, name_and_type_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::InterfaceMethodref(MethodRefInfo::new(class_index, name_and_type_index)))
 	}
 	
-	pub fn string(&mut self, string_index: CPIndex) -> CPIndex {
+	/// Puts a method or interface method reference along with the class and name and type it refers
+	/// to
+	pub fn methodref_utf8<T: AsRef<str>>(&mut self, class: T, name: T, descriptor: T, interface: bool) -> Result<CPIndex> {
+		let class = self.insert_class(class.as_ref())?;
+		let name_and_type = self.insert_nameandtype(name.as_ref(), descriptor.as_ref())?;
+		if interface {
+			self.put_referencing(ConstantType::InterfaceMethodref(MethodRefInfo::new(class, name_and_type)))
+		} else {
+			self.put_referencing(ConstantType::Methodref(MethodRefInfo::new(class, name_and_type)))
+		}
+	}
+	
+	pub fn string(&mut self, string_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::String(StringInfo::new(string_index)))
 	}
 	
-	pub fn string_utf<T: Into<String>>(&mut self, str: T) -> CPIndex {
-		let utf = self.utf8(str);
-		self.string(utf)
+	pub fn string_utf<T: AsRef<str>>(&mut self, str: T) -> Result<CPIndex> {
+		let utf = self.insert_utf8(str.as_ref())?;
+		self.put_referencing(ConstantType::String(StringInfo::new(utf)))
 	}
 	
-	pub fn integer(&mut self, bytes: i32) -> CPIndex {
+	pub fn integer(&mut self, bytes: i32) -> Result<CPIndex> {
 		self.put(ConstantType::Integer(IntegerInfo::new(bytes)))
 	}
 	
-	pub fn float(&mut self, bytes: f32) -> CPIndex {
+	pub fn float(&mut self, bytes: f32) -> Result<CPIndex> {
 		self.put(ConstantType::Float(FloatInfo::new(bytes)))
 	}
 	
-	pub fn long(&mut self, bytes: i64) -> CPIndex {
+	pub fn long(&mut self, bytes: i64) -> Result<CPIndex> {
 		self.put(ConstantType::Long(LongInfo::new(bytes)))
 	}
 	
-	pub fn double(&mut self, bytes: f64) -> CPIndex {
+	pub fn double(&mut self, bytes: f64) -> Result<CPIndex> {
 		self.put(ConstantType::Double(DoubleInfo::new(bytes)))
 	}
 	
-	pub fn nameandtype(&mut self, name_index: CPIndex, descriptor_index: CPIndex) -> CPIndex {
+	pub fn nameandtype(&mut self, name_index: CPIndex, descriptor_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::NameAndType(NameAndTypeInfo::new(name_index, descriptor_index)))
 	}
 	
-	pub fn utf8<T: Into<String>>(&mut self, str: T) -> CPIndex {
-		self.put(ConstantType::Utf8(Utf8Info::new(str.into())))
+	pub fn nameandtype_utf8<T: AsRef<str>>(&mut self, name: T, descriptor: T) -> Result<CPIndex> {
+		let index = self.insert_nameandtype(name.as_ref(), descriptor.as_ref())?;
+		self.mark_used(index);
+		Ok(index)
+	}
+	
+	/// Strings that are already in the pool are found without being copied
+	pub fn utf8<T: AsRef<str>>(&mut self, str: T) -> Result<CPIndex> {
+		let index = self.insert_utf8(str.as_ref())?;
+		self.mark_used(index);
+		Ok(index)
 	}
 	
-	pub fn methodhandle(&mut self, kind: MethodHandleKind, reference: CPIndex) -> CPIndex {
+	pub fn methodhandle(&mut self, kind: MethodHandleKind, reference: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::MethodHandle(MethodHandleInfo::new(kind, reference)))
 	}
 	
-	pub fn methodtype(&mut self, descriptor_index: CPIndex) -> CPIndex {
+	/// Puts the field or method the handle refers to along with the handle itself
+	pub fn handle(&mut self, handle: &Handle) -> Result<CPIndex> {
+		let class = self.insert_class(&handle.owner)?;
+		let name_and_type = self.insert_nameandtype(&handle.name, &handle.descriptor)?;
+		let reference = self.insert(match handle.kind {
+			MethodHandleKind::GetField | MethodHandleKind::GetStatic |
+			MethodHandleKind::PutField | MethodHandleKind::PutStatic => ConstantType::Fieldref(FieldRefInfo::new(class, name_and_type)),
+			_ if handle.interface => ConstantType::InterfaceMethodref(MethodRefInfo::new(class, name_and_type)),
+			_ => ConstantType::Methodref(MethodRefInfo::new(class, name_and_type))
+		})?;
+		self.put_referencing(ConstantType::MethodHandle(MethodHandleInfo::new(handle.kind, reference)))
+	}
+	
+	pub fn methodtype(&mut self, descriptor_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::MethodType(MethodTypeInfo::new(descriptor_index)))
 	}
 	
-	pub fn methodtype_utf8<T: Into<String>>(&mut self, str: T) -> CPIndex {
-		let utf = self.utf8(str);
-		self.methodtype(utf)
+	pub fn methodtype_utf8<T: AsRef<str>>(&mut self, str: T) -> Result<CPIndex> {
+		let utf = self.insert_utf8(str.as_ref())?;
+		self.put_referencing(ConstantType::MethodType(MethodTypeInfo::new(utf)))
 	}
 	
-	pub fn dynamicinfo(&mut self, bootstrap_method_attr_index: CPIndex, name_and_type_index: CPIndex) -> CPIndex {
+	pub fn dynamicinfo(&mut self, bootstrap_method_attr_index: CPIndex, name_and_type_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::Dynamic(DynamicInfo::new(bootstrap_method_attr_index, name_and_type_index)))
 	}
 	
-	pub fn invokedynamicinfo(&mut self, bootstrap_method_attr_index: CPIndex, name_and_type_index: CPIndex) -> CPIndex {
+	pub fn invokedynamicinfo(&mut self, bootstrap_method_attr_index: CPIndex, name_and_type_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::InvokeDynamic(InvokeDynamicInfo::new(bootstrap_method_attr_index, name_and_type_index)))
 	}
 	
-	pub fn module(&mut self, name_index: CPIndex) -> CPIndex {
+	pub fn invokedynamic_utf8<T: AsRef<str>>(&mut self, bootstrap_method_attr_index: u16, name: T, descriptor: T) -> Result<CPIndex> {
+		let name_and_type = self.insert_nameandtype(name.as_ref(), descriptor.as_ref())?;
+		self.put_referencing(ConstantType::InvokeDynamic(InvokeDynamicInfo::new(bootstrap_method_attr_index, name_and_type)))
+	}
+	
+	/// Adds an entry to the BootstrapMethods attribute written with the class, returning its index.
+	/// Equal entries share an index
+	pub fn bootstrap_method(&mut self, method: &BootstrapMethod) -> Result<u16> {
+		let index = match self.bootstrap_methods.iter().position(|x| x == method) {
+			Some(x) => x,
+			None => {
+				self.bootstrap_methods.push(method.clone());
+				self.bootstrap_methods.len() - 1
+			}
+		};
+		u16::try_from(index).map_err(|_| ParserError::other("More than 65535 bootstrap methods"))
+	}
+	
+	pub fn bootstrap_methods(&self) -> &[BootstrapMethod] {
+		self.bootstrap_methods.as_slice()
+	}
+	
+	/// Keeps the bootstrap methods of a parsed class at their original indices, so that code copied
+	/// unchanged still refers to the right entries
+	pub(crate) fn seed_bootstrap_methods(&mut self, methods: Vec<BootstrapMethod>) {
+		self.bootstrap_methods = methods;
+	}
+	
+	pub fn module(&mut self, name_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::Module(ModuleInfo::new(name_index)))
 	}
 	
-	pub fn package(&mut self, name_index: CPIndex) -> CPIndex {
+	pub fn package(&mut self, name_index: CPIndex) -> Result<CPIndex> {
 		self.put(ConstantType::Package(PackageInfo::new(name_index)))
 	}
 	
 	pub fn write<W: Write>(&mut self, wtr: &mut W) -> Result<()> {
 		wtr.write_u16::<BigEndian>(self.index as u16)?;
-		for (constant, _index) in self.inner.iter() {
+		for constant in self.seed.iter().flatten() {
 			constant.write(wtr)?;
 		}
+		let seeded = self.seed.len() as CPIndex;
+		for (constant, index) in self.inner.iter() {
+			if *index >= seeded {
+				constant.write(wtr)?;
+			}
+		}
 		
 		Ok(())
 	}
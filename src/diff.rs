@@ -0,0 +1,566 @@
+use crate::classfile::ClassFile;
+use crate::version::ClassVersion;
+use crate::access::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use crate::attributes::{Attribute, Annotation, ConstantValue, ElementValue, StackMapFrame, VerificationType};
+use crate::code::{CodeAttribute, ExceptionHandler};
+use crate::field::Field;
+use crate::method::Method;
+use crate::ast::{Insn, LabelInsn, LdcType, BootstrapArgument};
+use crate::intern::Name;
+use crate::error::Result;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Stands in for every label when instructions are compared regardless of where they jump to
+const ANY_LABEL: u32 = u32::MAX;
+/// Stands in for the labels of the old code that mark an instruction missing from the new code
+const UNMATCHED_LABEL: u32 = u32::MAX - 1;
+
+/// An item only present in the old or the new version, or present in both but different
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change<T> {
+	Added(T),
+	Removed(T),
+	Changed(T, T)
+}
+
+/// The differences between two versions of a class, see `diff`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClassDiff {
+	/// Each of these pairs is the old value followed by the new one, None if it did not change
+	pub version: Option<(ClassVersion, ClassVersion)>,
+	pub access_flags: Option<(ClassAccessFlags, ClassAccessFlags)>,
+	pub this_class: Option<(Name, Name)>,
+	pub super_class: Option<(Option<Name>, Option<Name>)>,
+	pub added_interfaces: Vec<Name>,
+	pub removed_interfaces: Vec<Name>,
+	/// Fields are matched by name and descriptor
+	pub added_fields: Vec<Field>,
+	pub removed_fields: Vec<Field>,
+	pub changed_fields: Vec<FieldDiff>,
+	/// Methods are matched by name and descriptor
+	pub added_methods: Vec<Method>,
+	pub removed_methods: Vec<Method>,
+	pub changed_methods: Vec<MethodDiff>,
+	/// Attributes are matched by name
+	pub attributes: Vec<Change<Attribute>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDiff {
+	pub name: Name,
+	pub descriptor: Name,
+	pub access_flags: Option<(FieldAccessFlags, FieldAccessFlags)>,
+	pub attributes: Vec<Change<Attribute>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodDiff {
+	pub name: Name,
+	pub descriptor: Name,
+	pub access_flags: Option<(MethodAccessFlags, MethodAccessFlags)>,
+	/// Every attribute but the code, unless the method only has code in one of the versions
+	pub attributes: Vec<Change<Attribute>>,
+	/// None if both versions have the same code, or if either has none
+	pub code: Option<CodeDiff>
+}
+
+/// The differences between two method bodies. Labels are not compared by identity but by the
+/// instruction they mark: a jump is unchanged as long as its target is the counterpart of its old
+/// target
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CodeDiff {
+	pub max_stack: Option<(u16, u16)>,
+	pub max_locals: Option<(u16, u16)>,
+	/// The edits turning the old instructions into the new ones, in order. Labels are not
+	/// instructions and are never edits themselves
+	pub insns: Vec<InsnEdit>,
+	/// The old and new handlers, if any of them changed
+	pub exceptions: Option<(Vec<ExceptionHandler>, Vec<ExceptionHandler>)>,
+	/// The attributes of the code, such as its line numbers, matched by name
+	pub attributes: Vec<Change<Attribute>>
+}
+
+/// Indices are those of the instruction in its `InsnList`
+#[derive(Clone, Debug, PartialEq)]
+pub enum InsnEdit {
+	/// An instruction only in the new code
+	Inserted(usize, Insn),
+	/// An instruction only in the old code
+	Removed(usize, Insn),
+	/// An instruction of the old code replaced by one of the new code
+	Changed {
+		old_index: usize,
+		new_index: usize,
+		old: Insn,
+		new: Insn
+	}
+}
+
+/// Compares two versions of a class, such as those from two builds of the same jar. Method bodies
+/// are decoded, so that their instructions are compared rather than their bytes
+pub fn diff(old: &ClassFile, new: &ClassFile) -> Result<ClassDiff> {
+	let mut diff = ClassDiff {
+		version: changed(&old.version, &new.version),
+		access_flags: changed(&old.access_flags, &new.access_flags),
+		this_class: changed(&old.this_class, &new.this_class),
+		super_class: changed(&old.super_class, &new.super_class),
+		added_interfaces: new.interfaces.iter().filter(|x| !old.interfaces.contains(x)).cloned().collect(),
+		removed_interfaces: old.interfaces.iter().filter(|x| !new.interfaces.contains(x)).cloned().collect(),
+		attributes: diff_attributes(&old.attributes, &new.attributes, same_attribute),
+		..ClassDiff::default()
+	};
+	
+	for field in old.fields.iter() {
+		match new.fields.iter().find(|x| x.name == field.name && x.descriptor == field.descriptor) {
+			Some(new_field) => {
+				let field_diff = FieldDiff {
+					name: field.name.clone(),
+					descriptor: field.descriptor.clone(),
+					access_flags: changed(&field.access_flags, &new_field.access_flags),
+					attributes: diff_attributes(&field.attributes, &new_field.attributes, same_attribute)
+				};
+				if field_diff.access_flags.is_some() || !field_diff.attributes.is_empty() {
+					diff.changed_fields.push(field_diff);
+				}
+			},
+			None => diff.removed_fields.push(field.clone())
+		}
+	}
+	diff.added_fields = new.fields.iter()
+		.filter(|field| !old.fields.iter().any(|x| x.name == field.name && x.descriptor == field.descriptor))
+		.cloned()
+		.collect();
+	
+	for method in old.methods.iter() {
+		match new.methods.iter().find(|x| x.name == method.name && x.descriptor == method.descriptor) {
+			Some(new_method) => {
+				let method_diff = diff_method(method, new_method)?;
+				if method_diff.access_flags.is_some() || !method_diff.attributes.is_empty() || method_diff.code.is_some() {
+					diff.changed_methods.push(method_diff);
+				}
+			},
+			None => diff.removed_methods.push(method.clone())
+		}
+	}
+	diff.added_methods = new.methods.iter()
+		.filter(|method| !old.methods.iter().any(|x| x.name == method.name && x.descriptor == method.descriptor))
+		.cloned()
+		.collect();
+	Ok(diff)
+}
+
+impl ClassDiff {
+	/// Whether both versions are the same
+	pub fn is_empty(&self) -> bool {
+		self == &ClassDiff::default()
+	}
+}
+
+fn changed<T: Clone + PartialEq>(old: &T, new: &T) -> Option<(T, T)> {
+	if old == new {
+		None
+	} else {
+		Some((old.clone(), new.clone()))
+	}
+}
+
+/// Pairs attributes up by name, in order
+fn diff_attributes<F: Fn(&Attribute, &Attribute) -> bool>(old: &[Attribute], new: &[Attribute], same: F) -> Vec<Change<Attribute>> {
+	let mut changes = Vec::new();
+	let mut used = vec![false; new.len()];
+	for attr in old.iter() {
+		let counterpart = new.iter()
+			.enumerate()
+			.position(|(index, x)| !used[index] && x.name() == attr.name());
+		match counterpart {
+			Some(index) => {
+				used[index] = true;
+				if !same(attr, &new[index]) {
+					changes.push(Change::Changed(attr.clone(), new[index].clone()));
+				}
+			},
+			None => changes.push(Change::Removed(attr.clone()))
+		}
+	}
+	for (attr, used) in new.iter().zip(used) {
+		if !used {
+			changes.push(Change::Added(attr.clone()));
+		}
+	}
+	changes
+}
+
+fn diff_method(old: &Method, new: &Method) -> Result<MethodDiff> {
+	let is_code = |attr: &&Attribute| matches!(attr, Attribute::Code(_) | Attribute::LazyCode(_));
+	let old_code = old.decode_code()?;
+	let new_code = new.decode_code()?;
+	let (attributes, code) = match (&old_code, &new_code) {
+		(Some(old_code), Some(new_code)) => {
+			let old_attributes: Vec<Attribute> = old.attributes.iter().filter(|x| !is_code(x)).cloned().collect();
+			let new_attributes: Vec<Attribute> = new.attributes.iter().filter(|x| !is_code(x)).cloned().collect();
+			(diff_attributes(&old_attributes, &new_attributes, same_attribute), diff_code(old_code, new_code))
+		},
+		_ => (diff_attributes(&old.attributes, &new.attributes, same_attribute), None)
+	};
+	Ok(MethodDiff {
+		name: old.name.clone(),
+		descriptor: old.descriptor.clone(),
+		access_flags: changed(&old.access_flags, &new.access_flags),
+		attributes,
+		code
+	})
+}
+
+fn diff_code(old: &CodeAttribute, new: &CodeAttribute) -> Option<CodeDiff> {
+	let old_indices = insn_indices(&old.insns.insns);
+	let new_indices = insn_indices(&new.insns.insns);
+	let old_positions = label_positions(&old.insns.insns);
+	let new_positions = label_positions(&new.insns.insns);
+	
+	// instructions are first aligned regardless of their labels, then the labels of aligned
+	// instructions are compared through the alignment
+	let without_labels = |insns: &[Insn], indices: &[usize]| -> Vec<Insn> {
+		indices.iter()
+			.map(|index| {
+				let mut insn = insns[*index].clone();
+				for label in insn_labels(&mut insn) {
+					*label = LabelInsn::new(ANY_LABEL);
+				}
+				insn
+			})
+			.collect()
+	};
+	let pairs = align(&without_labels(&old.insns.insns, &old_indices), &without_labels(&new.insns.insns, &new_indices));
+	let mut aligned: HashMap<usize, usize> = pairs.iter().copied().collect();
+	aligned.insert(old_indices.len(), new_indices.len());
+	let translate = |label: &mut LabelInsn| {
+		*label = LabelInsn::new(old_positions.get(label)
+			.and_then(|position| aligned.get(position))
+			.map_or(UNMATCHED_LABEL, |position| *position as u32));
+	};
+	let locate = |label: &mut LabelInsn| {
+		*label = LabelInsn::new(new_positions.get(label).map_or(ANY_LABEL, |position| *position as u32));
+	};
+	
+	let mut insns = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	for (a, b) in pairs.iter().copied().chain(std::iter::once((old_indices.len(), new_indices.len()))) {
+		// the unaligned instructions in between are changed in pairs, the remainder is removed or inserted
+		while i < a && j < b {
+			insns.push(InsnEdit::Changed {
+				old_index: old_indices[i],
+				new_index: new_indices[j],
+				old: old.insns.insns[old_indices[i]].clone(),
+				new: new.insns.insns[new_indices[j]].clone()
+			});
+			i += 1;
+			j += 1;
+		}
+		for index in old_indices[i..a].iter() {
+			insns.push(InsnEdit::Removed(*index, old.insns.insns[*index].clone()));
+		}
+		for index in new_indices[j..b].iter() {
+			insns.push(InsnEdit::Inserted(*index, new.insns.insns[*index].clone()));
+		}
+		if a < old_indices.len() {
+			let (old_insn, new_insn) = (&old.insns.insns[old_indices[a]], &new.insns.insns[new_indices[b]]);
+			let mut old_relabeled = old_insn.clone();
+			insn_labels(&mut old_relabeled).into_iter().for_each(translate);
+			let mut new_relabeled = new_insn.clone();
+			insn_labels(&mut new_relabeled).into_iter().for_each(locate);
+			if !same_insn(&old_relabeled, &new_relabeled) {
+				insns.push(InsnEdit::Changed {
+					old_index: old_indices[a],
+					new_index: new_indices[b],
+					old: old_insn.clone(),
+					new: new_insn.clone()
+				});
+			}
+		}
+		i = a + 1;
+		j = b + 1;
+	}
+	
+	let relabel_handlers = |handlers: &[ExceptionHandler], relabel: &dyn Fn(&mut LabelInsn)| -> Vec<ExceptionHandler> {
+		handlers.iter()
+			.map(|handler| {
+				let mut handler = handler.clone();
+				relabel(&mut handler.start);
+				relabel(&mut handler.end);
+				relabel(&mut handler.handler);
+				handler
+			})
+			.collect()
+	};
+	let exceptions = if relabel_handlers(&old.exceptions, &translate) == relabel_handlers(&new.exceptions, &locate) {
+		None
+	} else {
+		Some((old.exceptions.clone(), new.exceptions.clone()))
+	};
+	let attributes = diff_attributes(&old.attributes, &new.attributes, |a, b| {
+		let (mut a, mut b) = (a.clone(), b.clone());
+		attribute_labels(&mut a).into_iter().for_each(translate);
+		attribute_labels(&mut b).into_iter().for_each(locate);
+		same_attribute(&a, &b)
+	});
+	
+	let diff = CodeDiff {
+		max_stack: changed(&old.max_stack, &new.max_stack),
+		max_locals: changed(&old.max_locals, &new.max_locals),
+		insns,
+		exceptions,
+		attributes
+	};
+	if diff == CodeDiff::default() {
+		None
+	} else {
+		Some(diff)
+	}
+}
+
+/// The indices of the instructions that are not labels
+fn insn_indices(insns: &[Insn]) -> Vec<usize> {
+	insns.iter()
+		.enumerate()
+		.filter(|(_, insn)| !matches!(insn, Insn::Label(_)))
+		.map(|(index, _)| index)
+		.collect()
+}
+
+/// The position of the instruction each label marks, counting instructions but not labels
+fn label_positions(insns: &[Insn]) -> HashMap<LabelInsn, usize> {
+	let mut positions = HashMap::new();
+	let mut position = 0;
+	for insn in insns.iter() {
+		match insn {
+			Insn::Label(label) => {
+				positions.insert(*label, position);
+			},
+			_ => position += 1
+		}
+	}
+	positions
+}
+
+/// The labels an instruction refers to
+pub(crate) fn insn_labels(insn: &mut Insn) -> Vec<&mut LabelInsn> {
+	match insn {
+		Insn::Jump(x) => vec![&mut x.jump_to],
+		Insn::ConditionalJump(x) => vec![&mut x.jump_to],
+		Insn::LookupSwitch(x) => std::iter::once(&mut x.default).chain(x.cases.values_mut()).collect(),
+		Insn::TableSwitch(x) => std::iter::once(&mut x.default).chain(x.cases.iter_mut()).collect(),
+		_ => Vec::new()
+	}
+}
+
+/// The labels a code attribute refers to
+pub(crate) fn attribute_labels(attr: &mut Attribute) -> Vec<&mut LabelInsn> {
+	match attr {
+		Attribute::LineNumberTable(x) => x.lines.iter_mut().map(|line| &mut line.start).collect(),
+		Attribute::LocalVariableTable(x) => x.variables.iter_mut()
+			.flat_map(|variable| vec![&mut variable.start, &mut variable.end])
+			.collect(),
+		Attribute::StackMapTable(x) => x.frames.iter_mut()
+			.flat_map(|frame| {
+				let (start, types): (&mut LabelInsn, Vec<&mut VerificationType>) = match frame {
+					StackMapFrame::Same { start } => (start, Vec::new()),
+					StackMapFrame::SameLocals1StackItem { start, stack } => (start, vec![stack]),
+					StackMapFrame::Chop { start, .. } => (start, Vec::new()),
+					StackMapFrame::Append { start, locals } => (start, locals.iter_mut().collect()),
+					StackMapFrame::Full { start, locals, stack } => (start, locals.iter_mut().chain(stack.iter_mut()).collect())
+				};
+				std::iter::once(start).chain(types.into_iter().filter_map(|x| match x {
+					VerificationType::Uninitialized(label) => Some(label),
+					_ => None
+				}))
+			})
+			.collect(),
+		_ => Vec::new()
+	}
+}
+
+/// Whether two instructions are the same, floats and doubles being compared by their bits so that
+/// a NaN constant is the same as itself
+fn same_insn(a: &Insn, b: &Insn) -> bool {
+	match (a, b) {
+		(Insn::Ldc(a), Insn::Ldc(b)) => match (&a.constant, &b.constant) {
+			(LdcType::Float(a), LdcType::Float(b)) => a.to_bits() == b.to_bits(),
+			(LdcType::Double(a), LdcType::Double(b)) => a.to_bits() == b.to_bits(),
+			(a, b) => a == b
+		},
+		(Insn::InvokeDynamic(a), Insn::InvokeDynamic(b)) => a.name == b.name && a.descriptor == b.descriptor
+			&& a.bootstrap_handle == b.bootstrap_handle
+			&& a.bootstrap_arguments.len() == b.bootstrap_arguments.len()
+			&& a.bootstrap_arguments.iter().zip(b.bootstrap_arguments.iter()).all(|pair| match pair {
+				(BootstrapArgument::Float(a), BootstrapArgument::Float(b)) => a.to_bits() == b.to_bits(),
+				(BootstrapArgument::Double(a), BootstrapArgument::Double(b)) => a.to_bits() == b.to_bits(),
+				(a, b) => a == b
+			}),
+		_ => a == b
+	}
+}
+
+/// Whether two attributes are the same, comparing their constants like `same_insn`
+fn same_attribute(a: &Attribute, b: &Attribute) -> bool {
+	match (a, b) {
+		(Attribute::ConstantValue(a), Attribute::ConstantValue(b)) => match (&a.value, &b.value) {
+			(ConstantValue::Float(a), ConstantValue::Float(b)) => a.to_bits() == b.to_bits(),
+			(ConstantValue::Double(a), ConstantValue::Double(b)) => a.to_bits() == b.to_bits(),
+			(a, b) => a == b
+		},
+		(Attribute::Annotations(a), Attribute::Annotations(b)) => a.visible == b.visible
+			&& same_annotations(&a.annotations, &b.annotations),
+		(Attribute::ParameterAnnotations(a), Attribute::ParameterAnnotations(b)) => a.visible == b.visible
+			&& a.parameters.len() == b.parameters.len()
+			&& a.parameters.iter().zip(b.parameters.iter()).all(|(a, b)| same_annotations(a, b)),
+		(Attribute::AnnotationDefault(a), Attribute::AnnotationDefault(b)) => same_element_value(&a.value, &b.value),
+		_ => a == b
+	}
+}
+
+fn same_annotations(a: &[Annotation], b: &[Annotation]) -> bool {
+	a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| {
+		a.descriptor == b.descriptor && a.elements.len() == b.elements.len()
+			&& a.elements.iter().zip(b.elements.iter()).all(|((a_name, a), (b_name, b))| a_name == b_name && same_element_value(a, b))
+	})
+}
+
+fn same_element_value(a: &ElementValue, b: &ElementValue) -> bool {
+	match (a, b) {
+		(ElementValue::Float(a), ElementValue::Float(b)) => a.to_bits() == b.to_bits(),
+		(ElementValue::Double(a), ElementValue::Double(b)) => a.to_bits() == b.to_bits(),
+		(ElementValue::Annotation(a), ElementValue::Annotation(b)) => same_annotations(std::slice::from_ref(a), std::slice::from_ref(b)),
+		(ElementValue::Array(a), ElementValue::Array(b)) => a.len() == b.len()
+			&& a.iter().zip(b.iter()).all(|(a, b)| same_element_value(a, b)),
+		_ => a == b
+	}
+}
+
+/// The most entries the table of `align` may hold, 16 MiB
+const MAX_ALIGN_CELLS: usize = 1 << 22;
+
+/// The pairs of indices of a longest common subsequence of two lists, in order. The common prefix
+/// and suffix are skipped before the quadratic search of the rest, which is given up when its table
+/// would be too large, leaving the rest unaligned so that it is replaced as a whole
+fn align(old: &[Insn], new: &[Insn]) -> Vec<(usize, usize)> {
+	let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| same_insn(a, b)).count();
+	let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| same_insn(a, b)).count();
+	let (mut a, mut b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+	if (a.len() + 1).saturating_mul(b.len() + 1) > MAX_ALIGN_CELLS {
+		a = &[];
+		b = &[];
+	}
+	
+	// lengths[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+	let width = b.len() + 1;
+	let mut lengths = vec![0u32; (a.len() + 1) * width];
+	for i in (0..a.len()).rev() {
+		for j in (0..b.len()).rev() {
+			lengths[i * width + j] = if same_insn(&a[i], &b[j]) {
+				lengths[(i + 1) * width + j + 1] + 1
+			} else {
+				lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+			};
+		}
+	}
+	
+	let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+	let (mut i, mut j) = (0, 0);
+	while i < a.len() && j < b.len() {
+		if same_insn(&a[i], &b[j]) {
+			pairs.push((prefix + i, prefix + j));
+			i += 1;
+			j += 1;
+		} else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+			i += 1;
+		} else {
+			j += 1;
+		}
+	}
+	pairs.extend((0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)));
+	pairs
+}
+
+impl Display for ClassDiff {
+	/// A line per change, with the changes of a member indented below it
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		if let Some((old, new)) = &self.this_class {
+			writeln!(f, "class {} -> {}", old, new)?;
+		}
+		if let Some((old, new)) = &self.version {
+			writeln!(f, "version {:?}.{} -> {:?}.{}", old.major, old.minor, new.major, new.minor)?;
+		}
+		if let Some((old, new)) = &self.access_flags {
+			writeln!(f, "access {:?} -> {:?}", old, new)?;
+		}
+		if let Some((old, new)) = &self.super_class {
+			let name = |x: &Option<Name>| x.as_ref().map_or("none".to_string(), |x| x.to_string());
+			writeln!(f, "superclass {} -> {}", name(old), name(new))?;
+		}
+		for interface in self.added_interfaces.iter() {
+			writeln!(f, "+ interface {}", interface)?;
+		}
+		for interface in self.removed_interfaces.iter() {
+			writeln!(f, "- interface {}", interface)?;
+		}
+		write_attributes(f, &self.attributes, "")?;
+		for field in self.added_fields.iter() {
+			writeln!(f, "+ field {} {}", field.name, field.descriptor)?;
+		}
+		for field in self.removed_fields.iter() {
+			writeln!(f, "- field {} {}", field.name, field.descriptor)?;
+		}
+		for field in self.changed_fields.iter() {
+			writeln!(f, "~ field {} {}", field.name, field.descriptor)?;
+			if let Some((old, new)) = &field.access_flags {
+				writeln!(f, "    access {:?} -> {:?}", old, new)?;
+			}
+			write_attributes(f, &field.attributes, "    ")?;
+		}
+		for method in self.added_methods.iter() {
+			writeln!(f, "+ method {}{}", method.name, method.descriptor)?;
+		}
+		for method in self.removed_methods.iter() {
+			writeln!(f, "- method {}{}", method.name, method.descriptor)?;
+		}
+		for method in self.changed_methods.iter() {
+			writeln!(f, "~ method {}{}", method.name, method.descriptor)?;
+			if let Some((old, new)) = &method.access_flags {
+				writeln!(f, "    access {:?} -> {:?}", old, new)?;
+			}
+			write_attributes(f, &method.attributes, "    ")?;
+			if let Some(code) = &method.code {
+				if let Some((old, new)) = code.max_stack {
+					writeln!(f, "    max stack {} -> {}", old, new)?;
+				}
+				if let Some((old, new)) = code.max_locals {
+					writeln!(f, "    max locals {} -> {}", old, new)?;
+				}
+				for edit in code.insns.iter() {
+					match edit {
+						InsnEdit::Inserted(index, insn) => writeln!(f, "    + {}: {:?}", index, insn)?,
+						InsnEdit::Removed(index, insn) => writeln!(f, "    - {}: {:?}", index, insn)?,
+						InsnEdit::Changed { old_index, new_index, old, new } => writeln!(f, "    ~ {} -> {}: {:?} -> {:?}", old_index, new_index, old, new)?
+					}
+				}
+				if code.exceptions.is_some() {
+					writeln!(f, "    ~ exception handlers")?;
+				}
+				write_attributes(f, &code.attributes, "    ")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn write_attributes(f: &mut Formatter<'_>, attributes: &[Change<Attribute>], indent: &str) -> std::fmt::Result {
+	for change in attributes.iter() {
+		match change {
+			Change::Added(attr) => writeln!(f, "{}+ attribute {}", indent, attr.name())?,
+			Change::Removed(attr) => writeln!(f, "{}- attribute {}", indent, attr.name())?,
+			Change::Changed(attr, _) => writeln!(f, "{}~ attribute {}", indent, attr.name())?
+		}
+	}
+	Ok(())
+}
@@ -4,6 +4,7 @@ use std::fmt::{Debug};
 use crate::constantpool::ConstantType;
 use std::str::Utf8Error;
 use std::string::FromUtf8Error;
+use zip::result::ZipError;
 
 #[derive(Error, Debug)]
 pub enum ParserError {
@@ -38,8 +39,30 @@ pub enum ParserError {
 	InvalidUtf8(Utf8Error),
 	#[error("Too many instructions in method")]
 	TooManyInstructions(),
+	#[error("Too many constants in constant pool")]
+	TooManyConstants(),
+	#[error("Invalid constant pool reference from {index} to {reference} (expected {expected})")]
+	BadCpReference {
+		index: u16,
+		reference: u16,
+		expected: &'static str
+	},
 	#[error("Invalid Descriptor: {0}")]
 	InvalidDescriptor(String),
+	#[error("Invalid archive: {0}")]
+	Archive(ZipError),
+	#[error("Class not found: {0}")]
+	ClassNotFound(String),
+	#[error("Invalid mappings at line {line}: {msg}")]
+	InvalidMappings {
+		line: usize,
+		msg: String
+	},
+	#[error("Analysis failed at instruction {index}: {msg}")]
+	Analysis {
+		index: usize,
+		msg: String
+	},
 	#[error("{0}")]
 	Other(String)
 }
@@ -107,10 +130,46 @@ impl ParserError {
 		ParserError::TooManyInstructions().check_panic()
 	}
 	
+	pub fn too_many_constants() -> Self {
+		ParserError::TooManyConstants().check_panic()
+	}
+	
+	pub fn bad_cp_reference(index: u16, reference: u16, expected: &'static str) -> Self {
+		ParserError::BadCpReference {
+			index,
+			reference,
+			expected
+		}.check_panic()
+	}
+	
 	pub fn invalid_descriptor<T: Into<String>>(msg: T) -> Self {
 		ParserError::InvalidDescriptor(msg.into()).check_panic()
 	}
 	
+	pub fn archive(err: ZipError) -> Self {
+		ParserError::Archive(err).check_panic()
+	}
+	
+	pub fn class_not_found<T: Into<String>>(name: T) -> Self {
+		ParserError::ClassNotFound(name.into()).check_panic()
+	}
+	
+	/// Lines are numbered from 1
+	pub fn invalid_mappings<T: Into<String>>(line: usize, msg: T) -> Self {
+		ParserError::InvalidMappings {
+			line,
+			msg: msg.into()
+		}.check_panic()
+	}
+	
+	/// The index is that of the instruction in its `InsnList`
+	pub fn analysis<T: Into<String>>(index: usize, msg: T) -> Self {
+		ParserError::Analysis {
+			index,
+			msg: msg.into()
+		}.check_panic()
+	}
+	
 	#[inline]
 	pub fn other<T>(name: T) -> Self
 		where T: Into<String> {
@@ -137,4 +196,13 @@ impl From<std::string::FromUtf8Error> for ParserError {
 	}
 }
 
+impl From<ZipError> for ParserError {
+	fn from(err: ZipError) -> Self {
+		match err {
+			ZipError::Io(inner) => ParserError::io(inner),
+			err => ParserError::archive(err)
+		}
+	}
+}
+
 pub type Result<T> = result::Result<T, ParserError>;
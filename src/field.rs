@@ -3,7 +3,9 @@ use crate::access::FieldAccessFlags;
 use crate::constantpool::{ConstantPool, ConstantPoolWriter};
 use crate::attributes::{Attributes, Attribute, AttributeSource, SignatureAttribute};
 use crate::version::ClassVersion;
+use crate::classfile::ParseOptions;
 use crate::error::Result;
+use crate::intern::Name;
 use crate::utils::{VecUtils};
 use std::io::{Read, Write};
 use byteorder::{ReadBytesExt, BigEndian, WriteBytesExt};
@@ -15,12 +17,13 @@ pub mod Fields {
 	use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 	use crate::version::ClassVersion;
 	use crate::constantpool::{ConstantPool, ConstantPoolWriter};
+	use crate::classfile::ParseOptions;
 	
-	pub fn parse<T: Read>(rdr: &mut T, version: &ClassVersion, constant_pool: &ConstantPool) -> crate::Result<Vec<Field>> {
+	pub fn parse<T: Read>(rdr: &mut T, version: &ClassVersion, constant_pool: &ConstantPool, options: &ParseOptions) -> crate::Result<Vec<Field>> {
 		let num_fields = rdr.read_u16::<BigEndian>()? as usize;
 		let mut fields: Vec<Field> = Vec::with_capacity(num_fields);
 		for _ in 0..num_fields {
-			fields.push(Field::parse(rdr, version, constant_pool)?);
+			fields.push(Field::parse(rdr, version, constant_pool, options)?);
 		}
 		Ok(fields)
 	}
@@ -37,17 +40,17 @@ pub mod Fields {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Field {
 	pub access_flags: FieldAccessFlags,
-	pub name: String,
-	pub descriptor: String,
+	pub name: Name,
+	pub descriptor: Name,
 	pub attributes: Vec<Attribute>
 }
 
 impl Field {
-	pub fn parse<R: Read>(rdr: &mut R, version: &ClassVersion, constant_pool: &ConstantPool) -> Result<Self> {
+	pub fn parse<R: Read>(rdr: &mut R, version: &ClassVersion, constant_pool: &ConstantPool, options: &ParseOptions) -> Result<Self> {
 		let access_flags = FieldAccessFlags::parse(rdr)?;
 		let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
 		let descriptor = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
-		let attributes = Attributes::parse(rdr, AttributeSource::Field, version, constant_pool, &mut None)?;
+		let attributes = Attributes::parse(rdr, AttributeSource::Field, version, constant_pool, &mut None, options)?;
 		
 		Ok(Field {
 			access_flags,
@@ -57,7 +60,7 @@ impl Field {
 		})
 	}
 	
-	pub fn signature(&mut self) -> Option<&mut String> {
+	pub fn signature(&mut self) -> Option<&mut Name> {
 		for attr in self.attributes.iter_mut() {
 			if let Attribute::Signature(sig) = attr {
 				return Some(&mut sig.signature)
@@ -66,7 +69,7 @@ impl Field {
 		return None
 	}
 	
-	pub fn set_signature(&mut self, sig: Option<String>) {
+	pub fn set_signature(&mut self, sig: Option<Name>) {
 		// According to the JVM spec there must be at most one signature attribute in the attributes table
 		// first find the index of the existing sig
 		let index = self.attributes.find_first(|attr| {
@@ -86,8 +89,8 @@ impl Field {
 	
 	pub fn write<W: Write>(&self, wtr: &mut W, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
 		self.access_flags.write(wtr)?;
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.name.clone()))?;
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.descriptor.clone()))?;
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.name)?)?;
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.descriptor)?)?;
 		Attributes::write(wtr, &self.attributes, constant_pool, None)?;
 		Ok(())
 	}
@@ -0,0 +1,347 @@
+use crate::classfile::ClassFile;
+use crate::access::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use crate::ast::Insn;
+use crate::method::Method;
+use crate::intern::Name;
+use crate::error::Result;
+use derive_more::Constructor;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+
+/// Identifies a method by the class declaring it, its name and its descriptor
+#[derive(Constructor, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MethodRef {
+	pub owner: Name,
+	pub name: Name,
+	pub descriptor: Name
+}
+
+impl Display for MethodRef {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}.{}{}", self.owner, self.name, self.descriptor)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodNode {
+	pub access_flags: MethodAccessFlags,
+	pub name: Name,
+	pub descriptor: Name,
+	/// The method a bridge method delegates to, None for methods that are not bridges
+	pub bridge_target: Option<MethodRef>
+}
+
+impl MethodNode {
+	pub fn is_bridge(&self) -> bool {
+		self.access_flags.contains(MethodAccessFlags::BRIDGE)
+	}
+	
+	/// Whether this method takes part in overriding, which constructors, static and private methods
+	/// do not
+	pub fn is_virtual(&self) -> bool {
+		!self.access_flags.intersects(MethodAccessFlags::PRIVATE | MethodAccessFlags::STATIC)
+			&& !self.name.starts_with('<')
+	}
+	
+	pub fn is_package_private(&self) -> bool {
+		!self.access_flags.intersects(MethodAccessFlags::PUBLIC | MethodAccessFlags::PROTECTED | MethodAccessFlags::PRIVATE)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldNode {
+	pub access_flags: FieldAccessFlags,
+	pub name: Name,
+	pub descriptor: Name
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassNode {
+	pub name: Name,
+	pub access_flags: ClassAccessFlags,
+	pub super_class: Option<Name>,
+	pub interfaces: Vec<Name>,
+	pub fields: Vec<FieldNode>,
+	pub methods: Vec<MethodNode>
+}
+
+impl ClassNode {
+	pub fn is_interface(&self) -> bool {
+		self.access_flags.contains(ClassAccessFlags::INTERFACE)
+	}
+	
+	/// The package of this class, such as `java/lang`, empty for the default package
+	pub fn package(&self) -> &str {
+		package(&self.name)
+	}
+	
+	/// A class cannot declare two fields of the same name, so fields are found by name alone
+	pub fn field(&self, name: &str) -> Option<&FieldNode> {
+		self.fields.iter().find(|field| field.name == name)
+	}
+	
+	pub fn method(&self, name: &str, descriptor: &str) -> Option<&MethodNode> {
+		self.methods.iter().find(|method| method.name == name && method.descriptor == descriptor)
+	}
+	
+	pub fn method_ref(&self, method: &MethodNode) -> MethodRef {
+		MethodRef::new(self.name.clone(), method.name.clone(), method.descriptor.clone())
+	}
+}
+
+fn package(name: &str) -> &str {
+	match name.rfind('/') {
+		Some(index) => &name[..index],
+		None => ""
+	}
+}
+
+/// The classes of a program linked by their super class, interface and subtype edges, along with
+/// which methods override which.
+///
+/// Supertypes that are not part of the graph, such as those of the JDK, still appear as edges but
+/// their methods are unknown, so nothing is recorded as overriding them
+#[derive(Clone, Debug, Default)]
+pub struct InheritanceGraph {
+	classes: BTreeMap<Name, ClassNode>,
+	/// The direct subclasses and implementors of each class, sorted by name
+	subtypes: HashMap<Name, Vec<Name>>,
+	/// The methods each method overrides, nearest first
+	overrides: HashMap<MethodRef, Vec<MethodRef>>,
+	/// The methods overriding each method, sorted
+	overridden_by: HashMap<MethodRef, Vec<MethodRef>>
+}
+
+impl InheritanceGraph {
+	/// Builds the graph of the given classes. Code that was parsed lazily is decoded to find the
+	/// targets of bridge methods
+	pub fn build<'a, I: IntoIterator<Item = &'a ClassFile>>(classes: I) -> Result<Self> {
+		let mut graph = InheritanceGraph::default();
+		for class in classes {
+			let methods = class.methods.iter()
+				.map(|method| Ok(MethodNode {
+					access_flags: method.access_flags,
+					name: method.name.clone(),
+					descriptor: method.descriptor.clone(),
+					bridge_target: bridge_invoke(method)?
+				}))
+				.collect::<Result<Vec<MethodNode>>>()?;
+			let fields = class.fields.iter()
+				.map(|field| FieldNode {
+					access_flags: field.access_flags,
+					name: field.name.clone(),
+					descriptor: field.descriptor.clone()
+				})
+				.collect();
+			graph.classes.insert(class.this_class.clone(), ClassNode {
+				name: class.this_class.clone(),
+				access_flags: class.access_flags,
+				super_class: class.super_class.clone(),
+				interfaces: class.interfaces.clone(),
+				fields,
+				methods
+			});
+		}
+		graph.link();
+		Ok(graph)
+	}
+	
+	fn link(&mut self) {
+		for class in self.classes.values() {
+			for supertype in class.super_class.iter().chain(class.interfaces.iter()) {
+				self.subtypes.entry(supertype.clone()).or_default().push(class.name.clone());
+			}
+		}
+		for subtypes in self.subtypes.values_mut() {
+			subtypes.sort();
+			subtypes.dedup();
+		}
+	
+		// bridges invoke their target through the class they are declared in, so point them at the
+		// class actually declaring the target
+		let mut targets = Vec::new();
+		for class in self.classes.values() {
+			for (index, method) in class.methods.iter().enumerate() {
+				if let Some(target) = &method.bridge_target {
+					if let Some(resolved) = self.resolve_method(&target.owner, &target.name, &target.descriptor) {
+						targets.push((class.name.clone(), index, resolved));
+					}
+				}
+			}
+		}
+		for (class, index, target) in targets {
+			self.classes.get_mut(&class).unwrap().methods[index].bridge_target = Some(target);
+		}
+	
+		let mut overrides = HashMap::new();
+		for class in self.classes.values() {
+			let supertypes = self.supertypes(&class.name);
+			for method in class.methods.iter().filter(|method| method.is_virtual()) {
+				let overridden: Vec<MethodRef> = supertypes.iter()
+					.filter_map(|name| self.classes.get(name))
+					.filter_map(|supertype| {
+						let candidate = supertype.method(&method.name, &method.descriptor)?;
+						let visible = candidate.is_virtual()
+							&& (!candidate.is_package_private() || supertype.package() == class.package());
+						if visible {
+							Some(supertype.method_ref(candidate))
+						} else {
+							None
+						}
+					})
+					.collect();
+				if !overridden.is_empty() {
+					overrides.insert(class.method_ref(method), overridden);
+				}
+			}
+		}
+		for (method, overridden) in overrides.iter() {
+			for target in overridden {
+				self.overridden_by.entry(target.clone()).or_default().push(method.clone());
+			}
+		}
+		for methods in self.overridden_by.values_mut() {
+			methods.sort();
+		}
+		self.overrides = overrides;
+	}
+	
+	/// Every class in the graph, sorted by name
+	pub fn classes(&self) -> impl Iterator<Item = &ClassNode> {
+		self.classes.values()
+	}
+	
+	pub fn class(&self, name: &str) -> Option<&ClassNode> {
+		self.classes.get(name)
+	}
+	
+	pub fn method(&self, method: &MethodRef) -> Option<&MethodNode> {
+		self.class(&method.owner)?.method(&method.name, &method.descriptor)
+	}
+	
+	pub fn super_class(&self, name: &str) -> Option<&Name> {
+		self.class(name)?.super_class.as_ref()
+	}
+	
+	pub fn interfaces(&self, name: &str) -> &[Name] {
+		match self.class(name) {
+			Some(class) => &class.interfaces,
+			None => &[]
+		}
+	}
+	
+	/// The classes directly extending or implementing the given class, sorted by name
+	pub fn direct_subtypes(&self, name: &str) -> &[Name] {
+		match self.subtypes.get(name) {
+			Some(x) => x,
+			None => &[]
+		}
+	}
+	
+	/// Every class extending or implementing the given class, directly or not, nearest first
+	pub fn all_subtypes(&self, name: &str) -> Vec<Name> {
+		self.walk(name, |name| self.direct_subtypes(name).iter().collect())
+	}
+	
+	/// Every class or interface the given class extends or implements, directly or not, breadth
+	/// first with the super class of each class before its interfaces. Supertypes outside the graph
+	/// are included, but not their own supertypes
+	pub fn supertypes(&self, name: &str) -> Vec<Name> {
+		self.walk(name, |name| match self.class(name) {
+			Some(class) => class.super_class.iter().chain(class.interfaces.iter()).collect(),
+			None => Vec::new()
+		})
+	}
+	
+	fn walk<'a, F: Fn(&str) -> Vec<&'a Name>>(&'a self, name: &str, next: F) -> Vec<Name> {
+		let mut result = Vec::new();
+		let mut seen = HashSet::new();
+		let mut queue: VecDeque<&Name> = next(name).into();
+		while let Some(current) = queue.pop_front() {
+			// a class is only ever cyclic in malformed input
+			if current.as_str() == name || !seen.insert(current) {
+				continue;
+			}
+			result.push(current.clone());
+			queue.extend(next(current));
+		}
+		result
+	}
+	
+	/// The super classes of the given class, nearest first. The chain ends at the first class
+	/// outside the graph
+	pub fn super_classes(&self, name: &str) -> Vec<Name> {
+		self.walk(name, |name| self.super_class(name).into_iter().collect())
+	}
+	
+	/// Finds the method that a call to the given class would select: the nearest declaration among
+	/// the class and its super classes, otherwise the nearest among its interfaces
+	pub fn resolve_method(&self, class: &str, name: &str, descriptor: &str) -> Option<MethodRef> {
+		std::iter::once(Name::from(class))
+			.chain(self.super_classes(class))
+			.chain(self.supertypes(class))
+			.filter_map(|class| self.classes.get(&class))
+			.find_map(|class| class.method(name, descriptor).map(|method| class.method_ref(method)))
+	}
+	
+	/// Finds the class declaring the field that a reference through the given class would select,
+	/// the nearest among the class and its supertypes. None if no class of the graph declares it
+	pub fn resolve_field(&self, class: &str, name: &str) -> Option<&Name> {
+		std::iter::once(Name::from(class))
+			.chain(self.supertypes(class))
+			.filter_map(|class| self.classes.get(&class))
+			.find(|class| class.field(name).is_some())
+			.map(|class| &class.name)
+	}
+	
+	/// The methods of supertypes that the given method overrides, nearest first. Static, private and
+	/// constructor methods neither override nor are overridden, and package private methods are only
+	/// overridden from the same package
+	pub fn overrides(&self, method: &MethodRef) -> &[MethodRef] {
+		match self.overrides.get(method) {
+			Some(x) => x,
+			None => &[]
+		}
+	}
+	
+	/// The methods of subtypes that override the given method, sorted
+	pub fn overridden_by(&self, method: &MethodRef) -> &[MethodRef] {
+		match self.overridden_by.get(method) {
+			Some(x) => x,
+			None => &[]
+		}
+	}
+	
+	/// The method the given bridge method delegates to
+	pub fn bridge_target(&self, method: &MethodRef) -> Option<&MethodRef> {
+		self.method(method)?.bridge_target.as_ref()
+	}
+	
+	/// The bridge methods delegating to the given method, sorted
+	pub fn bridges(&self, method: &MethodRef) -> Vec<MethodRef> {
+		let mut bridges: Vec<MethodRef> = self.classes.values()
+			.flat_map(|class| class.methods.iter()
+				.filter(|bridge| bridge.bridge_target.as_ref() == Some(method))
+				.map(move |bridge| class.method_ref(bridge)))
+			.collect();
+		bridges.sort();
+		bridges
+	}
+}
+
+/// The method a bridge calls: the invocation of a method with the same name but another descriptor
+fn bridge_invoke(method: &Method) -> Result<Option<MethodRef>> {
+	if !method.access_flags.contains(MethodAccessFlags::BRIDGE) {
+		return Ok(None);
+	}
+	let code = match method.decode_code()? {
+		Some(x) => x,
+		None => return Ok(None)
+	};
+	Ok(code.insns.iter().find_map(|insn| match insn {
+		Insn::Invoke(x) if x.name == method.name && x.descriptor != method.descriptor => {
+			Some(MethodRef::new(x.class.clone(), x.name.clone(), x.descriptor.clone()))
+		},
+		_ => None
+	}))
+}
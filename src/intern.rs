@@ -0,0 +1,204 @@
+use std::borrow::{Borrow, Cow};
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// A shared, reference counted name such as `java/lang/Object` or `()V`
+///
+/// Every name read from a class is a clone of the `Utf8Info` it was read from, so the names of one
+/// class share their memory and comparing two copies of the same name only compares pointers
+#[derive(Clone, PartialOrd, Ord)]
+pub struct Name(Arc<str>);
+
+impl Name {
+	pub fn new(str: &str) -> Self {
+		Name(Arc::from(str))
+	}
+	
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+	
+	/// Whether both names share the same memory
+	pub fn ptr_eq(this: &Name, other: &Name) -> bool {
+		Arc::ptr_eq(&this.0, &other.0)
+	}
+}
+
+impl Deref for Name {
+	type Target = str;
+	
+	fn deref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl AsRef<str> for Name {
+	fn as_ref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl Borrow<str> for Name {
+	fn borrow(&self) -> &str {
+		&self.0
+	}
+}
+
+impl PartialEq for Name {
+	fn eq(&self, other: &Name) -> bool {
+		Name::ptr_eq(self, other) || self.0 == other.0
+	}
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+	// must hash like a str, so that names can be looked up by str
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.as_str().hash(state)
+	}
+}
+
+impl PartialEq<str> for Name {
+	fn eq(&self, other: &str) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl PartialEq<&str> for Name {
+	fn eq(&self, other: &&str) -> bool {
+		self.as_str() == *other
+	}
+}
+
+impl PartialEq<String> for Name {
+	fn eq(&self, other: &String) -> bool {
+		self.as_str() == other.as_str()
+	}
+}
+
+impl PartialEq<Cow<'_, str>> for Name {
+	fn eq(&self, other: &Cow<'_, str>) -> bool {
+		self.as_str() == other.as_ref()
+	}
+}
+
+impl PartialEq<Name> for str {
+	fn eq(&self, other: &Name) -> bool {
+		self == other.as_str()
+	}
+}
+
+impl PartialEq<Name> for &str {
+	fn eq(&self, other: &Name) -> bool {
+		*self == other.as_str()
+	}
+}
+
+impl Debug for Name {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		Debug::fmt(self.as_str(), f)
+	}
+}
+
+impl Display for Name {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		Display::fmt(self.as_str(), f)
+	}
+}
+
+impl From<&str> for Name {
+	fn from(str: &str) -> Self {
+		Name::new(str)
+	}
+}
+
+impl From<String> for Name {
+	fn from(str: String) -> Self {
+		Name(Arc::from(str))
+	}
+}
+
+impl From<&String> for Name {
+	fn from(str: &String) -> Self {
+		Name::new(str)
+	}
+}
+
+impl From<Cow<'_, str>> for Name {
+	fn from(str: Cow<'_, str>) -> Self {
+		Name::new(&str)
+	}
+}
+
+impl From<Name> for String {
+	fn from(name: Name) -> Self {
+		name.as_str().to_string()
+	}
+}
+
+/// Shares names between classes, so that parsing many classes keeps a single copy of each name
+///
+/// Clones of an interner share the same names
+#[derive(Clone, Default)]
+pub struct Interner {
+	names: Arc<Mutex<HashSet<Name>>>
+}
+
+impl Interner {
+	pub fn new() -> Self {
+		Interner::default()
+	}
+	
+	/// Returns the shared copy of the given name, adding it if it is not interned yet
+	pub fn intern(&self, str: &str) -> Name {
+		let mut names = self.names.lock().unwrap();
+		match names.get(str) {
+			Some(name) => name.clone(),
+			None => {
+				let name = Name::new(str);
+				names.insert(name.clone());
+				name
+			}
+		}
+	}
+	
+	/// Returns the shared copy of the given name, adding the name itself if it is not interned yet
+	pub fn intern_name(&self, name: &Name) -> Name {
+		let mut names = self.names.lock().unwrap();
+		match names.get(name) {
+			Some(name) => name.clone(),
+			None => {
+				names.insert(name.clone());
+				name.clone()
+			}
+		}
+	}
+	
+	pub fn len(&self) -> usize {
+		self.names.lock().unwrap().len()
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+impl PartialEq for Interner {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.names, &other.names)
+	}
+}
+
+impl Eq for Interner {}
+
+impl Debug for Interner {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Interner")
+			.field("names", &self.len())
+			.finish()
+	}
+}
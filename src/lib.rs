@@ -15,6 +15,26 @@ pub mod ast;
 pub mod insnlist;
 pub mod error;
 pub mod types;
+pub mod visitor;
+pub mod borrowed;
+pub mod intern;
+pub mod archive;
+pub mod classpath;
+pub mod hierarchy;
+pub mod callgraph;
+pub mod remap;
+pub mod mappings;
+pub mod relocate;
+pub mod matching;
+pub mod diff;
+pub mod compat;
+pub mod verify;
+pub mod typecheck;
+pub mod cfg;
+pub mod analysis;
+pub mod liveness;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 mod utils;
 
 
@@ -25,11 +45,47 @@ pub trait Serializable : Sized {
 
 #[cfg(test)]
 mod tests {
-	use crate::classfile::ClassFile;
-	use crate::error::Result;
+	use crate::classfile::{ClassFile, ParseOptions};
+	use crate::constantpool::{ConstantPool, ConstantPoolWriter, ConstantType, Utf8Info};
+	use crate::visitor::{self, ClassFileBuilder, ClassWriter};
+	use crate::borrowed::{BorrowedClassFile, BorrowedAttribute};
+	use crate::attributes::{Attribute, AttributeSource, ElementValue, StackMapFrame, VerificationType};
+	use crate::method::Method;
+	use crate::intern::{Name, Interner};
+	use crate::archive::{Archive, MANIFEST};
+	use crate::classpath::{ClassPath, jdk_fallback};
+	use crate::hierarchy::{InheritanceGraph, MethodRef};
+	use crate::callgraph::{CallGraph, CallKind};
+	use crate::ast::{Insn, BootstrapArgument, LdcType};
+	use crate::remap::remap;
+	use crate::mappings::Mappings;
+	use crate::relocate::{Relocator, Relocation};
+	use crate::matching::match_classes;
+	use crate::diff::{diff, InsnEdit};
+	use crate::compat::{check_compatibility, worst_breakage, ApiChange, Breakage};
+	use crate::verify::{verify_structure, Location};
+	use crate::typecheck::verify_types;
+	use crate::cfg::{ControlFlowGraph, EdgeKind};
+	use crate::analysis::{Analyzer, BasicInterpreter, BasicValue, TypeInterpreter, TypeValue, SourceInterpreter};
+	use crate::liveness::{Liveness, DefUse, Definition};
+	use crate::ast::{NopInsn, LdcInsn, NewObjectInsn, InvokeInsn, InvokeType, PopInsn, OpType};
+	use crate::ast::{LocalLoadInsn, JumpInsn, ConditionalJumpInsn, JumpCondition, CompareInsn, ReturnInsn, ReturnType, PrimitiveType};
+	use crate::ast::{LabelInsn, GetFieldInsn, LocalStoreInsn, NewArrayInsn, ArrayLengthInsn, ArrayLoadInsn, DupInsn};
+	use crate::types::Type;
+	use crate::code::{CodeAttribute, ExceptionHandler};
+	use crate::insnlist::InsnList;
+	use crate::version::{ClassVersion, MajorVersion};
+	use crate::access::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+	use crate::attributes::{ConstantValue, ConstantValueAttribute};
+	use std::sync::Arc;
+	use std::borrow::Cow;
+	use std::collections::HashMap;
+	use crate::error::{Result, ParserError};
+	use crate::Serializable;
 	use std::fs::{self, File, DirEntry, OpenOptions};
-	use std::io::{BufReader, BufWriter};
+	use std::io::{BufReader, BufWriter, Cursor};
 	use std::process::Command;
+	use std::path::PathBuf;
 	
 	fn read(dir: &str) -> Result<ClassFile> {
 		// Read
@@ -50,6 +106,26 @@ mod tests {
 	    Ok(class)
     }
 	
+	/// Compiles a class from classes/testing with a fixed target into its own temporary directory
+	fn compile(name: &str, dir: &str) -> Result<Vec<u8>> {
+		let out = javac(name, dir)?;
+		Ok(fs::read(out.join(format!("{}.class", name)))?)
+	}
+	
+	/// Compiles the test source into a directory below the temp dir, laid out by package
+	fn javac(name: &str, dir: &str) -> Result<PathBuf> {
+		let out = std::env::temp_dir().join(dir);
+		fs::create_dir_all(&out)?;
+		let status = Command::new("javac")
+			.args(["-source", "1.8", "-target", "1.8", "-d"])
+			.arg(&out)
+			.arg(format!("classes/testing/{}.java", name))
+			.status()
+			.unwrap();
+		assert!(status.success());
+		Ok(out)
+	}
+	
 	fn walk(dir: &str, op: &dyn Fn(DirEntry) -> Result<()>) -> Result<()> {
 		for entry in fs::read_dir(dir)? {
 			let entry = entry?;
@@ -110,4 +186,1104 @@ mod tests {
 		})?;
 		Ok(())
 	}
+	
+	#[test]
+	fn test_exact_round_trip() -> Result<()> {
+		// switches, branches and invokedynamic instructions are written back with the same encoding they
+		// were read with
+		for name in ["LookupSwitch", "Frames", "Calls"].iter() {
+			let bytes = compile(name, "classfile-rs-exact")?;
+			let class = ClassFile::parse_preserving_pool(&mut bytes.as_slice())?;
+			let mut written: Vec<u8> = Vec::with_capacity(bytes.len());
+			class.write(&mut written)?;
+			assert_eq!(bytes, written);
+		}
+		
+		let bytes = compile("Constants", "classfile-rs-exact")?;
+		let class = ClassFile::parse_preserving_pool(&mut bytes.as_slice())?;
+		assert!(!class.access_flags.contains(ClassAccessFlags::SUPER));
+		let mut written: Vec<u8> = Vec::with_capacity(bytes.len());
+		class.write(&mut written)?;
+		assert_eq!(bytes, written);
+		
+		let mut class = class;
+		let index = class.constant_pool.as_ref().unwrap().find_utf8("STRING").unwrap();
+		assert_eq!(class.constant_usages()?[index as usize], 1);
+		class.edit_constant_pool(|pool| {
+			pool.replace(index, ConstantType::Utf8(Utf8Info::new(String::from("RENAMED"))))?;
+			Ok(())
+		})?;
+		assert!(class.fields.iter().any(|field| field.name == "RENAMED"));
+		
+		let bytes = compile("Counter", "classfile-rs-exact")?;
+		let mut class = ClassFile::parse_preserving_pool(&mut bytes.as_slice())?;
+		assert!(class.access_flags.contains(ClassAccessFlags::SUPER));
+		let mut written: Vec<u8> = Vec::with_capacity(bytes.len());
+		class.write(&mut written)?;
+		assert_eq!(bytes, written);
+		let index = class.constant_pool.as_ref().unwrap().find_utf8("total").unwrap();
+		// the field, the method and the references to the field share the name
+		assert_eq!(class.constant_usages()?[index as usize], 3);
+		class.edit_constant_pool(|pool| {
+			pool.replace(index, ConstantType::Utf8(Utf8Info::new(String::from("count"))))?;
+			Ok(())
+		})?;
+		assert!(class.fields.iter().any(|field| field.name == "count"));
+		assert!(class.methods.iter().any(|method| method.name == "count"));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_wide_jumps() -> Result<()> {
+		let mut insns = InsnList::new();
+		let start = insns.new_label();
+		let end = insns.new_label();
+		insns.insns.push(Insn::Label(start));
+		insns.insns.push(Insn::LocalLoad(LocalLoadInsn::new(OpType::Int, 0)));
+		insns.insns.push(Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntEqZero, end)));
+		// more than 32 KiB of code between each jump and its target
+		insns.insns.extend(std::iter::repeat(Insn::Nop(NopInsn::new())).take(40000));
+		insns.insns.push(Insn::Jump(JumpInsn::new(start)));
+		insns.insns.push(Insn::Label(end));
+		insns.insns.push(Insn::Return(ReturnInsn::new(ReturnType::Void)));
+		let code = CodeAttribute::new(1, 1, insns, Vec::new(), Vec::new());
+		let mut bytes = Vec::new();
+		code.write(&mut bytes, &mut ConstantPoolWriter::new())?;
+		
+		// past max_stack, max_locals and code_length, the condition is negated to jump over a wide goto
+		let code_bytes = &bytes[8..];
+		assert_eq!(code_bytes[..5], [0x1A, 0x9A, 0, 8, 0xC8]);
+		assert_eq!(code_bytes[5..9], 40010i32.to_be_bytes());
+		assert_eq!(code_bytes[40009], 0xC8);
+		assert_eq!(code_bytes[40010..40014], (-40009i32).to_be_bytes());
+		assert_eq!(code_bytes[40014], 0xB1);
+		
+		let version = ClassVersion { major: MajorVersion::JAVA_8, minor: 0 };
+		let parsed = CodeAttribute::parse(&version, &ConstantPool::new(), bytes.clone(), &ParseOptions::default())?;
+		assert!(matches!(parsed.insns.insns[2], Insn::ConditionalJump(x) if x.condition == JumpCondition::IntNotEqZero));
+		assert!(matches!(parsed.insns.insns[3], Insn::Jump(_)));
+		let mut written = Vec::new();
+		parsed.write(&mut written, &mut ConstantPoolWriter::new())?;
+		assert_eq!(bytes, written);
+		
+		// ints are widened and compared as longs
+		let mut insns = InsnList::new();
+		insns.insns.push(Insn::LocalLoad(LocalLoadInsn::new(OpType::Int, 0)));
+		insns.insns.push(Insn::LocalLoad(LocalLoadInsn::new(OpType::Int, 1)));
+		insns.insns.push(Insn::Compare(CompareInsn::new(PrimitiveType::Int, false)));
+		insns.insns.push(Insn::Return(ReturnInsn::new(ReturnType::Int)));
+		let mut bytes = Vec::new();
+		CodeAttribute::new(4, 2, insns, Vec::new(), Vec::new()).write(&mut bytes, &mut ConstantPoolWriter::new())?;
+		assert_eq!(bytes[8..17], [0x1A, 0x1B, 0x85, 0x5D, 0x58, 0x85, 0x94, 0x74, 0xAC]);
+		
+		// a widened branch of a method with frames needs a frame after it, which is not invented
+		let mut class = ClassFile::parse(&mut compile("Frames", "classfile-rs-wide")?.as_slice())?;
+		let sum = class.methods.iter_mut().find(|x| x.name == "sum").unwrap();
+		let code = sum.code()?.unwrap();
+		let branch = code.insns.insns.iter().position(|insn| matches!(insn, Insn::ConditionalJump(x) if x.condition == JumpCondition::IntLessThanOrEqZero)).unwrap();
+		code.insns.insns.splice(branch + 1..branch + 1, std::iter::repeat(Insn::Nop(NopInsn::new())).take(40000));
+		let err = class.write(&mut Vec::new()).unwrap_err();
+		assert!(matches!(err, ParserError::InvalidInstruction { .. }));
+		
+		let sum = class.methods.iter_mut().find(|x| x.name == "sum").unwrap();
+		sum.code()?.unwrap().attributes.retain(|attr| !matches!(attr, Attribute::StackMapTable(_)));
+		let mut written = Vec::new();
+		class.write(&mut written)?;
+		let class = ClassFile::parse(&mut written.as_slice())?;
+		let sum = class.methods.iter().find(|x| x.name == "sum").unwrap().decode_code()?.unwrap();
+		assert!(sum.insns.insns.iter().any(|insn| matches!(insn, Insn::ConditionalJump(x) if x.condition == JumpCondition::IntGreaterThanZero)));
+		let classpath = ClassPath::new().with_fallback(jdk_fallback());
+		assert_eq!(verify_types(&class, &classpath)?, Vec::new());
+		Ok(())
+	}
+	
+	#[test]
+	fn test_too_many_constants() -> Result<()> {
+		let mut pool = ConstantPoolWriter::new();
+		// indices 1 to 65534 are usable
+		for i in 1..65534 {
+			pool.integer(i)?;
+		}
+		// a long needs two slots, so does not fit in the last one
+		assert!(matches!(pool.long(0), Err(ParserError::TooManyConstants())));
+		assert_eq!(pool.integer(65534)?, 65534);
+		assert!(matches!(pool.integer(65535), Err(ParserError::TooManyConstants())));
+		// constants already in the pool are still found
+		assert_eq!(pool.integer(1)?, 1);
+		
+		// entries taken from the writer to build others are counted once, as are those it finds itself
+		let mut pool = ConstantPoolWriter::new();
+		let name = pool.utf8("Foo")?;
+		let class = pool.class(name)?;
+		let value = pool.utf8("foo")?;
+		pool.string(value)?;
+		let (field, descriptor) = (pool.utf8("count")?, pool.utf8("I")?);
+		let name_and_type = pool.nameandtype(field, descriptor)?;
+		pool.fieldref(class, name_and_type)?;
+		let usages = pool.usages();
+		for index in [name, class, value, field, descriptor, name_and_type].iter() {
+			assert_eq!(usages[*index as usize], 1);
+		}
+		let class = pool.class_utf8("Bar")?;
+		// the class and this lookup
+		let name = pool.utf8("Bar")?;
+		assert_eq!(pool.usages()[name as usize], 2);
+		assert_eq!(pool.usages()[class as usize], 1);
+		Ok(())
+	}
+	
+	#[test]
+	fn test_straddling_constant() -> Result<()> {
+		// a count of 2 leaves only slot 1, but a long occupies slots 1 and 2
+		let bytes = [0, 2, 5, 0, 0, 0, 0, 0, 0, 0, 1];
+		assert!(matches!(ConstantPool::parse(&mut &bytes[..]), Err(ParserError::BadCpIndex(1))));
+		let bytes = [0, 3, 5, 0, 0, 0, 0, 0, 0, 0, 1];
+		assert_eq!(ConstantPool::parse(&mut &bytes[..])?.len(), 2);
+		Ok(())
+	}
+	
+	#[test]
+	fn test_bad_cp_reference() -> Result<()> {
+		// a class whose name refers to an integer
+		let bytes = [0, 3, 7, 0, 2, 3, 0, 0, 0, 0];
+		let result = ConstantPool::parse(&mut &bytes[..]);
+		assert!(matches!(result, Err(ParserError::BadCpReference { index: 1, reference: 2, expected: "Utf8" })));
+		// and one whose name is out of range
+		let bytes = [0, 2, 7, 0, 2];
+		let result = ConstantPool::parse(&mut &bytes[..]);
+		assert!(matches!(result, Err(ParserError::BadCpReference { index: 1, reference: 2, expected: "Utf8" })));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_visitor() -> Result<()> {
+		// classes with fields, methods, code, frames and invokedynamic
+		for name in ["Constants", "Frames", "Calls"].iter() {
+			let bytes = compile(name, "classfile-rs-visitor")?;
+			let class = ClassFile::parse(&mut bytes.as_slice())?;
+			
+			let mut builder = ClassFileBuilder::new();
+			visitor::read(&mut bytes.as_slice(), &mut builder)?;
+			assert_eq!(class, builder.build()?);
+			
+			// the BootstrapMethods attribute refers to the new pool, but the invokedynamic instructions
+			// resolved against it must be the same
+			let without_bootstrap_methods = |mut class: ClassFile| {
+				class.attributes.retain(|attr| !matches!(attr, Attribute::Unknown(x) if x.name == "BootstrapMethods"));
+				class
+			};
+			let mut writer = ClassWriter::new(Vec::new());
+			class.accept(&mut writer)?;
+			let written = writer.into_inner();
+			assert_eq!(without_bootstrap_methods(class.clone()), without_bootstrap_methods(ClassFile::parse(&mut written.as_slice())?));
+			
+			// reading straight into a writer gives the same class
+			let mut writer = ClassWriter::new(Vec::new());
+			visitor::read(&mut bytes.as_slice(), &mut writer)?;
+			let written = writer.into_inner();
+			assert_eq!(without_bootstrap_methods(class), without_bootstrap_methods(ClassFile::parse(&mut written.as_slice())?));
+		}
+		Ok(())
+	}
+	
+	#[test]
+	fn test_borrowed() -> Result<()> {
+		for name in ["Constants", "Calls", "Frames"].iter() {
+			let bytes = compile(name, "classfile-rs-borrowed")?;
+			let class = ClassFile::parse(&mut bytes.as_slice())?;
+			let borrowed = BorrowedClassFile::parse(&bytes)?;
+			assert_eq!(class.this_class, borrowed.this_class);
+			assert!(matches!(borrowed.this_class, Cow::Borrowed(_)));
+			assert_eq!(class.methods.len(), borrowed.methods.len());
+			let constant_pool = borrowed.constant_pool.to_constant_pool()?;
+			for (method, borrowed_method) in class.methods.iter().zip(borrowed.methods.iter()) {
+				assert_eq!(method.name, borrowed_method.name);
+				assert_eq!(method.descriptor, borrowed_method.descriptor);
+				// bodies decoded on their own, invokedynamic included, match those of the whole class
+				let code = borrowed_method.decode_code(&borrowed, &constant_pool, &ParseOptions::default()).transpose()?;
+				assert_eq!(method.decode_code()?.map(Cow::into_owned), code);
+			}
+			assert!(borrowed.constant_pool.class_names().any(|name| name.unwrap() == "java/lang/Object"));
+			assert_eq!(borrowed.constant_pool.len(), constant_pool.len());
+			assert_eq!(class, borrowed.to_class_file()?);
+			
+			// the attributes of code are decoded along with the labels of the code
+			let code = match borrowed.methods.iter().find_map(|method| method.code()) {
+				Some(code) => code.data,
+				None => continue
+			};
+			let length = u32::from_be_bytes([code[4], code[5], code[6], code[7]]) as usize;
+			let handlers = u16::from_be_bytes([code[8 + length], code[9 + length]]) as usize;
+			let rest = &code[8 + length + 2 + handlers * 8 + 2..];
+			let attribute = BorrowedAttribute {
+				name: Cow::Owned(constant_pool.utf8(u16::from_be_bytes([rest[0], rest[1]]))?.str.to_string()),
+				data: &rest[6..6 + u32::from_be_bytes([rest[2], rest[3], rest[4], rest[5]]) as usize]
+			};
+			assert_eq!(attribute.name, "LineNumberTable");
+			assert!(attribute.decode(AttributeSource::Code, &borrowed.version, &constant_pool, None).is_err());
+			let mut labels = HashMap::new();
+			assert!(matches!(attribute.decode(AttributeSource::Code, &borrowed.version, &constant_pool, Some(&mut labels))?, Attribute::LineNumberTable(_)));
+			assert!(!labels.is_empty());
+		}
+		Ok(())
+	}
+	
+	#[test]
+	fn test_lazy_code() -> Result<()> {
+		for name in ["Frames", "Calls"].iter() {
+			let bytes = compile(name, "classfile-rs-lazy")?;
+			let mut class = ClassFile::parse_lazy(&mut bytes.as_slice())?;
+			let mut written: Vec<u8> = Vec::with_capacity(bytes.len());
+			class.write(&mut written)?;
+			assert_eq!(bytes, written);
+			// writing left every body undecoded, so each was copied as it was read
+			for method in class.methods.iter() {
+				assert!(!method.attributes.iter().any(|attr| matches!(attr, Attribute::Code(_))));
+			}
+			
+			let eager = ClassFile::parse(&mut bytes.as_slice())?;
+			for (method, eager) in class.methods.clone().iter_mut().zip(eager.methods.iter()) {
+				let code = method.code()?.unwrap().clone();
+				assert_eq!(Some(&Attribute::Code(code)), eager.attributes.iter().find(|attr| matches!(attr, Attribute::Code(_))));
+			}
+			
+			// a rewritten body is encoded again while the others are still copied
+			let is_call = |insn: &Insn| matches!(insn, Insn::Invoke(x) if x.name != "<init>");
+			let index = class.methods.iter()
+				.position(|method| method.decode_code().unwrap().map_or(false, |code| code.insns.insns.iter().any(is_call)))
+				.unwrap();
+			let code = class.methods[index].code()?.unwrap();
+			for insn in code.insns.insns.iter_mut() {
+				match insn {
+					Insn::Invoke(x) if x.name != "<init>" => x.name = Name::from(format!("{}Renamed", x.name)),
+					_ => {}
+				}
+			}
+			let modified = code.clone();
+			let mut written: Vec<u8> = Vec::with_capacity(bytes.len());
+			class.write(&mut written)?;
+			let reparsed = ClassFile::parse(&mut written.as_slice())?;
+			for (i, (method, eager)) in reparsed.methods.iter().zip(eager.methods.iter()).enumerate() {
+				if i == index {
+					assert_eq!(Some(&modified), method.decode_code()?.as_deref());
+				} else {
+					assert_eq!(eager.decode_code()?, method.decode_code()?);
+				}
+			}
+		}
+		Ok(())
+	}
+	
+	#[test]
+	fn test_parse_options() -> Result<()> {
+		let bytes = compile("Frames", "classfile-rs-options")?;
+		let options = ParseOptions {
+			skip_debug: true,
+			expand_frames: true,
+			..ParseOptions::default()
+		};
+		let mut class = ClassFile::parse_with_options(&mut bytes.as_slice(), &options)?;
+		assert!(class.attributes.is_empty());
+		
+		let frames = |method: &mut Method| -> Result<Vec<(Vec<VerificationType>, Vec<VerificationType>)>> {
+			let code = method.code()?.unwrap();
+			assert!(!code.attributes.iter().any(|attr| matches!(attr, Attribute::LineNumberTable(_))));
+			Ok(code.attributes.iter().filter_map(|attr| match attr {
+				Attribute::StackMapTable(table) => Some(table),
+				_ => None
+			}).flat_map(|table| table.frames.iter()).map(|frame| match frame {
+				StackMapFrame::Full { locals, stack, .. } => (locals.clone(), stack.clone()),
+				frame => panic!("{:?} was not expanded", frame)
+			}).collect())
+		};
+		let array = VerificationType::Object("[I".into());
+		let sum = frames(&mut class.methods[1])?;
+		assert_eq!(sum[0].0, vec![array.clone(), VerificationType::Long, VerificationType::Long, VerificationType::Integer]);
+		assert_eq!(sum[2].0, vec![array, VerificationType::Long, VerificationType::Long]);
+		let describe = frames(&mut class.methods[2])?;
+		assert_eq!(describe[2].0, vec![
+			VerificationType::Object("Frames".into()),
+			VerificationType::Double,
+			VerificationType::Object("java/lang/String".into())
+		]);
+		assert_eq!(describe[2].1, vec![VerificationType::Object("java/lang/RuntimeException".into())]);
+		
+		// lazy code is expanded once it is decoded
+		let mut lazy = ClassFile::parse_with_options(&mut bytes.as_slice(), &ParseOptions {
+			lazy_code: true,
+			..options
+		})?;
+		assert_eq!(frames(&mut lazy.methods[1])?, sum);
+		assert_eq!(frames(&mut lazy.methods[2])?, describe);
+		
+		let class = ClassFile::parse_with_options(&mut bytes.as_slice(), &ParseOptions {
+			skip_code: true,
+			..ParseOptions::default()
+		})?;
+		assert!(class.methods.iter().all(|method| method.attributes.is_empty()));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_interned_names() -> Result<()> {
+		let frames = compile("Frames", "classfile-rs-interned")?;
+		let switch = compile("LookupSwitch", "classfile-rs-interned")?;
+		let init = |class: &ClassFile| class.methods.iter().find(|method| method.name == "<init>").unwrap().name.clone();
+		// without an interner every class has its own copy of each name
+		let first = ClassFile::parse(&mut frames.as_slice())?;
+		let second = ClassFile::parse(&mut frames.as_slice())?;
+		assert_eq!(init(&first), init(&second));
+		assert!(!Name::ptr_eq(&init(&first), &init(&second)));
+		
+		let options = ParseOptions {
+			interner: Some(Interner::new()),
+			..ParseOptions::default()
+		};
+		let frames = ClassFile::parse_with_options(&mut frames.as_slice(), &options)?;
+		let switch = ClassFile::parse_with_options(&mut switch.as_slice(), &options)?;
+		assert!(Name::ptr_eq(&init(&frames), &init(&switch)));
+		assert!(Name::ptr_eq(frames.super_class.as_ref().unwrap(), switch.super_class.as_ref().unwrap()));
+		Ok(())
+	}
+	
+	#[test]
+	#[cfg(feature = "parallel")]
+	fn test_parallel() -> Result<()> {
+		use crate::parallel;
+		let input = std::env::temp_dir().join("classfile-rs-parallel");
+		let output = std::env::temp_dir().join("classfile-rs-parallel-out");
+		let _ = fs::remove_dir_all(&input);
+		let _ = fs::remove_dir_all(&output);
+		fs::create_dir_all(input.join("nested"))?;
+		fs::write(input.join("nested/Frames.class"), compile("Frames", "classfile-rs-parallel-src")?)?;
+		fs::write(input.join("LookupSwitch.class"), compile("LookupSwitch", "classfile-rs-parallel-src")?)?;
+		fs::write(input.join("Broken.class"), [0xCA, 0xFE])?;
+		// a class that is listed but cannot be read
+		std::os::unix::fs::symlink(input.join("Missing.class"), input.join("nested/Dangling.class"))?;
+		
+		let (sources, errors) = parallel::read_dir(&input)?;
+		assert_eq!(sources.len(), 3);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].0.to_str(), Some("nested/Dangling.class"));
+		
+		let batch = parallel::transform_dir(&input, &output, &ParseOptions::default(), |_, class| {
+			class.this_class = format!("renamed/{}", class.this_class).into();
+			Ok(())
+		})?;
+		let paths: Vec<_> = batch.results.iter().map(|(path, _)| path.to_str().unwrap()).collect();
+		assert_eq!(paths, vec!["LookupSwitch.class", "nested/Frames.class"]);
+		let errors: Vec<_> = batch.errors.iter().map(|(path, _)| path.to_str().unwrap()).collect();
+		assert_eq!(errors, vec!["Broken.class", "nested/Dangling.class"]);
+		
+		let (sources, errors) = parallel::read_dir(&output)?;
+		assert!(errors.is_empty());
+		let written = parallel::parse_all(&sources, &ParseOptions::default());
+		assert!(written.is_ok());
+		assert_eq!(written.results[1].1.this_class, "renamed/Frames");
+		
+		// a jar is written back with its entries in order and its resources unchanged
+		let mut jar = Archive::new();
+		jar.insert(MANIFEST, b"Manifest-Version: 1.0\r\n".to_vec());
+		jar.insert("nested/Frames.class", compile("Frames", "classfile-rs-parallel-src")?);
+		jar.insert("nested/data.txt", b"resource".to_vec());
+		jar.insert("Broken.class", vec![0xCA, 0xFE]);
+		jar.insert("LookupSwitch.class", compile("LookupSwitch", "classfile-rs-parallel-src")?);
+		let mut bytes = Cursor::new(Vec::new());
+		let batch = parallel::transform_archive(&jar, &mut bytes, &ParseOptions::default(), |_, class| {
+			class.this_class = format!("renamed/{}", class.this_class).into();
+			Ok(())
+		})?;
+		let paths: Vec<_> = batch.results.iter().map(|(path, _)| path.to_str().unwrap()).collect();
+		assert_eq!(paths, vec!["LookupSwitch.class", "nested/Frames.class"]);
+		assert_eq!(batch.errors.len(), 1);
+		assert_eq!(batch.errors[0].0.to_str(), Some("Broken.class"));
+		let written = Archive::read(Cursor::new(bytes.into_inner()))?;
+		let names: Vec<&str> = written.entries.iter().map(|entry| entry.name.as_str()).collect();
+		assert_eq!(names, jar.entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<&str>>());
+		assert_eq!(written.get("nested/data.txt").unwrap().data, b"resource");
+		assert_eq!(written.get("Broken.class").unwrap().data, [0xCA, 0xFE]);
+		let frames = ClassFile::parse(&mut written.get("nested/Frames.class").unwrap().data.as_slice())?;
+		assert_eq!(frames.this_class, "renamed/Frames");
+		Ok(())
+	}
+	
+	#[test]
+	fn test_archive() -> Result<()> {
+		let frames = compile("Frames", "classfile-rs-archive")?;
+		let switch = compile("LookupSwitch", "classfile-rs-archive")?;
+		let mut archive = Archive::new();
+		archive.insert("Frames.class", frames.clone());
+		archive.insert("res/data.txt", b"resource".to_vec());
+		archive.insert("META-INF/versions/11/Frames.class", switch.clone());
+		archive.insert("META-INF/versions/17/Frames.class", switch);
+		archive.insert(MANIFEST, b"Manifest-Version: 1.0\r\nMulti-Release: true\r\n".to_vec());
+		
+		let mut bytes = Cursor::new(Vec::new());
+		archive.write(&mut bytes)?;
+		let mut archive = Archive::read(Cursor::new(bytes.get_ref()))?;
+		let names: Vec<&str> = archive.entries.iter().map(|entry| entry.name.as_str()).collect();
+		assert_eq!(names, vec![MANIFEST, "Frames.class", "res/data.txt", "META-INF/versions/11/Frames.class", "META-INF/versions/17/Frames.class"]);
+		assert!(archive.is_multi_release());
+		assert_eq!(archive.classes_for_release(8)[0].data, frames);
+		let release = archive.classes_for_release(15);
+		assert_eq!(release.len(), 1);
+		assert_eq!(release[0].release(), Some(11));
+		assert_eq!(release[0].class_name(), Some("Frames"));
+		
+		let errors = archive.transform(&ParseOptions::default(), |_, class| {
+			class.this_class = format!("renamed/{}", class.this_class).into();
+			Ok(())
+		});
+		assert!(errors.is_empty());
+		assert_eq!(archive.get("res/data.txt").unwrap().data, b"resource");
+		for (_, class) in archive.parse_classes(&ParseOptions::default()) {
+			assert!(class?.this_class.starts_with("renamed/"));
+		}
+		
+		// unchanged timestamps and order give identical archives
+		let mut first = Cursor::new(Vec::new());
+		let mut second = Cursor::new(Vec::new());
+		archive.write(&mut first)?;
+		Archive::read(Cursor::new(first.get_ref()))?.write(&mut second)?;
+		assert_eq!(first.into_inner(), second.into_inner());
+		Ok(())
+	}
+	
+	#[test]
+	fn test_class_path() -> Result<()> {
+		compile("Hierarchy", "classfile-rs-classpath")?;
+		let mut class_path = ClassPath::new().with_fallback(jdk_fallback());
+		class_path.add_dir(std::env::temp_dir().join("classfile-rs-classpath"));
+		
+		assert_eq!(class_path.super_classes("Circle")?, vec![Name::from("Base"), Name::from("java/lang/Object")]);
+		assert_eq!(class_path.all_interfaces("Circle")?, vec![Name::from("java/io/Serializable"), Name::from("Shape"), Name::from("java/lang/Comparable")]);
+		assert!(class_path.is_assignable_from("Shape", "Square")?);
+		assert!(class_path.is_assignable_from("java/lang/Comparable", "Circle")?);
+		assert!(!class_path.is_assignable_from("java/io/Serializable", "Square")?);
+		assert!(class_path.is_assignable_from("[LShape;", "[LCircle;")?);
+		assert!(!class_path.is_assignable_from("[LCircle;", "[LSquare;")?);
+		assert!(class_path.is_assignable_from("java/lang/Cloneable", "[I")?);
+		assert_eq!(class_path.common_super_class("Circle", "Square")?, "Base");
+		assert_eq!(class_path.common_super_class("Circle", "java/lang/String")?, "java/lang/Object");
+		assert_eq!(class_path.common_super_class("Base", "Circle")?, "Base");
+		assert!(Arc::ptr_eq(&class_path.require("Circle")?, &class_path.require("Circle")?));
+		assert!(class_path.class("java/lang/String")?.is_none());
+		assert!(class_path.super_classes("Missing").is_err());
+		Ok(())
+	}
+	
+	#[test]
+	fn test_inheritance_graph() -> Result<()> {
+		compile("Hierarchy", "classfile-rs-hierarchy")?;
+		let dir = std::env::temp_dir().join("classfile-rs-hierarchy");
+		let classes = ["Hierarchy", "Shape", "Base", "Circle", "Square"].iter()
+			.map(|name| ClassFile::parse_lazy(&mut fs::read(dir.join(format!("{}.class", name)))?.as_slice()))
+			.collect::<Result<Vec<ClassFile>>>()?;
+		let graph = InheritanceGraph::build(&classes)?;
+		
+		assert_eq!(graph.direct_subtypes("Base"), &[Name::from("Circle"), Name::from("Square")]);
+		assert_eq!(graph.all_subtypes("Shape"), vec![Name::from("Base"), Name::from("Circle"), Name::from("Square")]);
+		assert_eq!(graph.supertypes("Circle"), vec![Name::from("Base"), Name::from("java/io/Serializable"), Name::from("java/lang/Object"), Name::from("Shape"), Name::from("java/lang/Comparable")]);
+		
+		let area = |class: &str| MethodRef::new(class.into(), "area".into(), "()D".into());
+		assert_eq!(graph.overrides(&area("Circle")), &[area("Shape")]);
+		assert_eq!(graph.overridden_by(&area("Shape")), &[area("Circle"), area("Square")]);
+		assert!(graph.overrides(&area("Shape")).is_empty());
+		let init = MethodRef::new("Circle".into(), "<init>".into(), "()V".into());
+		assert!(graph.overrides(&init).is_empty());
+		
+		let bridge = MethodRef::new("Shape".into(), "compareTo".into(), "(Ljava/lang/Object;)I".into());
+		let target = MethodRef::new("Shape".into(), "compareTo".into(), "(LShape;)I".into());
+		assert_eq!(graph.bridge_target(&bridge), Some(&target));
+		assert_eq!(graph.bridges(&target), vec![bridge]);
+		assert_eq!(graph.resolve_method("Square", "compareTo", "(LShape;)I"), Some(target));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_call_graph() -> Result<()> {
+		compile("Calls", "classfile-rs-calls")?;
+		let dir = std::env::temp_dir().join("classfile-rs-calls");
+		let classes = ["Calls", "Animal", "Dog", "Cat"].iter()
+			.map(|name| ClassFile::parse(&mut fs::read(dir.join(format!("{}.class", name)))?.as_slice()))
+			.collect::<Result<Vec<ClassFile>>>()?;
+		let hierarchy = InheritanceGraph::build(&classes)?;
+		let graph = CallGraph::build(&classes, &hierarchy)?;
+		let method = |class: &str, name: &str, descriptor: &str| MethodRef::new(class.into(), name.into(), descriptor.into());
+		
+		// invokedynamic instructions are resolved against the bootstrap methods of the class
+		let describe = method("Calls", "describe", "(LAnimal;)Ljava/lang/String;");
+		let code = classes[0].methods.iter().find(|x| x.name == "describe").unwrap().decode_code()?.unwrap();
+		let indy = code.insns.iter().find_map(|insn| match insn {
+			Insn::InvokeDynamic(x) => Some(x.clone()),
+			_ => None
+		}).unwrap();
+		assert_eq!(indy.bootstrap_handle.owner, "java/lang/invoke/LambdaMetafactory");
+		assert!(matches!(&indy.bootstrap_arguments[1], BootstrapArgument::MethodHandle(x) if x.name.starts_with("lambda$describe$")));
+		
+		let lambda = graph.callees(&describe).iter().find(|call| call.kind == CallKind::Dynamic).unwrap().callee.clone();
+		assert_eq!(lambda.owner, "Calls");
+		let sounds: Vec<&MethodRef> = graph.callees(&lambda).iter().map(|call| &call.callee).collect();
+		assert_eq!(sounds, vec![&method("Cat", "sound", "()Ljava/lang/String;"), &method("Dog", "sound", "()Ljava/lang/String;")]);
+		assert!(graph.callees(&describe).iter().any(|call| call.callee == method("java/util/function/Supplier", "get", "()Ljava/lang/Object;")));
+		
+		let helper = method("Calls", "helper", "()V");
+		assert_eq!(graph.callers(&helper).len(), 1);
+		assert_eq!(graph.callers(&helper)[0].caller, method("Calls", "unused", "()V"));
+		let reachable = graph.reachable(&[method("Calls", "main", "([Ljava/lang/String;)V")]);
+		assert!(reachable.contains(&method("Dog", "sound", "()Ljava/lang/String;")));
+		assert!(reachable.contains(&method("Dog", "<init>", "()V")));
+		assert!(!reachable.contains(&helper));
+		assert!(graph.to_dot().contains("\t\"Calls.unused()V\" -> \"Calls.helper()V\" [label=\"static\"];"));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_remap() -> Result<()> {
+		compile("Remap", "classfile-rs-remap")?;
+		let dir = std::env::temp_dir().join("classfile-rs-remap");
+		let mut classes = ["Remap", "Remap$Holder", "Remap$Holder$Item", "Label", "Vehicle", "Car", "Van", "Truck"].iter()
+			.map(|name| ClassFile::parse_lazy(&mut fs::read(dir.join(format!("{}.class", name)))?.as_slice()))
+			.collect::<Result<Vec<ClassFile>>>()?;
+		let mut mappings = Mappings::parse_proguard("\
+# compiler: R8
+Remap -> Remap:
+Remap$Holder -> h:
+Remap$Holder$Item -> h$i:
+    java.lang.Object value -> v
+Vehicle -> a:
+    1:1:int wheels():0:0 -> b
+    java.lang.String name() -> c
+Car -> d:
+    int count -> e
+Label -> f:
+Van -> g:
+Truck -> j:
+")?;
+		mappings.propagate(&InheritanceGraph::build(&classes)?);
+		assert_eq!(mappings.method("Car", "wheels", "()I"), Some(&Name::from("b")));
+		assert_eq!(mappings.method("Car", "<init>", "(I)V"), None);
+		assert_eq!(mappings.field("Van", "count", "I"), Some(&Name::from("e")));
+		assert_eq!(mappings.field("Truck", "count", "I"), None);
+		
+		// every format reads back what it writes
+		let mut tiny = Vec::new();
+		mappings.write_tiny(&mut tiny, "official", "named")?;
+		let tiny = String::from_utf8(tiny).unwrap();
+		assert_eq!(Mappings::parse_tiny(&tiny, "official", "named")?, mappings);
+		assert_eq!(Mappings::parse_tiny(&tiny, "named", "official")?, mappings.inverse());
+		let mut srg = Vec::new();
+		mappings.write_srg(&mut srg)?;
+		assert_eq!(Mappings::parse_srg(std::str::from_utf8(&srg).unwrap())?, mappings);
+		let mut proguard = Vec::new();
+		mappings.write_proguard(&mut proguard)?;
+		assert_eq!(Mappings::parse_proguard(std::str::from_utf8(&proguard).unwrap())?, mappings);
+		let tsrg = Mappings::parse_tsrg("tsrg2 obf srg\nVehicle a\n\twheels ()I b\n\t\tstatic\nCar d\n\tcount e\n")?;
+		assert_eq!(tsrg.method("Vehicle", "wheels", "()I"), Some(&Name::from("b")));
+		assert_eq!(tsrg.field("Car", "count", "I"), Some(&Name::from("e")));
+		
+		for class in classes.iter_mut() {
+			remap(class, &mappings)?;
+		}
+		let car = &classes[5];
+		assert_eq!(car.this_class, "d");
+		assert_eq!(car.super_class.as_deref(), Some("a"));
+		assert_eq!(car.methods.iter().map(|x| x.name.as_str()).collect::<Vec<&str>>(), vec!["<init>", "b", "c"]);
+		let field = &car.fields[0];
+		assert_eq!(field.name, "e");
+		let annotation = field.attributes.iter().find_map(|attr| match attr {
+			Attribute::Annotations(x) => x.annotations.first(),
+			_ => None
+		}).unwrap();
+		assert_eq!(annotation.descriptor, "Lf;");
+		assert_eq!(annotation.elements[1], (Name::from("type"), ElementValue::Class(Name::from("Ld;"))));
+		let seats = classes[6].methods.iter().find(|x| x.name == "seats").unwrap().decode_code()?.unwrap();
+		assert!(seats.insns.iter().any(|insn| matches!(insn, Insn::GetField(x) if x.name == "e")));
+		assert_eq!(classes[7].fields[0].name, "count");
+		
+		let remap_class = &mut classes[0];
+		assert_eq!(remap_class.fields[0].signature().map(|x| x.as_str()), Some("Lh<La;>.i;"));
+		let code = remap_class.methods.iter_mut().find(|x| x.name == "main").unwrap().code()?.unwrap();
+		let handle = code.insns.iter().find_map(|insn| match insn {
+			Insn::InvokeDynamic(x) => x.bootstrap_arguments.iter().find_map(|argument| match argument {
+				BootstrapArgument::MethodHandle(x) => Some(x.clone()),
+				_ => None
+			}),
+			_ => None
+		}).unwrap();
+		assert_eq!((handle.owner.as_str(), handle.name.as_str()), ("a", "b"));
+		// the retained pools are dropped, so the old names are not written
+		for class in classes.iter() {
+			assert!(class.constant_pool.is_none());
+			let mut buf = Vec::new();
+			class.write(&mut buf)?;
+			let pool = ClassFile::parse_preserving_pool(&mut buf.as_slice())?.constant_pool.unwrap();
+			assert_eq!(pool.find_utf8("Vehicle"), None);
+			assert_eq!(pool.find_utf8("LVehicle;"), None);
+		}
+		let mut buf = Vec::new();
+		classes[0].write(&mut buf)?;
+		let mut written = ClassFile::parse(&mut buf.as_slice())?;
+		let code = written.methods.iter_mut().find(|x| x.name == "main").unwrap().code()?.unwrap();
+		assert!(code.insns.iter().any(|insn| matches!(insn, Insn::InvokeDynamic(x) if x.bootstrap_arguments.iter().any(|argument| {
+			matches!(argument, BootstrapArgument::MethodHandle(x) if x.owner == "a" && x.name == "b")
+		}))));
+		
+		// the frames of code decoded when the class is parsed are renamed too
+		let mut frames = ClassFile::parse(&mut compile("Frames", "classfile-rs-remap")?.as_slice())?;
+		let mut mappings = Mappings::new();
+		mappings.add_class("Frames", "F");
+		remap(&mut frames, &mappings)?;
+		let mut buf = Vec::new();
+		frames.write(&mut buf)?;
+		let options = ParseOptions {
+			expand_frames: true,
+			..ParseOptions::default()
+		};
+		let mut frames = ClassFile::parse_with_options(&mut buf.as_slice(), &options)?;
+		let code = frames.methods.iter_mut().find(|x| x.name == "describe").unwrap().code()?.unwrap();
+		let locals = code.attributes.iter().find_map(|attr| match attr {
+			Attribute::StackMapTable(table) => table.frames.iter().find_map(|frame| match frame {
+				StackMapFrame::Full { locals, .. } => locals.first().cloned(),
+				_ => None
+			}),
+			_ => None
+		});
+		assert_eq!(locals, Some(VerificationType::Object("F".into())));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_match_classes() -> Result<()> {
+		compile("Remap", "classfile-rs-match")?;
+		let dir = std::env::temp_dir().join("classfile-rs-match");
+		let read = || ["Remap", "Remap$Holder", "Remap$Holder$Item", "Label", "Vehicle", "Car"].iter()
+			.map(|name| ClassFile::parse_lazy(&mut fs::read(dir.join(format!("{}.class", name)))?.as_slice()))
+			.collect::<Result<Vec<ClassFile>>>();
+		let original = read()?;
+		let mut obfuscated = read()?;
+		let mut mappings = Mappings::parse_proguard("\
+Remap -> Remap:
+Remap$Holder -> h:
+Remap$Holder$Item -> h$i:
+    java.lang.Object value -> v
+Vehicle -> a:
+    int wheels() -> b
+    java.lang.String name() -> c
+Car -> d:
+    int count -> e
+Label -> f:
+")?;
+		mappings.propagate(&InheritanceGraph::build(&original)?);
+		for class in obfuscated.iter_mut() {
+			remap(class, &mappings)?;
+		}
+		
+		let matches = match_classes(&original, &obfuscated)?;
+		let inferred = matches.mappings(0.0);
+		assert_eq!(inferred.class("Vehicle"), Some(&Name::from("a")));
+		assert_eq!(inferred.class("Remap$Holder$Item"), Some(&Name::from("h$i")));
+		assert_eq!(inferred.method("Vehicle", "wheels", "()I"), Some(&Name::from("b")));
+		assert_eq!(inferred.method("Car", "name", "()Ljava/lang/String;"), Some(&Name::from("c")));
+		assert_eq!(inferred.field("Car", "count", "I"), Some(&Name::from("e")));
+		
+		let mut proguard = Vec::new();
+		matches.write_proguard(&mut proguard, 0.0)?;
+		assert_eq!(Mappings::parse_proguard(std::str::from_utf8(&proguard).unwrap())?, inferred);
+		let mut tiny = Vec::new();
+		matches.write_tiny(&mut tiny, "named", "official", 0.0)?;
+		assert_eq!(Mappings::parse_tiny(std::str::from_utf8(&tiny).unwrap(), "named", "official")?, inferred);
+		Ok(())
+	}
+	
+	#[test]
+	fn test_diff() -> Result<()> {
+		compile("Remap", "classfile-rs-diff")?;
+		let dir = std::env::temp_dir().join("classfile-rs-diff");
+		let old = ClassFile::parse(&mut fs::read(dir.join("Remap.class"))?.as_slice())?;
+		assert!(diff(&old, &old.clone())?.is_empty());
+		
+		// instructions inserted before jump targets leave the jumps unchanged
+		let mut new = old.clone();
+		new.fields.clear();
+		let main = new.methods.iter_mut().find(|x| x.name == "main").unwrap();
+		main.access_flags |= MethodAccessFlags::SYNCHRONIZED;
+		let code = main.code()?.unwrap();
+		let label = code.insns.new_label();
+		let jump = code.insns.iter().position(|insn| matches!(insn, Insn::ConditionalJump(_))).unwrap();
+		code.insns.insns.insert(jump, Insn::Nop(NopInsn::new()));
+		code.insns.insns.insert(0, Insn::Label(label));
+		let changes = diff(&old, &new)?;
+		assert_eq!(changes.removed_fields.len(), 1);
+		assert_eq!(changes.changed_methods.len(), 1);
+		let method = &changes.changed_methods[0];
+		assert_eq!(method.name, "main");
+		assert!(method.access_flags.is_some());
+		let code = method.code.as_ref().unwrap();
+		assert_eq!(code.insns, vec![InsnEdit::Inserted(jump + 1, Insn::Nop(NopInsn::new()))]);
+		assert!(changes.to_string().contains("~ method main([Ljava/lang/String;)V"));
+		
+		// bodies too long to align are replaced as a whole
+		let long = |class: &mut ClassFile, start: i32| -> Result<()> {
+			let code = class.methods.iter_mut().find(|x| x.name == "main").unwrap().code()?.unwrap();
+			code.insns.insns = (start..start + 2500).map(|x| Insn::Ldc(LdcInsn::new(LdcType::Int(x)))).collect();
+			Ok(())
+		};
+		let (mut before, mut after) = (old.clone(), old.clone());
+		long(&mut before, 0)?;
+		long(&mut after, 1)?;
+		let changes = diff(&before, &after)?;
+		let code = changes.changed_methods[0].code.as_ref().unwrap();
+		assert_eq!(code.insns.len(), 2500);
+		assert!(code.insns.iter().enumerate().all(|(i, edit)| matches!(edit, InsnEdit::Changed { old_index, new_index, .. } if *old_index == i && *new_index == i)));
+		
+		// NaN constants are the same as themselves
+		let mut nan = old.clone();
+		let code = nan.methods.iter_mut().find(|x| x.name == "main").unwrap().code()?.unwrap();
+		code.insns.insns.insert(0, Insn::Ldc(LdcInsn::new(LdcType::Double(f64::NAN))));
+		code.insns.insns.insert(1, Insn::Pop(PopInsn::new(true)));
+		nan.fields[0].attributes.push(Attribute::ConstantValue(ConstantValueAttribute { value: ConstantValue::Float(f32::NAN) }));
+		assert!(diff(&nan, &nan.clone())?.is_empty());
+		Ok(())
+	}
+	
+	#[test]
+	fn test_compatibility() -> Result<()> {
+		let old = vec![ClassFile::parse(&mut compile("Compat", "classfile-rs-compat")?.as_slice())?];
+		assert!(check_compatibility(&old, &old).is_empty());
+		
+		let mut new = old.clone();
+		let class = &mut new[0];
+		class.access_flags |= ClassAccessFlags::FINAL;
+		class.methods.retain(|x| x.name != "run");
+		for attr in class.fields[0].attributes.iter_mut() {
+			if let Attribute::ConstantValue(x) = attr {
+				x.value = ConstantValue::Int(2);
+			}
+		}
+		let incompatibilities = check_compatibility(&old, &new);
+		let changes: Vec<(Option<&str>, &ApiChange)> = incompatibilities.iter()
+			.map(|x| (x.member.as_ref().map(|(name, _)| name.as_str()), &x.change))
+			.collect();
+		assert_eq!(changes, vec![
+			(None, &ApiChange::ClassNowFinal),
+			(Some("LIMIT"), &ApiChange::ConstantValueChanged(ConstantValue::Int(1), Some(ConstantValue::Int(2)))),
+			(Some("run"), &ApiChange::MethodRemoved)
+		]);
+		assert_eq!(worst_breakage(&incompatibilities), Some(Breakage::Binary));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_verify_structure() -> Result<()> {
+		let mut class = ClassFile::parse(&mut compile("Compat", "classfile-rs-verify")?.as_slice())?;
+		assert_eq!(verify_structure(&class)?, Vec::new());
+		
+		class.access_flags |= ClassAccessFlags::FINAL | ClassAccessFlags::ABSTRACT;
+		class.fields[1].access_flags |= FieldAccessFlags::FINAL | FieldAccessFlags::VOLATILE;
+		let duplicate = class.methods[1].clone();
+		class.methods.push(duplicate);
+		let diagnostics = verify_structure(&class)?;
+		let messages: Vec<String> = diagnostics.iter().map(|x| x.to_string()).collect();
+		assert_eq!(messages, vec![
+			"class: Class is both final and abstract",
+			"field count I: Field is both final and volatile",
+			"method run()V: Duplicate method"
+		]);
+		assert_eq!(diagnostics[2].location, Location::Method { name: Name::from("run"), descriptor: Name::from("()V") });
+		Ok(())
+	}
+	
+	#[test]
+	fn test_verify_types() -> Result<()> {
+		let out = std::env::temp_dir().join("classfile-rs-typecheck");
+		for name in ["Calls", "Compat", "Frames", "Hierarchy", "LookupSwitch"].iter() {
+			javac(name, "classfile-rs-typecheck")?;
+		}
+		let mut classpath = ClassPath::new().with_fallback(jdk_fallback());
+		classpath.add_dir(&out);
+		for entry in fs::read_dir(&out)? {
+			let class = ClassFile::parse(&mut fs::read(entry?.path())?.as_slice())?;
+			assert_eq!(verify_types(&class, &classpath)?, Vec::new(), "{}", class.this_class);
+		}
+		
+		let class = ClassFile::parse(&mut fs::read(out.join("Compat.class"))?.as_slice())?;
+		let messages = |edit: &dyn Fn(&mut Vec<Insn>)| -> Result<Vec<String>> {
+			let mut class = class.clone();
+			let run = class.methods.iter_mut().find(|x| x.name == "run").unwrap();
+			edit(&mut run.code()?.unwrap().insns.insns);
+			Ok(verify_types(&class, &classpath)?.iter().map(|x| x.to_string()).collect())
+		};
+		let position = |insns: &Vec<Insn>, f: &dyn Fn(&Insn) -> bool| insns.iter().position(f).unwrap();
+		assert_eq!(messages(&|insns| {
+			let ret = position(insns, &|insn| matches!(insn, Insn::Return(_)));
+			insns.remove(ret);
+		})?, vec!["method run()V at instruction 7 after L1: Execution falls off the end of the code"]);
+		assert_eq!(messages(&|insns| {
+			let one = position(insns, &|insn| matches!(insn, Insn::Ldc(_)));
+			insns[one] = Insn::Ldc(LdcInsn::new(LdcType::Float(1.0)));
+		})?, vec!["method run()V at instruction 5 after L0: Expected int but found float"]);
+		assert_eq!(messages(&|insns| {
+			let this = position(insns, &|insn| matches!(insn, Insn::LocalLoad(_)));
+			insns.remove(this);
+		})?, vec!["method run()V at instruction 1 after L0: Stack underflow"]);
+		assert_eq!(messages(&|insns| {
+			insns.splice(0..0, vec![
+				Insn::NewObject(NewObjectInsn::new(Name::from("java/lang/StringBuilder"))),
+				Insn::Invoke(InvokeInsn::new(InvokeType::Instance, Name::from("java/lang/StringBuilder"), Name::from("toString"), Name::from("()Ljava/lang/String;"), false)),
+				Insn::Pop(PopInsn::new(false))
+			]);
+		})?, vec!["method run()V at instruction 1: Expected java/lang/StringBuilder but found the uninitialized object created at instruction 0, \
+which is used before its constructor is called"]);
+		
+		// the join is executed again after the int meets the string there, and still reported
+		let (int, class, join) = (LabelInsn::new(1000), LabelInsn::new(1001), LabelInsn::new(1002));
+		let messages_at_join = messages(&|insns| {
+			insns.splice(0..0, vec![
+				Insn::Ldc(LdcInsn::new(LdcType::Int(0))),
+				Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntEqZero, int)),
+				Insn::Ldc(LdcInsn::new(LdcType::String(Name::from("a")))),
+				Insn::Jump(JumpInsn::new(join)),
+				Insn::Label(int),
+				Insn::Ldc(LdcInsn::new(LdcType::Int(0))),
+				Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntEqZero, class)),
+				Insn::Ldc(LdcInsn::new(LdcType::Int(1))),
+				Insn::Jump(JumpInsn::new(join)),
+				Insn::Label(class),
+				Insn::Ldc(LdcInsn::new(LdcType::Class(Name::from("java/lang/String")))),
+				Insn::Label(join),
+				Insn::Pop(PopInsn::new(false))
+			]);
+		})?;
+		assert_eq!(messages_at_join.len(), 1);
+		assert!(messages_at_join[0].ends_with("at instruction 11 after L1002: Stack holds java/lang/String and int where paths meet"), "{}", messages_at_join[0]);
+		
+		// arrays of references that meet are arrays of the common superclass of their elements
+		let (integers, arrays) = (LabelInsn::new(1003), LabelInsn::new(1004));
+		assert_eq!(messages(&|insns| {
+			insns.splice(0..0, vec![
+				Insn::Ldc(LdcInsn::new(LdcType::Int(1))),
+				Insn::Ldc(LdcInsn::new(LdcType::Int(0))),
+				Insn::ConditionalJump(ConditionalJumpInsn::new(JumpCondition::IntEqZero, integers)),
+				Insn::NewArray(NewArrayInsn::new(Type::Reference(Some("java/lang/String".into())))),
+				Insn::Jump(JumpInsn::new(arrays)),
+				Insn::Label(integers),
+				Insn::NewArray(NewArrayInsn::new(Type::Reference(Some("java/lang/Integer".into())))),
+				Insn::Label(arrays),
+				Insn::Dup(DupInsn::new(1, 0)),
+				Insn::ArrayLength(ArrayLengthInsn::new()),
+				Insn::Pop(PopInsn::new(false)),
+				Insn::Ldc(LdcInsn::new(LdcType::Int(0))),
+				Insn::ArrayLoad(ArrayLoadInsn::new(Type::Reference(None))),
+				Insn::Invoke(InvokeInsn::new(InvokeType::Instance, Name::from("java/lang/Object"), Name::from("hashCode"), Name::from("()I"), false)),
+				Insn::Pop(PopInsn::new(false))
+			]);
+		})?, Vec::<String>::new());
+		
+		// invalid descriptors are reported rather than trusted
+		assert_eq!(messages(&|insns| {
+			insns.splice(0..0, vec![
+				Insn::GetField(GetFieldInsn::new(false, Name::from("Compat"), Name::from("broken"), Name::from("L"))),
+				Insn::Invoke(InvokeInsn::new(InvokeType::Static, Name::from("Compat"), Name::from("broken"), Name::from("()"), false))
+			]);
+		})?, vec!["method run()V at instruction 0: Invalid descriptor L"]);
+		assert_eq!(messages(&|insns| {
+			insns.insert(0, Insn::Invoke(InvokeInsn::new(InvokeType::Static, Name::from("Compat"), Name::from("broken"), Name::from("()"), false)));
+		})?, vec!["method run()V at instruction 0: Invalid descriptor ()"]);
+		Ok(())
+	}
+	
+	#[test]
+	fn test_control_flow_graph() -> Result<()> {
+		let mut class = ClassFile::parse(&mut compile("Frames", "classfile-rs-cfg")?.as_slice())?;
+		let sum = class.methods.iter_mut().find(|x| x.name == "sum").unwrap().code()?.unwrap();
+		let graph = ControlFlowGraph::build(sum)?;
+		let order = graph.reverse_post_order();
+		assert_eq!(order.len(), graph.blocks.len());
+		assert_eq!(order[0], 0);
+		let loops = graph.loops();
+		assert_eq!(loops.len(), 1);
+		let dominators = graph.dominators();
+		assert!(loops[0].blocks.iter().all(|block| dominators.dominates(loops[0].header, *block)));
+		assert_eq!(loops[0].back_edges.len(), 1);
+		let latch = loops[0].back_edges[0].from;
+		assert!(matches!(sum.insns.insns[graph.blocks[latch].end - 1], Insn::Jump(_) | Insn::ConditionalJump(_)));
+		let exit = graph.block_of(sum.insns.insns.iter().position(|insn| matches!(insn, Insn::Return(_))).unwrap());
+		assert!(!loops[0].contains(exit));
+		assert_eq!(dominators.immediate_dominator(exit), Some(loops[0].header));
+		
+		let describe = class.methods.iter_mut().find(|x| x.name == "describe").unwrap().code()?.unwrap();
+		let graph = ControlFlowGraph::build(describe)?;
+		let handler = graph.blocks.iter()
+			.flat_map(|block| block.successors.iter())
+			.find(|edge| edge.kind == EdgeKind::Exception(Some(Name::from("java/lang/RuntimeException"))))
+			.unwrap()
+			.to;
+		assert!(graph.blocks[handler].predecessors.iter().all(|edge| matches!(edge.kind, EdgeKind::Exception(_))));
+		assert!(graph.loops().is_empty());
+		assert_eq!(graph.dominators().children(0).len(), 3);
+		Ok(())
+	}
+	
+	#[test]
+	fn test_analyzer() -> Result<()> {
+		let class = ClassFile::parse(&mut compile("Frames", "classfile-rs-analysis")?.as_slice())?;
+		let classpath = ClassPath::new().with_fallback(jdk_fallback());
+		for method in class.methods.iter() {
+			Analyzer::new(BasicInterpreter).analyze(&class.this_class, method)?;
+			Analyzer::new(SourceInterpreter).analyze(&class.this_class, method)?;
+		}
+		
+		let sum = class.methods.iter().find(|x| x.name == "sum").unwrap();
+		let insns = sum.decode_code()?.unwrap().insns.insns.clone();
+		let frames = Analyzer::new(BasicInterpreter).analyze(&class.this_class, sum)?;
+		let ret = insns.iter().position(|insn| matches!(insn, Insn::Return(_))).unwrap();
+		let frame = frames[ret].as_ref().unwrap();
+		assert_eq!(frame.stack, vec![BasicValue::Int]);
+		assert_eq!(frame.locals[..3], [BasicValue::Reference, BasicValue::Long, BasicValue::Uninitialized]);
+		assert_eq!(frame.locals[3].op_type(), Some(OpType::Long));
+		
+		let describe = class.methods.iter().find(|x| x.name == "describe").unwrap();
+		let insns = describe.decode_code()?.unwrap().insns.insns.clone();
+		let frames = Analyzer::new(TypeInterpreter::new(&classpath)).analyze(&class.this_class, describe)?;
+		let string = TypeValue::Reference(Name::from("java/lang/String"));
+		let trim = insns.iter().position(|insn| matches!(insn, Insn::Invoke(x) if x.name == "trim")).unwrap();
+		assert_eq!(frames[trim].as_ref().unwrap().stack, vec![string.clone()]);
+		assert_eq!(frames[trim].as_ref().unwrap().locals[..4], [TypeValue::Reference(Name::from("Frames")), TypeValue::Double, TypeValue::Uninitialized, string]);
+		let handler = insns.iter().rposition(|insn| matches!(insn, Insn::LocalStore(_))).unwrap();
+		assert_eq!(frames[handler].as_ref().unwrap().stack, vec![TypeValue::Reference(Name::from("java/lang/RuntimeException"))]);
+		
+		let compat = ClassFile::parse(&mut compile("Compat", "classfile-rs-analysis")?.as_slice())?;
+		let run = compat.methods.iter().find(|x| x.name == "run").unwrap();
+		let insns = run.decode_code()?.unwrap().insns.insns.clone();
+		let frames = Analyzer::new(SourceInterpreter).analyze(&compat.this_class, run)?;
+		let sources = |index: usize| -> Vec<Vec<usize>> {
+			frames[index].as_ref().unwrap().stack.iter().map(|value| value.insns.iter().copied().collect()).collect()
+		};
+		let position = |f: &dyn Fn(&Insn) -> bool| insns.iter().position(f).unwrap();
+		let load = position(&|insn| matches!(insn, Insn::LocalLoad(_)));
+		let dup = position(&|insn| matches!(insn, Insn::Dup(_)));
+		let get = position(&|insn| matches!(insn, Insn::GetField(_)));
+		let one = position(&|insn| matches!(insn, Insn::Ldc(_)));
+		let add = position(&|insn| matches!(insn, Insn::Add(_)));
+		assert_eq!(sources(get), vec![vec![load], vec![dup]]);
+		assert_eq!(sources(add), vec![vec![load], vec![get], vec![one]]);
+		
+		let mut broken = run.clone();
+		broken.code()?.unwrap().insns.insns.remove(load);
+		let err = Analyzer::new(BasicInterpreter).analyze(&compat.this_class, &broken).unwrap_err();
+		assert!(matches!(err, ParserError::Analysis { index, .. } if index == load));
+		
+		let mut malformed = run.clone();
+		if let Insn::GetField(x) = &mut malformed.code()?.unwrap().insns.insns[get] {
+			x.descriptor = Name::from("L");
+		}
+		let err = Analyzer::new(TypeInterpreter::new(&classpath)).analyze(&compat.this_class, &malformed).unwrap_err();
+		assert!(matches!(err, ParserError::InvalidDescriptor(_)));
+		assert!(TypeValue::of_descriptor("").is_err());
+		Ok(())
+	}
+	
+	#[test]
+	fn test_liveness() -> Result<()> {
+		let class = ClassFile::parse(&mut compile("Frames", "classfile-rs-liveness")?.as_slice())?;
+		let sum = class.methods.iter().find(|x| x.name == "sum").unwrap().decode_code()?.unwrap();
+		let insns = &sum.insns.insns;
+		let liveness = Liveness::compute(&sum)?;
+		assert_eq!(liveness.live_in(0).iter().copied().collect::<Vec<u16>>(), vec![0, 1, 2]);
+		let ret = insns.iter().position(|insn| matches!(insn, Insn::Return(_))).unwrap();
+		assert!(liveness.live_out(ret).is_empty());
+		let stores: Vec<usize> = (0..insns.len()).filter(|index| matches!(insns[*index], Insn::LocalStore(x) if x.index == 3)).collect();
+		assert_eq!(stores.len(), 2);
+		assert!(!liveness.is_live(stores[0], 3) && !liveness.is_live(stores[0], 4));
+		assert!(liveness.live_out(stores[0]).contains(&3) && liveness.live_out(stores[0]).contains(&4));
+		
+		let def_use = DefUse::compute(&sum)?;
+		let total = (0..ret).rev().find(|index| matches!(insns[*index], Insn::LocalLoad(x) if x.index == 3)).unwrap();
+		assert_eq!(def_use.definitions(total), [Definition::Insn(stores[0]), Definition::Insn(stores[1])]);
+		let start = insns.iter().position(|insn| matches!(insn, Insn::LocalLoad(x) if x.index == 1)).unwrap();
+		assert_eq!(def_use.definitions(start), [Definition::Entry(1)]);
+		let increment = insns.iter().position(|insn| matches!(insn, Insn::IncrementInt(_))).unwrap();
+		assert!(def_use.definitions(increment).contains(&Definition::Insn(increment)));
+		assert!(def_use.uses(Definition::Insn(stores[0])).contains(&total));
+		assert!(def_use.unused_stores().is_empty());
+		
+		let describe = class.methods.iter().find(|x| x.name == "describe").unwrap().decode_code()?.unwrap();
+		let caught = describe.insns.insns.iter().rposition(|insn| matches!(insn, Insn::LocalStore(_))).unwrap();
+		assert_eq!(DefUse::compute(&describe)?.unused_stores(), vec![caught]);
+		
+		// a store within a handler's range does not kill what the handler reads
+		let mut insns = InsnList::new();
+		let (start, end, handler) = (insns.new_label(), insns.new_label(), insns.new_label());
+		insns.insns.extend(vec![
+			Insn::Label(start),
+			Insn::Ldc(LdcInsn::new(LdcType::Int(2))),
+			Insn::LocalStore(LocalStoreInsn::new(OpType::Int, 0)),
+			Insn::Label(end),
+			Insn::LocalLoad(LocalLoadInsn::new(OpType::Int, 0)),
+			Insn::Return(ReturnInsn::new(ReturnType::Int)),
+			Insn::Label(handler),
+			Insn::Pop(PopInsn::new(false)),
+			Insn::LocalLoad(LocalLoadInsn::new(OpType::Int, 0)),
+			Insn::Return(ReturnInsn::new(ReturnType::Int))
+		]);
+		let exceptions = vec![ExceptionHandler {
+			start,
+			end,
+			handler,
+			catch_type: None
+		}];
+		let code = CodeAttribute::new(1, 1, insns, exceptions, Vec::new());
+		let liveness = Liveness::compute(&code)?;
+		assert!(liveness.is_live(2, 0));
+		assert!(liveness.is_live(0, 0));
+		Ok(())
+	}
+	
+	#[test]
+	fn test_relocate() -> Result<()> {
+		let dir = javac("Relocate", "classfile-rs-relocate")?.join("com/example/lib");
+		let mut archive = Archive::new();
+		archive.insert(MANIFEST, b"Manifest-Version: 1.0\r\n".to_vec());
+		archive.insert("com/example/lib/", Vec::new());
+		for name in ["Relocate", "Relocate$Inner", "Relocate$Greeter", "Relocate$Hello"].iter() {
+			archive.insert(format!("com/example/lib/{}.class", name), fs::read(dir.join(format!("{}.class", name)))?);
+		}
+		archive.insert("com/example/lib/data.txt", b"data".to_vec());
+		archive.insert("com/example/other/data.txt", b"other".to_vec());
+		archive.insert("META-INF/services/com.example.lib.Relocate$Greeter", b"# greeters\ncom.example.lib.Relocate$Hello\n".to_vec());
+		
+		let relocator = Relocator::new(vec![Relocation::new("com/example/lib", "shaded/lib")]);
+		assert!(relocator.relocate_archive(&mut archive, &ParseOptions::default()).is_empty());
+		let names: Vec<&str> = archive.entries.iter().map(|entry| entry.name.as_str()).collect();
+		assert_eq!(names, vec![MANIFEST, "shaded/lib/", "shaded/lib/Relocate.class", "shaded/lib/Relocate$Inner.class",
+			"shaded/lib/Relocate$Greeter.class", "shaded/lib/Relocate$Hello.class", "shaded/lib/data.txt",
+			"com/example/other/data.txt", "META-INF/services/shaded.lib.Relocate$Greeter"]);
+		assert_eq!(archive.entries[8].data, b"# greeters\nshaded.lib.Relocate$Hello\n");
+		
+		let mut class = ClassFile::parse(&mut archive.entries[2].data.as_slice())?;
+		assert_eq!(class.this_class, "shaded/lib/Relocate");
+		let constant = class.fields[0].attributes.iter().find_map(|attr| match attr {
+			Attribute::ConstantValue(x) => Some(x.value.clone()),
+			_ => None
+		});
+		assert_eq!(constant, Some(ConstantValue::String(Name::from("shaded.lib.Relocate"))));
+		let inner_classes = class.attributes.iter().find_map(|attr| match attr {
+			Attribute::InnerClasses(x) => Some(x),
+			_ => None
+		}).unwrap();
+		assert!(inner_classes.classes.iter().all(|x| x.inner_class.starts_with("shaded/lib/Relocate$")));
+		let code = class.methods.iter_mut().find(|x| x.name == "main").unwrap().code()?.unwrap();
+		assert!(code.insns.iter().any(|insn| matches!(insn, Insn::Ldc(x) if x.constant == LdcType::String(Name::from("/shaded/lib/data.txt")))));
+		
+		// the retained pool no longer names the old package
+		for entry in archive.entries[2..6].iter() {
+			let pool = ClassFile::parse_preserving_pool(&mut entry.data.as_slice())?.constant_pool.unwrap();
+			assert!(pool.iter().all(|(_, constant)| match constant {
+				ConstantType::Utf8(x) => !x.str.contains("com/example/lib") && !x.str.contains("com.example.lib"),
+				_ => true
+			}), "{}", entry.name);
+		}
+		Ok(())
+	}
 }
@@ -0,0 +1,285 @@
+use crate::code::CodeAttribute;
+use crate::ast::{Insn, OpType};
+use crate::cfg::{ControlFlowGraph, EdgeKind};
+use crate::error::Result;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How an instruction accesses a local variable
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Access {
+	local: u16,
+	/// The number of slots, 2 for longs and doubles
+	size: u16,
+	reads: bool,
+	writes: bool
+}
+
+impl Access {
+	fn of(insn: &Insn) -> Option<Access> {
+		let size = |kind: OpType| if kind == OpType::Long || kind == OpType::Double { 2 } else { 1 };
+		match insn {
+			Insn::LocalLoad(x) => Some(Access { local: x.index, size: size(x.kind), reads: true, writes: false }),
+			Insn::LocalStore(x) => Some(Access { local: x.index, size: size(x.kind), reads: false, writes: true }),
+			Insn::IncrementInt(x) => Some(Access { local: x.index, size: 1, reads: true, writes: true }),
+			_ => None
+		}
+	}
+	
+	fn slots(&self) -> impl Iterator<Item = u16> {
+		self.local..self.local + self.size
+	}
+}
+
+/// The local variable slots that are live before and after each instruction of a method's code,
+/// that is read later along some path before being written. A long or double is two slots, both
+/// read by its loads and written by its stores.
+///
+/// An exception may be thrown by any instruction covered by a handler, so whatever is live at the
+/// handler is live before and after each of them
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Liveness {
+	live_in: Vec<BTreeSet<u16>>,
+	live_out: Vec<BTreeSet<u16>>
+}
+
+impl Liveness {
+	/// Computes liveness for the given code, failing if a jump or handler refers to a label that is
+	/// not placed
+	pub fn compute(code: &CodeAttribute) -> Result<Self> {
+		let graph = ControlFlowGraph::build(code)?;
+		Ok(Liveness::compute_with(code, &graph))
+	}
+	
+	/// Computes liveness using a graph already built for the code
+	pub fn compute_with(code: &CodeAttribute, graph: &ControlFlowGraph) -> Self {
+		let insns = &code.insns.insns;
+		let mut block_in: Vec<BTreeSet<u16>> = vec![BTreeSet::new(); graph.blocks.len()];
+		let mut liveness = Liveness {
+			live_in: vec![BTreeSet::new(); insns.len()],
+			live_out: vec![BTreeSet::new(); insns.len()]
+		};
+		let mut changed = true;
+		while changed {
+			changed = false;
+			// backwards, so that most successors are done before their predecessors
+			for (index, block) in graph.blocks.iter().enumerate().rev() {
+				let live = liveness.compute_block(insns, block.start, block.end, graph, index, &block_in);
+				if live != block_in[index] {
+					block_in[index] = live;
+					changed = true;
+				}
+			}
+		}
+		liveness
+	}
+	
+	/// Computes the sets of a block's instructions from what is live at the start of other blocks,
+	/// returning what is live at its own start
+	fn compute_block(&mut self, insns: &[Insn], start: usize, end: usize, graph: &ControlFlowGraph, index: usize, block_in: &[BTreeSet<u16>]) -> BTreeSet<u16> {
+		let mut live = BTreeSet::new();
+		let mut handlers = BTreeSet::new();
+		for edge in graph.blocks[index].successors.iter() {
+			match edge.kind {
+				EdgeKind::Exception(_) => handlers.extend(block_in[edge.to].iter().copied()),
+				_ => live.extend(block_in[edge.to].iter().copied())
+			}
+		}
+		for insn in (start..end).rev() {
+			live.extend(handlers.iter().copied());
+			self.live_out[insn] = live.clone();
+			if let Some(access) = Access::of(&insns[insn]) {
+				if access.writes {
+					for slot in access.slots() {
+						live.remove(&slot);
+					}
+				}
+				if access.reads {
+					live.extend(access.slots());
+				}
+			}
+			// the handler may be entered before the instruction runs, so a store does not kill what it reads
+			live.extend(handlers.iter().copied());
+			self.live_in[insn] = live.clone();
+		}
+		live
+	}
+	
+	/// The slots live before the instruction at the given index of the `InsnList`
+	pub fn live_in(&self, insn: usize) -> &BTreeSet<u16> {
+		&self.live_in[insn]
+	}
+	
+	/// The slots live after the instruction at the given index of the `InsnList`
+	pub fn live_out(&self, insn: usize) -> &BTreeSet<u16> {
+		&self.live_out[insn]
+	}
+	
+	/// Whether the value of the slot before the instruction may still be read
+	pub fn is_live(&self, insn: usize, local: u16) -> bool {
+		self.live_in[insn].contains(&local)
+	}
+}
+
+/// Where the value of a local variable comes from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Definition {
+	/// The value the slot holds when the method is entered, a parameter, `this` or nothing
+	Entry(u16),
+	/// A `LocalStore` or `IncrementInt` at this index of the `InsnList`
+	Insn(usize)
+}
+
+/// The def-use and use-def chains of the local variables of a method's code: which definitions
+/// each load or increment may read, and which reads each definition may reach. A use of a long or
+/// double is reached by the definitions of its first slot. A definition of a slot replaces any
+/// long or double that overlaps it.
+///
+/// The locals before any instruction covered by an exception handler reach the handler
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct DefUse {
+	/// The definitions reaching each use, by instruction index
+	use_def: BTreeMap<usize, Vec<Definition>>,
+	/// The uses reached by each definition, every store and increment included
+	def_use: BTreeMap<Definition, Vec<usize>>
+}
+
+/// The definitions each slot may hold
+type Reaching = Vec<BTreeSet<Definition>>;
+
+impl DefUse {
+	/// Computes the chains for the given code, failing if a jump or handler refers to a label that
+	/// is not placed
+	pub fn compute(code: &CodeAttribute) -> Result<Self> {
+		let graph = ControlFlowGraph::build(code)?;
+		Ok(DefUse::compute_with(code, &graph))
+	}
+	
+	/// Computes the chains using a graph already built for the code
+	pub fn compute_with(code: &CodeAttribute, graph: &ControlFlowGraph) -> Self {
+		let insns = &code.insns.insns;
+		let slots = insns.iter()
+			.filter_map(Access::of)
+			.map(|access| (access.local + access.size) as usize)
+			.chain(std::iter::once(code.max_locals as usize))
+			.max()
+			.unwrap_or(0);
+		let mut def_use = DefUse::default();
+		if graph.blocks.is_empty() {
+			return def_use;
+		}
+		let order = graph.reverse_post_order();
+		let mut block_in: Vec<Option<Reaching>> = vec![None; graph.blocks.len()];
+		block_in[0] = Some((0..slots).map(|slot| std::iter::once(Definition::Entry(slot as u16)).collect()).collect());
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for block in order.iter().copied() {
+				let mut reaching = match &block_in[block] {
+					Some(x) => x.clone(),
+					None => continue
+				};
+				let data = &graph.blocks[block];
+				let mut thrown = reaching.clone();
+				for (index, insn) in insns.iter().enumerate().take(data.end).skip(data.start) {
+					merge(&mut thrown, &reaching);
+					define(&mut reaching, index, insn);
+				}
+				for edge in data.successors.iter() {
+					let out = match edge.kind {
+						EdgeKind::Exception(_) => &thrown,
+						_ => &reaching
+					};
+					changed |= match &mut block_in[edge.to] {
+						Some(existing) => merge(existing, out),
+						None => {
+							block_in[edge.to] = Some(out.clone());
+							true
+						}
+					};
+				}
+			}
+		}
+	
+		for (block, data) in graph.blocks.iter().enumerate() {
+			let mut reaching = match block_in[block].take() {
+				Some(x) => x,
+				None => continue
+			};
+			for (index, insn) in insns.iter().enumerate().take(data.end).skip(data.start) {
+				if let Some(access) = Access::of(insn) {
+					if access.reads {
+						let definitions: Vec<Definition> = reaching[access.local as usize].iter().copied().collect();
+						for definition in definitions.iter() {
+							def_use.def_use.entry(*definition).or_default().push(index);
+						}
+						def_use.use_def.insert(index, definitions);
+					}
+					if access.writes {
+						def_use.def_use.entry(Definition::Insn(index)).or_default();
+					}
+				}
+				define(&mut reaching, index, insn);
+			}
+		}
+		for uses in def_use.def_use.values_mut() {
+			uses.sort_unstable();
+		}
+		def_use
+	}
+	
+	/// The definitions the load or increment at the given index may read, in order. Empty for other
+	/// instructions and for unreachable code
+	pub fn definitions(&self, insn: usize) -> &[Definition] {
+		match self.use_def.get(&insn) {
+			Some(x) => x,
+			None => &[]
+		}
+	}
+	
+	/// The loads and increments a definition may reach, by index in the `InsnList`
+	pub fn uses(&self, definition: Definition) -> &[usize] {
+		match self.def_use.get(&definition) {
+			Some(x) => x,
+			None => &[]
+		}
+	}
+	
+	/// The reachable stores and increments whose value is never read, in code order
+	pub fn unused_stores(&self) -> Vec<usize> {
+		self.def_use.iter()
+			.filter_map(|(definition, uses)| match definition {
+				Definition::Insn(index) if uses.is_empty() => Some(*index),
+				_ => None
+			})
+			.collect()
+	}
+}
+
+/// Applies the definition made by an instruction, if any
+fn define(reaching: &mut Reaching, index: usize, insn: &Insn) {
+	let access = match Access::of(insn) {
+		Some(x) if x.writes => x,
+		_ => return
+	};
+	// a long or double partly overwritten is lost from both of its slots
+	let replaced: BTreeSet<Definition> = access.slots()
+		.flat_map(|slot| reaching[slot as usize].iter().copied())
+		.collect();
+	for definitions in reaching.iter_mut() {
+		definitions.retain(|definition| !replaced.contains(definition));
+	}
+	for slot in access.slots() {
+		reaching[slot as usize] = std::iter::once(Definition::Insn(index)).collect();
+	}
+}
+
+/// Merges the definitions of another path into these, returning whether they changed
+fn merge(reaching: &mut Reaching, other: &Reaching) -> bool {
+	let mut changed = false;
+	for (definitions, other) in reaching.iter_mut().zip(other.iter()) {
+		for definition in other.iter() {
+			changed |= definitions.insert(*definition);
+		}
+	}
+	changed
+}
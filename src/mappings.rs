@@ -0,0 +1,524 @@
+use crate::remap::Remapper;
+use crate::hierarchy::{InheritanceGraph, MethodRef};
+use crate::access::FieldAccessFlags;
+use crate::intern::Name;
+use crate::error::{Result, ParserError};
+use derive_more::Constructor;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+/// A field or method, named as it is in the original program
+#[derive(Constructor, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MemberKey {
+	pub owner: Name,
+	pub name: Name,
+	/// Empty for fields read from formats that do not record their type, which are renamed whatever
+	/// their type
+	pub descriptor: Name
+}
+
+/// New names for classes, fields and methods, keyed by their original names. Member descriptors
+/// also use the original class names, as every supported format does
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mappings {
+	classes: BTreeMap<Name, Name>,
+	fields: BTreeMap<MemberKey, Name>,
+	methods: BTreeMap<MemberKey, Name>
+}
+
+impl Mappings {
+	pub fn new() -> Self {
+		Mappings::default()
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.classes.is_empty() && self.fields.is_empty() && self.methods.is_empty()
+	}
+	
+	pub fn add_class<T: Into<Name>, U: Into<Name>>(&mut self, from: T, to: U) {
+		self.classes.insert(from.into(), to.into());
+	}
+	
+	pub fn add_field<T: Into<Name>, U: Into<Name>, V: Into<Name>, W: Into<Name>>(&mut self, owner: T, name: U, descriptor: V, to: W) {
+		self.fields.insert(MemberKey::new(owner.into(), name.into(), descriptor.into()), to.into());
+	}
+	
+	pub fn add_method<T: Into<Name>, U: Into<Name>, V: Into<Name>, W: Into<Name>>(&mut self, owner: T, name: U, descriptor: V, to: W) {
+		self.methods.insert(MemberKey::new(owner.into(), name.into(), descriptor.into()), to.into());
+	}
+	
+	pub fn class(&self, name: &str) -> Option<&Name> {
+		self.classes.get(name)
+	}
+	
+	/// Falls back to a mapping that does not record the type of the field
+	pub fn field(&self, owner: &str, name: &str, descriptor: &str) -> Option<&Name> {
+		self.fields.get(&MemberKey::new(owner.into(), name.into(), descriptor.into()))
+			.or_else(|| self.fields.get(&MemberKey::new(owner.into(), name.into(), Name::from(""))))
+	}
+	
+	pub fn method(&self, owner: &str, name: &str, descriptor: &str) -> Option<&Name> {
+		self.methods.get(&MemberKey::new(owner.into(), name.into(), descriptor.into()))
+	}
+	
+	/// Every class mapping, sorted by original name
+	pub fn classes(&self) -> impl Iterator<Item = (&Name, &Name)> {
+		self.classes.iter()
+	}
+	
+	/// Every field mapping, sorted by owner then name
+	pub fn fields(&self) -> impl Iterator<Item = (&MemberKey, &Name)> {
+		self.fields.iter()
+	}
+	
+	/// Every method mapping, sorted by owner then name
+	pub fn methods(&self) -> impl Iterator<Item = (&MemberKey, &Name)> {
+		self.methods.iter()
+	}
+	
+	/// Every class that is renamed or has renamed members, sorted
+	fn owners(&self) -> BTreeSet<&Name> {
+		self.classes.keys()
+			.chain(self.fields.keys().map(|key| &key.owner))
+			.chain(self.methods.keys().map(|key| &key.owner))
+			.collect()
+	}
+	
+	fn members<'a>(members: &'a BTreeMap<MemberKey, Name>, owner: &'a Name) -> impl Iterator<Item = (&'a MemberKey, &'a Name)> {
+		members.range(MemberKey::new(owner.clone(), Name::from(""), Name::from(""))..)
+			.take_while(move |(key, _)| &key.owner == owner)
+	}
+	
+	/// The mappings from the new names back to the original ones
+	pub fn inverse(&self) -> Self {
+		let mut inverse = Mappings::new();
+		for (from, to) in self.classes.iter() {
+			inverse.add_class(to.clone(), from.clone());
+		}
+		for (key, to) in self.fields.iter() {
+			inverse.add_field(self.class_name(&key.owner), to.clone(), self.descriptor(&key.descriptor), key.name.clone());
+		}
+		for (key, to) in self.methods.iter() {
+			inverse.add_method(self.class_name(&key.owner), to.clone(), self.descriptor(&key.descriptor), key.name.clone());
+		}
+		inverse
+	}
+	
+	/// Copies each method mapping to the subtypes of its owner that have no mapping of their own for
+	/// the method, so that the methods overriding it and calls made through a subtype are renamed
+	/// along with it. Methods the hierarchy knows to be static, private or constructors are left
+	/// alone.
+	///
+	/// Field mappings are copied the same way, so that accesses made through a subtype are renamed,
+	/// except to subtypes that declare a field of the same name hiding it and to the subtypes of those
+	pub fn propagate(&mut self, hierarchy: &InheritanceGraph) {
+		let mut inherited = Vec::new();
+		for (key, to) in self.methods.iter() {
+			let method = MethodRef::new(key.owner.clone(), key.name.clone(), key.descriptor.clone());
+			if matches!(hierarchy.method(&method), Some(node) if !node.is_virtual()) {
+				continue;
+			}
+			for subtype in hierarchy.all_subtypes(&key.owner) {
+				inherited.push((MemberKey::new(subtype, key.name.clone(), key.descriptor.clone()), to.clone()));
+			}
+		}
+		for (key, to) in inherited {
+			self.methods.entry(key).or_insert(to);
+		}
+		
+		let mut inherited = Vec::new();
+		for (key, to) in self.fields.iter() {
+			let field = hierarchy.class(&key.owner).and_then(|class| class.field(&key.name));
+			if matches!(field, Some(node) if node.access_flags.contains(FieldAccessFlags::PRIVATE)) {
+				continue;
+			}
+			for subtype in hierarchy.all_subtypes(&key.owner) {
+				if matches!(hierarchy.resolve_field(&subtype, &key.name), Some(owner) if *owner != key.owner) {
+					continue;
+				}
+				inherited.push((MemberKey::new(subtype, key.name.clone(), key.descriptor.clone()), to.clone()));
+			}
+		}
+		for (key, to) in inherited {
+			self.fields.entry(key).or_insert(to);
+		}
+	}
+	
+	/// Reads mappings in the format written by ProGuard and R8, where a class line such as
+	/// `com.example.Foo -> a:` is followed by its indented fields and methods
+	pub fn parse_proguard(text: &str) -> Result<Self> {
+		let mut mappings = Mappings::new();
+		let mut class: Option<Name> = None;
+		for (index, line) in text.lines().enumerate() {
+			let number = index + 1;
+			let trimmed = line.trim();
+			if trimmed.is_empty() || trimmed.starts_with('#') {
+				continue;
+			}
+			let (from, to) = trimmed.split_once(" -> ")
+				.ok_or_else(|| ParserError::invalid_mappings(number, "Expected '->'"))?;
+			if !line.starts_with(char::is_whitespace) {
+				let to = to.strip_suffix(':')
+					.ok_or_else(|| ParserError::invalid_mappings(number, "Expected ':' after class"))?;
+				let from = Name::from(from.replace('.', "/"));
+				mappings.add_class(from.clone(), to.replace('.', "/"));
+				class = Some(from);
+				continue;
+			}
+			let owner = class.clone()
+				.ok_or_else(|| ParserError::invalid_mappings(number, "Member outside of a class"))?;
+			// methods may be preceded by the lines they span, such as `1:5:void run() -> a`
+			let from = from.trim_start_matches(|c: char| c.is_ascii_digit() || c == ':');
+			let (kind, name) = from.split_once(' ')
+				.ok_or_else(|| ParserError::invalid_mappings(number, "Expected a type and a name"))?;
+			match name.find('(') {
+				Some(open) => {
+					let close = name.find(')')
+						.ok_or_else(|| ParserError::invalid_mappings(number, "Expected ')'"))?;
+					let method = &name[..open];
+					// methods inlined from other classes are qualified by their class
+					if method.contains('.') {
+						continue;
+					}
+					let mut descriptor = String::from("(");
+					for parameter in name[open + 1..close].split(',').filter(|parameter| !parameter.is_empty()) {
+						descriptor.push_str(&java_descriptor(parameter.trim(), number)?);
+					}
+					descriptor.push(')');
+					descriptor.push_str(&java_descriptor(kind, number)?);
+					mappings.add_method(owner, method, descriptor, to);
+				},
+				None => mappings.add_field(owner, name, java_descriptor(kind, number)?, to)
+			}
+		}
+		Ok(mappings)
+	}
+	
+	/// Fails if a field has no descriptor, as the format records the type of every field
+	pub fn write_proguard<W: Write>(&self, wtr: &mut W) -> Result<()> {
+		for owner in self.owners() {
+			let to = self.class_name(owner);
+			writeln!(wtr, "{} -> {}:", owner.replace('/', "."), to.replace('/', "."))?;
+			for (key, to) in Mappings::members(&self.fields, owner) {
+				writeln!(wtr, "    {} {} -> {}", java_type(field_descriptor(key)?)?.0, key.name, to)?;
+			}
+			for (key, to) in Mappings::members(&self.methods, owner) {
+				let (parameters, result) = java_method(&key.descriptor)?;
+				writeln!(wtr, "    {} {}({}) -> {}", result, key.name, parameters.join(","), to)?;
+			}
+		}
+		Ok(())
+	}
+	
+	/// Reads Tiny v2 mappings, as used by Fabric, from the namespace `from` to the namespace `to`,
+	/// such as `official` to `named`. Parameters, local variables and comments are ignored
+	pub fn parse_tiny(text: &str, from: &str, to: &str) -> Result<Self> {
+		let mut lines = text.lines().enumerate();
+		let header: Vec<&str> = lines.next().map(|(_, line)| line.split('\t').collect()).unwrap_or_default();
+		if header.len() < 5 || header[0] != "tiny" || header[1] != "2" {
+			return Err(ParserError::invalid_mappings(1, "Expected a tiny v2 header"));
+		}
+		let namespaces = &header[3..];
+		let namespace = |name: &str| namespaces.iter()
+			.position(|namespace| *namespace == name)
+			.ok_or_else(|| ParserError::invalid_mappings(1, format!("No namespace named {}", name)));
+		let (from, to) = (namespace(from)?, namespace(to)?);
+	
+		// member descriptors are named in the first namespace, so they can only be translated once
+		// every class has been read
+		let mut classes: Vec<(Vec<String>, Vec<TinyMember>)> = Vec::new();
+		let mut escaped = false;
+		for (index, line) in lines {
+			let number = index + 1;
+			let depth = line.len() - line.trim_start_matches('\t').len();
+			let columns: Vec<&str> = line[depth..].split('\t').collect();
+			let names = |start: usize| -> Result<Vec<String>> {
+				let names = columns.get(start..start + namespaces.len())
+					.ok_or_else(|| ParserError::invalid_mappings(number, "Expected a name for every namespace"))?;
+				Ok(names.iter().map(|name| if escaped { unescape(name) } else { name.to_string() }).collect())
+			};
+			match (depth, columns[0]) {
+				(0, "c") => classes.push((names(1)?, Vec::new())),
+				(1, kind @ ("f" | "m")) if !classes.is_empty() => {
+					let descriptor = columns.get(1)
+						.ok_or_else(|| ParserError::invalid_mappings(number, "Expected a descriptor"))?;
+					let member = (kind == "m", *descriptor, names(2)?);
+					classes.last_mut().unwrap().1.push(member);
+				},
+				(1, "escaped-names") if classes.is_empty() => escaped = true,
+				(0, _) => return Err(ParserError::invalid_mappings(number, format!("Unknown section {}", columns[0]))),
+				// comments, parameters and local variables
+				_ => {}
+			}
+		}
+	
+		let mut source = Mappings::new();
+		for (names, _) in classes.iter() {
+			source.add_class(names[0].as_str(), tiny_name(names, from));
+		}
+		let mut mappings = Mappings::new();
+		for (names, members) in classes.iter() {
+			let owner = tiny_name(names, from);
+			if !names[to].is_empty() {
+				mappings.add_class(owner, names[to].as_str());
+			}
+			for (method, descriptor, names) in members.iter() {
+				if names[to].is_empty() {
+					continue;
+				}
+				let descriptor = source.descriptor(&Name::from(*descriptor));
+				if *method {
+					mappings.add_method(owner, tiny_name(names, from), descriptor, names[to].as_str());
+				} else {
+					mappings.add_field(owner, tiny_name(names, from), descriptor, names[to].as_str());
+				}
+			}
+		}
+		Ok(mappings)
+	}
+	
+	/// Writes Tiny v2 mappings between the two given namespaces. Fails if a field has no descriptor
+	pub fn write_tiny<W: Write>(&self, wtr: &mut W, from: &str, to: &str) -> Result<()> {
+		writeln!(wtr, "tiny\t2\t0\t{}\t{}", from, to)?;
+		for owner in self.owners() {
+			writeln!(wtr, "c\t{}\t{}", owner, self.class_name(owner))?;
+			for (key, to) in Mappings::members(&self.fields, owner) {
+				writeln!(wtr, "\tf\t{}\t{}\t{}", field_descriptor(key)?, key.name, to)?;
+			}
+			for (key, to) in Mappings::members(&self.methods, owner) {
+				writeln!(wtr, "\tm\t{}\t{}\t{}", key.descriptor, key.name, to)?;
+			}
+		}
+		Ok(())
+	}
+	
+	/// Reads SRG mappings, made of `CL:`, `FD:` and `MD:` lines. Fields of XSRG mappings keep their
+	/// descriptors, plain SRG fields are renamed whatever their type
+	pub fn parse_srg<'a>(text: &'a str) -> Result<Self> {
+		let mut mappings = Mappings::new();
+		for (index, line) in text.lines().enumerate() {
+			let number = index + 1;
+			let columns: Vec<&str> = line.split_whitespace().collect();
+			let member = |column: &'a str| split_member(column)
+				.ok_or_else(|| ParserError::invalid_mappings(number, format!("Expected a class and name in {}", column)));
+			match columns.as_slice() {
+				[] | ["PK:", ..] => {},
+				["CL:", from, to] => mappings.add_class(*from, *to),
+				["FD:", from, to] => {
+					let (owner, name) = member(from)?;
+					mappings.add_field(owner, name, "", member(to)?.1);
+				},
+				["FD:", from, descriptor, to, _] => {
+					let (owner, name) = member(from)?;
+					mappings.add_field(owner, name, *descriptor, member(to)?.1);
+				},
+				["MD:", from, descriptor, to, _] => {
+					let (owner, name) = member(from)?;
+					mappings.add_method(owner, name, *descriptor, member(to)?.1);
+				},
+				_ if line.trim_start().starts_with('#') => {},
+				_ => return Err(ParserError::invalid_mappings(number, format!("Unknown line {}", line)))
+			}
+		}
+		Ok(mappings)
+	}
+	
+	/// Writes SRG mappings, or XSRG mappings if any field has a descriptor
+	pub fn write_srg<W: Write>(&self, wtr: &mut W) -> Result<()> {
+		let extended = self.fields.keys().any(|key| !key.descriptor.is_empty());
+		for (from, to) in self.classes.iter() {
+			writeln!(wtr, "CL: {} {}", from, to)?;
+		}
+		for (key, to) in self.fields.iter() {
+			let owner = self.class_name(&key.owner);
+			if extended {
+				writeln!(wtr, "FD: {}/{} {} {}/{} {}", key.owner, key.name, key.descriptor, owner, to, self.descriptor(&key.descriptor))?;
+			} else {
+				writeln!(wtr, "FD: {}/{} {}/{}", key.owner, key.name, owner, to)?;
+			}
+		}
+		for (key, to) in self.methods.iter() {
+			let owner = self.class_name(&key.owner);
+			writeln!(wtr, "MD: {}/{} {} {}/{} {}", key.owner, key.name, key.descriptor, owner, to, self.descriptor(&key.descriptor))?;
+		}
+		Ok(())
+	}
+	
+	/// Reads TSRG mappings, where a class line such as `com/example/Foo a` is followed by its
+	/// indented fields and methods. TSRG v2 files, which start with a `tsrg2` header, are read from
+	/// their first namespace to their second
+	pub fn parse_tsrg(text: &str) -> Result<Self> {
+		let mut mappings = Mappings::new();
+		let mut lines = text.lines().enumerate().peekable();
+		let mut namespaces = 2;
+		if let Some((_, header)) = lines.peek() {
+			if header.starts_with("tsrg2 ") {
+				namespaces = header.split_whitespace().count() - 1;
+				lines.next();
+			}
+		}
+		if namespaces < 2 {
+			return Err(ParserError::invalid_mappings(1, "Expected at least two namespaces"));
+		}
+		let mut class: Option<Name> = None;
+		for (index, line) in lines {
+			let number = index + 1;
+			let depth = line.len() - line.trim_start_matches(['\t', ' ']).len();
+			let columns: Vec<&str> = line.split_whitespace().collect();
+			if columns.is_empty() || columns[0].starts_with('#') {
+				continue;
+			}
+			if depth == 0 {
+				if columns.len() < namespaces {
+					return Err(ParserError::invalid_mappings(number, "Expected a name for every namespace"));
+				}
+				let from = Name::from(columns[0]);
+				mappings.add_class(from.clone(), columns[1]);
+				class = Some(from);
+				continue;
+			}
+			// parameters and the static marker of v2 methods are nested below their method
+			if line.starts_with("\t\t") {
+				continue;
+			}
+			let owner = class.clone()
+				.ok_or_else(|| ParserError::invalid_mappings(number, "Member outside of a class"))?;
+			if columns.len() == namespaces + 1 && columns[1].starts_with('(') {
+				mappings.add_method(owner, columns[0], columns[1], columns[2]);
+			} else if columns.len() == namespaces + 1 {
+				mappings.add_field(owner, columns[0], columns[1], columns[2]);
+			} else if columns.len() == namespaces {
+				mappings.add_field(owner, columns[0], "", columns[1]);
+			} else {
+				return Err(ParserError::invalid_mappings(number, format!("Unknown line {}", line)));
+			}
+		}
+		Ok(mappings)
+	}
+}
+
+impl Remapper for Mappings {
+	fn map_class(&self, name: &str) -> Option<Name> {
+		self.class(name).cloned()
+	}
+	
+	fn map_field(&self, owner: &str, name: &str, descriptor: &str) -> Option<Name> {
+		self.field(owner, name, descriptor).cloned()
+	}
+	
+	fn map_method(&self, owner: &str, name: &str, descriptor: &str) -> Option<Name> {
+		self.method(owner, name, descriptor).cloned()
+	}
+}
+
+/// Whether a member is a method, its descriptor and its name in every namespace
+type TinyMember<'a> = (bool, &'a str, Vec<String>);
+
+/// The name in the given namespace, or in the first namespace if it is left empty
+fn tiny_name(names: &[String], namespace: usize) -> &str {
+	if names[namespace].is_empty() {
+		&names[0]
+	} else {
+		&names[namespace]
+	}
+}
+
+fn unescape(name: &str) -> String {
+	let mut result = String::with_capacity(name.len());
+	let mut chars = name.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			result.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('n') => result.push('\n'),
+			Some('r') => result.push('\r'),
+			Some('t') => result.push('\t'),
+			Some('0') => result.push('\0'),
+			Some(c) => result.push(c),
+			None => result.push('\\')
+		}
+	}
+	result
+}
+
+/// Splits `owner/name` at its last slash
+fn split_member(member: &str) -> Option<(&str, &str)> {
+	let index = member.rfind('/')?;
+	Some((&member[..index], &member[index + 1..]))
+}
+
+fn field_descriptor(key: &MemberKey) -> Result<&Name> {
+	if key.descriptor.is_empty() {
+		Err(ParserError::other(format!("The type of field {}.{} is unknown", key.owner, key.name)))
+	} else {
+		Ok(&key.descriptor)
+	}
+}
+
+/// The descriptor of a Java type such as `int[]` or `java.lang.String`
+fn java_descriptor(java: &str, line: usize) -> Result<String> {
+	let mut base = java;
+	let mut descriptor = String::new();
+	while let Some(element) = base.strip_suffix("[]") {
+		descriptor.push('[');
+		base = element;
+	}
+	if base.is_empty() {
+		return Err(ParserError::invalid_mappings(line, "Expected a type"));
+	}
+	match base {
+		"boolean" => descriptor.push('Z'),
+		"byte" => descriptor.push('B'),
+		"char" => descriptor.push('C'),
+		"short" => descriptor.push('S'),
+		"int" => descriptor.push('I'),
+		"long" => descriptor.push('J'),
+		"float" => descriptor.push('F'),
+		"double" => descriptor.push('D'),
+		"void" => descriptor.push('V'),
+		_ => {
+			descriptor.push('L');
+			descriptor.push_str(&base.replace('.', "/"));
+			descriptor.push(';');
+		}
+	}
+	Ok(descriptor)
+}
+
+/// The Java name of the first type in a descriptor, along with the length of its descriptor
+pub(crate) fn java_type(descriptor: &str) -> Result<(String, usize)> {
+	let dimensions = descriptor.len() - descriptor.trim_start_matches('[').len();
+	let (base, length) = match descriptor[dimensions..].chars().next() {
+		Some('Z') => ("boolean".to_string(), 1),
+		Some('B') => ("byte".to_string(), 1),
+		Some('C') => ("char".to_string(), 1),
+		Some('S') => ("short".to_string(), 1),
+		Some('I') => ("int".to_string(), 1),
+		Some('J') => ("long".to_string(), 1),
+		Some('F') => ("float".to_string(), 1),
+		Some('D') => ("double".to_string(), 1),
+		Some('V') => ("void".to_string(), 1),
+		Some('L') => {
+			let end = descriptor[dimensions..].find(';')
+				.ok_or_else(|| ParserError::invalid_descriptor("Type missing ';'"))?;
+			(descriptor[dimensions + 1..dimensions + end].replace('/', "."), end + 1)
+		},
+		_ => return Err(ParserError::invalid_descriptor(format!("Unknown type in {}", descriptor)))
+	};
+	Ok((base + &"[]".repeat(dimensions), dimensions + length))
+}
+
+/// The Java names of the parameter types and return type of a method descriptor
+pub(crate) fn java_method(descriptor: &str) -> Result<(Vec<String>, String)> {
+	let mut rest = descriptor.strip_prefix('(')
+		.ok_or_else(|| ParserError::invalid_descriptor(format!("Expected '(' in {}", descriptor)))?;
+	let mut parameters = Vec::new();
+	while !rest.starts_with(')') {
+		let (parameter, length) = java_type(rest)?;
+		parameters.push(parameter);
+		rest = &rest[length..];
+	}
+	Ok((parameters, java_type(&rest[1..])?.0))
+}
@@ -0,0 +1,440 @@
+use crate::classfile::ClassFile;
+use crate::access::{ClassAccessFlags, MethodAccessFlags, FieldAccessFlags};
+use crate::attributes::{Attribute, ConstantValue};
+use crate::ast::{Insn, LdcType};
+use crate::mappings::{Mappings, MemberKey, java_type, java_method};
+use crate::remap::Remapper;
+use crate::intern::Name;
+use crate::error::Result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+
+/// Pairs scoring below this are never matched
+const MIN_SCORE: f32 = 0.3;
+/// Classes are matched again using the classes matched by the previous round, until the matches
+/// stop changing or this many rounds have run
+const ROUNDS: usize = 4;
+
+/// An original item paired with its obfuscated counterpart
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match<T> {
+	pub original: T,
+	pub obfuscated: T,
+	/// From 0 to 1: the similarity of the pair, lowered by up to half when either side is about as
+	/// similar to another item
+	pub confidence: f32
+}
+
+/// The classes, fields and methods of a program matched with those of its obfuscated build, see
+/// `match_classes`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Matches {
+	/// Sorted by original name
+	pub classes: Vec<Match<Name>>,
+	/// Sorted by original owner then name. Only the members of matched classes are matched
+	pub fields: Vec<Match<MemberKey>>,
+	/// Sorted by original owner then name. Only the members of matched classes are matched
+	pub methods: Vec<Match<MemberKey>>
+}
+
+/// Infers which obfuscated class, field and method each original one became by comparing their
+/// structure: access flags, descriptors with the names of the given classes left out, the
+/// instructions of their code and the constants and outside members they use. Both sets should
+/// hold the same part of the program.
+///
+/// Every pair of classes is compared, so the time taken grows with the product of the sizes of the
+/// two sets
+pub fn match_classes(original: &[ClassFile], obfuscated: &[ClassFile]) -> Result<Matches> {
+	let original_names: HashSet<&str> = original.iter().map(|class| class.this_class.as_str()).collect();
+	let obfuscated_names: HashSet<&str> = obfuscated.iter().map(|class| class.this_class.as_str()).collect();
+	let original = original.iter()
+		.map(|class| ClassFeatures::new(class, &original_names))
+		.collect::<Result<Vec<ClassFeatures>>>()?;
+	let obfuscated = obfuscated.iter()
+		.map(|class| ClassFeatures::new(class, &obfuscated_names))
+		.collect::<Result<Vec<ClassFeatures>>>()?;
+	
+	let mut class_matches: Vec<(usize, usize, f32)> = Vec::new();
+	for _ in 0..ROUNDS {
+		let (original_shapes, obfuscated_shapes) = shapes(&original, &obfuscated, &class_matches, &original_names, &obfuscated_names);
+		let scores: Vec<Vec<f32>> = original.iter()
+			.map(|a| obfuscated.iter().map(|b| a.similarity(b, &original_shapes, &obfuscated_shapes)).collect())
+			.collect();
+		let matches = assign(&scores);
+		let changed = matches.iter().map(|(a, b, _)| (a, b)).ne(class_matches.iter().map(|(a, b, _)| (a, b)));
+		class_matches = matches;
+		if !changed {
+			break;
+		}
+	}
+	
+	let (original_shapes, obfuscated_shapes) = shapes(&original, &obfuscated, &class_matches, &original_names, &obfuscated_names);
+	let mut matches = Matches::default();
+	for (a, b, confidence) in class_matches {
+		let (a, b) = (&original[a], &obfuscated[b]);
+		matches.classes.push(Match {
+			original: a.name.clone(),
+			obfuscated: b.name.clone(),
+			confidence
+		});
+		matches.fields.extend(match_members(&a.fields, &b.fields, &original_shapes, &obfuscated_shapes));
+		matches.methods.extend(match_members(&a.methods, &b.methods, &original_shapes, &obfuscated_shapes));
+	}
+	matches.classes.sort_by(|a, b| a.original.cmp(&b.original));
+	matches.fields.sort_by(|a, b| a.original.cmp(&b.original));
+	matches.methods.sort_by(|a, b| a.original.cmp(&b.original));
+	Ok(matches)
+}
+
+impl Matches {
+	/// The mappings from original to obfuscated names of the matches at least as confident as the
+	/// given confidence. The members of a class that is left out are left out too
+	pub fn mappings(&self, min_confidence: f32) -> Mappings {
+		let mut mappings = Mappings::new();
+		for class in self.confident_classes(min_confidence) {
+			mappings.add_class(class.original.clone(), class.obfuscated.clone());
+			for field in self.confident_members(&self.fields, &class.original, min_confidence) {
+				mappings.add_field(field.original.owner.clone(), field.original.name.clone(), field.original.descriptor.clone(), field.obfuscated.name.clone());
+			}
+			for method in self.confident_members(&self.methods, &class.original, min_confidence) {
+				mappings.add_method(method.original.owner.clone(), method.original.name.clone(), method.original.descriptor.clone(), method.obfuscated.name.clone());
+			}
+		}
+		mappings
+	}
+	
+	/// Writes the mappings of the matches at least as confident as the given confidence in ProGuard
+	/// format, followed by a `# confidence` comment for each class and member
+	pub fn write_proguard<W: Write>(&self, wtr: &mut W, min_confidence: f32) -> Result<()> {
+		for class in self.confident_classes(min_confidence) {
+			writeln!(wtr, "{} -> {}:", class.original.replace('/', "."), class.obfuscated.replace('/', "."))?;
+			writeln!(wtr, "# confidence {:.3}", class.confidence)?;
+			for field in self.confident_members(&self.fields, &class.original, min_confidence) {
+				writeln!(wtr, "    {} {} -> {}", java_type(&field.original.descriptor)?.0, field.original.name, field.obfuscated.name)?;
+				writeln!(wtr, "    # confidence {:.3}", field.confidence)?;
+			}
+			for method in self.confident_members(&self.methods, &class.original, min_confidence) {
+				let (parameters, result) = java_method(&method.original.descriptor)?;
+				writeln!(wtr, "    {} {}({}) -> {}", result, method.original.name, parameters.join(","), method.obfuscated.name)?;
+				writeln!(wtr, "    # confidence {:.3}", method.confidence)?;
+			}
+		}
+		Ok(())
+	}
+	
+	/// Writes the mappings of the matches at least as confident as the given confidence in Tiny v2
+	/// format, from the `from` namespace of the original names to the `to` namespace of the
+	/// obfuscated ones, with a `confidence` comment for each class and member
+	pub fn write_tiny<W: Write>(&self, wtr: &mut W, from: &str, to: &str, min_confidence: f32) -> Result<()> {
+		writeln!(wtr, "tiny\t2\t0\t{}\t{}", from, to)?;
+		for class in self.confident_classes(min_confidence) {
+			writeln!(wtr, "c\t{}\t{}", class.original, class.obfuscated)?;
+			writeln!(wtr, "\tc\tconfidence {:.3}", class.confidence)?;
+			for field in self.confident_members(&self.fields, &class.original, min_confidence) {
+				writeln!(wtr, "\tf\t{}\t{}\t{}", field.original.descriptor, field.original.name, field.obfuscated.name)?;
+				writeln!(wtr, "\t\tc\tconfidence {:.3}", field.confidence)?;
+			}
+			for method in self.confident_members(&self.methods, &class.original, min_confidence) {
+				writeln!(wtr, "\tm\t{}\t{}\t{}", method.original.descriptor, method.original.name, method.obfuscated.name)?;
+				writeln!(wtr, "\t\tc\tconfidence {:.3}", method.confidence)?;
+			}
+		}
+		Ok(())
+	}
+	
+	fn confident_classes(&self, min_confidence: f32) -> impl Iterator<Item = &Match<Name>> {
+		self.classes.iter().filter(move |class| class.confidence >= min_confidence)
+	}
+	
+	/// Constructors and static initializers are left out, as they are never renamed
+	fn confident_members<'a>(&'a self, members: &'a [Match<MemberKey>], owner: &'a Name, min_confidence: f32) -> impl Iterator<Item = &'a Match<MemberKey>> {
+		members.iter().filter(move |member| {
+			&member.original.owner == owner && member.confidence >= min_confidence && !member.original.name.starts_with('<')
+		})
+	}
+}
+
+/// What a class is compared by
+struct ClassFeatures {
+	name: Name,
+	kind: ClassAccessFlags,
+	supertypes: Vec<Name>,
+	fields: Vec<MemberFeatures>,
+	methods: Vec<MemberFeatures>,
+	/// The instruction bigrams of every method, sorted
+	bigrams: Vec<u64>,
+	constants: BTreeSet<String>
+}
+
+/// What a field or method is compared by
+struct MemberFeatures {
+	key: MemberKey,
+	is_static: bool,
+	access: u16,
+	/// Where the member is declared among those of its kind, from 0 to 1
+	position: f32,
+	/// Each pair of consecutive instructions, sorted
+	bigrams: Vec<u64>,
+	/// Strings, numbers and members of classes outside of the set
+	constants: BTreeSet<String>
+}
+
+impl ClassFeatures {
+	fn new(class: &ClassFile, names: &HashSet<&str>) -> Result<Self> {
+		let position = |index: usize, len: usize| if len > 1 {
+			index as f32 / (len - 1) as f32
+		} else {
+			0.0
+		};
+		let fields: Vec<MemberFeatures> = class.fields.iter()
+			.enumerate()
+			.map(|(index, field)| {
+				let mut constants = BTreeSet::new();
+				for attr in field.attributes.iter() {
+					if let Attribute::ConstantValue(x) = attr {
+						constants.insert(match &x.value {
+							ConstantValue::String(x) => format!("s:{}", x),
+							x => format!("{:?}", x)
+						});
+					}
+				}
+				MemberFeatures {
+					key: MemberKey::new(class.this_class.clone(), field.name.clone(), field.descriptor.clone()),
+					is_static: field.access_flags.contains(FieldAccessFlags::STATIC),
+					access: field.access_flags.bits(),
+					position: position(index, class.fields.len()),
+					bigrams: Vec::new(),
+					constants
+				}
+			})
+			.collect();
+		let mut methods = Vec::with_capacity(class.methods.len());
+		for (index, method) in class.methods.iter().enumerate() {
+			let (bigrams, constants) = match method.decode_code()? {
+				Some(code) => code_features(code.insns.iter(), names),
+				None => (Vec::new(), BTreeSet::new())
+			};
+			methods.push(MemberFeatures {
+				key: MemberKey::new(class.this_class.clone(), method.name.clone(), method.descriptor.clone()),
+				is_static: method.access_flags.contains(MethodAccessFlags::STATIC),
+				access: method.access_flags.bits(),
+				position: position(index, class.methods.len()),
+				bigrams,
+				constants
+			});
+		}
+		let mut bigrams: Vec<u64> = methods.iter().flat_map(|method| method.bigrams.iter().copied()).collect();
+		bigrams.sort_unstable();
+		let constants = fields.iter().chain(methods.iter())
+			.flat_map(|member| member.constants.iter().cloned())
+			.collect();
+		Ok(ClassFeatures {
+			name: class.this_class.clone(),
+			kind: class.access_flags & (ClassAccessFlags::INTERFACE | ClassAccessFlags::ABSTRACT | ClassAccessFlags::ENUM | ClassAccessFlags::ANNOTATION),
+			supertypes: class.super_class.iter().chain(class.interfaces.iter()).cloned().collect(),
+			fields,
+			methods,
+			bigrams,
+			constants
+		})
+	}
+	
+	fn similarity(&self, other: &ClassFeatures, shapes: &Shapes, other_shapes: &Shapes) -> f32 {
+		if self.kind != other.kind {
+			return 0.0;
+		}
+		let supertypes = similarity(
+			&sorted(self.supertypes.iter().map(|name| shapes.class_name(name))),
+			&sorted(other.supertypes.iter().map(|name| other_shapes.class_name(name)))
+		);
+		let signatures = |members: &[MemberFeatures], shapes: &Shapes| sorted(members.iter().map(|member| (member.is_static, shapes.descriptor(&member.key.descriptor))));
+		let fields = similarity(&signatures(&self.fields, shapes), &signatures(&other.fields, other_shapes));
+		let methods = similarity(&signatures(&self.methods, shapes), &signatures(&other.methods, other_shapes));
+		let code = similarity(&self.bigrams, &other.bigrams);
+		let constants = jaccard(&self.constants, &other.constants);
+		0.15 * supertypes + 0.15 * fields + 0.25 * methods + 0.25 * code + 0.2 * constants
+	}
+}
+
+impl MemberFeatures {
+	fn similarity(&self, other: &MemberFeatures, shapes: &Shapes, other_shapes: &Shapes) -> f32 {
+		if self.is_static != other.is_static {
+			return 0.0;
+		}
+		// constructors and static initializers keep their names
+		let special = self.key.name.starts_with('<') || other.key.name.starts_with('<');
+		if special && self.key.name != other.key.name {
+			return 0.0;
+		}
+		let descriptor = shapes.descriptor(&self.key.descriptor) == other_shapes.descriptor(&other.key.descriptor);
+		let access = self.access == other.access;
+		let position = 1.0 - (self.position - other.position).abs();
+		let code = similarity(&self.bigrams, &other.bigrams);
+		let constants = jaccard(&self.constants, &other.constants);
+		0.35 * descriptor as u8 as f32 + 0.1 * access as u8 as f32 + 0.1 * position + 0.25 * code + 0.2 * constants
+	}
+}
+
+/// The instruction bigrams and constants of a method
+fn code_features<'a, I: Iterator<Item = &'a Insn>>(insns: I, names: &HashSet<&str>) -> (Vec<u64>, BTreeSet<String>) {
+	let mut bigrams = Vec::new();
+	let mut constants = BTreeSet::new();
+	let mut previous = 0u64;
+	for insn in insns {
+		if let Insn::Label(_) = insn {
+			continue;
+		}
+		let mut hasher = DefaultHasher::new();
+		std::mem::discriminant(insn).hash(&mut hasher);
+		let kind = hasher.finish();
+		bigrams.push(previous.wrapping_mul(31).wrapping_add(kind));
+		previous = kind;
+		match insn {
+			Insn::Ldc(x) => match &x.constant {
+				LdcType::String(x) => {
+					constants.insert(format!("s:{}", x));
+				},
+				LdcType::Int(x) => {
+					constants.insert(format!("i:{}", x));
+				},
+				LdcType::Long(x) => {
+					constants.insert(format!("j:{}", x));
+				},
+				LdcType::Float(x) => {
+					constants.insert(format!("f:{}", x));
+				},
+				LdcType::Double(x) => {
+					constants.insert(format!("d:{}", x));
+				},
+				_ => {}
+			},
+			Insn::Invoke(x) if !names.contains(x.class.as_str()) => {
+				constants.insert(format!("m:{}.{}", x.class, x.name));
+			},
+			Insn::GetField(x) if !names.contains(x.class.as_str()) => {
+				constants.insert(format!("f:{}.{}", x.class, x.name));
+			},
+			Insn::PutField(x) if !names.contains(x.class.as_str()) => {
+				constants.insert(format!("f:{}.{}", x.class, x.name));
+			},
+			_ => {}
+		}
+	}
+	bigrams.sort_unstable();
+	(bigrams, constants)
+}
+
+/// Names the classes of descriptors the same way on both sides: matched classes by their original
+/// name, other classes of the set by an empty name, and classes outside the set by their own name
+struct Shapes<'a> {
+	known: HashMap<&'a str, &'a str>,
+	names: &'a HashSet<&'a str>
+}
+
+impl Remapper for Shapes<'_> {
+	fn map_class(&self, name: &str) -> Option<Name> {
+		match self.known.get(name) {
+			Some(x) => Some(Name::from(*x)),
+			None if self.names.contains(name) => Some(Name::from("")),
+			None => None
+		}
+	}
+	
+	fn map_field(&self, _owner: &str, _name: &str, _descriptor: &str) -> Option<Name> {
+		None
+	}
+	
+	fn map_method(&self, _owner: &str, _name: &str, _descriptor: &str) -> Option<Name> {
+		None
+	}
+}
+
+fn shapes<'a>(original: &'a [ClassFeatures], obfuscated: &'a [ClassFeatures], matches: &[(usize, usize, f32)],
+	original_names: &'a HashSet<&'a str>, obfuscated_names: &'a HashSet<&'a str>) -> (Shapes<'a>, Shapes<'a>) {
+	let original_shapes = Shapes {
+		known: matches.iter().map(|(a, _, _)| (original[*a].name.as_str(), original[*a].name.as_str())).collect(),
+		names: original_names
+	};
+	let obfuscated_shapes = Shapes {
+		known: matches.iter().map(|(a, b, _)| (obfuscated[*b].name.as_str(), original[*a].name.as_str())).collect(),
+		names: obfuscated_names
+	};
+	(original_shapes, obfuscated_shapes)
+}
+
+fn match_members(original: &[MemberFeatures], obfuscated: &[MemberFeatures], shapes: &Shapes, other_shapes: &Shapes) -> Vec<Match<MemberKey>> {
+	let scores: Vec<Vec<f32>> = original.iter()
+		.map(|a| obfuscated.iter().map(|b| a.similarity(b, shapes, other_shapes)).collect())
+		.collect();
+	assign(&scores).into_iter()
+		.map(|(a, b, confidence)| Match {
+			original: original[a].key.clone(),
+			obfuscated: obfuscated[b].key.clone(),
+			confidence
+		})
+		.collect()
+}
+
+/// Pairs up the rows and columns of a score matrix, most similar first, and rates each pair. The
+/// pairs are returned by row
+fn assign(scores: &[Vec<f32>]) -> Vec<(usize, usize, f32)> {
+	let columns = scores.first().map_or(0, |row| row.len());
+	let mut pairs: Vec<(usize, usize)> = (0..scores.len())
+		.flat_map(|a| (0..columns).map(move |b| (a, b)))
+		.filter(|(a, b)| scores[*a][*b] >= MIN_SCORE)
+		.collect();
+	pairs.sort_by(|x, y| scores[y.0][y.1].partial_cmp(&scores[x.0][x.1]).unwrap().then(x.cmp(y)));
+	let mut used_rows = vec![false; scores.len()];
+	let mut used_columns = vec![false; columns];
+	let mut result = Vec::new();
+	for (a, b) in pairs {
+		if used_rows[a] || used_columns[b] {
+			continue;
+		}
+		used_rows[a] = true;
+		used_columns[b] = true;
+		let score = scores[a][b];
+		let runner_up = (0..columns).filter(|x| *x != b).map(|x| scores[a][x])
+			.chain((0..scores.len()).filter(|x| *x != a).map(|x| scores[x][b]))
+			.fold(0.0, f32::max)
+			.min(score);
+		result.push((a, b, score - runner_up / 2.0));
+	}
+	result.sort_by_key(|(a, _, _)| *a);
+	result
+}
+
+fn sorted<T: Ord, I: Iterator<Item = T>>(items: I) -> Vec<T> {
+	let mut items: Vec<T> = items.collect();
+	items.sort();
+	items
+}
+
+/// The share of items two sorted lists have in common, counting repeated items
+fn similarity<T: Ord>(a: &[T], b: &[T]) -> f32 {
+	if a.is_empty() && b.is_empty() {
+		return 1.0;
+	}
+	let (mut i, mut j, mut common) = (0, 0, 0);
+	while i < a.len() && j < b.len() {
+		match a[i].cmp(&b[j]) {
+			std::cmp::Ordering::Less => i += 1,
+			std::cmp::Ordering::Greater => j += 1,
+			std::cmp::Ordering::Equal => {
+				common += 1;
+				i += 1;
+				j += 1;
+			}
+		}
+	}
+	common as f32 / a.len().max(b.len()) as f32
+}
+
+fn jaccard<T: Ord>(a: &BTreeSet<T>, b: &BTreeSet<T>) -> f32 {
+	if a.is_empty() && b.is_empty() {
+		return 1.0;
+	}
+	a.intersection(b).count() as f32 / a.union(b).count() as f32
+}
@@ -1,12 +1,16 @@
 use crate::access::MethodAccessFlags;
 use crate::attributes::{Attribute, Attributes, AttributeSource, SignatureAttribute, ExceptionsAttribute};
 use crate::version::ClassVersion;
+use crate::classfile::ParseOptions;
 use crate::constantpool::{ConstantPool, ConstantPoolWriter};
 use crate::Serializable;
 use crate::error::Result;
-use crate::utils::{VecUtils};
-use crate::code::CodeAttribute;
+use crate::intern::Name;
+use crate::utils::{VecUtils, ReadUtils};
+use crate::code::{CodeAttribute, LazyCodeAttribute};
 use std::io::{Read, Write};
+use std::borrow::Cow;
+use std::sync::Arc;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 #[allow(non_snake_case)]
@@ -16,12 +20,19 @@ pub mod Methods {
 	use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 	use crate::version::ClassVersion;
 	use crate::constantpool::{ConstantPool, ConstantPoolWriter};
+	use crate::classfile::ParseOptions;
+	use std::sync::Arc;
 	
-	pub fn parse<T: Read>(rdr: &mut T, version: &ClassVersion, constant_pool: &ConstantPool) -> crate::Result<Vec<Method>> {
+	/// The pool is shared with the code of each method if the options ask for lazy code
+	pub fn parse<T: Read>(rdr: &mut T, version: &ClassVersion, constant_pool: &Arc<ConstantPool>, options: &ParseOptions) -> crate::Result<Vec<Method>> {
 		let num_fields = rdr.read_u16::<BigEndian>()? as usize;
 		let mut fields: Vec<Method> = Vec::with_capacity(num_fields);
 		for _ in 0..num_fields {
-			fields.push(Method::parse(rdr, version, constant_pool)?);
+			fields.push(if options.lazy_code {
+				Method::parse_lazy(rdr, version, constant_pool, options)?
+			} else {
+				Method::parse(rdr, version, constant_pool, options)?
+			});
 		}
 		Ok(fields)
 	}
@@ -38,18 +49,18 @@ pub mod Methods {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Method {
 	pub access_flags: MethodAccessFlags,
-	pub name: String,
-	pub descriptor: String,
+	pub name: Name,
+	pub descriptor: Name,
 	pub attributes: Vec<Attribute>
 }
 
 impl Method {
-	pub fn parse<R: Read>(rdr: &mut R, version: &ClassVersion, constant_pool: &ConstantPool) -> Result<Self> {
+	pub fn parse<R: Read>(rdr: &mut R, version: &ClassVersion, constant_pool: &ConstantPool, options: &ParseOptions) -> Result<Self> {
 		let access_flags = MethodAccessFlags::parse(rdr)?;
 		let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
 		let descriptor = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
 		
-		let attributes = Attributes::parse(rdr, AttributeSource::Method, version, constant_pool, &mut None)?;
+		let attributes = Attributes::parse(rdr, AttributeSource::Method, version, constant_pool, &mut None, options)?;
 		
 		let meth = Method {
 			access_flags,
@@ -60,7 +71,38 @@ impl Method {
 		Ok(meth)
 	}
 	
-	pub fn signature(&mut self) -> Option<&mut String> {
+	/// Parses the method, keeping its code as a `LazyCodeAttribute` which is decoded on first access
+	pub fn parse_lazy<R: Read>(rdr: &mut R, version: &ClassVersion, constant_pool: &Arc<ConstantPool>, options: &ParseOptions) -> Result<Self> {
+		let access_flags = MethodAccessFlags::parse(rdr)?;
+		let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
+		let descriptor = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
+		
+		let num_attributes = rdr.read_u16::<BigEndian>()? as usize;
+		let mut attributes: Vec<Attribute> = Vec::with_capacity(num_attributes);
+		for _ in 0..num_attributes {
+			let name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
+			let attribute_length = rdr.read_u32::<BigEndian>()? as usize;
+			if options.skips(&name) {
+				rdr.skip_nbytes(attribute_length)?;
+				continue;
+			}
+			let buf: Vec<u8> = rdr.read_nbytes(attribute_length)?;
+			attributes.push(if &*name == "Code" {
+				Attribute::LazyCode(LazyCodeAttribute::new(*version, constant_pool.clone(), buf, options.clone()))
+			} else {
+				Attribute::parse_named(name, buf, &AttributeSource::Method, version, constant_pool, None, options)?
+			});
+		}
+		
+		Ok(Method {
+			access_flags,
+			name,
+			descriptor,
+			attributes
+		})
+	}
+	
+	pub fn signature(&mut self) -> Option<&mut Name> {
 		for attr in self.attributes.iter_mut() {
 			if let Attribute::Signature(sig) = attr {
 				return Some(&mut sig.signature)
@@ -69,7 +111,7 @@ impl Method {
 		return None
 	}
 	
-	pub fn set_signature(&mut self, sig: Option<String>) {
+	pub fn set_signature(&mut self, sig: Option<Name>) {
 		let index = self.attributes.find_first(|attr| {
 			if let Attribute::Signature(_) = attr { true } else { false }
 		});
@@ -85,7 +127,7 @@ impl Method {
 		}
 	}
 	
-	pub fn exceptions(&mut self) -> Option<&mut Vec<String>> {
+	pub fn exceptions(&mut self) -> Option<&mut Vec<Name>> {
 		for attr in self.attributes.iter_mut() {
 			if let Attribute::Exceptions(x) = attr {
 				return Some(&mut x.exceptions)
@@ -94,7 +136,7 @@ impl Method {
 		return None
 	}
 	
-	pub fn set_exceptions(&mut self, exc: Option<Vec<String>>) {
+	pub fn set_exceptions(&mut self, exc: Option<Vec<Name>>) {
 		let index = self.attributes.find_first(|attr| {
 			if let Attribute::Exceptions(_) = attr { true } else { false }
 		});
@@ -110,18 +152,34 @@ impl Method {
 		}
 	}
 	
-	pub fn code(&mut self) -> Option<&mut CodeAttribute> {
+	/// Decodes the code first if it was parsed lazily
+	pub fn code(&mut self) -> Result<Option<&mut CodeAttribute>> {
 		for attr in self.attributes.iter_mut() {
+			if let Attribute::LazyCode(x) = attr {
+				*attr = Attribute::Code(x.decode()?);
+			}
 			if let Attribute::Code(x) = attr {
-				return Some(x)
+				return Ok(Some(x))
 			}
 		}
-		return None
+		Ok(None)
+	}
+	
+	/// The code of this method without modifying it, lazy code is decoded into a copy
+	pub fn decode_code(&self) -> Result<Option<Cow<'_, CodeAttribute>>> {
+		for attr in self.attributes.iter() {
+			match attr {
+				Attribute::Code(x) => return Ok(Some(Cow::Borrowed(x))),
+				Attribute::LazyCode(x) => return Ok(Some(Cow::Owned(x.decode()?))),
+				_ => {}
+			}
+		}
+		Ok(None)
 	}
 	
 	pub fn set_code(&mut self, code: Option<CodeAttribute>) {
 		let index = self.attributes.find_first(|attr| {
-			if let Attribute::Code(_) = attr { true } else { false }
+			matches!(attr, Attribute::Code(_) | Attribute::LazyCode(_))
 		});
 		if let Some(code) = code {
 			let attr = Attribute::Code(code);
@@ -135,10 +193,22 @@ impl Method {
 		}
 	}
 	
+	/// Expands the stack map frames of the code of this method into full frames. Lazy code is
+	/// left untouched
+	pub fn expand_frames(&mut self, this_class: &str) -> Result<()> {
+		let is_static = self.access_flags.contains(MethodAccessFlags::STATIC);
+		for attr in self.attributes.iter_mut() {
+			if let Attribute::Code(code) = attr {
+				code.expand_frames(this_class, &self.name, &self.descriptor, is_static)?;
+			}
+		}
+		Ok(())
+	}
+	
 	pub fn write<W: Write>(&self, wtr: &mut W, constant_pool: &mut ConstantPoolWriter) -> Result<()> {
 		self.access_flags.write(wtr)?;
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.name.clone()))?;
-		wtr.write_u16::<BigEndian>(constant_pool.utf8(self.descriptor.clone()))?;
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.name)?)?;
+		wtr.write_u16::<BigEndian>(constant_pool.utf8(&self.descriptor)?)?;
 		Attributes::write(wtr, &self.attributes, constant_pool, None)?;
 		Ok(())
 	}
@@ -0,0 +1,207 @@
+use crate::classfile::{ClassFile, ParseOptions};
+use crate::archive::Archive;
+use crate::error::{Result, ParserError};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// The bytes of a class, named by its path relative to the directory or archive it came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassSource {
+	pub path: PathBuf,
+	pub bytes: Vec<u8>
+}
+
+impl ClassSource {
+	pub fn new<P: Into<PathBuf>>(path: P, bytes: Vec<u8>) -> Self {
+		ClassSource {
+			path: path.into(),
+			bytes
+		}
+	}
+}
+
+/// The outcome of processing many classes on rayon's thread pool. A class that fails is reported in
+/// `errors` without aborting the rest, and both lists are sorted by path
+#[derive(Debug)]
+pub struct Batch<T> {
+	pub results: Vec<(PathBuf, T)>,
+	pub errors: Vec<(PathBuf, ParserError)>
+}
+
+impl <T> Batch<T> {
+	fn collect(outcomes: Vec<(PathBuf, Result<T>)>) -> Self {
+		let mut results = Vec::with_capacity(outcomes.len());
+		let mut errors = Vec::new();
+		for (path, outcome) in outcomes {
+			match outcome {
+				Ok(x) => results.push((path, x)),
+				Err(err) => errors.push((path, err))
+			}
+		}
+		Batch {
+			results,
+			errors
+		}
+	}
+	
+	pub fn is_ok(&self) -> bool {
+		self.errors.is_empty()
+	}
+}
+
+/// The classes read from a directory along with the files and subdirectories that could not be
+/// read, both sorted by path relative to the directory
+pub type ReadDir = (Vec<ClassSource>, Vec<(PathBuf, ParserError)>);
+
+/// Reads every `.class` file below the given directory. Only failing to list the directory itself
+/// is an error, anything below it that cannot be read is reported without aborting the rest
+pub fn read_dir<P: AsRef<Path>>(dir: P) -> Result<ReadDir> {
+	let dir = dir.as_ref();
+	let mut paths = Vec::new();
+	let mut errors = Vec::new();
+	for entry in fs::read_dir(dir)? {
+		match entry {
+			Ok(entry) => find_classes(entry.path(), &mut paths, &mut errors),
+			Err(err) => errors.push((dir.to_path_buf(), err.into()))
+		}
+	}
+	paths.sort();
+	let outcomes: Vec<(PathBuf, Result<Vec<u8>>)> = paths.into_par_iter()
+		.map(|path| {
+			let outcome = fs::read(&path).map_err(ParserError::from);
+			(path, outcome)
+		})
+		.collect();
+	let relative = |path: PathBuf| path.strip_prefix(dir).map(Path::to_path_buf).unwrap_or(path);
+	let mut sources = Vec::with_capacity(outcomes.len());
+	for (path, outcome) in outcomes {
+		match outcome {
+			Ok(bytes) => sources.push(ClassSource::new(relative(path), bytes)),
+			Err(err) => errors.push((path, err))
+		}
+	}
+	let mut errors: Vec<(PathBuf, ParserError)> = errors.into_iter().map(|(path, err)| (relative(path), err)).collect();
+	errors.sort_by(|a, b| a.0.cmp(&b.0));
+	Ok((sources, errors))
+}
+
+/// The classes of an archive that a JVM of the given release would load, see
+/// `Archive::classes_for_release`
+pub fn read_archive(archive: &Archive, release: u32) -> Vec<ClassSource> {
+	archive.classes_for_release(release)
+		.into_iter()
+		.map(|entry| ClassSource::new(entry.path(), entry.data.clone()))
+		.collect()
+}
+
+fn find_classes(path: PathBuf, paths: &mut Vec<PathBuf>, errors: &mut Vec<(PathBuf, ParserError)>) {
+	if !path.is_dir() {
+		if path.extension() == Some(OsStr::new("class")) {
+			paths.push(path);
+		}
+		return;
+	}
+	let entries = match fs::read_dir(&path) {
+		Ok(x) => x,
+		Err(err) => return errors.push((path, err.into()))
+	};
+	for entry in entries {
+		match entry {
+			Ok(entry) => find_classes(entry.path(), paths, errors),
+			Err(err) => errors.push((path.clone(), err.into()))
+		}
+	}
+}
+
+/// Parses every source in parallel
+pub fn parse_all(sources: &[ClassSource], options: &ParseOptions) -> Batch<ClassFile> {
+	process(sources, options, |_, class| Ok(class))
+}
+
+/// Parses every source in parallel and passes each parsed class to the given closure
+pub fn process<T, F>(sources: &[ClassSource], options: &ParseOptions, op: F) -> Batch<T>
+	where T: Send, F: Fn(&Path, ClassFile) -> Result<T> + Sync {
+	let outcomes = sources.par_iter()
+		.map(|source| {
+			let outcome = ClassFile::parse_with_options(&mut source.bytes.as_slice(), options)
+				.and_then(|class| op(&source.path, class));
+			(source.path.clone(), outcome)
+		})
+		.collect();
+	Batch::collect(outcomes)
+}
+
+/// Parses every source in parallel, transforms it with the given closure and writes it back to
+/// bytes. The returned sources keep the paths they were read from
+pub fn transform<F>(sources: &[ClassSource], options: &ParseOptions, op: F) -> Batch<ClassSource>
+	where F: Fn(&Path, &mut ClassFile) -> Result<()> + Sync {
+	process(sources, options, |path, mut class| {
+		op(path, &mut class)?;
+		let mut bytes = Vec::new();
+		class.write(&mut bytes)?;
+		Ok(ClassSource::new(path, bytes))
+	})
+}
+
+/// Transforms every class below the input directory and writes the results to the same relative
+/// paths below the output directory. Classes that fail, or cannot be read, are not written, the
+/// output is written one file at a time in path order
+pub fn transform_dir<P, Q, F>(input: P, output: Q, options: &ParseOptions, op: F) -> Result<Batch<()>>
+	where P: AsRef<Path>, Q: AsRef<Path>, F: Fn(&Path, &mut ClassFile) -> Result<()> + Sync {
+	let (sources, read_errors) = read_dir(input)?;
+	let transformed = transform(&sources, options, op);
+	let mut outcomes = Vec::with_capacity(sources.len());
+	for (path, source) in transformed.results {
+		let target = output.as_ref().join(&path);
+		let outcome = write_file(&target, &source.bytes);
+		outcomes.push((path, outcome));
+	}
+	let mut batch = Batch::collect(outcomes);
+	batch.errors.extend(transformed.errors);
+	batch.errors.extend(read_errors);
+	batch.errors.sort_by(|a, b| a.0.cmp(&b.0));
+	Ok(batch)
+}
+
+/// Transforms every class entry of the archive, including multi-release overlays, and writes the
+/// archive with the transformed classes as a zip. The entries keep their order and other entries
+/// are copied unchanged, as are classes that fail, which are reported by their entry name
+pub fn transform_archive<W, F>(archive: &Archive, wtr: W, options: &ParseOptions, op: F) -> Result<Batch<()>>
+	where W: Write + Seek, F: Fn(&Path, &mut ClassFile) -> Result<()> + Sync {
+	let sources: Vec<ClassSource> = archive.classes()
+		.map(|entry| ClassSource::new(&entry.name, entry.data.clone()))
+		.collect();
+	let transformed = transform(&sources, options, op);
+	let mut results = Vec::with_capacity(transformed.results.len());
+	let mut classes: HashMap<PathBuf, Vec<u8>> = HashMap::with_capacity(transformed.results.len());
+	for (path, source) in transformed.results {
+		classes.insert(path.clone(), source.bytes);
+		results.push((path, ()));
+	}
+	let mut output = archive.clone();
+	for entry in output.entries.iter_mut().filter(|entry| entry.is_class()) {
+		if let Some(bytes) = classes.remove(Path::new(&entry.name)) {
+			entry.data = bytes;
+		}
+	}
+	output.write(wtr)?;
+	let mut batch = Batch {
+		results,
+		errors: transformed.errors
+	};
+	batch.results.sort_by(|a, b| a.0.cmp(&b.0));
+	batch.errors.sort_by(|a, b| a.0.cmp(&b.0));
+	Ok(batch)
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	fs::write(path, bytes)?;
+	Ok(())
+}
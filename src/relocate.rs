@@ -0,0 +1,238 @@
+use crate::classfile::{ClassFile, ParseOptions};
+use crate::archive::Archive;
+use crate::remap::{remap_references, Remapper};
+use crate::attributes::{Attribute, ConstantValue};
+use crate::ast::{Insn, LdcType};
+use crate::constantpool::{ConstantPool, ConstantType, CPIndex, Utf8Info};
+use crate::intern::Name;
+use crate::error::{Result, ParserError};
+use std::sync::Arc;
+
+const SERVICES: &str = "META-INF/services/";
+
+/// Moves the classes and resources of a package and its subpackages to another package
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+	/// The package in internal form, such as `com/google/common`
+	pub from: String,
+	/// The package it moves to, such as `ours/shaded/guava`
+	pub to: String,
+	/// Classes, resources and packages below `from` that are not moved, such as
+	/// `com/google/common/annotations`
+	pub excludes: Vec<String>
+}
+
+impl Relocation {
+	pub fn new<T: Into<String>, U: Into<String>>(from: T, to: U) -> Self {
+		Relocation {
+			from: from.into().trim_end_matches('/').to_string(),
+			to: to.into().trim_end_matches('/').to_string(),
+			excludes: Vec::new()
+		}
+	}
+	
+	/// The new path of a class or resource below `from`, such as `com/google/common/base/Joiner`
+	pub fn apply(&self, path: &str) -> Option<String> {
+		let rest = below(path, &self.from)?;
+		if self.excludes.iter().any(|exclude| below(path, exclude.trim_end_matches('/')).is_some()) {
+			return None;
+		}
+		Some(format!("{}{}", self.to, rest))
+	}
+}
+
+/// The part of the path after the given package, if the path is the package or is below it
+fn below<'a>(path: &'a str, package: &str) -> Option<&'a str> {
+	let rest = path.strip_prefix(package)?;
+	if rest.is_empty() || rest.starts_with('/') {
+		Some(rest)
+	} else {
+		None
+	}
+}
+
+/// Relocates packages within classes, and within the resources and service files of archives, as is
+/// done when shading dependencies into a jar
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Relocator {
+	/// Applied in order, the first one matching a name wins
+	pub relocations: Vec<Relocation>,
+	/// Relocate string constants naming a relocated class or resource as well, whether in internal
+	/// form such as `com/google/common/base/Joiner`, in binary form such as
+	/// `com.google.common.base.Joiner` or as an absolute resource path
+	pub relocate_strings: bool
+}
+
+impl Relocator {
+	/// String constants are relocated, as classes are often loaded by name
+	pub fn new(relocations: Vec<Relocation>) -> Self {
+		Relocator {
+			relocations,
+			relocate_strings: true
+		}
+	}
+	
+	/// The new path of a class or resource, None if it is not relocated
+	pub fn relocate_path(&self, path: &str) -> Option<String> {
+		self.relocations.iter().find_map(|relocation| relocation.apply(path))
+	}
+	
+	/// The new binary name of a class, such as `ours.shaded.guava.base.Joiner` for
+	/// `com.google.common.base.Joiner`
+	pub fn relocate_binary_name(&self, name: &str) -> Option<String> {
+		if name.contains('/') {
+			return None;
+		}
+		self.relocate_path(&name.replace('.', "/")).map(|path| path.replace('/', "."))
+	}
+	
+	/// The new value of a string constant that names a relocated class or resource
+	pub fn relocate_string(&self, value: &str) -> Option<String> {
+		if let Some(path) = value.strip_prefix('/') {
+			self.relocate_path(path).map(|path| format!("/{}", path))
+		} else if value.contains('/') {
+			self.relocate_path(value)
+		} else if value.contains('.') {
+			self.relocate_binary_name(value)
+		} else {
+			None
+		}
+	}
+	
+	/// Relocates the class and every class it refers to, see `remap`. Unlike `remap`, a retained
+	/// constant pool is kept with its Utf8 entries relocated in place, so that the old packages are
+	/// not left in the pool and attributes this crate does not decode name the new ones
+	pub fn relocate_class(&self, class: &mut ClassFile) -> Result<()> {
+		remap_references(class, self)?;
+		if self.relocate_strings {
+			for field in class.fields.iter_mut() {
+				for attr in field.attributes.iter_mut() {
+					if let Attribute::ConstantValue(x) = attr {
+						if let ConstantValue::String(value) = &mut x.value {
+							self.relocate_name(value);
+						}
+					}
+				}
+			}
+			for method in class.methods.iter_mut() {
+				if let Some(code) = method.code()? {
+					for insn in code.insns.insns.iter_mut() {
+						if let Insn::Ldc(x) = insn {
+							if let LdcType::String(value) = &mut x.constant {
+								self.relocate_name(value);
+							}
+						}
+					}
+				}
+			}
+		}
+		if let Some(pool) = &class.constant_pool {
+			class.constant_pool = Some(Arc::new(self.relocate_pool(pool)));
+		}
+		Ok(())
+	}
+	
+	/// Relocates every Utf8 entry that is a relocated class name, resource path, descriptor or
+	/// signature, or a string naming one when strings are relocated
+	fn relocate_pool(&self, pool: &ConstantPool) -> ConstantPool {
+		let mut pool = pool.clone();
+		let relocated: Vec<(CPIndex, String)> = pool.iter()
+			.filter_map(|(index, constant)| match constant {
+				ConstantType::Utf8(x) => Some((index, &x.str)),
+				_ => None
+			})
+			.filter_map(|(index, value)| {
+				let relocated = self.relocate_path(value)
+					.or_else(|| match value.contains(';').then(|| self.signature(value)) {
+						Some(Ok(signature)) if signature != *value => Some(signature.to_string()),
+						_ => None
+					})
+					.or_else(|| if self.relocate_strings { self.relocate_string(value) } else { None })?;
+				Some((index, relocated))
+			})
+			.collect();
+		for (index, value) in relocated {
+			pool.set(index, Some(ConstantType::Utf8(Utf8Info::new(value))));
+		}
+		pool
+	}
+	
+	fn relocate_name(&self, value: &mut Name) {
+		if let Some(relocated) = self.relocate_string(value) {
+			*value = Name::from(relocated);
+		}
+	}
+	
+	/// Relocates every class of the archive, then moves the resources below relocated packages and
+	/// renames the service files of relocated services, along with the providers they list.
+	///
+	/// Classes are parsed lazily while retaining their constant pools whatever the given options, so
+	/// that the attributes this crate does not decode stay valid. Classes that fail
+	/// to relocate are returned with their error and left as they were
+	pub fn relocate_archive(&self, archive: &mut Archive, options: &ParseOptions) -> Vec<(String, ParserError)> {
+		let options = ParseOptions {
+			lazy_code: true,
+			keep_constant_pool: true,
+			..options.clone()
+		};
+		let errors = archive.transform(&options, |_, class| self.relocate_class(class));
+		for entry in archive.entries.iter_mut() {
+			// the release prefix of multi-release entries is kept
+			let path = entry.path().to_string();
+			let prefix = &entry.name[..entry.name.len() - path.len()];
+			let relocated = if let Some(service) = path.strip_prefix(SERVICES) {
+				if let Ok(text) = std::str::from_utf8(&entry.data) {
+					if let Some(text) = self.relocate_providers(text) {
+						entry.data = text.into_bytes();
+					}
+				}
+				self.relocate_binary_name(service).map(|service| format!("{}{}", SERVICES, service))
+			} else if path.starts_with("META-INF/") {
+				None
+			} else {
+				self.relocate_path(&path)
+			};
+			if let Some(relocated) = relocated {
+				entry.name = format!("{}{}", prefix, relocated);
+			}
+		}
+		errors
+	}
+	
+	/// Relocates the providers listed by a service file, keeping its comments and layout
+	fn relocate_providers(&self, text: &str) -> Option<String> {
+		let mut changed = false;
+		let lines: Vec<String> = text.split('\n')
+			.map(|line| {
+				let end = line.find('#').unwrap_or(line.len());
+				let provider = line[..end].trim();
+				match self.relocate_binary_name(provider) {
+					Some(relocated) if !provider.is_empty() => {
+						changed = true;
+						line.replacen(provider, &relocated, 1)
+					},
+					_ => line.to_string()
+				}
+			})
+			.collect();
+		if changed {
+			Some(lines.join("\n"))
+		} else {
+			None
+		}
+	}
+}
+
+impl Remapper for Relocator {
+	fn map_class(&self, name: &str) -> Option<Name> {
+		self.relocate_path(name).map(Name::from)
+	}
+	
+	fn map_field(&self, _owner: &str, _name: &str, _descriptor: &str) -> Option<Name> {
+		None
+	}
+	
+	fn map_method(&self, _owner: &str, _name: &str, _descriptor: &str) -> Option<Name> {
+		None
+	}
+}
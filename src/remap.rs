@@ -0,0 +1,480 @@
+use crate::classfile::ClassFile;
+use crate::attributes::{Attribute, Annotation, ElementValue, StackMapFrame, VerificationType};
+use crate::code::CodeAttribute;
+use crate::ast::{Insn, LdcType, BootstrapArgument, Handle};
+use crate::constantpool::MethodHandleKind;
+use crate::types::Type;
+use crate::intern::Name;
+use crate::error::{Result, ParserError};
+
+const LAMBDA_METAFACTORY: &str = "java/lang/invoke/LambdaMetafactory";
+
+/// Decides the new names of classes, fields and methods. Lookups are always made with the original
+/// names, and returning None keeps a name as it is
+pub trait Remapper {
+	/// The new internal name of a class, such as `a/b` for `com/example/Foo`
+	fn map_class(&self, name: &str) -> Option<Name>;
+	
+	fn map_field(&self, owner: &str, name: &str, descriptor: &str) -> Option<Name>;
+	
+	fn map_method(&self, owner: &str, name: &str, descriptor: &str) -> Option<Name>;
+	
+	fn class_name(&self, name: &Name) -> Name {
+		self.map_class(name).unwrap_or_else(|| name.clone())
+	}
+	
+	/// Renames a class constant, which is either an internal name or an array descriptor
+	fn type_name(&self, name: &Name) -> Name {
+		if name.starts_with('[') {
+			self.descriptor(name)
+		} else {
+			self.class_name(name)
+		}
+	}
+	
+	/// Renames every class in a field or method descriptor
+	fn descriptor(&self, descriptor: &Name) -> Name {
+		let mut result = String::with_capacity(descriptor.len());
+		let mut changed = false;
+		let mut rest = descriptor.as_str();
+		while let Some(start) = rest.find('L') {
+			let end = match rest[start..].find(';') {
+				Some(x) => start + x,
+				None => break
+			};
+			let class = &rest[start + 1..end];
+			result.push_str(&rest[..=start]);
+			match self.map_class(class) {
+				Some(mapped) => {
+					changed |= mapped != class;
+					result.push_str(&mapped);
+				},
+				None => result.push_str(class)
+			}
+			result.push(';');
+			rest = &rest[end + 1..];
+		}
+		if !changed {
+			return descriptor.clone();
+		}
+		result.push_str(rest);
+		Name::from(result)
+	}
+	
+	/// Renames every class in a class, field or method signature, including inner classes of
+	/// parameterized types which are looked up by their binary name, such as `Outer$Inner`
+	fn signature(&self, signature: &Name) -> Result<Name> {
+		let mut writer = SignatureRemapper {
+			remapper: self,
+			signature: signature.as_str(),
+			pos: 0,
+			result: String::with_capacity(signature.len())
+		};
+		writer.signature()?;
+		Ok(Name::from(writer.result))
+	}
+	
+	fn field_name(&self, owner: &Name, name: &Name, descriptor: &Name) -> Name {
+		self.map_field(owner, name, descriptor).unwrap_or_else(|| name.clone())
+	}
+	
+	/// Constructors and static initializers keep their names
+	fn method_name(&self, owner: &Name, name: &Name, descriptor: &Name) -> Name {
+		if name.starts_with('<') {
+			return name.clone();
+		}
+		self.map_method(owner, name, descriptor).unwrap_or_else(|| name.clone())
+	}
+}
+
+struct SignatureRemapper<'a, R: Remapper + ?Sized> {
+	remapper: &'a R,
+	signature: &'a str,
+	pos: usize,
+	result: String
+}
+
+impl <'a, R: Remapper + ?Sized> SignatureRemapper<'a, R> {
+	fn peek(&self) -> Result<u8> {
+		self.signature.as_bytes()
+			.get(self.pos)
+			.copied()
+			.ok_or_else(|| ParserError::invalid_descriptor(format!("Unexpected end of signature {}", self.signature)))
+	}
+	
+	fn copy(&mut self) -> Result<u8> {
+		let next = self.peek()?;
+		self.result.push(next as char);
+		self.pos += 1;
+		Ok(next)
+	}
+	
+	/// Reads up to the next of the given characters
+	fn identifier(&mut self, ends: &[u8]) -> Result<&'a str> {
+		let start = self.pos;
+		while !ends.contains(&self.peek()?) {
+			self.pos += 1;
+		}
+		Ok(&self.signature[start..self.pos])
+	}
+	
+	fn signature(&mut self) -> Result<()> {
+		if self.peek()? == b'<' {
+			self.type_parameters()?;
+		}
+		if self.peek()? == b'(' {
+			self.copy()?;
+			while self.peek()? != b')' {
+				self.java_type()?;
+			}
+			self.copy()?;
+			self.java_type()?;
+			while self.pos < self.signature.len() {
+				if self.copy()? != b'^' {
+					return Err(self.error());
+				}
+				self.reference_type()?;
+			}
+		} else {
+			// a field has a single type, a class its super class followed by its interfaces
+			while self.pos < self.signature.len() {
+				self.reference_type()?;
+			}
+		}
+		Ok(())
+	}
+	
+	fn type_parameters(&mut self) -> Result<()> {
+		self.copy()?;
+		while self.peek()? != b'>' {
+			let name = self.identifier(b":")?;
+			self.result.push_str(name);
+			// the class bound may be empty, the interface bounds follow it
+			while self.peek()? == b':' {
+				self.copy()?;
+				if matches!(self.peek()?, b'L' | b'T' | b'[') {
+					self.reference_type()?;
+				}
+			}
+		}
+		self.copy()?;
+		Ok(())
+	}
+	
+	fn java_type(&mut self) -> Result<()> {
+		match self.peek()? {
+			b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b'V' => {
+				self.copy()?;
+				Ok(())
+			},
+			_ => self.reference_type()
+		}
+	}
+	
+	fn reference_type(&mut self) -> Result<()> {
+		match self.peek()? {
+			b'L' => self.class_type(),
+			b'T' => {
+				while self.copy()? != b';' {}
+				Ok(())
+			},
+			b'[' => {
+				self.copy()?;
+				self.java_type()
+			},
+			_ => Err(self.error())
+		}
+	}
+	
+	fn class_type(&mut self) -> Result<()> {
+		self.copy()?;
+		let mut class = Name::from(self.identifier(b"<.;")?);
+		self.result.push_str(&self.remapper.class_name(&class));
+		loop {
+			if self.peek()? == b'<' {
+				self.type_arguments()?;
+			}
+			match self.copy()? {
+				b'.' => {
+					let inner = self.identifier(b"<.;")?;
+					let outer = format!("{}$", self.remapper.class_name(&class));
+					class = Name::from(format!("{}${}", class, inner));
+					let mapped = self.remapper.class_name(&class);
+					// keep only the simple name of the inner class
+					let start = if mapped.starts_with(&outer) {
+						outer.len()
+					} else {
+						mapped.rfind('$').map_or(0, |index| index + 1)
+					};
+					self.result.push_str(&mapped[start..]);
+				},
+				b';' => return Ok(()),
+				_ => return Err(self.error())
+			}
+		}
+	}
+	
+	fn type_arguments(&mut self) -> Result<()> {
+		self.copy()?;
+		while self.peek()? != b'>' {
+			match self.peek()? {
+				b'*' => {
+					self.copy()?;
+				},
+				b'+' | b'-' => {
+					self.copy()?;
+					self.reference_type()?;
+				},
+				_ => self.reference_type()?
+			}
+		}
+		self.copy()?;
+		Ok(())
+	}
+	
+	fn error(&self) -> ParserError {
+		ParserError::invalid_descriptor(format!("Invalid signature {} at {}", self.signature, self.pos))
+	}
+}
+
+/// Renames the class, its members and every reference it makes to other classes and members.
+///
+/// Lazily parsed code is decoded, and the types of its stack map frames are renamed like any other.
+/// A retained constant pool still names the old classes and members, so it is dropped and the class
+/// is written with a new one. Attributes this crate does not decode, such as LocalVariableTypeTable
+/// or type annotations, refer to the old pool and are dropped with it, the BootstrapMethods
+/// attribute being rebuilt from the renamed invokedynamic instructions
+pub fn remap<R: Remapper + ?Sized>(class: &mut ClassFile, remapper: &R) -> Result<()> {
+	remap_references(class, remapper)?;
+	class.constant_pool = None;
+	retain_decoded(&mut class.attributes);
+	for field in class.fields.iter_mut() {
+		retain_decoded(&mut field.attributes);
+	}
+	for method in class.methods.iter_mut() {
+		retain_decoded(&mut method.attributes);
+	}
+	Ok(())
+}
+
+/// Renames everything `remap` does, leaving a retained constant pool and the attributes that
+/// refer to it untouched
+pub(crate) fn remap_references<R: Remapper + ?Sized>(class: &mut ClassFile, remapper: &R) -> Result<()> {
+	let owner = class.this_class.clone();
+	for field in class.fields.iter_mut() {
+		field.name = remapper.field_name(&owner, &field.name, &field.descriptor);
+		field.descriptor = remapper.descriptor(&field.descriptor);
+		remap_attributes(&mut field.attributes, remapper)?;
+	}
+	for method in class.methods.iter_mut() {
+		method.name = remapper.method_name(&owner, &method.name, &method.descriptor);
+		method.descriptor = remapper.descriptor(&method.descriptor);
+		remap_attributes(&mut method.attributes, remapper)?;
+	}
+	class.this_class = remapper.class_name(&owner);
+	class.super_class = class.super_class.as_ref().map(|name| remapper.class_name(name));
+	for interface in class.interfaces.iter_mut() {
+		*interface = remapper.class_name(interface);
+	}
+	remap_attributes(&mut class.attributes, remapper)
+}
+
+/// Drops the undecoded attributes among the given ones and those of their code
+fn retain_decoded(attributes: &mut Vec<Attribute>) {
+	attributes.retain(|attr| !matches!(attr, Attribute::Unknown(_)));
+	for attr in attributes.iter_mut() {
+		if let Attribute::Code(x) = attr {
+			x.attributes.retain(|attr| !matches!(attr, Attribute::Unknown(_)));
+		}
+	}
+}
+
+fn remap_attributes<R: Remapper + ?Sized>(attributes: &mut [Attribute], remapper: &R) -> Result<()> {
+	for attr in attributes.iter_mut() {
+		match attr {
+			Attribute::Signature(x) => x.signature = remapper.signature(&x.signature)?,
+			Attribute::Exceptions(x) => {
+				for exception in x.exceptions.iter_mut() {
+					*exception = remapper.class_name(exception);
+				}
+			},
+			Attribute::Annotations(x) => {
+				for annotation in x.annotations.iter_mut() {
+					remap_annotation(annotation, remapper);
+				}
+			},
+			Attribute::ParameterAnnotations(x) => {
+				for annotation in x.parameters.iter_mut().flatten() {
+					remap_annotation(annotation, remapper);
+				}
+			},
+			Attribute::AnnotationDefault(x) => remap_element_value(&mut x.value, remapper),
+			Attribute::Code(x) => remap_code(x, remapper)?,
+			Attribute::LazyCode(x) => {
+				let mut code = x.decode()?;
+				remap_code(&mut code, remapper)?;
+				*attr = Attribute::Code(code);
+			},
+			Attribute::LocalVariableTable(x) => {
+				for variable in x.variables.iter_mut() {
+					variable.descriptor = remapper.descriptor(&variable.descriptor);
+				}
+			},
+			Attribute::StackMapTable(x) => {
+				for frame in x.frames.iter_mut() {
+					let types = match frame {
+						StackMapFrame::Same { .. } | StackMapFrame::Chop { .. } => continue,
+						StackMapFrame::SameLocals1StackItem { stack, .. } => std::slice::from_mut(stack),
+						StackMapFrame::Append { locals, .. } => locals.as_mut_slice(),
+						StackMapFrame::Full { locals, stack, .. } => {
+							remap_verification_types(locals, remapper);
+							stack.as_mut_slice()
+						}
+					};
+					remap_verification_types(types, remapper);
+				}
+			},
+			Attribute::InnerClasses(x) => {
+				for class in x.classes.iter_mut() {
+					class.inner_name = class.inner_name.as_ref().map(|name| inner_name(&class.inner_class, name, remapper));
+					class.inner_class = remapper.class_name(&class.inner_class);
+					class.outer_class = class.outer_class.as_ref().map(|name| remapper.class_name(name));
+				}
+			},
+			Attribute::EnclosingMethod(x) => {
+				if let Some((name, descriptor)) = &mut x.method {
+					*name = remapper.method_name(&x.class, name, descriptor);
+					*descriptor = remapper.descriptor(descriptor);
+				}
+				x.class = remapper.class_name(&x.class);
+			},
+			Attribute::NestHost(x) => x.host = remapper.class_name(&x.host),
+			Attribute::NestMembers(x) => {
+				for member in x.members.iter_mut() {
+					*member = remapper.class_name(member);
+				}
+			},
+			_ => {}
+		}
+	}
+	Ok(())
+}
+
+/// The simple name of an inner class once it is renamed, which is taken from its new name unless
+/// only its package changes
+fn inner_name<R: Remapper + ?Sized>(class: &Name, name: &Name, remapper: &R) -> Name {
+	let mapped = remapper.class_name(class);
+	let simple = |class: &str| class.rfind('/').map_or(class, |index| &class[index + 1..]).to_string();
+	if mapped == *class || simple(&mapped) == simple(class) {
+		return name.clone();
+	}
+	match mapped.rfind('$') {
+		// anonymous and local classes are numbered before their name
+		Some(index) => Name::from(mapped[index + 1..].trim_start_matches(|c: char| c.is_ascii_digit())),
+		None => name.clone()
+	}
+}
+
+fn remap_verification_types<R: Remapper + ?Sized>(types: &mut [VerificationType], remapper: &R) {
+	for kind in types.iter_mut() {
+		if let VerificationType::Object(name) = kind {
+			*name = remapper.type_name(name);
+		}
+	}
+}
+
+fn remap_annotation<R: Remapper + ?Sized>(annotation: &mut Annotation, remapper: &R) {
+	annotation.descriptor = remapper.descriptor(&annotation.descriptor);
+	for (_, value) in annotation.elements.iter_mut() {
+		remap_element_value(value, remapper);
+	}
+}
+
+fn remap_element_value<R: Remapper + ?Sized>(value: &mut ElementValue, remapper: &R) {
+	match value {
+		ElementValue::Enum { descriptor, name } => {
+			let owner = Name::from(descriptor.trim_start_matches('L').trim_end_matches(';'));
+			*name = remapper.field_name(&owner, name, descriptor);
+			*descriptor = remapper.descriptor(descriptor);
+		},
+		ElementValue::Class(x) => *x = remapper.descriptor(x),
+		ElementValue::Annotation(x) => remap_annotation(x, remapper),
+		ElementValue::Array(values) => {
+			for value in values.iter_mut() {
+				remap_element_value(value, remapper);
+			}
+		},
+		_ => {}
+	}
+}
+
+fn remap_code<R: Remapper + ?Sized>(code: &mut CodeAttribute, remapper: &R) -> Result<()> {
+	for insn in code.insns.insns.iter_mut() {
+		match insn {
+			Insn::Invoke(x) => {
+				x.name = remapper.method_name(&x.class, &x.name, &x.descriptor);
+				x.descriptor = remapper.descriptor(&x.descriptor);
+				x.class = remapper.type_name(&x.class);
+			},
+			Insn::GetField(x) => {
+				x.name = remapper.field_name(&x.class, &x.name, &x.descriptor);
+				x.descriptor = remapper.descriptor(&x.descriptor);
+				x.class = remapper.class_name(&x.class);
+			},
+			Insn::PutField(x) => {
+				x.name = remapper.field_name(&x.class, &x.name, &x.descriptor);
+				x.descriptor = remapper.descriptor(&x.descriptor);
+				x.class = remapper.class_name(&x.class);
+			},
+			Insn::NewObject(x) => x.kind = remapper.class_name(&x.kind),
+			Insn::CheckCast(x) => x.kind = remapper.type_name(&x.kind),
+			Insn::InstanceOf(x) => x.class = remapper.type_name(&x.class),
+			Insn::MultiNewArray(x) => x.kind = remapper.type_name(&x.kind),
+			Insn::NewArray(x) => {
+				if let Type::Reference(Some(kind)) = &mut x.kind {
+					*kind = remapper.type_name(kind);
+				}
+			},
+			Insn::Ldc(x) => match &mut x.constant {
+				LdcType::Class(name) => *name = remapper.type_name(name),
+				LdcType::MethodType(descriptor) => *descriptor = remapper.descriptor(descriptor),
+				_ => {}
+			},
+			Insn::InvokeDynamic(x) => {
+				// a lambda is named after the interface method it implements, which is the only
+				// method of the class its call site returns
+				if &*x.bootstrap_handle.owner == LAMBDA_METAFACTORY {
+					if let (Some(BootstrapArgument::MethodType(implemented)), Some(end)) = (x.bootstrap_arguments.first(), x.descriptor.rfind(')')) {
+						let interface = x.descriptor[end + 1..].trim_start_matches('L').trim_end_matches(';');
+						x.name = remapper.method_name(&Name::from(interface), &x.name, implemented);
+					}
+				}
+				x.descriptor = remapper.descriptor(&x.descriptor);
+				remap_handle(&mut x.bootstrap_handle, remapper);
+				for argument in x.bootstrap_arguments.iter_mut() {
+					match argument {
+						BootstrapArgument::Class(name) => *name = remapper.type_name(name),
+						BootstrapArgument::MethodType(descriptor) => *descriptor = remapper.descriptor(descriptor),
+						BootstrapArgument::MethodHandle(handle) => remap_handle(handle, remapper),
+						_ => {}
+					}
+				}
+			},
+			_ => {}
+		}
+	}
+	for handler in code.exceptions.iter_mut() {
+		handler.catch_type = handler.catch_type.as_ref().map(|name| remapper.class_name(name));
+	}
+	remap_attributes(&mut code.attributes, remapper)
+}
+
+fn remap_handle<R: Remapper + ?Sized>(handle: &mut Handle, remapper: &R) {
+	handle.name = match handle.kind {
+		MethodHandleKind::GetField | MethodHandleKind::GetStatic | MethodHandleKind::PutField
+			| MethodHandleKind::PutStatic => remapper.field_name(&handle.owner, &handle.name, &handle.descriptor),
+		_ => remapper.method_name(&handle.owner, &handle.name, &handle.descriptor)
+	};
+	handle.descriptor = remapper.descriptor(&handle.descriptor);
+	handle.owner = remapper.type_name(&handle.owner);
+}
@@ -0,0 +1,829 @@
+use crate::classfile::ClassFile;
+use crate::method::Method;
+use crate::code::CodeAttribute;
+use crate::access::MethodAccessFlags;
+use crate::ast::{Insn, LabelInsn, OpType, ReturnType, PrimitiveType, IntegerType, JumpCondition, LdcType, InvokeType};
+use crate::types::Type;
+use crate::classpath::{ClassPath, OBJECT};
+use crate::verify::{Diagnostic, Location, field_descriptor_len};
+use crate::intern::Name;
+use crate::error::Result;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+
+const THROWABLE: &str = "java/lang/Throwable";
+
+/// The outcome of simulating an instruction, an error being a message explaining why it does not
+/// verify
+type Check<T> = std::result::Result<T, String>;
+
+/// The type of a local or stack value, as tracked by the verifier
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+	/// Unusable, such as the second half of a long or a local whose types conflict
+	Top,
+	Int,
+	Float,
+	Long,
+	Double,
+	Null,
+	/// `this` in a constructor, before a super or this constructor is called
+	UninitializedThis,
+	/// An object created by the `new` instruction at this index, before its constructor is called
+	Uninitialized(usize),
+	/// An object of the class with this internal name, or an array with this descriptor
+	Reference(Name)
+}
+
+impl Value {
+	fn size(&self) -> usize {
+		match self {
+			Value::Long | Value::Double => 2,
+			_ => 1
+		}
+	}
+	
+	fn is_reference(&self) -> bool {
+		matches!(self, Value::Null | Value::UninitializedThis | Value::Uninitialized(_) | Value::Reference(_))
+	}
+	
+	/// The value of a field descriptor, booleans, bytes, chars and shorts being ints
+	fn of_descriptor(descriptor: &str) -> Value {
+		match descriptor.as_bytes()[0] {
+			b'F' => Value::Float,
+			b'J' => Value::Long,
+			b'D' => Value::Double,
+			b'L' => Value::Reference(Name::from(&descriptor[1..descriptor.len() - 1])),
+			b'[' => Value::Reference(Name::from(descriptor)),
+			_ => Value::Int
+		}
+	}
+	
+	/// The value of a field descriptor, failing if the descriptor is invalid
+	fn of_field_descriptor(descriptor: &str) -> Check<Value> {
+		if field_descriptor_len(descriptor) != Some(descriptor.len()) || descriptor == "V" {
+			return Err(format!("Invalid descriptor {}", descriptor));
+		}
+		Ok(Value::of_descriptor(descriptor))
+	}
+	
+	fn of_primitive(kind: PrimitiveType) -> Value {
+		match kind {
+			PrimitiveType::Long => Value::Long,
+			PrimitiveType::Float => Value::Float,
+			PrimitiveType::Double => Value::Double,
+			_ => Value::Int
+		}
+	}
+	
+	fn of_integer(kind: IntegerType) -> Value {
+		match kind {
+			IntegerType::Int => Value::Int,
+			IntegerType::Long => Value::Long
+		}
+	}
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Value::Top => write!(f, "an unusable value"),
+			Value::Int => write!(f, "int"),
+			Value::Float => write!(f, "float"),
+			Value::Long => write!(f, "long"),
+			Value::Double => write!(f, "double"),
+			Value::Null => write!(f, "null"),
+			Value::UninitializedThis => write!(f, "uninitialized this"),
+			Value::Uninitialized(index) => write!(f, "the uninitialized object created at instruction {}", index),
+			Value::Reference(name) => write!(f, "{}", name)
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Frame {
+	locals: Vec<Value>,
+	/// Longs and doubles are a single value, but take two slots of the stack
+	stack: Vec<Value>
+}
+
+impl Frame {
+	fn push(&mut self, value: Value) {
+		self.stack.push(value);
+	}
+	
+	fn pop(&mut self) -> Check<Value> {
+		self.stack.pop().ok_or_else(|| "Stack underflow".to_string())
+	}
+	
+	/// Pops values taking exactly the given number of slots, in the order they were pushed
+	fn pop_slots(&mut self, slots: usize) -> Check<Vec<Value>> {
+		let mut values = Vec::new();
+		let mut popped = 0;
+		while popped < slots {
+			let value = self.pop()?;
+			popped += value.size();
+			values.push(value);
+		}
+		if popped != slots {
+			return Err("Instruction splits a long or double on the stack".to_string());
+		}
+		values.reverse();
+		Ok(values)
+	}
+	
+	fn stack_size(&self) -> usize {
+		self.stack.iter().map(|value| value.size()).sum()
+	}
+	
+	fn load(&self, index: u16) -> Check<&Value> {
+		self.locals.get(index as usize)
+			.ok_or_else(|| format!("Local {} is beyond max_locals {}", index, self.locals.len()))
+	}
+	
+	fn store(&mut self, index: u16, value: Value) -> Check<()> {
+		let index = index as usize;
+		if index + value.size() > self.locals.len() {
+			return Err(format!("Local {} is beyond max_locals {}", index, self.locals.len()));
+		}
+		// the long or double in the previous local loses its second half
+		if index > 0 && self.locals[index - 1].size() == 2 {
+			self.locals[index - 1] = Value::Top;
+		}
+		if value.size() == 2 {
+			self.locals[index + 1] = Value::Top;
+		}
+		self.locals[index] = value;
+		Ok(())
+	}
+	
+	/// Replaces an uninitialized object with the initialized one once its constructor is called
+	fn initialize(&mut self, uninitialized: &Value, initialized: Value) {
+		for value in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+			if value == uninitialized {
+				*value = initialized.clone();
+			}
+		}
+	}
+}
+
+/// Where control goes after an instruction
+enum Flow {
+	Next,
+	Jump(LabelInsn)
+}
+
+/// Verifies the code of every method of a class by type inference, as the JVM does for classes
+/// without stack map frames: the types of the locals and the stack are simulated along every path
+/// until they no longer change. Stack underflow and overflow, values of the wrong type, objects
+/// used before their constructor is called, code that falls off its end and jumps to labels that
+/// are not placed are reported, at most once per instruction.
+///
+/// The class path is asked whether reference types are assignable, and for the common superclass
+/// of references merged where paths meet. Classes it cannot find are assumed to be assignable, and
+/// like the JVM any reference may be used where an interface is expected. Stack map frames are not
+/// read, so code that does not match its frames is not caught
+pub fn verify_types(class: &ClassFile, classpath: &ClassPath) -> Result<Vec<Diagnostic>> {
+	let mut diagnostics = Vec::new();
+	for method in class.methods.iter() {
+		if let Some(code) = method.decode_code()? {
+			let checker = TypeChecker {
+				class,
+				method,
+				code: &code,
+				classpath
+			};
+			let labels = closest_labels(&code.insns.insns);
+			for (index, message) in checker.verify() {
+				diagnostics.push(Diagnostic {
+					location: Location::Insn {
+						name: method.name.clone(),
+						descriptor: method.descriptor.clone(),
+						index,
+						label: labels.get(index).copied().flatten()
+					},
+					message
+				});
+			}
+		}
+	}
+	Ok(diagnostics)
+}
+
+/// The label placed at or before each instruction, closest first
+fn closest_labels(insns: &[Insn]) -> Vec<Option<LabelInsn>> {
+	let mut label = None;
+	insns.iter()
+		.map(|insn| {
+			if let Insn::Label(x) = insn {
+				label = Some(*x);
+			}
+			label
+		})
+		.collect()
+}
+
+/// The parameter descriptors and return descriptor of a method descriptor, None if it is invalid
+pub(crate) fn method_types(descriptor: &str) -> Option<(Vec<&str>, &str)> {
+	let mut rest = descriptor.strip_prefix('(')?;
+	let mut parameters = Vec::new();
+	while !rest.starts_with(')') {
+		let len = field_descriptor_len(rest)?;
+		if &rest[..len] == "V" {
+			return None;
+		}
+		parameters.push(&rest[..len]);
+		rest = &rest[len..];
+	}
+	let result = &rest[1..];
+	if field_descriptor_len(result) != Some(result.len()) {
+		return None;
+	}
+	Some((parameters, result))
+}
+
+struct TypeChecker<'a> {
+	class: &'a ClassFile,
+	method: &'a Method,
+	code: &'a CodeAttribute,
+	classpath: &'a ClassPath
+}
+
+impl TypeChecker<'_> {
+	/// The error of each instruction that does not verify, by index
+	fn verify(&self) -> BTreeMap<usize, String> {
+		let insns = &self.code.insns.insns;
+		let mut errors = BTreeMap::new();
+		let (parameters, _) = match method_types(&self.method.descriptor) {
+			Some(x) => x,
+			// invalid descriptors are left to the structural verifier
+			None => return errors
+		};
+		if insns.is_empty() {
+			errors.insert(0, "Code is empty".to_string());
+			return errors;
+		}
+		let labels: HashMap<LabelInsn, usize> = insns.iter()
+			.enumerate()
+			.filter_map(|(index, insn)| match insn {
+				Insn::Label(label) => Some((*label, index)),
+				_ => None
+			})
+			.collect();
+		let mut handlers = Vec::new();
+		for handler in self.code.exceptions.iter() {
+			match (labels.get(&handler.start), labels.get(&handler.end), labels.get(&handler.handler)) {
+				(Some(start), Some(end), Some(target)) => {
+					let catch = handler.catch_type.clone().unwrap_or_else(|| Name::from(THROWABLE));
+					handlers.push((*start, *end, *target, Value::Reference(catch)));
+				},
+				_ => {
+					errors.insert(0, "Exception handler uses a label that is not placed".to_string());
+				}
+			}
+		}
+	
+		let mut locals = vec![Value::Top; self.code.max_locals as usize];
+		let mut slot = 0;
+		let mut initial = Vec::new();
+		if !self.method.access_flags.contains(MethodAccessFlags::STATIC) {
+			initial.push(if self.method.name == "<init>" && self.class.this_class != OBJECT {
+				Value::UninitializedThis
+			} else {
+				Value::Reference(self.class.this_class.clone())
+			});
+		}
+		initial.extend(parameters.iter().map(|x| Value::of_descriptor(x)));
+		for value in initial {
+			let size = value.size();
+			if slot + size > locals.len() {
+				errors.insert(0, format!("Parameters do not fit in max_locals {}", self.code.max_locals));
+				locals.resize(slot + size, Value::Top);
+			}
+			locals[slot] = value;
+			slot += size;
+		}
+	
+		let mut frames: Vec<Option<Frame>> = vec![None; insns.len()];
+		frames[0] = Some(Frame {
+			locals,
+			stack: Vec::new()
+		});
+		// kept apart from the errors of the instructions, which are cleared once they execute cleanly
+		let mut merge_errors = BTreeMap::new();
+		let mut queue = VecDeque::from(vec![0]);
+		let mut queued = vec![false; insns.len()];
+		queued[0] = true;
+		while let Some(index) = queue.pop_front() {
+			queued[index] = false;
+			let mut frame = frames[index].clone().unwrap();
+			let mut targets: Vec<(usize, Frame)> = handlers.iter()
+				.filter(|(start, end, _, _)| *start <= index && index < *end)
+				.map(|(_, _, target, catch)| (*target, Frame {
+					locals: frame.locals.clone(),
+					stack: vec![catch.clone()]
+				}))
+				.collect();
+			let flows = match self.execute(&mut frame, index, &insns[index]) {
+				Ok(flows) => flows,
+				Err(message) => {
+					errors.insert(index, message);
+					continue;
+				}
+			};
+			if frame.stack_size() > self.code.max_stack as usize {
+				errors.insert(index, format!("Stack overflow, {} slots are needed but max_stack is {}", frame.stack_size(), self.code.max_stack));
+				continue;
+			}
+			errors.remove(&index);
+			for flow in flows {
+				match flow {
+					Flow::Next if index + 1 == insns.len() => {
+						errors.insert(index, "Execution falls off the end of the code".to_string());
+					},
+					Flow::Next => targets.push((index + 1, frame.clone())),
+					Flow::Jump(label) => match labels.get(&label) {
+						Some(target) => targets.push((*target, frame.clone())),
+						None => {
+							errors.insert(index, "Jump to a label that is not placed".to_string());
+						}
+					}
+				}
+			}
+			for (target, frame) in targets {
+				let merged = match &frames[target] {
+					None => Some(frame),
+					Some(existing) => match self.merge(existing, &frame) {
+						Ok(merged) => merged,
+						Err(message) => {
+							merge_errors.insert(target, message);
+							None
+						}
+					}
+				};
+				if let Some(merged) = merged {
+					frames[target] = Some(merged);
+					if !queued[target] {
+						queued[target] = true;
+						queue.push_back(target);
+					}
+				}
+			}
+		}
+		for (index, message) in merge_errors {
+			errors.entry(index).or_insert(message);
+		}
+		errors
+	}
+	
+	/// The frame where two paths meet, None if it is the existing frame
+	fn merge(&self, existing: &Frame, incoming: &Frame) -> Check<Option<Frame>> {
+		if existing.stack.len() != incoming.stack.len() {
+			return Err(format!("Stack heights {} and {} differ where paths meet", existing.stack_size(), incoming.stack_size()));
+		}
+		let mut merged = existing.clone();
+		for (value, other) in merged.stack.iter_mut().zip(incoming.stack.iter()) {
+			let result = self.merge_values(value, other);
+			if result == Value::Top && *value != Value::Top {
+				return Err(format!("Stack holds {} and {} where paths meet", value, other));
+			}
+			*value = result;
+		}
+		for (value, other) in merged.locals.iter_mut().zip(incoming.locals.iter()) {
+			*value = self.merge_values(value, other);
+		}
+		if &merged == existing {
+			Ok(None)
+		} else {
+			Ok(Some(merged))
+		}
+	}
+	
+	fn merge_values(&self, a: &Value, b: &Value) -> Value {
+		match (a, b) {
+			_ if a == b => a.clone(),
+			(Value::Null, Value::Reference(_)) => b.clone(),
+			(Value::Reference(_), Value::Null) => a.clone(),
+			(Value::Reference(a), Value::Reference(b)) => Value::Reference(self.merge_references(a, b)),
+			_ => Value::Top
+		}
+	}
+	
+	/// The common superclass of two references. Arrays of references are arrays of the common
+	/// superclass of their elements, other arrays that differ are only objects
+	fn merge_references(&self, a: &str, b: &str) -> Name {
+		if a == b {
+			return Name::from(a);
+		}
+		// the element of an array of references, as an internal name or an array descriptor
+		let element = |x: &str| match x.strip_prefix('L') {
+			Some(class) => class.strip_suffix(';').map(Name::from),
+			None if x.starts_with('[') => Some(Name::from(x)),
+			None => None
+		};
+		match (a.strip_prefix('['), b.strip_prefix('[')) {
+			(Some(a), Some(b)) => match (element(a), element(b)) {
+				(Some(a), Some(b)) => {
+					let merged = self.merge_references(&a, &b);
+					if merged.starts_with('[') {
+						Name::from(format!("[{}", merged))
+					} else {
+						Name::from(format!("[L{};", merged))
+					}
+				},
+				_ => Name::from(OBJECT)
+			},
+			(None, None) => self.classpath.common_super_class(a, b).unwrap_or_else(|_| Name::from(OBJECT)),
+			_ => Name::from(OBJECT)
+		}
+	}
+	
+	fn is_assignable(&self, to: &str, from: &str) -> bool {
+		if to == from || to == OBJECT {
+			return true;
+		}
+		// like the JVM, any reference may be used where an interface is expected
+		if !to.starts_with('[') && self.classpath.is_interface(to).unwrap_or(true) {
+			return true;
+		}
+		self.classpath.is_assignable_from(to, from).unwrap_or(true)
+	}
+	
+	/// Checks that a value can be used where the expected one is
+	fn check(&self, expected: &Value, found: &Value) -> Check<()> {
+		let matches = match (expected, found) {
+			(Value::Reference(_), Value::Null) => true,
+			(Value::Reference(to), Value::Reference(from)) => self.is_assignable(to, from),
+			(Value::Reference(_), Value::Uninitialized(_)) | (Value::Reference(_), Value::UninitializedThis) => {
+				return Err(format!("Expected {} but found {}, which is used before its constructor is called", expected, found));
+			},
+			_ => expected == found
+		};
+		if matches {
+			Ok(())
+		} else {
+			Err(format!("Expected {} but found {}", expected, found))
+		}
+	}
+	
+	fn pop(&self, frame: &mut Frame, expected: &Value) -> Check<Value> {
+		let value = frame.pop()?;
+		self.check(expected, &value)?;
+		Ok(value)
+	}
+	
+	/// Pops an initialized reference or null
+	fn pop_object(&self, frame: &mut Frame) -> Check<Value> {
+		self.pop(frame, &Value::Reference(Name::from(OBJECT)))
+	}
+	
+	/// Pops an array, returning the descriptor of its elements, None if it is null
+	fn pop_array(&self, frame: &mut Frame) -> Check<Option<String>> {
+		match frame.pop()? {
+			Value::Null => Ok(None),
+			Value::Reference(name) if name.starts_with('[') => Ok(Some(name[1..].to_string())),
+			value => Err(format!("Expected an array but found {}", value))
+		}
+	}
+	
+	fn execute(&self, frame: &mut Frame, index: usize, insn: &Insn) -> Check<Vec<Flow>> {
+		match insn {
+			Insn::Label(_) | Insn::Nop(_) | Insn::BreakPoint(_) | Insn::ImpDep1(_) | Insn::ImpDep2(_) => {},
+			Insn::ArrayLoad(x) => {
+				self.pop(frame, &Value::Int)?;
+				let element = self.pop_array(frame)?;
+				if let Some(element) = &element {
+					if !array_holds(&x.kind, element) {
+						return Err(format!("Array of {} does not hold {:?}", element, x.kind));
+					}
+				}
+				frame.push(match (&x.kind, element) {
+					(Type::Reference(_), Some(element)) => Value::of_field_descriptor(&element)?,
+					(Type::Reference(_), None) => Value::Null,
+					(kind, _) => value_of_type(kind)
+				});
+			},
+			Insn::ArrayStore(x) => {
+				match x.kind {
+					Type::Reference(_) => self.pop_object(frame)?,
+					ref kind => self.pop(frame, &value_of_type(kind))?
+				};
+				self.pop(frame, &Value::Int)?;
+				if let Some(element) = self.pop_array(frame)? {
+					if !array_holds(&x.kind, &element) {
+						return Err(format!("Array of {} does not hold {:?}", element, x.kind));
+					}
+				}
+			},
+			Insn::Ldc(x) => frame.push(match &x.constant {
+				LdcType::Null => Value::Null,
+				LdcType::String(_) => Value::Reference(Name::from("java/lang/String")),
+				LdcType::Int(_) => Value::Int,
+				LdcType::Float(_) => Value::Float,
+				LdcType::Long(_) => Value::Long,
+				LdcType::Double(_) => Value::Double,
+				LdcType::Class(_) => Value::Reference(Name::from("java/lang/Class")),
+				LdcType::MethodType(_) => Value::Reference(Name::from("java/lang/invoke/MethodType")),
+				LdcType::MethodHandle() => Value::Reference(Name::from("java/lang/invoke/MethodHandle")),
+				// the type of a dynamic constant is not decoded
+				LdcType::Dynamic() => Value::Reference(Name::from(OBJECT))
+			}),
+			Insn::LocalLoad(x) => {
+				let value = frame.load(x.index)?.clone();
+				if !local_holds(x.kind, &value) {
+					return Err(format!("Local {} holds {} rather than {:?}", x.index, value, x.kind));
+				}
+				frame.push(value);
+			},
+			Insn::LocalStore(x) => {
+				let value = frame.pop()?;
+				if !local_holds(x.kind, &value) {
+					return Err(format!("Expected {:?} but found {}", x.kind, value));
+				}
+				frame.store(x.index, value)?;
+			},
+			Insn::NewArray(x) => {
+				self.pop(frame, &Value::Int)?;
+				let descriptor = match &x.kind {
+					Type::Reference(Some(name)) if name.starts_with('[') => format!("[{}", name),
+					Type::Reference(Some(name)) => format!("[L{};", name),
+					Type::Reference(None) => format!("[L{};", OBJECT),
+					Type::Boolean => "[Z".to_string(),
+					Type::Byte => "[B".to_string(),
+					Type::Char => "[C".to_string(),
+					Type::Short => "[S".to_string(),
+					Type::Int => "[I".to_string(),
+					Type::Long => "[J".to_string(),
+					Type::Float => "[F".to_string(),
+					Type::Double => "[D".to_string(),
+					Type::Void => return Err("Array of void".to_string())
+				};
+				frame.push(Value::Reference(Name::from(descriptor)));
+			},
+			Insn::Return(x) => {
+				let (_, result) = method_types(&self.method.descriptor).unwrap();
+				match x.kind {
+					ReturnType::Void if result == "V" => {},
+					ReturnType::Void => return Err(format!("Returns nothing from a method returning {}", result)),
+					_ if result == "V" => return Err("Returns a value from a void method".to_string()),
+					ReturnType::Reference => {
+						self.pop(frame, &Value::of_descriptor(result))?;
+					},
+					kind => {
+						let expected = Value::of_descriptor(result);
+						let value = frame.pop()?;
+						if value != return_value(kind) || value != expected {
+							return Err(format!("Returns {} from a method returning {}", value, result));
+						}
+					}
+				}
+				if frame.locals.contains(&Value::UninitializedThis) {
+					return Err("Constructor returns before calling a super or this constructor".to_string());
+				}
+				return Ok(Vec::new());
+			},
+			Insn::ArrayLength(_) => {
+				self.pop_array(frame)?;
+				frame.push(Value::Int);
+			},
+			Insn::Throw(_) => {
+				self.pop(frame, &Value::Reference(Name::from(THROWABLE)))?;
+				return Ok(Vec::new());
+			},
+			Insn::CheckCast(x) => {
+				self.pop_object(frame)?;
+				frame.push(Value::Reference(x.kind.clone()));
+			},
+			Insn::Convert(x) => {
+				self.pop(frame, &Value::of_primitive(x.from))?;
+				frame.push(Value::of_primitive(x.to));
+			},
+			Insn::Add(x) => self.binary(frame, Value::of_primitive(x.kind))?,
+			Insn::Subtract(x) => self.binary(frame, Value::of_primitive(x.kind))?,
+			Insn::Multiply(x) => self.binary(frame, Value::of_primitive(x.kind))?,
+			Insn::Divide(x) => self.binary(frame, Value::of_primitive(x.kind))?,
+			Insn::Remainder(x) => self.binary(frame, Value::of_primitive(x.kind))?,
+			Insn::Negate(x) => {
+				let value = Value::of_primitive(x.kind);
+				self.pop(frame, &value)?;
+				frame.push(value);
+			},
+			Insn::Compare(x) => {
+				let value = Value::of_primitive(x.kind);
+				self.pop(frame, &value)?;
+				self.pop(frame, &value)?;
+				frame.push(Value::Int);
+			},
+			Insn::And(x) => self.binary(frame, Value::of_integer(x.kind))?,
+			Insn::Or(x) => self.binary(frame, Value::of_integer(x.kind))?,
+			Insn::Xor(x) => self.binary(frame, Value::of_integer(x.kind))?,
+			Insn::ShiftLeft(x) => self.shift(frame, Value::of_integer(x.kind))?,
+			Insn::ShiftRight(x) => self.shift(frame, Value::of_integer(x.kind))?,
+			Insn::LogicalShiftRight(x) => self.shift(frame, Value::of_integer(x.kind))?,
+			Insn::Dup(x) => {
+				let top = frame.pop_slots(x.num as usize)?;
+				let below = frame.pop_slots(x.down as usize)?;
+				for value in top.iter().chain(below.iter()).chain(top.iter()) {
+					frame.push(value.clone());
+				}
+			},
+			Insn::Pop(x) => {
+				frame.pop_slots(if x.pop_two { 2 } else { 1 })?;
+			},
+			Insn::Swap(_) => {
+				let mut values = frame.pop_slots(2)?;
+				if values.len() != 2 {
+					return Err("Swaps a long or double".to_string());
+				}
+				values.reverse();
+				frame.stack.extend(values);
+			},
+			Insn::GetField(x) => {
+				if x.instance {
+					self.pop(frame, &Value::Reference(x.class.clone()))?;
+				}
+				frame.push(Value::of_field_descriptor(&x.descriptor)?);
+			},
+			Insn::PutField(x) => {
+				self.pop(frame, &Value::of_field_descriptor(&x.descriptor)?)?;
+				if x.instance {
+					// constructors may set the fields of their class before calling the super constructor
+					let object = frame.pop()?;
+					if object != Value::UninitializedThis || x.class != self.class.this_class {
+						self.check(&Value::Reference(x.class.clone()), &object)?;
+					}
+				}
+			},
+			Insn::Jump(x) => return Ok(vec![Flow::Jump(x.jump_to)]),
+			Insn::ConditionalJump(x) => {
+				match x.condition {
+					JumpCondition::IsNull | JumpCondition::NotNull => self.pop_reference(frame)?,
+					JumpCondition::ReferencesEqual | JumpCondition::ReferencesNotEqual => {
+						self.pop_reference(frame)?;
+						self.pop_reference(frame)?;
+					},
+					JumpCondition::IntEqZero | JumpCondition::IntNotEqZero | JumpCondition::IntLessThanZero
+						| JumpCondition::IntLessThanOrEqZero | JumpCondition::IntGreaterThanZero | JumpCondition::IntGreaterThanOrEqZero => {
+						self.pop(frame, &Value::Int)?;
+					},
+					_ => {
+						self.pop(frame, &Value::Int)?;
+						self.pop(frame, &Value::Int)?;
+					}
+				}
+				return Ok(vec![Flow::Next, Flow::Jump(x.jump_to)]);
+			},
+			Insn::IncrementInt(x) => {
+				let value = frame.load(x.index)?;
+				if value != &Value::Int {
+					return Err(format!("Local {} holds {} rather than an int", x.index, value));
+				}
+			},
+			Insn::InstanceOf(_) => {
+				self.pop_object(frame)?;
+				frame.push(Value::Int);
+			},
+			Insn::InvokeDynamic(x) => {
+				let (parameters, result) = method_types(&x.descriptor).ok_or_else(|| format!("Invalid descriptor {}", x.descriptor))?;
+				for parameter in parameters.iter().rev() {
+					self.pop(frame, &Value::of_descriptor(parameter))?;
+				}
+				if result != "V" {
+					frame.push(Value::of_descriptor(result));
+				}
+			},
+			Insn::Invoke(x) => {
+				let (parameters, result) = method_types(&x.descriptor).ok_or_else(|| format!("Invalid descriptor {}", x.descriptor))?;
+				for parameter in parameters.iter().rev() {
+					self.pop(frame, &Value::of_descriptor(parameter))?;
+				}
+				if x.name == "<init>" {
+					if x.kind != InvokeType::Special {
+						return Err("Constructors can only be called by invokespecial".to_string());
+					}
+					let object = frame.pop()?;
+					let initialized = match &object {
+						Value::UninitializedThis => {
+							let is_super = self.class.super_class.as_ref() == Some(&x.class);
+							if x.class != self.class.this_class && !is_super {
+								return Err(format!("Constructor of {} called on uninitialized this", x.class));
+							}
+							self.class.this_class.clone()
+						},
+						Value::Uninitialized(new) => match &self.code.insns.insns[*new] {
+							Insn::NewObject(created) if created.kind == x.class => x.class.clone(),
+							_ => return Err(format!("Constructor of {} called on {}", x.class, object))
+						},
+						_ => return Err(format!("Constructor called on {}, which is already initialized", object))
+					};
+					frame.initialize(&object, Value::Reference(initialized));
+				} else if x.kind != InvokeType::Static {
+					self.pop(frame, &Value::Reference(x.class.clone()))?;
+				}
+				if result != "V" {
+					frame.push(Value::of_descriptor(result));
+				}
+			},
+			Insn::LookupSwitch(x) => {
+				self.pop(frame, &Value::Int)?;
+				return Ok(std::iter::once(x.default).chain(x.cases.values().copied()).map(Flow::Jump).collect());
+			},
+			Insn::TableSwitch(x) => {
+				self.pop(frame, &Value::Int)?;
+				return Ok(std::iter::once(x.default).chain(x.cases.iter().copied()).map(Flow::Jump).collect());
+			},
+			Insn::MonitorEnter(_) | Insn::MonitorExit(_) => {
+				self.pop_object(frame)?;
+			},
+			Insn::MultiNewArray(x) => {
+				let dimensions = x.kind.len() - x.kind.trim_start_matches('[').len();
+				if x.dimensions == 0 || dimensions < x.dimensions as usize {
+					return Err(format!("Cannot create {} dimensions of {}", x.dimensions, x.kind));
+				}
+				for _ in 0..x.dimensions {
+					self.pop(frame, &Value::Int)?;
+				}
+				frame.push(Value::Reference(x.kind.clone()));
+			},
+			Insn::NewObject(x) => {
+				if x.kind.starts_with('[') {
+					return Err("Arrays cannot be created by new".to_string());
+				}
+				frame.push(Value::Uninitialized(index));
+			}
+		}
+		Ok(vec![Flow::Next])
+	}
+	
+	fn binary(&self, frame: &mut Frame, value: Value) -> Check<()> {
+		self.pop(frame, &value)?;
+		self.pop(frame, &value)?;
+		frame.push(value);
+		Ok(())
+	}
+	
+	fn shift(&self, frame: &mut Frame, value: Value) -> Check<()> {
+		self.pop(frame, &Value::Int)?;
+		self.pop(frame, &value)?;
+		frame.push(value);
+		Ok(())
+	}
+	
+	/// Pops any reference, initialized or not
+	fn pop_reference(&self, frame: &mut Frame) -> Check<()> {
+		let value = frame.pop()?;
+		if !value.is_reference() {
+			return Err(format!("Expected a reference but found {}", value));
+		}
+		Ok(())
+	}
+}
+
+fn value_of_type(kind: &Type) -> Value {
+	match kind {
+		Type::Long => Value::Long,
+		Type::Float => Value::Float,
+		Type::Double => Value::Double,
+		Type::Reference(_) => Value::Reference(Name::from(OBJECT)),
+		Type::Void => Value::Top,
+		_ => Value::Int
+	}
+}
+
+fn return_value(kind: ReturnType) -> Value {
+	match kind {
+		ReturnType::Long => Value::Long,
+		ReturnType::Float => Value::Float,
+		ReturnType::Double => Value::Double,
+		ReturnType::Reference => Value::Reference(Name::from(OBJECT)),
+		ReturnType::Void => Value::Top,
+		_ => Value::Int
+	}
+}
+
+/// Whether an array with elements of the given descriptor can be accessed by an array instruction
+/// of this kind. Byte instructions access boolean arrays too
+fn array_holds(kind: &Type, element: &str) -> bool {
+	match kind {
+		Type::Reference(_) => element.starts_with(['L', '[']),
+		Type::Boolean | Type::Byte => element == "B" || element == "Z",
+		Type::Char => element == "C",
+		Type::Short => element == "S",
+		Type::Int => element == "I",
+		Type::Long => element == "J",
+		Type::Float => element == "F",
+		Type::Double => element == "D",
+		Type::Void => false
+	}
+}
+
+/// Whether a load or store of this kind can move the value
+fn local_holds(kind: OpType, value: &Value) -> bool {
+	match kind {
+		OpType::Reference => value.is_reference(),
+		OpType::Long => value == &Value::Long,
+		OpType::Float => value == &Value::Float,
+		OpType::Double => value == &Value::Double,
+		_ => value == &Value::Int
+	}
+}
@@ -1,4 +1,5 @@
 use crate::error::{Result, ParserError};
+use crate::intern::Name;
 
 const VOID: char = 'V';
 const BYTE: char = 'B';
@@ -12,7 +13,7 @@ const BOOLEAN: char = 'Z';
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Type {
-	Reference(Option<String>), // If None then the reference refers to no particular class
+	Reference(Option<Name>), // If None then the reference refers to no particular class
 	Boolean,
 	Byte,
 	Char,
@@ -42,7 +43,7 @@ impl Type {
 	}
 }
 
-pub fn parse_method_desc(desc: &String) -> Result<(Vec<Type>, Type)> {
+pub fn parse_method_desc(desc: &str) -> Result<(Vec<Type>, Type)> {
 	parse_method_desc_chars(&desc.as_bytes())
 }
 
@@ -65,7 +66,7 @@ fn parse_method_desc_chars(desc: &[u8]) -> Result<(Vec<Type>, Type)> {
 	Ok((args, ret))
 }
 
-pub fn parse_type(desc: &String) -> Result<(Type, usize)> {
+pub fn parse_type(desc: &str) -> Result<(Type, usize)> {
 	parse_type_chars(&desc.as_bytes(), 0)
 }
 
@@ -92,7 +93,7 @@ fn parse_type_chars(desc: &[u8], mut index: usize) -> Result<(Type, usize)> {
 				}
 				buf.push(desc[index] as char);
 			}
-			(Type::Reference(Some(buf)), index + 1)
+			(Type::Reference(Some(Name::from(buf))), index + 1)
 		}
 		x => return Err(ParserError::invalid_descriptor(format!("Unknown type '{}'", x)))
 	})
@@ -41,6 +41,18 @@ pub trait ReadUtils: Read {
 		self.read_exact(&mut buf)?;
 		Ok(buf)
 	}
+	
+	/// Reads and discards the given number of bytes
+	fn skip_nbytes(&mut self, nbytes: usize) -> std::io::Result<()> {
+		let mut buf = [0u8; 256];
+		let mut remaining = nbytes;
+		while remaining > 0 {
+			let len = remaining.min(buf.len());
+			self.read_exact(&mut buf[..len])?;
+			remaining -= len;
+		}
+		Ok(())
+	}
 }
 impl<W: Read + ?Sized> ReadUtils for W {}
 
@@ -0,0 +1,382 @@
+use crate::classfile::ClassFile;
+use crate::version::MajorVersion;
+use crate::access::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use crate::attributes::{Attribute, ConstantValue};
+use crate::field::Field;
+use crate::method::Method;
+use crate::ast::{Insn, LabelInsn};
+use crate::diff::{insn_labels, attribute_labels};
+use crate::classpath::OBJECT;
+use crate::intern::Name;
+use crate::error::Result;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// The part of a class a diagnostic is about
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Location {
+	Class,
+	Field {
+		name: Name,
+		descriptor: Name
+	},
+	Method {
+		name: Name,
+		descriptor: Name
+	},
+	/// An instruction of a method, by its index in the `InsnList`, along with the closest label
+	/// before it
+	Insn {
+		name: Name,
+		descriptor: Name,
+		index: usize,
+		label: Option<LabelInsn>
+	}
+}
+
+/// A rule of the class file format that a class breaks
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+	pub location: Location,
+	pub message: String
+}
+
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match &self.location {
+			Location::Class => write!(f, "class: {}", self.message),
+			Location::Field { name, descriptor } => write!(f, "field {} {}: {}", name, descriptor, self.message),
+			Location::Method { name, descriptor } => write!(f, "method {}{}: {}", name, descriptor, self.message),
+			Location::Insn { name, descriptor, index, label: Some(label) } => write!(f, "method {}{} at instruction {} after L{}: {}", name, descriptor, index, label.id, self.message),
+			Location::Insn { name, descriptor, index, label: None } => write!(f, "method {}{} at instruction {}: {}", name, descriptor, index, self.message)
+		}
+	}
+}
+
+/// Checks a class against the format checks of JVMS 4.8: the combinations of access flags, the
+/// names and descriptors, the special methods, duplicate members, which methods have code, the
+/// rules specific to interfaces and where each attribute may appear. Labels used by the code that
+/// are never placed in it are reported as well, as the class could not be written.
+///
+/// Lazily parsed code is decoded to be checked, which is the only source of errors. An empty list
+/// means the class is well formed
+pub fn verify_structure(class: &ClassFile) -> Result<Vec<Diagnostic>> {
+	let mut verifier = StructureVerifier {
+		class,
+		diagnostics: Vec::new()
+	};
+	verifier.verify_class();
+	let mut fields = HashSet::new();
+	for field in class.fields.iter() {
+		if !fields.insert((&field.name, &field.descriptor)) {
+			verifier.report(field_location(field), "Duplicate field");
+		}
+		verifier.verify_field(field);
+	}
+	let mut methods = HashSet::new();
+	for method in class.methods.iter() {
+		if !methods.insert((&method.name, &method.descriptor)) {
+			verifier.report(method_location(method), "Duplicate method");
+		}
+		verifier.verify_method(method)?;
+	}
+	Ok(verifier.diagnostics)
+}
+
+struct StructureVerifier<'a> {
+	class: &'a ClassFile,
+	diagnostics: Vec<Diagnostic>
+}
+
+impl StructureVerifier<'_> {
+	fn report<T: Into<String>>(&mut self, location: Location, message: T) {
+		self.diagnostics.push(Diagnostic {
+			location,
+			message: message.into()
+		});
+	}
+	
+	fn is_interface(&self) -> bool {
+		self.class.access_flags.contains(ClassAccessFlags::INTERFACE)
+	}
+	
+	fn verify_class(&mut self) {
+		let class = self.class;
+		let flags = class.access_flags;
+		if flags.intersects(ClassAccessFlags::PRIVATE | ClassAccessFlags::PROTECTED | ClassAccessFlags::STATIC) {
+			self.report(Location::Class, format!("Illegal class flags {:?}, which only nested classes have in InnerClasses", flags & (ClassAccessFlags::PRIVATE | ClassAccessFlags::PROTECTED | ClassAccessFlags::STATIC)));
+		}
+		if self.is_interface() {
+			if !flags.contains(ClassAccessFlags::ABSTRACT) {
+				self.report(Location::Class, "Interface is not abstract");
+			}
+			if flags.intersects(ClassAccessFlags::FINAL | ClassAccessFlags::SUPER | ClassAccessFlags::ENUM) {
+				self.report(Location::Class, format!("Interface has flags {:?}", flags & (ClassAccessFlags::FINAL | ClassAccessFlags::SUPER | ClassAccessFlags::ENUM)));
+			}
+			if class.super_class.as_deref() != Some(OBJECT) {
+				self.report(Location::Class, "Superclass of an interface is not java/lang/Object");
+			}
+		} else {
+			if flags.contains(ClassAccessFlags::ANNOTATION) {
+				self.report(Location::Class, "Annotation interface is not an interface");
+			}
+			if flags.contains(ClassAccessFlags::FINAL | ClassAccessFlags::ABSTRACT) {
+				self.report(Location::Class, "Class is both final and abstract");
+			}
+		}
+	
+		if !is_class_name(&class.this_class) {
+			self.report(Location::Class, format!("Invalid class name {}", class.this_class));
+		}
+		match &class.super_class {
+			Some(name) if !is_class_name(name) => self.report(Location::Class, format!("Invalid superclass name {}", name)),
+			None if class.this_class != OBJECT => self.report(Location::Class, "Missing superclass"),
+			_ => {}
+		}
+		let mut interfaces = HashSet::new();
+		for interface in class.interfaces.iter() {
+			if !is_class_name(interface) {
+				self.report(Location::Class, format!("Invalid interface name {}", interface));
+			}
+			if !interfaces.insert(interface) {
+				self.report(Location::Class, format!("Duplicate interface {}", interface));
+			}
+		}
+	
+		self.verify_attributes(Location::Class, &class.attributes, &[
+			"SourceFile", "InnerClasses", "EnclosingMethod", "NestHost", "NestMembers", "Signature",
+			"RuntimeVisibleAnnotations", "RuntimeInvisibleAnnotations"
+		]);
+		let has = |name: &str| class.attributes.iter().any(|attr| attr.name() == name);
+		if has("NestHost") && has("NestMembers") {
+			self.report(Location::Class, "Both NestHost and NestMembers attributes");
+		}
+	}
+	
+	fn verify_field(&mut self, field: &Field) {
+		let location = field_location(field);
+		let flags = field.access_flags;
+		if !has_one_visibility(flags.bits()) {
+			self.report(location.clone(), "More than one of public, private and protected");
+		}
+		if flags.contains(FieldAccessFlags::FINAL | FieldAccessFlags::VOLATILE) {
+			self.report(location.clone(), "Field is both final and volatile");
+		}
+		if self.is_interface() {
+			let required = FieldAccessFlags::PUBLIC | FieldAccessFlags::STATIC | FieldAccessFlags::FINAL;
+			if !flags.contains(required) || !(required | FieldAccessFlags::SYNTHETIC).contains(flags) {
+				self.report(location.clone(), format!("Interface field has flags {:?} rather than public static final", flags));
+			}
+		}
+		if !is_unqualified_name(&field.name) {
+			self.report(location.clone(), "Invalid field name");
+		}
+		if field_descriptor_len(&field.descriptor) != Some(field.descriptor.len()) || field.descriptor.as_str() == "V" {
+			self.report(location.clone(), "Invalid field descriptor");
+		}
+	
+		self.verify_attributes(location.clone(), &field.attributes, &[
+			"ConstantValue", "Signature", "RuntimeVisibleAnnotations", "RuntimeInvisibleAnnotations"
+		]);
+		for attr in field.attributes.iter() {
+			if let Attribute::ConstantValue(x) = attr {
+				let matches = match &x.value {
+					ConstantValue::Int(_) => ["I", "S", "C", "B", "Z"].contains(&field.descriptor.as_str()),
+					ConstantValue::Long(_) => field.descriptor == "J",
+					ConstantValue::Float(_) => field.descriptor == "F",
+					ConstantValue::Double(_) => field.descriptor == "D",
+					ConstantValue::String(_) => field.descriptor == "Ljava/lang/String;"
+				};
+				if !matches {
+					self.report(location.clone(), format!("Constant value {:?} does not suit the field type", x.value));
+				}
+			}
+		}
+	}
+	
+	fn verify_method(&mut self, method: &Method) -> Result<()> {
+		let location = method_location(method);
+		let flags = method.access_flags;
+		let version = self.class.version.major;
+		if !has_one_visibility(flags.bits()) {
+			self.report(location.clone(), "More than one of public, private and protected");
+		}
+		if self.is_interface() {
+			let illegal = MethodAccessFlags::PROTECTED | MethodAccessFlags::FINAL | MethodAccessFlags::SYNCHRONIZED | MethodAccessFlags::NATIVE;
+			if version >= MajorVersion::JAVA_8 {
+				if flags.intersects(illegal) {
+					self.report(location.clone(), format!("Interface method has flags {:?}", flags & illegal));
+				}
+				if !flags.intersects(MethodAccessFlags::PUBLIC | MethodAccessFlags::PRIVATE) && method.name != "<clinit>" {
+					self.report(location.clone(), "Interface method is neither public nor private");
+				}
+			} else if !flags.contains(MethodAccessFlags::PUBLIC | MethodAccessFlags::ABSTRACT) && method.name != "<clinit>" {
+				self.report(location.clone(), "Interface method is not public abstract before Java 8");
+			}
+		}
+		if flags.contains(MethodAccessFlags::ABSTRACT) {
+			let illegal = MethodAccessFlags::PRIVATE | MethodAccessFlags::STATIC | MethodAccessFlags::FINAL | MethodAccessFlags::SYNCHRONIZED | MethodAccessFlags::NATIVE;
+			if flags.intersects(illegal) {
+				self.report(location.clone(), format!("Abstract method has flags {:?}", flags & illegal));
+			}
+		}
+	
+		match method.name.as_str() {
+			"<init>" => {
+				let illegal = MethodAccessFlags::STATIC | MethodAccessFlags::FINAL | MethodAccessFlags::SYNCHRONIZED | MethodAccessFlags::BRIDGE | MethodAccessFlags::NATIVE | MethodAccessFlags::ABSTRACT;
+				if flags.intersects(illegal) {
+					self.report(location.clone(), format!("Constructor has flags {:?}", flags & illegal));
+				}
+				if self.is_interface() {
+					self.report(location.clone(), "Interface has a constructor");
+				}
+				if !method.descriptor.ends_with(")V") {
+					self.report(location.clone(), "Constructor does not return void");
+				}
+			},
+			"<clinit>" => {
+				if method.descriptor != "()V" {
+					self.report(location.clone(), "Static initializer descriptor is not ()V");
+				}
+				if version >= MajorVersion::JAVA_7 && !flags.contains(MethodAccessFlags::STATIC) {
+					self.report(location.clone(), "Static initializer is not static");
+				}
+			},
+			name => if !is_unqualified_name(name) || name.contains(['<', '>']) {
+				self.report(location.clone(), "Invalid method name");
+			}
+		}
+		match method_descriptor_slots(&method.descriptor) {
+			Some(slots) => {
+				let this = if flags.contains(MethodAccessFlags::STATIC) { 0 } else { 1 };
+				if slots + this > 255 {
+					self.report(location.clone(), "More than 255 parameter slots");
+				}
+			},
+			None => self.report(location.clone(), "Invalid method descriptor")
+		}
+	
+		self.verify_attributes(location.clone(), &method.attributes, &[
+			"Code", "Exceptions", "Signature", "RuntimeVisibleAnnotations", "RuntimeInvisibleAnnotations",
+			"RuntimeVisibleParameterAnnotations", "RuntimeInvisibleParameterAnnotations", "AnnotationDefault"
+		]);
+		let bodiless = flags.intersects(MethodAccessFlags::ABSTRACT | MethodAccessFlags::NATIVE);
+		match method.decode_code()? {
+			Some(_) if bodiless => self.report(location.clone(), "Abstract or native method has code"),
+			None if !bodiless => self.report(location.clone(), "Method has no code"),
+			Some(code) => {
+				self.verify_attributes(location.clone(), &code.attributes, &["LineNumberTable", "LocalVariableTable", "StackMapTable"]);
+				let placed: HashSet<LabelInsn> = code.insns.iter()
+					.filter_map(|insn| match insn {
+						Insn::Label(label) => Some(*label),
+						_ => None
+					})
+					.collect();
+				let mut used: Vec<LabelInsn> = Vec::new();
+				for insn in code.insns.iter() {
+					used.extend(insn_labels(&mut insn.clone()).into_iter().map(|label| *label));
+				}
+				for handler in code.exceptions.iter() {
+					used.extend([handler.start, handler.end, handler.handler]);
+				}
+				for attr in code.attributes.iter() {
+					used.extend(attribute_labels(&mut attr.clone()).into_iter().map(|label| *label));
+				}
+				if used.iter().any(|label| !placed.contains(label)) {
+					self.report(location, "Code uses a label that is not placed in its instructions");
+				}
+			},
+			None => {}
+		}
+		Ok(())
+	}
+	
+	/// Reports the known attributes that do not belong in this location, and those present more than
+	/// once. Unknown attributes are left alone
+	fn verify_attributes(&mut self, location: Location, attributes: &[Attribute], allowed: &[&str]) {
+		let mut seen = HashSet::new();
+		for attr in attributes.iter() {
+			if let Attribute::Unknown(_) = attr {
+				continue;
+			}
+			let name = attr.name();
+			if !allowed.contains(&name) {
+				self.report(location.clone(), format!("{} attribute is not allowed here", name));
+			}
+			let repeatable = matches!(attr, Attribute::LineNumberTable(_) | Attribute::LocalVariableTable(_));
+			if !seen.insert(name) && !repeatable {
+				self.report(location.clone(), format!("Duplicate {} attribute", name));
+			}
+		}
+	}
+}
+
+fn field_location(field: &Field) -> Location {
+	Location::Field {
+		name: field.name.clone(),
+		descriptor: field.descriptor.clone()
+	}
+}
+
+fn method_location(method: &Method) -> Location {
+	Location::Method {
+		name: method.name.clone(),
+		descriptor: method.descriptor.clone()
+	}
+}
+
+fn has_one_visibility(access: u16) -> bool {
+	(access & (MethodAccessFlags::PUBLIC | MethodAccessFlags::PRIVATE | MethodAccessFlags::PROTECTED).bits()).count_ones() <= 1
+}
+
+/// An unqualified name of JVMS 4.2.2, which fields and methods have
+fn is_unqualified_name(name: &str) -> bool {
+	!name.is_empty() && !name.contains(['.', ';', '[', '/'])
+}
+
+/// A binary class name in internal form, such as `java/lang/Object`
+fn is_class_name(name: &str) -> bool {
+	name.split('/').all(is_unqualified_name)
+}
+
+/// The length of the field descriptor at the start of the given string, None if it is not one
+pub(crate) fn field_descriptor_len(descriptor: &str) -> Option<usize> {
+	let dimensions = descriptor.len() - descriptor.trim_start_matches('[').len();
+	if dimensions > 255 {
+		return None;
+	}
+	let rest = &descriptor[dimensions..];
+	let len = match rest.chars().next()? {
+		'B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 'Z' => 1,
+		'V' if dimensions == 0 => 1,
+		'L' => {
+			let end = rest.find(';')?;
+			if !is_class_name(&rest[1..end]) {
+				return None;
+			}
+			end + 1
+		},
+		_ => return None
+	};
+	Some(dimensions + len)
+}
+
+/// The number of local variable slots the parameters of a method descriptor take, None if it is
+/// not a valid method descriptor
+fn method_descriptor_slots(descriptor: &str) -> Option<usize> {
+	let mut rest = descriptor.strip_prefix('(')?;
+	let mut slots = 0;
+	while !rest.starts_with(')') {
+		let len = field_descriptor_len(rest)?;
+		if &rest[..len] == "V" {
+			return None;
+		}
+		slots += if &rest[..len] == "J" || &rest[..len] == "D" { 2 } else { 1 };
+		rest = &rest[len..];
+	}
+	let result = &rest[1..];
+	if field_descriptor_len(result) != Some(result.len()) {
+		return None;
+	}
+	Some(slots)
+}
@@ -0,0 +1,645 @@
+use crate::Serializable;
+use crate::version::ClassVersion;
+use crate::constantpool::{ConstantPool, ConstantPoolWriter, CPIndex};
+use crate::access::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use crate::attributes::{Attribute, Attributes, AttributeSource, LocalVariable, LocalVariableTableAttribute, LineNumber, LineNumberTableAttribute};
+use crate::code::{CodeAttribute, ExceptionHandler, parse_bootstrap_methods};
+use crate::classfile::{ClassFile, ParseOptions, write_class_attributes};
+use crate::field::Field;
+use crate::method::Method;
+use crate::ast::{Insn, LabelInsn};
+use crate::insnlist::InsnList;
+use crate::error::{Result, ParserError};
+use crate::intern::Name;
+use crate::utils::ReadUtils;
+use byteorder::{ReadBytesExt, BigEndian, WriteBytesExt};
+use std::io::{Read, Write};
+
+/// Receives the contents of a class as a stream of events, in the order they appear in the class.
+///
+/// Fields and methods are delimited by `visit_field`/`visit_field_end` and
+/// `visit_method`/`visit_method_end`, and the body of a method by `visit_code`/`visit_code_end`.
+/// Every event does nothing by default, so implementors only override what they need. Visitors
+/// can be chained by wrapping another visitor and forwarding (possibly modified) events to it.
+#[allow(unused_variables)]
+pub trait ClassVisitor {
+	fn visit_header(&mut self, version: ClassVersion, access_flags: ClassAccessFlags, this_class: &Name, super_class: Option<&Name>, interfaces: &[Name]) -> Result<()> {
+		Ok(())
+	}
+
+	/// Return false to skip the attributes of this field
+	fn visit_field(&mut self, access_flags: FieldAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		Ok(true)
+	}
+
+	fn visit_field_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_field_end(&mut self) -> Result<()> {
+		Ok(())
+	}
+
+	/// Return false to skip the attributes and code of this method
+	fn visit_method(&mut self, access_flags: MethodAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		Ok(true)
+	}
+
+	fn visit_method_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		Ok(())
+	}
+
+	/// Return false to skip the body of this method. The body will not be decoded
+	fn visit_code(&mut self, max_stack: u16, max_locals: u16) -> Result<bool> {
+		Ok(true)
+	}
+
+	fn visit_try_catch(&mut self, handler: &ExceptionHandler) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_label(&mut self, label: LabelInsn) -> Result<()> {
+		Ok(())
+	}
+
+	/// Called for every instruction apart from labels, which are passed to `visit_label`
+	fn visit_insn(&mut self, insn: &Insn) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_local_variable(&mut self, variable: &LocalVariable) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_line_number(&mut self, line: &LineNumber) -> Result<()> {
+		Ok(())
+	}
+
+	/// Attributes of the code other than the local variable and line number tables
+	fn visit_code_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_code_end(&mut self) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_method_end(&mut self) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		Ok(())
+	}
+
+	fn visit_end(&mut self) -> Result<()> {
+		Ok(())
+	}
+}
+
+impl <V: ClassVisitor + ?Sized> ClassVisitor for &mut V {
+	fn visit_header(&mut self, version: ClassVersion, access_flags: ClassAccessFlags, this_class: &Name, super_class: Option<&Name>, interfaces: &[Name]) -> Result<()> {
+		(**self).visit_header(version, access_flags, this_class, super_class, interfaces)
+	}
+
+	fn visit_field(&mut self, access_flags: FieldAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		(**self).visit_field(access_flags, name, descriptor)
+	}
+
+	fn visit_field_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		(**self).visit_field_attribute(attribute)
+	}
+
+	fn visit_field_end(&mut self) -> Result<()> {
+		(**self).visit_field_end()
+	}
+
+	fn visit_method(&mut self, access_flags: MethodAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		(**self).visit_method(access_flags, name, descriptor)
+	}
+
+	fn visit_method_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		(**self).visit_method_attribute(attribute)
+	}
+
+	fn visit_code(&mut self, max_stack: u16, max_locals: u16) -> Result<bool> {
+		(**self).visit_code(max_stack, max_locals)
+	}
+
+	fn visit_try_catch(&mut self, handler: &ExceptionHandler) -> Result<()> {
+		(**self).visit_try_catch(handler)
+	}
+
+	fn visit_label(&mut self, label: LabelInsn) -> Result<()> {
+		(**self).visit_label(label)
+	}
+
+	fn visit_insn(&mut self, insn: &Insn) -> Result<()> {
+		(**self).visit_insn(insn)
+	}
+
+	fn visit_local_variable(&mut self, variable: &LocalVariable) -> Result<()> {
+		(**self).visit_local_variable(variable)
+	}
+
+	fn visit_line_number(&mut self, line: &LineNumber) -> Result<()> {
+		(**self).visit_line_number(line)
+	}
+
+	fn visit_code_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		(**self).visit_code_attribute(attribute)
+	}
+
+	fn visit_code_end(&mut self) -> Result<()> {
+		(**self).visit_code_end()
+	}
+
+	fn visit_method_end(&mut self) -> Result<()> {
+		(**self).visit_method_end()
+	}
+
+	fn visit_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		(**self).visit_attribute(attribute)
+	}
+
+	fn visit_end(&mut self) -> Result<()> {
+		(**self).visit_end()
+	}
+}
+
+/// Reads a class and pushes its contents to the given visitor without building a `ClassFile`.
+/// Only one method body is decoded at a time, and only if the visitor asks for it
+pub fn read<R: Read, V: ClassVisitor + ?Sized>(rdr: &mut R, visitor: &mut V) -> Result<()> {
+	read_with_options(rdr, visitor, &ParseOptions::default())
+}
+
+/// Like `read`, but attributes excluded by the options are never passed to the visitor
+pub fn read_with_options<R: Read, V: ClassVisitor + ?Sized>(rdr: &mut R, visitor: &mut V, options: &ParseOptions) -> Result<()> {
+	let magic = rdr.read_u32::<BigEndian>()?;
+	if magic != 0xCAFEBABE {
+		return Err(ParserError::unrecognised("header", magic.to_string()));
+	}
+	let version = ClassVersion::parse(rdr)?;
+	let mut constant_pool = ConstantPool::parse(rdr)?;
+	if let Some(interner) = &options.interner {
+		constant_pool.intern(interner);
+	}
+	let access_flags = ClassAccessFlags::parse(rdr)?;
+	let this_class = class_name(&constant_pool, rdr.read_u16::<BigEndian>()?)?;
+	let super_class = match rdr.read_u16::<BigEndian>()? {
+		0 => None,
+		i => Some(class_name(&constant_pool, i)?)
+	};
+	let num_interfaces = rdr.read_u16::<BigEndian>()? as usize;
+	let mut interfaces: Vec<Name> = Vec::with_capacity(num_interfaces);
+	for _ in 0..num_interfaces {
+		interfaces.push(class_name(&constant_pool, rdr.read_u16::<BigEndian>()?)?.clone());
+	}
+	visitor.visit_header(version, access_flags, this_class, super_class, interfaces.as_slice())?;
+
+	let num_fields = rdr.read_u16::<BigEndian>()?;
+	for _ in 0..num_fields {
+		let access_flags = FieldAccessFlags::parse(rdr)?;
+		let name = &constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str;
+		let descriptor = &constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str;
+		if visitor.visit_field(access_flags, name, descriptor)? {
+			for attribute in Attributes::parse(rdr, AttributeSource::Field, &version, &constant_pool, &mut None, options)?.iter() {
+				visitor.visit_field_attribute(attribute)?;
+			}
+			visitor.visit_field_end()?;
+		} else {
+			skip_attributes(rdr)?;
+		}
+	}
+
+	// invokedynamic instructions refer to the bootstrap methods of the class, which are only read
+	// after the methods, so the class attributes are read before any method is visited
+	let mut rest = Vec::new();
+	rdr.read_to_end(&mut rest)?;
+	let mut rdr = rest.as_slice();
+	let num_methods = rdr.read_u16::<BigEndian>()?;
+	for _ in 0..num_methods {
+		rdr.skip_nbytes(6)?;
+		skip_attributes(&mut rdr)?;
+	}
+	let attributes = Attributes::parse(&mut rdr, AttributeSource::Class, &version, &constant_pool, &mut None, options)?;
+	let bootstrap_methods = parse_bootstrap_methods(&attributes, &constant_pool)?;
+	
+	let rdr = &mut rest.as_slice();
+	let num_methods = rdr.read_u16::<BigEndian>()?;
+	for _ in 0..num_methods {
+		let access_flags = MethodAccessFlags::parse(rdr)?;
+		let name = &constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str;
+		let descriptor = &constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str;
+		if visitor.visit_method(access_flags, name, descriptor)? {
+			let num_attributes = rdr.read_u16::<BigEndian>()?;
+			for _ in 0..num_attributes {
+				let attribute_name = constant_pool.utf8(rdr.read_u16::<BigEndian>()?)?.str.clone();
+				let attribute_length = rdr.read_u32::<BigEndian>()? as usize;
+				if options.skips(&attribute_name) {
+					rdr.skip_nbytes(attribute_length)?;
+					continue;
+				}
+				let buf: Vec<u8> = rdr.read_nbytes(attribute_length)?;
+				if &*attribute_name == "Code" {
+					let mut header = buf.as_slice();
+					let max_stack = header.read_u16::<BigEndian>()?;
+					let max_locals = header.read_u16::<BigEndian>()?;
+					if visitor.visit_code(max_stack, max_locals)? {
+						let mut code = CodeAttribute::parse_with_bootstrap_methods(&version, &constant_pool, &bootstrap_methods, buf, options)?;
+						if options.expand_frames {
+							code.expand_frames(this_class, name, descriptor, access_flags.contains(MethodAccessFlags::STATIC))?;
+						}
+						accept_code_body(&code, visitor)?;
+					}
+				} else {
+					let attribute = Attribute::parse_named(attribute_name, buf, &AttributeSource::Method, &version, &constant_pool, None, options)?;
+					visitor.visit_method_attribute(&attribute)?;
+				}
+			}
+			visitor.visit_method_end()?;
+		} else {
+			skip_attributes(rdr)?;
+		}
+	}
+
+	for attribute in attributes.iter() {
+		visitor.visit_attribute(attribute)?;
+	}
+	visitor.visit_end()
+}
+
+fn class_name(constant_pool: &ConstantPool, index: CPIndex) -> Result<&Name> {
+	Ok(&constant_pool.utf8(constant_pool.class(index)?.name_index)?.str)
+}
+
+fn skip_attributes<R: Read>(rdr: &mut R) -> Result<()> {
+	let num_attributes = rdr.read_u16::<BigEndian>()?;
+	for _ in 0..num_attributes {
+		rdr.read_u16::<BigEndian>()?;
+		let attribute_length = rdr.read_u32::<BigEndian>()? as usize;
+		rdr.skip_nbytes(attribute_length)?;
+	}
+	Ok(())
+}
+
+/// Pushes the contents of an already decoded method body, following a `visit_code` call
+fn accept_code_body<V: ClassVisitor + ?Sized>(code: &CodeAttribute, visitor: &mut V) -> Result<()> {
+	for handler in code.exceptions.iter() {
+		visitor.visit_try_catch(handler)?;
+	}
+	for insn in code.insns.iter() {
+		match insn {
+			Insn::Label(label) => visitor.visit_label(*label)?,
+			insn => visitor.visit_insn(insn)?
+		}
+	}
+	for attribute in code.attributes.iter() {
+		match attribute {
+			Attribute::LocalVariableTable(table) => {
+				for variable in table.variables.iter() {
+					visitor.visit_local_variable(variable)?;
+				}
+			},
+			Attribute::LineNumberTable(table) => {
+				for line in table.lines.iter() {
+					visitor.visit_line_number(line)?;
+				}
+			},
+			attribute => visitor.visit_code_attribute(attribute)?
+		}
+	}
+	visitor.visit_code_end()
+}
+
+impl ClassFile {
+	/// Pushes the contents of this class to the given visitor
+	pub fn accept<V: ClassVisitor + ?Sized>(&self, visitor: &mut V) -> Result<()> {
+		let super_class = self.super_class.as_ref();
+		visitor.visit_header(self.version, self.access_flags, &self.this_class, super_class, self.interfaces.as_slice())?;
+		for field in self.fields.iter() {
+			if visitor.visit_field(field.access_flags, &field.name, &field.descriptor)? {
+				for attribute in field.attributes.iter() {
+					visitor.visit_field_attribute(attribute)?;
+				}
+				visitor.visit_field_end()?;
+			}
+		}
+		for method in self.methods.iter() {
+			if visitor.visit_method(method.access_flags, &method.name, &method.descriptor)? {
+				for attribute in method.attributes.iter() {
+					match attribute {
+						Attribute::Code(code) => {
+							if visitor.visit_code(code.max_stack, code.max_locals)? {
+								accept_code_body(code, visitor)?;
+							}
+						},
+						Attribute::LazyCode(code) => {
+							if visitor.visit_code(code.max_stack()?, code.max_locals()?)? {
+								accept_code_body(&code.decode()?, visitor)?;
+							}
+						},
+						attribute => visitor.visit_method_attribute(attribute)?
+					}
+				}
+				visitor.visit_method_end()?;
+			}
+		}
+		for attribute in self.attributes.iter() {
+			visitor.visit_attribute(attribute)?;
+		}
+		visitor.visit_end()
+	}
+}
+
+/// Builds a `ClassFile` from the events it receives
+pub struct ClassFileBuilder {
+	class: Option<ClassFile>
+}
+
+impl ClassFileBuilder {
+	pub fn new() -> Self {
+		ClassFileBuilder {
+			class: None
+		}
+	}
+
+	pub fn build(self) -> Result<ClassFile> {
+		self.class.ok_or_else(|| ParserError::none("Class header"))
+	}
+
+	fn class(&mut self) -> Result<&mut ClassFile> {
+		self.class.as_mut().ok_or_else(|| ParserError::none("Class header"))
+	}
+
+	fn field(&mut self) -> Result<&mut Field> {
+		self.class()?.fields.last_mut().ok_or_else(|| ParserError::none("Field"))
+	}
+
+	fn method(&mut self) -> Result<&mut Method> {
+		self.class()?.methods.last_mut().ok_or_else(|| ParserError::none("Method"))
+	}
+
+	/// While a method body is being visited its code is the last attribute of the method
+	fn code(&mut self) -> Result<&mut CodeAttribute> {
+		match self.method()?.attributes.last_mut() {
+			Some(Attribute::Code(code)) => Ok(code),
+			_ => Err(ParserError::none("Code"))
+		}
+	}
+
+	fn mark_label(code: &mut CodeAttribute, label: LabelInsn) {
+		// labels of the visited code must remain unique when new labels are created
+		if label.id >= code.insns.labels {
+			code.insns.labels = label.id + 1;
+		}
+	}
+}
+
+impl Default for ClassFileBuilder {
+	fn default() -> Self {
+		ClassFileBuilder::new()
+	}
+}
+
+impl ClassVisitor for ClassFileBuilder {
+	fn visit_header(&mut self, version: ClassVersion, access_flags: ClassAccessFlags, this_class: &Name, super_class: Option<&Name>, interfaces: &[Name]) -> Result<()> {
+		self.class = Some(ClassFile {
+			magic: 0xCAFEBABE,
+			version,
+			access_flags,
+			this_class: this_class.clone(),
+			super_class: super_class.cloned(),
+			interfaces: interfaces.to_vec(),
+			fields: Vec::new(),
+			methods: Vec::new(),
+			attributes: Vec::new(),
+			constant_pool: None
+		});
+		Ok(())
+	}
+
+	fn visit_field(&mut self, access_flags: FieldAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		self.class()?.fields.push(Field {
+			access_flags,
+			name: name.clone(),
+			descriptor: descriptor.clone(),
+			attributes: Vec::new()
+		});
+		Ok(true)
+	}
+
+	fn visit_field_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.field()?.attributes.push(attribute.clone());
+		Ok(())
+	}
+
+	fn visit_method(&mut self, access_flags: MethodAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		self.class()?.methods.push(Method {
+			access_flags,
+			name: name.clone(),
+			descriptor: descriptor.clone(),
+			attributes: Vec::new()
+		});
+		Ok(true)
+	}
+
+	fn visit_method_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.method()?.attributes.push(attribute.clone());
+		Ok(())
+	}
+
+	fn visit_code(&mut self, max_stack: u16, max_locals: u16) -> Result<bool> {
+		let code = CodeAttribute::new(max_stack, max_locals, InsnList::new(), Vec::new(), Vec::new());
+		self.method()?.attributes.push(Attribute::Code(code));
+		Ok(true)
+	}
+
+	fn visit_try_catch(&mut self, handler: &ExceptionHandler) -> Result<()> {
+		let code = self.code()?;
+		for label in [handler.start, handler.end, handler.handler].iter() {
+			ClassFileBuilder::mark_label(code, *label);
+		}
+		code.exceptions.push(handler.clone());
+		Ok(())
+	}
+
+	fn visit_label(&mut self, label: LabelInsn) -> Result<()> {
+		let code = self.code()?;
+		ClassFileBuilder::mark_label(code, label);
+		code.insns.insns.push(Insn::Label(label));
+		Ok(())
+	}
+
+	fn visit_insn(&mut self, insn: &Insn) -> Result<()> {
+		self.code()?.insns.insns.push(insn.clone());
+		Ok(())
+	}
+
+	fn visit_local_variable(&mut self, variable: &LocalVariable) -> Result<()> {
+		let code = self.code()?;
+		ClassFileBuilder::mark_label(code, variable.start);
+		ClassFileBuilder::mark_label(code, variable.end);
+		// consecutive variables belong to the same table
+		if let Some(Attribute::LocalVariableTable(table)) = code.attributes.last_mut() {
+			table.variables.push(variable.clone());
+		} else {
+			code.attributes.push(Attribute::LocalVariableTable(LocalVariableTableAttribute {
+				variables: vec![variable.clone()]
+			}));
+		}
+		Ok(())
+	}
+
+	fn visit_line_number(&mut self, line: &LineNumber) -> Result<()> {
+		let code = self.code()?;
+		ClassFileBuilder::mark_label(code, line.start);
+		if let Some(Attribute::LineNumberTable(table)) = code.attributes.last_mut() {
+			table.lines.push(*line);
+		} else {
+			code.attributes.push(Attribute::LineNumberTable(LineNumberTableAttribute {
+				lines: vec![*line]
+			}));
+		}
+		Ok(())
+	}
+
+	fn visit_code_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.code()?.attributes.push(attribute.clone());
+		Ok(())
+	}
+
+	fn visit_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.class()?.attributes.push(attribute.clone());
+		Ok(())
+	}
+}
+
+/// Writes the events it receives as a class file.
+/// Each field and method is written as soon as it ends, so only one member is held at a time
+pub struct ClassWriter<W: Write> {
+	wtr: W,
+	builder: ClassFileBuilder,
+	constant_pool: ConstantPoolWriter,
+	fields: Vec<u8>,
+	num_fields: u16,
+	methods: Vec<u8>,
+	num_methods: u16
+}
+
+impl <W: Write> ClassWriter<W> {
+	pub fn new(wtr: W) -> Self {
+		ClassWriter::with_constant_pool(wtr, ConstantPoolWriter::new())
+	}
+
+	/// Writes using the given constant pool, for example one seeded from a parsed pool
+	pub fn with_constant_pool(wtr: W, constant_pool: ConstantPoolWriter) -> Self {
+		ClassWriter {
+			wtr,
+			builder: ClassFileBuilder::new(),
+			constant_pool,
+			fields: Vec::new(),
+			num_fields: 0,
+			methods: Vec::new(),
+			num_methods: 0
+		}
+	}
+
+	pub fn into_inner(self) -> W {
+		self.wtr
+	}
+}
+
+impl <W: Write> ClassVisitor for ClassWriter<W> {
+	fn visit_header(&mut self, version: ClassVersion, access_flags: ClassAccessFlags, this_class: &Name, super_class: Option<&Name>, interfaces: &[Name]) -> Result<()> {
+		self.builder.visit_header(version, access_flags, this_class, super_class, interfaces)
+	}
+
+	fn visit_field(&mut self, access_flags: FieldAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		self.builder.visit_field(access_flags, name, descriptor)
+	}
+
+	fn visit_field_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.builder.visit_field_attribute(attribute)
+	}
+
+	fn visit_field_end(&mut self) -> Result<()> {
+		let field = self.builder.class()?.fields.pop().ok_or_else(|| ParserError::none("Field"))?;
+		field.write(&mut self.fields, &mut self.constant_pool)?;
+		self.num_fields = self.num_fields.checked_add(1).ok_or_else(|| ParserError::other("Too many fields"))?;
+		Ok(())
+	}
+
+	fn visit_method(&mut self, access_flags: MethodAccessFlags, name: &Name, descriptor: &Name) -> Result<bool> {
+		self.builder.visit_method(access_flags, name, descriptor)
+	}
+
+	fn visit_method_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.builder.visit_method_attribute(attribute)
+	}
+
+	fn visit_code(&mut self, max_stack: u16, max_locals: u16) -> Result<bool> {
+		self.builder.visit_code(max_stack, max_locals)
+	}
+
+	fn visit_try_catch(&mut self, handler: &ExceptionHandler) -> Result<()> {
+		self.builder.visit_try_catch(handler)
+	}
+
+	fn visit_label(&mut self, label: LabelInsn) -> Result<()> {
+		self.builder.visit_label(label)
+	}
+
+	fn visit_insn(&mut self, insn: &Insn) -> Result<()> {
+		self.builder.visit_insn(insn)
+	}
+
+	fn visit_local_variable(&mut self, variable: &LocalVariable) -> Result<()> {
+		self.builder.visit_local_variable(variable)
+	}
+
+	fn visit_line_number(&mut self, line: &LineNumber) -> Result<()> {
+		self.builder.visit_line_number(line)
+	}
+
+	fn visit_code_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.builder.visit_code_attribute(attribute)
+	}
+
+	fn visit_method_end(&mut self) -> Result<()> {
+		let method = self.builder.class()?.methods.pop().ok_or_else(|| ParserError::none("Method"))?;
+		method.write(&mut self.methods, &mut self.constant_pool)?;
+		self.num_methods = self.num_methods.checked_add(1).ok_or_else(|| ParserError::other("Too many methods"))?;
+		Ok(())
+	}
+
+	fn visit_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+		self.builder.visit_attribute(attribute)
+	}
+
+	fn visit_end(&mut self) -> Result<()> {
+		let class = self.builder.class()?;
+		let mut body: Vec<u8> = Vec::with_capacity(self.fields.len() + self.methods.len() + 32);
+		class.access_flags.write(&mut body)?;
+		body.write_u16::<BigEndian>(self.constant_pool.class_utf8(&class.this_class)?)?;
+		match &class.super_class {
+			Some(x) => body.write_u16::<BigEndian>(self.constant_pool.class_utf8(x)?)?,
+			None => body.write_u16::<BigEndian>(0)?
+		}
+		body.write_u16::<BigEndian>(class.interfaces.len() as u16)?;
+		for interface in class.interfaces.iter() {
+			body.write_u16::<BigEndian>(self.constant_pool.class_utf8(interface)?)?;
+		}
+		body.write_u16::<BigEndian>(self.num_fields)?;
+		body.write_all(self.fields.as_slice())?;
+		body.write_u16::<BigEndian>(self.num_methods)?;
+		body.write_all(self.methods.as_slice())?;
+		write_class_attributes(&mut body, &class.attributes, &mut self.constant_pool)?;
+
+		self.wtr.write_u32::<BigEndian>(class.magic)?;
+		class.version.write(&mut self.wtr)?;
+		self.constant_pool.write(&mut self.wtr)?;
+		self.wtr.write_all(body.as_slice())?;
+		Ok(())
+	}
+}